@@ -0,0 +1,195 @@
+use std::collections::{BTreeMap, BTreeSet};
+use serde_json::Value;
+use web3::types::U256;
+use crate::provider::WalletDataProvider;
+use crate::receipt::RRReceipt;
+use crate::{block_times, filters, parse_amount, registry};
+
+/// The native currency.
+pub const RON: &str = "RON";
+
+/// Methods paying out staking rewards, which tax tools treat as income rather than deposits.
+const STAKING_REWARD_METHODS: &[&str] = &["claimRewards", "claimReward", "claimPendingRewards", "getReward", "harvest"];
+
+/// What a transaction was from the point of view of the wallet. All accounting exports use the
+/// same classification.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Deposit,
+    Withdrawal,
+    Trade,
+    StakingReward,
+    Other,
+}
+
+/// An amount of one currency moved into or out of the wallet.
+pub struct Leg {
+    pub currency: &'static str,
+    amount: U256,
+    decimals: usize,
+}
+
+impl Leg {
+    /// The amount as a decimal string, e.g. `0.5`.
+    pub fn amount(&self) -> String {
+        filters::format_units(self.amount, self.decimals)
+    }
+}
+
+/// A transaction as an accounting event of a wallet.
+pub struct Event {
+    pub kind: Kind,
+    pub incoming: Vec<Leg>,
+    pub outgoing: Vec<Leg>,
+    /// The fee in RON if the wallet sent and paid the transaction.
+    pub fee: Option<String>,
+    pub hash: String,
+    /// The time of the block as `YYYY-MM-DD HH:MM:SS` in UTC, `None` for transactions that could
+    /// not be fetched and have no block.
+    pub date: Option<String>,
+    pub from: String,
+    pub to: String,
+    pub method: Option<String>,
+    /// Why a transaction is `Other`.
+    pub note: Option<String>,
+    /// The transfers from or to the wallet without a leg: NFTs and tokens the export can't value.
+    pub unvalued: usize,
+}
+
+/// Looks up the block times of `numbers` for the dates of an accounting export, which can't be
+/// imported without them. Fails if any of them is unknown.
+pub async fn look_up_dates(provider: &dyn WalletDataProvider, numbers: impl IntoIterator<Item = u64>) {
+    // Transactions that could not be fetched have no block, and no rows.
+    let missing = block_times::fetch(provider, numbers.into_iter().filter(|number| *number != 0)).await;
+
+    if let Err(error) = require_dates(&missing) {
+        panic!("{}", error);
+    }
+}
+
+/// Like `look_up_dates` for exports read from disk, which only take the times from the block
+/// cache.
+pub fn cached_dates(numbers: impl IntoIterator<Item = u64>) -> Result<(), String> {
+    let missing: Vec<u64> = numbers.into_iter()
+        .filter(|number| *number != 0 && block_times::get(*number).is_none())
+        .collect::<BTreeSet<u64>>()
+        .into_iter()
+        .collect();
+
+    require_dates(&missing)
+}
+
+fn require_dates(missing: &[u64]) -> Result<(), String> {
+    match missing.first() {
+        None => Ok(()),
+        Some(first) => Err(format!(
+            "Could not look up the time of {} blocks (first {}), accounting exports need the date of every transaction. \
+            Fill the block cache with `wally blocks --from-export=EXPORT --cache-dir=DIR` and pass the same --cache-dir",
+            missing.len(), first
+        ))
+    }
+}
+
+/// Classifies a serialized record for `wallet`, the address (or pseudonym) the export belongs to.
+/// Native RON and `Transfer` logs of well-known tokens are netted per currency, so a swap which
+/// sends and refunds WRON only counts the difference. Failed transactions only cost their fee.
+pub fn event(record: &Value, wallet: &str) -> Event {
+    let text = |key: &str| record.get(key).and_then(Value::as_str).unwrap_or_default().to_string();
+    let from = text("from");
+    let to = text("to");
+    let sent = from.eq_ignore_ascii_case(wallet);
+    let failed = record.get("status").and_then(Value::as_str) == Some("failed");
+
+    let fee = record.get("feeRon").and_then(Value::as_str)
+        .filter(|fee| sent && *fee != "0" && record.get("feePayer").and_then(Value::as_str) != Some("sponsored"))
+        .map(str::to_string);
+
+    let mut net: BTreeMap<&'static str, (usize, U256, U256)> = BTreeMap::new();
+    let mut note = None;
+    let mut unvalued = 0;
+
+    if !failed {
+        let value = record.get("value").and_then(parse_amount).unwrap_or_default();
+        if !value.is_zero() {
+            let (_, incoming, outgoing) = net.entry(RON).or_insert((filters::RON_DECIMALS, U256::zero(), U256::zero()));
+            if sent {
+                *outgoing = outgoing.saturating_add(value);
+            }
+            if to.eq_ignore_ascii_case(wallet) {
+                *incoming = incoming.saturating_add(value);
+            }
+        }
+
+        let receipt = record.get("output").cloned().map(RRReceipt::from_value);
+        let logs = receipt.as_ref().map(|receipt| receipt.logs.as_slice()).unwrap_or_default();
+        for log in logs.iter().filter(|log| log.name.as_deref() == Some("Transfer")) {
+            let address = log.address.as_deref().unwrap_or_default();
+
+            let (sender, recipient, amount) = match log.transfer() {
+                Some(transfer) => transfer,
+                None if filters::mentions(&log.raw, wallet) => {
+                    note = Some(format!("transfer of {} without an amount, e.g. an NFT", address));
+                    unvalued += 1;
+                    continue;
+                }
+                None => continue
+            };
+            if !sender.eq_ignore_ascii_case(wallet) && !recipient.eq_ignore_ascii_case(wallet) {
+                continue;
+            }
+
+            let (currency, decimals) = match registry::token(address) {
+                Some(token) => token,
+                None => {
+                    note = Some(format!("transfer of the unknown token {}", address));
+                    unvalued += 1;
+                    continue;
+                }
+            };
+
+            let (_, incoming, outgoing) = net.entry(currency).or_insert((decimals, U256::zero(), U256::zero()));
+            if sender.eq_ignore_ascii_case(wallet) {
+                *outgoing = outgoing.saturating_add(amount);
+            }
+            if recipient.eq_ignore_ascii_case(wallet) {
+                *incoming = incoming.saturating_add(amount);
+            }
+        }
+    }
+
+    let mut incoming = vec![];
+    let mut outgoing = vec![];
+    for (currency, (decimals, received, spent)) in net {
+        match received.cmp(&spent) {
+            std::cmp::Ordering::Greater => incoming.push(Leg { currency, amount: received - spent, decimals }),
+            std::cmp::Ordering::Less => outgoing.push(Leg { currency, amount: spent - received, decimals }),
+            std::cmp::Ordering::Equal => {}
+        }
+    }
+
+    let method = record.get("input").and_then(filters::method_name).map(str::to_string);
+
+    let kind = match (note.is_some() || failed, incoming.is_empty(), outgoing.is_empty()) {
+        (true, _, _) => Kind::Other,
+        (false, false, false) => Kind::Trade,
+        (false, false, true) if method.as_deref().is_some_and(|method| STAKING_REWARD_METHODS.contains(&method)) => Kind::StakingReward,
+        (false, false, true) => Kind::Deposit,
+        (false, true, false) => Kind::Withdrawal,
+        (false, true, true) => Kind::Other,
+    };
+
+    if kind == Kind::Other && note.is_none() {
+        note = Some(match failed {
+            true => "failed transaction".to_string(),
+            false => "no value moved".to_string()
+        });
+    }
+
+    let hash = text("hash");
+    let date = match record.get("blockNumber").and_then(Value::as_u64).unwrap_or_default() {
+        0 => None,
+        block => Some(block_times::date(block).unwrap_or_else(|| panic!("The time of block {} of {} was not looked up", block, hash)))
+    };
+
+    Event { kind, incoming, outgoing, fee, hash, date, from, to, method, note, unvalued }
+}
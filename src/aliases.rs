@@ -0,0 +1,90 @@
+use std::collections::{BTreeMap, HashMap};
+use web3::types::Address;
+use crate::normalize_address;
+
+/// Longest alias accepted, to keep file names manageable.
+const MAX_LENGTH: usize = 64;
+
+/// Stems of files written for the whole run.
+const RESERVED: &[&str] = &["merged", "aliases", "anonymize-mapping"];
+
+/// A wallet passed with `--address`, optionally named, e.g. `--address=treasury=ronin:…`.
+pub struct WalletArg {
+    pub address: String,
+    pub alias: Option<String>,
+}
+
+/// Aliases become file names, so only characters safe on every file system are allowed.
+fn validate(alias: &str) -> Result<(), String> {
+    if alias.is_empty() || alias.len() > MAX_LENGTH {
+        return Err(format!("Invalid alias '{}', it must have 1 to {} characters", alias, MAX_LENGTH));
+    }
+    if !alias.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("Invalid alias '{}', only letters, digits, '-' and '_' are allowed", alias));
+    }
+    if RESERVED.contains(&alias.to_lowercase().as_str()) {
+        return Err(format!("Invalid alias '{}', the name is reserved for files of the whole run", alias));
+    }
+    if normalize_address(alias).parse::<Address>().is_ok() {
+        return Err(format!("Invalid alias '{}', it looks like an address", alias));
+    }
+
+    Ok(())
+}
+
+impl WalletArg {
+    pub fn parse(arg: &str) -> Result<WalletArg, String> {
+        let (alias, address) = match arg.split_once('=') {
+            Some((alias, address)) => {
+                validate(alias)?;
+                (Some(alias.to_string()), address)
+            }
+            None => (None, arg)
+        };
+
+        match normalize_address(address).parse::<Address>() {
+            Ok(_) => Ok(WalletArg { address: normalize_address(address), alias }),
+            Err(_) => Err(format!("Could not parse address {}!", address))
+        }
+    }
+}
+
+/// Drops repeated addresses and rejects an address with two aliases as well as an alias given to
+/// two addresses. Aliases are compared case-insensitively, like file names on some systems.
+pub fn deduplicate(wallets: Vec<WalletArg>) -> Result<Vec<WalletArg>, String> {
+    let mut unique: Vec<WalletArg> = vec![];
+    let mut owners: HashMap<String, String> = HashMap::new();
+
+    for wallet in wallets {
+        if let Some(alias) = &wallet.alias {
+            match owners.get(&alias.to_lowercase()) {
+                Some(owner) if !owner.eq_ignore_ascii_case(&wallet.address) => {
+                    return Err(format!("The alias {} is used for both {} and {}", alias, owner, wallet.address));
+                }
+                _ => {
+                    owners.insert(alias.to_lowercase(), wallet.address.clone());
+                }
+            }
+        }
+
+        match unique.iter_mut().find(|other| other.address.eq_ignore_ascii_case(&wallet.address)) {
+            None => unique.push(wallet),
+            Some(other) => match (&other.alias, wallet.alias) {
+                (Some(first), Some(second)) if !first.eq_ignore_ascii_case(&second) => {
+                    return Err(format!("{} has the two aliases {} and {}", wallet.address, first, second));
+                }
+                (None, alias) => other.alias = alias,
+                _ => {}
+            }
+        }
+    }
+
+    Ok(unique)
+}
+
+/// The aliases and their addresses, written to `aliases.json` so the files stay interpretable.
+pub fn mapping(wallets: &[WalletArg]) -> BTreeMap<String, String> {
+    wallets.iter()
+        .filter_map(|wallet| wallet.alias.clone().map(|alias| (alias, wallet.address.clone())))
+        .collect()
+}
@@ -0,0 +1,72 @@
+use std::collections::{BTreeMap, HashMap};
+use serde_json::Value;
+use crate::registry;
+
+/// Raw fields which would leak the original addresses (e.g. indexed log topics).
+const RAW_FIELDS: &[&str] = &["data", "topics", "input", "raw"];
+
+fn is_address(text: &str) -> bool {
+    text.len() == 42 && text.starts_with("0x") && text[2..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Replaces addresses with stable pseudonyms (`wallet_01`, `wallet_02`, ...) so exports can be
+/// shared without revealing counterparties. Well-known contracts keep their address.
+pub struct Anonymizer {
+    pseudonyms: HashMap<String, String>,
+}
+
+impl Anonymizer {
+    /// The exported wallets get the first pseudonyms.
+    pub fn new(wallets: &[String]) -> Anonymizer {
+        let mut anonymizer = Anonymizer { pseudonyms: HashMap::new() };
+
+        for wallet in wallets {
+            anonymizer.pseudonym(wallet);
+        }
+
+        anonymizer
+    }
+
+    pub fn pseudonym(&mut self, address: &str) -> String {
+        let count = self.pseudonyms.len();
+
+        self.pseudonyms.entry(address.to_lowercase())
+            .or_insert_with(|| format!("wallet_{:02}", count + 1))
+            .clone()
+    }
+
+    fn replace(&mut self, text: &str) -> Option<String> {
+        match is_address(text) && registry::label(text).is_none() {
+            true => Some(self.pseudonym(text)),
+            false => None
+        }
+    }
+
+    /// Replaces addresses in all strings and object keys of a value, recursively. Raw fields are
+    /// dropped from the decoded input and output and the raw logs.
+    pub fn anonymize(&mut self, value: Value) -> Value {
+        self.anonymize_value(value, false)
+    }
+
+    fn anonymize_value(&mut self, value: Value, decoded: bool) -> Value {
+        match value {
+            Value::String(text) => Value::String(self.replace(&text).unwrap_or(text)),
+            Value::Array(values) => Value::Array(values.into_iter().map(|value| self.anonymize_value(value, decoded)).collect()),
+            Value::Object(map) => Value::Object(
+                map.into_iter()
+                    .filter(|(key, _)| !decoded || !RAW_FIELDS.contains(&key.as_str()))
+                    .map(|(key, value)| {
+                        let decoded = decoded || key == "input" || key == "output" || key == "rawLogs";
+                        (self.replace(&key).unwrap_or(key), self.anonymize_value(value, decoded))
+                    })
+                    .collect()
+            ),
+            value => value
+        }
+    }
+
+    /// The pseudonym to address mapping, which must never be shared alongside the export.
+    pub fn mapping(&self) -> BTreeMap<String, String> {
+        self.pseudonyms.iter().map(|(address, pseudonym)| (pseudonym.clone(), address.clone())).collect()
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashSet;
+use std::path::Path;
+use crate::export::{read_records, FORMAT_VERSION};
+use crate::manifest::{Coverage, Manifest};
+use crate::{ArgParser, RRDecodedTransaction, RRTransactionHash};
+
+/// Whether `--append` was passed.
+pub fn enabled() -> bool {
+    ArgParser::flag("--append")
+}
+
+/// What is kept of the export being appended to.
+pub struct Existing {
+    pub path: String,
+    pub records: usize,
+    pub hashes: HashSet<RRTransactionHash>,
+    /// Nonces of the transactions the wallet sent, for the nonce gap check.
+    pub sent_nonces: Vec<u64>,
+    /// The blocks and statuses of the existing records, for the manifest.
+    pub coverage: Coverage,
+}
+
+/// Reads the previous export of `wallet` at `path`, `None` if there is none yet. Fails if the
+/// export can't be read as records of this version or belongs to another wallet.
+pub fn load(path: &str, wallet: &str) -> Result<Option<Existing>, String> {
+    if !Path::new(path).exists() {
+        return Ok(None);
+    }
+
+    let mut existing = Existing { path: path.to_string(), records: 0, hashes: HashSet::new(), sent_nonces: vec![], coverage: Coverage::default() };
+    let mut involved = false;
+
+    for record in read_records::<RRDecodedTransaction>(path) {
+        let record = record.map_err(|error| format!("Could not append to {}, it is not an export of this version: {}", path, error))?;
+
+        let sent = record.from.eq_ignore_ascii_case(wallet);
+        involved |= sent || record.to.eq_ignore_ascii_case(wallet);
+        if sent {
+            existing.sent_nonces.extend(record.nonce);
+        }
+
+        existing.coverage.add(&record);
+        existing.records += 1;
+        existing.hashes.insert(record.hash.to_lowercase());
+    }
+
+    // Not every transaction of a wallet has it as sender or recipient, but an export without any
+    // such transaction is someone else's.
+    if existing.records > 0 && !involved {
+        return Err(format!("Could not append to {}, it is not an export of {}", path, wallet));
+    }
+
+    Ok(Some(existing))
+}
+
+/// Checks that `previous`, the manifest at `path` of the export appended to, was written for `wallet`
+/// in the format of this version. Records of another format or wallet can't be appended to.
+pub fn check_manifest(path: &str, previous: &Manifest, wallet: &str) -> Result<(), String> {
+    if previous.format_version != FORMAT_VERSION {
+        return Err(format!(
+            "Could not append, {} was written in format version {} and this version of wally writes {}",
+            path, previous.format_version, FORMAT_VERSION
+        ));
+    }
+
+    match previous.address.as_deref() {
+        Some(address) if address.eq_ignore_ascii_case(wallet) => Ok(()),
+        Some(address) => Err(format!("Could not append, {} belongs to an export of {}, not {}", path, address, wallet)),
+        None => Err(format!("Could not append, {} belongs to an export of several wallets, not {}", path, wallet))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::testing::{OTHER, WALLET};
+    use super::*;
+
+    fn manifest(format_version: u32, address: Option<&str>) -> Manifest {
+        serde_json::from_value(json!({
+            "formatVersion": format_version, "toolVersion": "0.1.0", "network": "mainnet", "address": address, "hosts": [],
+            "filters": {}, "flags": [], "firstBlock": 100, "lastBlock": 108, "records": { "total": 1, "successful": 1, "reverted": 0, "unknown": 0 },
+            "failedTransactions": 0, "startedAt": "", "finishedAt": "", "files": []
+        })).unwrap()
+    }
+
+    #[test]
+    fn accepts_the_manifest_of_the_wallet() {
+        assert!(check_manifest("w.manifest.json", &manifest(FORMAT_VERSION, Some(WALLET)), &WALLET.to_uppercase().replace("0X", "0x")).is_ok());
+    }
+
+    #[test]
+    fn refuses_another_format_version() {
+        let error = check_manifest("w.manifest.json", &manifest(FORMAT_VERSION + 1, Some(WALLET)), WALLET).unwrap_err();
+        assert_eq!(error, format!(
+            "Could not append, w.manifest.json was written in format version {} and this version of wally writes {}", FORMAT_VERSION + 1, FORMAT_VERSION
+        ));
+    }
+
+    #[test]
+    fn refuses_another_wallet() {
+        let error = check_manifest("w.manifest.json", &manifest(FORMAT_VERSION, Some(OTHER)), WALLET).unwrap_err();
+        assert_eq!(error, format!("Could not append, w.manifest.json belongs to an export of {}, not {}", OTHER, WALLET));
+
+        assert!(check_manifest("w.manifest.json", &manifest(FORMAT_VERSION, None), WALLET).is_err());
+    }
+}
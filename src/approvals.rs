@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use serde::Serialize;
+use web3::types::U256;
+use web3::ethabi::{self, Token};
+use web3::types::Address;
+use crate::multicall::parse_hex;
+use crate::rpc::Rpc;
+use crate::{block_times, filters, network, normalize_address, parse_amount, registry, ArgParser, RRDecodedTransaction, TxStatus};
+
+/// Allowances this large never run out in practice, whether they are `2^256 - 1` or another
+/// "infinite" value a dapp picked.
+const UNLIMITED_BITS: usize = 128;
+
+const ALLOWANCE: &str = "0xdd62ed3e";
+
+/// Whether `--report=approvals` was passed.
+pub fn report() -> bool {
+    ArgParser::all("--report").iter().any(|report| report == "approvals")
+}
+
+/// Checks the allowances of active approvals on chain with `--verify-allowances`, printing what
+/// couldn't be verified.
+pub async fn verify_from_args(report: &mut Report) {
+    if !ArgParser::flag("--verify-allowances") {
+        return;
+    }
+
+    match Rpc::from_args() {
+        Ok(rpc) => {
+            for error in report.verify(&rpc).await {
+                println!("Warning: could not verify an allowance: {}", error);
+            }
+        }
+        Err(error) => println!("Warning: the allowances are not verified, {}", error)
+    }
+}
+
+/// Why an active approval should be reviewed.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Risk {
+    /// The spender is not in the contract registry.
+    UnknownSpender,
+    Unlimited,
+    /// The wallet never called the spender after approving it.
+    UnusedSpender,
+}
+
+impl Risk {
+    fn describe(&self) -> &'static str {
+        match self {
+            Risk::UnknownSpender => "unknown spender",
+            Risk::Unlimited => "unlimited",
+            Risk::UnusedSpender => "spender not used since",
+        }
+    }
+}
+
+/// An `approve`, `increaseAllowance` or `decreaseAllowance` call of a wallet that left a non-zero
+/// allowance.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Approval {
+    pub owner: String,
+    pub token: String,
+    pub token_label: Option<&'static str>,
+    pub spender: String,
+    pub spender_label: Option<&'static str>,
+    pub method: String,
+    /// The allowance after the call in the smallest unit of the token as a decimal string. For
+    /// increases and decreases it assumes the allowance before was the one of the export.
+    pub allowance: String,
+    /// `allowance` for tokens with known decimals, `allowance` remains authoritative.
+    pub allowance_formatted: Option<String>,
+    pub unlimited: bool,
+    pub transaction_hash: String,
+    pub block_number: u64,
+    /// The time of the block, if it was looked up or is in the block cache.
+    pub time: Option<String>,
+    /// The later transaction setting the allowance to zero.
+    pub revoked_by: Option<String>,
+    /// The later call changing the allowance to another non-zero value.
+    pub replaced_by: Option<String>,
+    /// The current allowance according to the token contract, only set with `--verify-allowances`.
+    pub on_chain_allowance: Option<String>,
+    /// Why the approval should be reviewed, only set for active approvals.
+    pub risks: Vec<Risk>,
+    /// The spender in the block explorer, for approvals to review.
+    pub explorer_url: Option<String>,
+    /// Whether the wallet called the spender after the approval.
+    #[serde(skip)]
+    used_since: bool,
+}
+
+impl Approval {
+    /// Whether the allowance still stands at the end of the export, and on chain if verified.
+    pub fn active(&self) -> bool {
+        self.revoked_by.is_none() && self.replaced_by.is_none() && self.on_chain_allowance.as_deref() != Some("0")
+    }
+
+    fn assess(&mut self) {
+        let risks = [
+            (self.spender_label.is_none(), Risk::UnknownSpender),
+            (self.unlimited, Risk::Unlimited),
+            (!self.used_since, Risk::UnusedSpender),
+        ];
+
+        self.risks = match self.active() {
+            true => risks.into_iter().filter(|(applies, _)| *applies).map(|(_, risk)| risk).collect(),
+            false => vec![]
+        };
+        self.explorer_url = match self.risks.is_empty() {
+            true => None,
+            false => Some(format!("{}/address/{}", network::current().explorer_url(), self.spender))
+        };
+    }
+}
+
+/// The approvals of an export, written to `<wallet>.approvals.json`.
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub active: usize,
+    pub unlimited_active: usize,
+    /// Active approvals with risks.
+    pub review_recommended: usize,
+    /// Approvals the export considers active, but whose allowance is zero on chain.
+    pub revoked_elsewhere: usize,
+    /// Calls setting an allowance to zero without an approval in the export.
+    pub unmatched_revocations: usize,
+    pub approvals: Vec<Approval>,
+}
+
+/// The spender and amount of an allowance call, its first two params.
+fn call(input: &serde_json::Value) -> Option<(&str, String, U256)> {
+    let method = filters::method_name(input)?;
+    if !["approve", "increaseAllowance", "decreaseAllowance"].contains(&method) {
+        return None;
+    }
+
+    let params = filters::params(input);
+    let spender = params.first()?.1.as_str().filter(|spender| spender.len() == 42)?;
+    let amount = parse_amount(params.get(1)?.1)?;
+
+    Some((method, normalize_address(spender).to_lowercase(), amount))
+}
+
+/// Collects the allowance calls of the wallets in sorted records and follows every approval to
+/// its revocation or replacement. Only calls the wallet sent itself count, calls inside a
+/// multicall approve for the multicall contract. Reverted transactions are skipped. `wallet` is
+/// `None` for merged exports, whose records name their wallet.
+pub fn scan(records: impl Iterator<Item = RRDecodedTransaction>, wallet: Option<&str>) -> Report {
+    let mut report = Report::default();
+    // The latest approval and allowance of each owner, token and spender.
+    let mut latest: HashMap<(String, String, String), (Option<usize>, U256)> = HashMap::new();
+    // The approvals of each owner and spender not followed by a call to the spender yet.
+    let mut unused: HashMap<(String, String), Vec<usize>> = HashMap::new();
+
+    for record in records {
+        let owner = wallet.map(str::to_string).or_else(|| record.wallet.clone()).unwrap_or_default();
+        if !record.from.eq_ignore_ascii_case(&owner) || record.status == Some(TxStatus::Failed) {
+            continue;
+        }
+
+        let to = normalize_address(&record.to).to_lowercase();
+        for index in unused.remove(&(owner.to_lowercase(), to.clone())).unwrap_or_default() {
+            report.approvals[index].used_since = true;
+        }
+
+        let Some((method, spender, amount)) = record.input.as_ref().and_then(call) else {
+            continue;
+        };
+
+        let token = to;
+        let hash = record.hash.to_lowercase();
+        let known = latest.get(&(owner.to_lowercase(), token.clone(), spender.clone())).copied();
+        let (previous, allowance) = known.unwrap_or_default();
+
+        let allowance = match method {
+            "increaseAllowance" => allowance.saturating_add(amount),
+            "decreaseAllowance" => allowance.saturating_sub(amount),
+            _ => amount
+        };
+
+        if let Some(previous) = previous {
+            match allowance.is_zero() {
+                true => report.approvals[previous].revoked_by = Some(hash.clone()),
+                false => report.approvals[previous].replaced_by = Some(hash.clone())
+            }
+        } else if known.is_none() && allowance.is_zero() {
+            report.unmatched_revocations += 1;
+        }
+
+        let index = match allowance.is_zero() {
+            true => None,
+            false => {
+                report.approvals.push(Approval {
+                    owner: owner.to_lowercase(),
+                    token_label: registry::label(&token),
+                    spender_label: registry::label(&spender),
+                    spender: spender.clone(),
+                    method: method.to_string(),
+                    allowance: allowance.to_string(),
+                    allowance_formatted: registry::token(&token).map(|(_, decimals)| filters::format_units(allowance, decimals)),
+                    unlimited: allowance.bits() > UNLIMITED_BITS,
+                    transaction_hash: hash,
+                    block_number: record.block_number,
+                    time: block_times::get(record.block_number).map(|(_, time)| time),
+                    revoked_by: None,
+                    replaced_by: None,
+                    on_chain_allowance: None,
+                    risks: vec![],
+                    explorer_url: None,
+                    used_since: false,
+                    token: token.clone(),
+                });
+                unused.entry((owner.to_lowercase(), spender.clone())).or_default().push(report.approvals.len() - 1);
+                Some(report.approvals.len() - 1)
+            }
+        };
+        latest.insert((owner.to_lowercase(), token, spender), (index, allowance));
+    }
+
+    report.assess();
+    report
+}
+
+impl Report {
+    fn assess(&mut self) {
+        for approval in self.approvals.iter_mut() {
+            approval.assess();
+        }
+
+        self.active = self.approvals.iter().filter(|approval| approval.active()).count();
+        self.unlimited_active = self.approvals.iter().filter(|approval| approval.active() && approval.unlimited).count();
+        self.review_recommended = self.approvals.iter().filter(|approval| !approval.risks.is_empty()).count();
+        self.revoked_elsewhere = self.approvals.iter().filter(|approval| approval.revoked_by.is_none() && approval.on_chain_allowance.as_deref() == Some("0")).count();
+    }
+
+    /// Requests the current allowance of every active approval from the token contract, so
+    /// allowances revoked with another tool don't count as active. Returns why approvals couldn't
+    /// be verified.
+    async fn verify(&mut self, rpc: &Rpc) -> Vec<String> {
+        let mut errors = vec![];
+
+        for approval in self.approvals.iter_mut().filter(|approval| approval.active()) {
+            let (Ok(token), Ok(owner), Ok(spender)) = (approval.token.parse::<Address>(), approval.owner.parse::<Address>(), approval.spender.parse::<Address>()) else {
+                continue;
+            };
+            let mut data = parse_hex(ALLOWANCE).unwrap();
+            data.extend(ethabi::encode(&[Token::Address(owner), Token::Address(spender)]));
+
+            match rpc.call(token, data).await {
+                Ok(result) if result.len() == 32 => approval.on_chain_allowance = Some(U256::from_big_endian(&result).to_string()),
+                Ok(_) => errors.push(format!("{} has no allowance for {}", approval.token, approval.spender)),
+                Err(error) => errors.push(error)
+            }
+        }
+
+        self.assess();
+        errors
+    }
+}
+
+/// The report as printed at the end of a run and by `wally stats`: the counts and every
+/// allowance still standing.
+pub fn lines(report: &Report) -> Vec<String> {
+    let mut lines = vec![format!(
+        "Token approvals: {} ({} still active, {} of them unlimited, {} to review, {} revoked)",
+        report.approvals.len(), report.active, report.unlimited_active, report.review_recommended,
+        report.approvals.iter().filter(|approval| approval.revoked_by.is_some()).count() + report.revoked_elsewhere
+    )];
+
+    for approval in report.approvals.iter().filter(|approval| approval.active()) {
+        let amount = match (approval.unlimited, &approval.allowance_formatted) {
+            (true, _) => "unlimited".to_string(),
+            (false, Some(formatted)) => formatted.clone(),
+            (false, None) => approval.allowance.clone()
+        };
+        let on_chain = match &approval.on_chain_allowance {
+            Some(on_chain) if *on_chain != approval.allowance => format!(", {} on chain", on_chain),
+            _ => String::new()
+        };
+        lines.push(format!(
+            "  {} for {} on {}: {}{} since {} (block {}{})",
+            approval.token_label.unwrap_or(&approval.token), approval.spender_label.unwrap_or(&approval.spender), approval.owner,
+            amount, on_chain, approval.transaction_hash, approval.block_number, approval.time.as_ref().map(|time| format!(", {}", time)).unwrap_or_default()
+        ));
+
+        if let Some(explorer_url) = &approval.explorer_url {
+            let risks: Vec<&str> = approval.risks.iter().map(Risk::describe).collect();
+            lines.push(format!("    review recommended ({}): {}", risks.join(", "), explorer_url));
+        }
+    }
+
+    if report.revoked_elsewhere > 0 {
+        lines.push(format!("  {} approvals were revoked outside the export, their allowance is zero on chain", report.revoked_elsewhere));
+    }
+
+    lines
+}
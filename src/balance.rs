@@ -0,0 +1,118 @@
+use web3::types::U256;
+use crate::sort::RecordSorter;
+use crate::{filters, ArgParser, FeePayer, RRDecodedTransaction, TxStatus};
+
+/// A signed amount in wei. The RON supply fits many times over.
+pub type Wei = i128;
+
+fn wei(amount: U256) -> Wei {
+    match amount.bits() <= 127 {
+        true => amount.low_u128() as Wei,
+        false => Wei::MAX
+    }
+}
+
+/// Formats an amount as RON, e.g. `-0.5`.
+pub fn format_ron(amount: Wei) -> String {
+    let formatted = filters::format_units(U256::from(amount.unsigned_abs()), filters::RON_DECIMALS);
+
+    match amount < 0 {
+        true => format!("-{}", formatted),
+        false => formatted
+    }
+}
+
+/// An amount of the balance report, in wei with `--raw-amounts`.
+pub fn report_amount(amount: Wei) -> String {
+    match filters::raw_amounts() {
+        true => format!("{} wei", amount),
+        false => format_ron(amount)
+    }
+}
+
+/// Whether `--balance`, or `--report=balance` which implies it, was passed.
+pub fn enabled() -> bool {
+    ArgParser::flag("--balance") || report()
+}
+
+/// Whether the balance checkpoints should be printed with `--report=balance`.
+pub fn report() -> bool {
+    ArgParser::all("--report").iter().any(|report| match report.as_str() {
+        "balance" => true,
+        "approvals" | "largest" => false,
+        _ => panic!("Invalid --report value {}, expected balance, approvals or largest", report)
+    })
+}
+
+/// How a transaction changes the RON balance of the wallet: the value it sent or received if it
+/// succeeded, minus the fee if the wallet sent and paid it.
+fn change(record: &RRDecodedTransaction, wallet: &str) -> Wei {
+    let succeeded = record.status != Some(TxStatus::Failed);
+    let value = record.value.map(wei).unwrap_or(0);
+    let mut change = 0;
+
+    if record.from.eq_ignore_ascii_case(wallet) {
+        let fee = record.gas_used
+            .filter(|_| record.fee_payer != Some(FeePayer::Sponsored))
+            .zip(record.effective_gas_price.or(record.gas_price))
+            .map(|(gas_used, gas_price)| gas_used as Wei * gas_price as Wei)
+            .unwrap_or(0);
+
+        change -= fee;
+        if succeeded {
+            change -= value;
+        }
+    }
+
+    if record.to.eq_ignore_ascii_case(wallet) && succeeded {
+        change += value;
+    }
+
+    change
+}
+
+/// The balance after a transaction.
+pub struct Checkpoint {
+    pub hash: String,
+    pub block: u64,
+    pub balance: Wei,
+}
+
+/// The balance history of a wallet reconstructed from its exported transactions.
+pub struct Reconstruction {
+    pub transactions: usize,
+    /// The first transaction after which the balance is negative, which means transactions that
+    /// added RON are missing, e.g. internal transactions.
+    pub first_negative: Option<Checkpoint>,
+    /// The balance after the last transaction.
+    pub last: Option<Checkpoint>,
+}
+
+impl Reconstruction {
+    pub fn balance(&self) -> Wei {
+        self.last.as_ref().map_or(0, |last| last.balance)
+    }
+}
+
+/// Sets `balance_after` on the sorted records of `wallet`, starting from a balance of zero, and
+/// returns them in a new sorter.
+pub fn reconstruct(records: impl Iterator<Item = RRDecodedTransaction>, wallet: &str) -> (RecordSorter, Reconstruction) {
+    let mut sorter = RecordSorter::new();
+    let mut reconstruction = Reconstruction { transactions: 0, first_negative: None, last: None };
+    let mut balance: Wei = 0;
+
+    for mut record in records {
+        balance += change(&record, wallet);
+        record.balance_after = Some(format_ron(balance));
+
+        if balance < 0 && reconstruction.first_negative.is_none() {
+            reconstruction.first_negative = Some(Checkpoint { hash: record.hash.clone(), block: record.block_number, balance });
+        }
+
+        reconstruction.transactions += 1;
+        reconstruction.last = Some(Checkpoint { hash: record.hash.clone(), block: record.block_number, balance });
+        sorter.push(record);
+    }
+
+    (sorter, reconstruction)
+}
@@ -0,0 +1,35 @@
+use serde_json::Value;
+use web3::signing::keccak256;
+use crate::{multicall, ArgParser};
+
+/// The maximum size of strings in the decoded input and output, from `--max-blob-bytes`.
+pub fn max_blob_bytes() -> Option<usize> {
+    ArgParser::split(&"--max-blob-bytes".to_string()).map(|bytes| {
+        bytes.parse().unwrap_or_else(|_| panic!("Invalid --max-blob-bytes value {}, expected a number of bytes", bytes))
+    })
+}
+
+/// Replaces everything after the first `max_bytes` bytes of `text` with a marker holding the
+/// original length and the keccak256 hash of the original text, so the data stays identifiable.
+fn truncated(text: &str, max_bytes: usize) -> String {
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    format!("{}...[truncated, {} bytes, keccak256 {}]", &text[..end], text.len(), multicall::to_hex(&keccak256(text.as_bytes())))
+}
+
+/// Truncates all strings longer than `max_bytes` inside a decoded value, recursively. Object
+/// keys are left alone. Returns the number of truncated strings.
+pub fn truncate(value: &mut Value, max_bytes: usize) -> usize {
+    match value {
+        Value::String(text) if text.len() > max_bytes => {
+            *text = truncated(text, max_bytes);
+            1
+        }
+        Value::Array(values) => values.iter_mut().map(|value| truncate(value, max_bytes)).sum(),
+        Value::Object(map) => map.values_mut().map(|value| truncate(value, max_bytes)).sum(),
+        _ => 0
+    }
+}
@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::{normalize_address, parse_quantity};
+
+/// What `wally blocks` exports of a block.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RRBlock {
+    pub block_number: u64,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// `timestamp` as RFC 3339 in UTC.
+    pub time: String,
+    /// The validator that produced the block.
+    pub miner: String,
+    pub transaction_count: usize,
+}
+
+impl RRBlock {
+    /// Parses a block as the RPC returns it. Quantities may be hex or decimal, and the transactions
+    /// either a list of hashes or objects, or only their number.
+    pub fn from_value(number: u64, value: &Value) -> Result<RRBlock, String> {
+        let timestamp = value.get("timestamp").and_then(parse_quantity)
+            .ok_or_else(|| format!("Could not parse the timestamp of block {}", number))?;
+        let miner = value.get("miner").or_else(|| value.get("validator")).and_then(Value::as_str)
+            .ok_or_else(|| format!("Could not parse the miner of block {}", number))?;
+        let transaction_count = match value.get("transactions").and_then(Value::as_array) {
+            Some(transactions) => transactions.len(),
+            None => value.get("transactionCount").and_then(parse_quantity)
+                .ok_or_else(|| format!("Could not parse the transactions of block {}", number))? as usize
+        };
+
+        let time = chrono::DateTime::from_timestamp(timestamp as i64, 0)
+            .ok_or_else(|| format!("Block {} has an invalid timestamp {}", number, timestamp))?
+            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+
+        Ok(RRBlock { block_number: number, timestamp, time, miner: normalize_address(miner), transaction_count })
+    }
+}
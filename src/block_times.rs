@@ -0,0 +1,87 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+use crate::cache;
+use crate::provider::WalletDataProvider;
+
+/// The timestamps of the blocks looked up during the run, in addition to the block cache.
+static TIMES: Mutex<BTreeMap<u64, u64>> = Mutex::new(BTreeMap::new());
+
+/// The timestamp of block `number` and the same as RFC 3339 in UTC, if it was looked up or is in
+/// the block cache.
+pub fn get(number: u64) -> Option<(u64, String)> {
+    let timestamp = match TIMES.lock().unwrap().get(&number) {
+        Some(timestamp) => *timestamp,
+        None => cache::load_block(number)?.timestamp
+    };
+    let time = chrono::DateTime::from_timestamp(timestamp as i64, 0)?.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+
+    Some((timestamp, time))
+}
+
+/// `get` formatted as `YYYY-MM-DD HH:MM:SS` in UTC, the form the accounting imports expect.
+pub fn date(number: u64) -> Option<String> {
+    let (timestamp, _) = get(number)?;
+    chrono::DateTime::from_timestamp(timestamp as i64, 0).map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+/// Looks up the timestamps of `numbers`, from the block cache or fetched from `provider` and
+/// added to the cache. Returns the blocks whose timestamp could not be looked up, all of the
+/// uncached ones if the provider can't deliver blocks.
+pub async fn fetch(provider: &dyn WalletDataProvider, numbers: impl IntoIterator<Item = u64>) -> Vec<u64> {
+    let mut missing = vec![];
+
+    for number in numbers.into_iter().collect::<BTreeSet<u64>>() {
+        if TIMES.lock().unwrap().contains_key(&number) {
+            continue;
+        }
+        if let Some(block) = cache::load_block(number) {
+            TIMES.lock().unwrap().insert(number, block.timestamp);
+            continue;
+        }
+        if !provider.capabilities().blocks {
+            missing.push(number);
+            continue;
+        }
+
+        match provider.get_block(number).await {
+            Ok(block) => {
+                cache::store_block(&block);
+                TIMES.lock().unwrap().insert(number, block.timestamp);
+            }
+            Err(_) => missing.push(number)
+        }
+    }
+
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use crate::testing::FakeProvider;
+    use super::*;
+
+    #[tokio::test]
+    async fn fetches_each_block_once() {
+        let provider = FakeProvider { blocks: [(7_000_001, 1_704_067_200), (7_000_002, 1_704_070_800)].into(), ..FakeProvider::default() };
+
+        let missing = fetch(&provider, [7_000_002, 7_000_001, 7_000_002, 7_000_003]).await;
+        assert_eq!(missing, vec![7_000_003]);
+        assert_eq!(provider.requests.load(Ordering::Relaxed), 3);
+        assert_eq!(get(7_000_001), Some((1_704_067_200, "2024-01-01T00:00:00Z".to_string())));
+        assert_eq!(get(7_000_002), Some((1_704_070_800, "2024-01-01T01:00:00Z".to_string())));
+        assert_eq!(get(7_000_003), None);
+
+        // Known blocks aren't requested again, missing ones are.
+        assert_eq!(fetch(&provider, [7_000_001, 7_000_003]).await, vec![7_000_003]);
+        assert_eq!(provider.requests.load(Ordering::Relaxed), 4);
+    }
+
+    #[tokio::test]
+    async fn providers_without_blocks_miss_every_uncached_block() {
+        let provider = FakeProvider::default();
+
+        assert_eq!(fetch(&provider, [7_100_001, 7_100_002]).await, vec![7_100_001, 7_100_002]);
+        assert_eq!(provider.requests.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,150 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use reqwest::{Request, Response};
+use reqwest_middleware::{ClientWithMiddleware, Middleware, Next};
+use task_local_extensions::Extensions;
+use crate::{diagnostics, logging, ArgParser, DEFAULT_USER_AGENT, HEALTH_CHECK_TIMEOUT};
+
+/// Consecutive failed requests, across all transactions in flight, after which fetching is paused.
+const TRIP_THRESHOLD: usize = 5;
+
+/// The first and the longest interval between two probes of a paused run.
+const FIRST_PROBE_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_PROBE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long an outage may last before the run is given up, unless `--max-outage` is passed.
+const DEFAULT_MAX_OUTAGE: Duration = Duration::from_secs(600);
+
+static ABORTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the run was given up because the API was down for longer than `--max-outage`.
+pub fn aborted() -> bool {
+    ABORTED.load(Ordering::SeqCst)
+}
+
+pub fn max_outage_from_args() -> Duration {
+    ArgParser::split(&"--max-outage".to_string()).map_or(DEFAULT_MAX_OUTAGE, |seconds| {
+        Duration::from_secs(seconds.parse().unwrap_or_else(|_| panic!("Invalid --max-outage value {}, expected a number of seconds", seconds)))
+    })
+}
+
+#[derive(Debug)]
+struct Outage(Duration);
+
+impl fmt::Display for Outage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the API has been unreachable for {}s, see --max-outage", self.0.as_secs())
+    }
+}
+
+impl std::error::Error for Outage {}
+
+fn is_failure(response: &reqwest_middleware::Result<Response>) -> bool {
+    match response {
+        Ok(response) => diagnostics::is_transient(response.status()),
+        Err(_) => true
+    }
+}
+
+/// Pauses all requests once the API keeps failing, instead of letting every transaction in flight
+/// use up its retries against a server that is down. While the circuit is open a single request
+/// probes the hosts at growing intervals and the others wait for it. Added after the retry
+/// middleware, so the retries wait as well.
+pub struct CircuitBreaker {
+    http: ClientWithMiddleware,
+    probe_urls: Vec<String>,
+    max_outage: Duration,
+    consecutive_failures: AtomicUsize,
+    open: AtomicBool,
+    probing: tokio::sync::Mutex<()>,
+}
+
+impl CircuitBreaker {
+    pub fn new(http: ClientWithMiddleware, probe_urls: Vec<String>, max_outage: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            http,
+            probe_urls,
+            max_outage,
+            consecutive_failures: AtomicUsize::new(0),
+            open: AtomicBool::new(false),
+            probing: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    fn record(&self, failed: bool) {
+        if !failed {
+            self.consecutive_failures.store(0, Ordering::SeqCst);
+            return;
+        }
+
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::SeqCst) + 1;
+        if failures >= TRIP_THRESHOLD && !self.open.swap(true, Ordering::SeqCst) {
+            let message = format!("{} API requests failed in a row, pausing all requests until the API recovers", failures);
+            println!("{}", message);
+            logging::warn(&message);
+        }
+    }
+
+    async fn probe(&self) -> bool {
+        for url in &self.probe_urls {
+            let response = self.http.get(url).header("user-agent", DEFAULT_USER_AGENT).timeout(HEALTH_CHECK_TIMEOUT).send().await;
+            if response.is_ok_and(|response| response.status().is_success()) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Waits until the circuit is closed again, failing once the outage lasted too long.
+    async fn wait(&self) -> Result<(), Outage> {
+        let _probing = self.probing.lock().await;
+        let started = Instant::now();
+        let mut interval = FIRST_PROBE_INTERVAL;
+
+        while self.open.load(Ordering::SeqCst) {
+            if aborted() || started.elapsed() >= self.max_outage {
+                if !ABORTED.swap(true, Ordering::SeqCst) {
+                    let message = format!("The API has been unreachable for {}s, giving up", started.elapsed().as_secs());
+                    println!("{}", message);
+                    logging::error(&message);
+                }
+                return Err(Outage(self.max_outage));
+            }
+
+            tokio::time::sleep(interval.min(self.max_outage.saturating_sub(started.elapsed()))).await;
+            interval = (interval * 2).min(MAX_PROBE_INTERVAL);
+
+            if self.probe().await {
+                self.consecutive_failures.store(0, Ordering::SeqCst);
+                self.open.store(false, Ordering::SeqCst);
+
+                let message = format!("The API recovered after {}s, resuming", started.elapsed().as_secs());
+                println!("{}", message);
+                logging::info(&message);
+            } else {
+                let next = interval.min(self.max_outage.saturating_sub(started.elapsed()));
+                logging::info(&format!("The API is still unreachable after {}s, next probe in {}s", started.elapsed().as_secs(), next.as_secs()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for CircuitBreaker {
+    async fn handle(&self, req: Request, extensions: &mut Extensions, next: Next<'_>) -> reqwest_middleware::Result<Response> {
+        if aborted() {
+            return Err(reqwest_middleware::Error::middleware(Outage(self.max_outage)));
+        }
+        if self.open.load(Ordering::SeqCst) {
+            self.wait().await.map_err(reqwest_middleware::Error::middleware)?;
+        }
+
+        let response = next.run(req, extensions).await;
+        self.record(is_failure(&response));
+        response
+    }
+}
@@ -0,0 +1,106 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use reqwest::header::{HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use serde::{Deserialize, Serialize};
+use crate::block::RRBlock;
+use crate::{logging, network, ArgParser};
+
+/// Conditional list requests sent, and the ones answered with `304 Not Modified`.
+static CONDITIONAL: AtomicUsize = AtomicUsize::new(0);
+static NOT_MODIFIED: AtomicUsize = AtomicUsize::new(0);
+
+/// The directory of `--cache-dir`, where responses are kept between runs.
+pub fn dir() -> Option<PathBuf> {
+    ArgParser::all("--cache-dir").pop().map(PathBuf::from)
+}
+
+/// The number of conditional list requests of the run and how many of them were answered with
+/// `304 Not Modified`.
+pub fn list_requests() -> (usize, usize) {
+    (CONDITIONAL.load(Ordering::Relaxed), NOT_MODIFIED.load(Ordering::Relaxed))
+}
+
+/// A transaction list of an earlier run with the validators the API sent along, so unchanged
+/// lists are answered with `304 Not Modified` instead of the whole list.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedList {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    pub body: String,
+}
+
+impl CachedList {
+    /// Where the list at the API path `path` is cached, by network since the same address has
+    /// different transactions on each.
+    pub fn path(path: &str) -> Option<PathBuf> {
+        let name: String = path.trim_matches('/').to_lowercase().chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '-' }).collect();
+
+        dir().map(|dir| dir.join("lists").join(network::current().name().to_lowercase()).join(format!("{}.json", name)))
+    }
+
+    /// The cached list at `path`. A missing or unreadable cache entry means a full request.
+    pub fn load(path: &PathBuf) -> Option<CachedList> {
+        serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()
+    }
+
+    /// The list of a response, `None` if the API sent no validators.
+    pub fn from_response(headers: &HeaderMap, body: String) -> Option<CachedList> {
+        let header = |name| headers.get(name).and_then(|value: &HeaderValue| value.to_str().ok()).map(str::to_string);
+        let (etag, last_modified) = (header(ETAG), header(LAST_MODIFIED));
+
+        (etag.is_some() || last_modified.is_some()).then_some(CachedList { etag, last_modified, body })
+    }
+
+    /// The headers asking the API to only send the list if it changed, counted as a conditional request.
+    pub fn conditional_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(etag) = self.etag.as_deref().and_then(|etag| HeaderValue::from_str(etag).ok()) {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = self.last_modified.as_deref().and_then(|last_modified| HeaderValue::from_str(last_modified).ok()) {
+            headers.insert(IF_MODIFIED_SINCE, last_modified);
+        }
+
+        CONDITIONAL.fetch_add(1, Ordering::Relaxed);
+        headers
+    }
+
+    pub fn not_modified() {
+        NOT_MODIFIED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Writes the entry. A cache that can't be written only costs the next run a full request.
+    pub fn store(&self, path: &PathBuf) {
+        store(path, serde_json::to_string(self).unwrap(), "the transaction list");
+    }
+}
+
+/// Writes a cache entry through a temporary file so a concurrent run never reads half of it.
+fn store(path: &PathBuf, contents: String, what: &str) {
+    let temporary = path.with_extension(format!("json.{}.tmp", std::process::id()));
+    let result = std::fs::create_dir_all(path.parent().unwrap())
+        .and_then(|_| std::fs::write(&temporary, contents))
+        .and_then(|_| std::fs::rename(&temporary, path));
+
+    if let Err(error) = result {
+        logging::warn(&format!("Could not cache {} in {}: {}", what, path.display(), error));
+    }
+}
+
+/// Where block `number` is cached. Blocks don't change once they are in the chain, so unlike the
+/// lists they are never requested again.
+fn block_path(number: u64) -> Option<PathBuf> {
+    dir().map(|dir| dir.join("blocks").join(network::current().name().to_lowercase()).join(format!("{}.json", number)))
+}
+
+/// Block `number` of an earlier run, if it was cached.
+pub fn load_block(number: u64) -> Option<RRBlock> {
+    serde_json::from_str(&std::fs::read_to_string(block_path(number)?).ok()?).ok()
+}
+
+pub fn store_block(block: &RRBlock) {
+    if let Some(path) = block_path(block.block_number) {
+        store(&path, serde_json::to_string(block).unwrap(), &format!("block {}", block.block_number));
+    }
+}
@@ -0,0 +1,111 @@
+use std::sync::OnceLock;
+use serde_json::{Number, Value};
+use crate::ArgParser;
+
+/// Integers up to this magnitude are exact as floats.
+const MAX_SAFE_INTEGER: f64 = 9007199254740992.0;
+
+/// Whether `--canonical` was passed. Checked for every record, so it is parsed once.
+pub fn enabled() -> bool {
+    static CANONICAL: OnceLock<bool> = OnceLock::new();
+    *CANONICAL.get_or_init(|| ArgParser::flag("--canonical"))
+}
+
+/// Floats without a fractional part are written as integers, `1.0` and `1` from two API
+/// instances are the same number.
+fn write_number(number: &Number, out: &mut String) {
+    match number.as_f64() {
+        Some(float) if number.is_f64() && float.fract() == 0.0 && float.abs() < MAX_SAFE_INTEGER => {
+            out.push_str(&(float as i64).to_string());
+        }
+        _ => out.push_str(&number.to_string())
+    }
+}
+
+fn write(value: &Value, out: &mut String) {
+    match value {
+        Value::Number(number) => write_number(number, out),
+        Value::Array(values) => {
+            out.push('[');
+            for (index, value) in values.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                write(value, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.as_bytes().cmp(b.as_bytes()));
+
+            out.push('{');
+            for (index, (key, value)) in entries.into_iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::to_string(key).unwrap());
+                out.push(':');
+                write(value, out);
+            }
+            out.push('}');
+        }
+        Value::Null | Value::Bool(_) | Value::String(_) => out.push_str(&serde_json::to_string(value).unwrap()),
+    }
+}
+
+/// Serializes `value` canonically: without whitespace, with the keys of every object sorted
+/// by their bytes and numbers in a single spelling, so equal data gives identical bytes.
+pub fn to_string(value: &Value) -> String {
+    let mut out = String::new();
+    write(value, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::testing::record;
+    use super::*;
+
+    #[test]
+    fn key_order_does_not_matter() {
+        let a: Value = serde_json::from_str(r#"{"b":1,"a":{"y":[1,{"q":null,"p":true}],"x":"s"},"B":2}"#).unwrap();
+        let b: Value = serde_json::from_str(r#"{"a":{"x":"s","y":[1,{"p":true,"q":null}]},"B":2,"b":1}"#).unwrap();
+
+        assert_eq!(to_string(&a), to_string(&b));
+        assert_eq!(to_string(&a), r#"{"B":2,"a":{"x":"s","y":[1,{"p":true,"q":null}]},"b":1}"#);
+    }
+
+    #[test]
+    fn numbers_have_one_spelling() {
+        let a: Value = serde_json::from_str(r#"{"gasUsed":21000.0,"fee":0.5,"nested":[1.0,-3.0,2]}"#).unwrap();
+        let b: Value = serde_json::from_str(r#"{"gasUsed":21000,"fee":0.50,"nested":[1,-3,2.0]}"#).unwrap();
+
+        assert_eq!(to_string(&a), to_string(&b));
+        assert_eq!(to_string(&a), r#"{"fee":0.5,"gasUsed":21000,"nested":[1,-3,2]}"#);
+        // Beyond 2^53 floats aren't exact integers, they are left as they are.
+        assert_eq!(to_string(&json!(1e300)), serde_json::to_string(&json!(1e300)).unwrap());
+    }
+
+    #[test]
+    fn strings_keep_their_escapes() {
+        let value = json!({ "input": "quote \" and \u{e9} and \n", "ké": 1 });
+
+        assert_eq!(to_string(&value), "{\"input\":\"quote \\\" and \u{e9} and \\n\",\"k\u{e9}\":1}");
+        assert_eq!(serde_json::from_str::<Value>(&to_string(&value)).unwrap(), value);
+    }
+
+    #[test]
+    fn records_are_byte_identical_across_passes() {
+        let receipt = json!({ "status": true, "logs": [{ "name": "Transfer", "events": [{ "name": "value", "value": 5.0 }] }], "gasUsed": "0x5208" });
+        let record = record(1, 100, json!({ "transactionIndex": 2, "output": receipt }));
+
+        let first = to_string(&crate::record_value(&record, None));
+        let reparsed: Value = serde_json::from_str(&first).unwrap();
+        let second = to_string(&reparsed);
+
+        assert_eq!(first.as_bytes(), second.as_bytes());
+        assert!(first.contains(r#"{"name":"value","value":5}"#), "{}", first);
+    }
+}
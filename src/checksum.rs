@@ -0,0 +1,73 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use sha2::{Digest, Sha256};
+
+/// Hex encoding of a digest.
+pub fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The hex SHA-256 of `bytes`.
+pub fn of(bytes: &[u8]) -> String {
+    to_hex(&Sha256::digest(bytes))
+}
+
+/// Hashes everything written through it, so the checksum of a file is known once it was written
+/// without reading it again.
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W) -> HashingWriter<W> {
+        HashingWriter { inner, hasher: Sha256::new() }
+    }
+
+    /// Returns the wrapped writer and the hex SHA-256 of the written bytes.
+    pub fn finish(self) -> (W, String) {
+        (self.inner, to_hex(&self.hasher.finalize()))
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(bytes)?;
+        self.hasher.update(&bytes[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The hex SHA-256 of the file at `path`, read in a streaming fashion.
+pub fn of_file(path: &str) -> std::io::Result<String> {
+    let mut writer = HashingWriter::new(std::io::sink());
+    std::io::copy(&mut File::open(path)?, &mut writer)?;
+    Ok(writer.finish().1)
+}
+
+/// The sidecar holding the checksum of `path`.
+pub fn sidecar_path(path: &str) -> String {
+    format!("{}.sha256", path)
+}
+
+/// The contents of the sidecar of `path`, in the format of `sha256sum` so `sha256sum -c` can
+/// check it from the directory of the file.
+pub fn sidecar_line(sha256: &str, path: &str) -> String {
+    let file_name = Path::new(path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    format!("{}  {}\n", sha256, file_name)
+}
+
+/// The checksum of a sidecar, ignoring the file name. Lines of `sha256sum --binary` are accepted.
+pub fn parse_sidecar(contents: &str) -> Option<String> {
+    let digest = contents.split_whitespace().next()?.to_lowercase();
+
+    match digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        true => Some(digest),
+        false => None
+    }
+}
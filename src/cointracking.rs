@@ -0,0 +1,99 @@
+use serde_json::Value;
+use crate::accounting::{self, Event, Kind, Leg};
+use crate::csv::escape;
+
+/// The columns of the CoinTracking CSV import.
+pub const HEADER: &str = "Type,Buy Amount,Buy Currency,Sell Amount,Sell Currency,Fee,Fee Currency,Exchange,Trade Group,Comment,Date,Tx-ID";
+
+/// The exchange column, CoinTracking uses it to group the imported balances.
+const EXCHANGE: &str = "Ronin";
+
+#[allow(clippy::too_many_arguments)]
+fn row(kind: &str, buy: Option<&Leg>, sell: Option<&Leg>, fee: Option<&str>, trade_group: &str, comment: &str, date: &str, hash: &str) -> String {
+    let amount = |leg: Option<&Leg>| leg.map(Leg::amount).unwrap_or_default();
+    let currency = |leg: Option<&Leg>| leg.map(|leg| leg.currency).unwrap_or_default();
+
+    [
+        kind.to_string(),
+        amount(buy),
+        currency(buy).to_string(),
+        amount(sell),
+        currency(sell).to_string(),
+        fee.unwrap_or_default().to_string(),
+        fee.map_or("", |_| accounting::RON).to_string(),
+        EXCHANGE.to_string(),
+        trade_group.to_string(),
+        comment.to_string(),
+        date.to_string(),
+        hash.to_string(),
+    ].iter().map(|field| escape(field)).collect::<Vec<String>>().join(",")
+}
+
+/// Formats the accounting event of a serialized record as CoinTracking rows. Events moving
+/// several currencies in one direction can't be a single row, their legs are written as deposits
+/// and withdrawals sharing the transaction hash as their trade group. Transactions without a
+/// block have no date and no rows.
+pub fn rows(record: &Value, wallet: &str) -> String {
+    let Event { kind, incoming, outgoing, fee, hash, date, method, note, .. } = accounting::event(record, wallet);
+    let Some(date) = date else {
+        return String::new();
+    };
+    let comment = method.unwrap_or_default();
+    let fee = fee.as_deref();
+
+    match kind {
+        Kind::Trade if incoming.len() == 1 && outgoing.len() == 1 => {
+            row("Trade", incoming.first(), outgoing.first(), fee, "", &comment, &date, &hash)
+        }
+        Kind::Other => {
+            let comment = format!("{} ({})", hash, note.unwrap_or_default());
+            row("Other", None, None, fee, "", &comment, &date, &hash)
+        }
+        _ => {
+            let deposit = match kind {
+                Kind::StakingReward => "Staking",
+                _ => "Deposit"
+            };
+            let legs: Vec<(Option<&Leg>, Option<&Leg>, &str)> = incoming.iter().map(|leg| (Some(leg), None, deposit))
+                .chain(outgoing.iter().map(|leg| (None, Some(leg), "Withdrawal")))
+                .collect();
+            let trade_group = match legs.len() > 1 {
+                true => hash.as_str(),
+                false => ""
+            };
+
+            legs.iter().enumerate()
+                .map(|(index, (buy, sell, kind))| row(kind, *buy, *sell, fee.filter(|_| index == 0), trade_group, &comment, &date, &hash))
+                .collect::<Vec<String>>()
+                .join("\n")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::testing::{hash, FakeProvider, OTHER, WALLET};
+    use super::*;
+
+    #[tokio::test]
+    async fn rows_are_dated_with_the_block_time() {
+        let provider = FakeProvider { blocks: [(7_200_100, 1_704_067_200)].into(), ..FakeProvider::default() };
+        accounting::look_up_dates(&provider, [7_200_100, 0]).await;
+
+        let record = json!({ "from": WALLET, "to": OTHER, "hash": hash(1), "blockNumber": 7_200_100, "value": "1500000000000000000" });
+        assert_eq!(rows(&record, WALLET), format!("Withdrawal,,,1.5,RON,,,Ronin,,,2024-01-01 00:00:00,{}", hash(1)));
+
+        // A transaction that could not be fetched has no block and no row.
+        let unfetched = json!({ "from": "null", "to": "null", "hash": hash(2), "blockNumber": 0 });
+        assert_eq!(rows(&unfetched, WALLET), "");
+    }
+
+    #[test]
+    fn exports_from_disk_need_cached_blocks() {
+        assert!(accounting::cached_dates([0]).is_ok());
+
+        let error = accounting::cached_dates([7_300_002, 7_300_001, 7_300_002]).unwrap_err();
+        assert!(error.starts_with("Could not look up the time of 2 blocks (first 7300001)"), "{}", error);
+    }
+}
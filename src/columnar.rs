@@ -0,0 +1,173 @@
+use std::sync::Arc;
+use arrow_array::builder::{StringBuilder, UInt64Builder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema};
+use serde_json::Value;
+use crate::{csv, filters, Direction};
+
+/// Records buffered before they are written as one record batch.
+pub const BATCH_SIZE: usize = 8_192;
+
+/// The columns of columnar exports. Decoded payloads are stored as JSON encoded strings.
+pub fn schema() -> Schema {
+    Schema::new(vec![
+        Field::new("hash", DataType::Utf8, false),
+        Field::new("block_number", DataType::UInt64, false),
+        Field::new("transaction_index", DataType::UInt64, true),
+        Field::new("nonce", DataType::UInt64, true),
+        Field::new("from", DataType::Utf8, false),
+        Field::new("to", DataType::Utf8, false),
+        Field::new("value", DataType::Utf8, true),
+        Field::new("direction", DataType::Utf8, true),
+        Field::new("counterparty", DataType::Utf8, true),
+        Field::new("status", DataType::Utf8, true),
+        Field::new("method", DataType::Utf8, true),
+        Field::new("gas_used", DataType::UInt64, true),
+        Field::new("gas_price", DataType::UInt64, true),
+        Field::new("effective_gas_price", DataType::UInt64, true),
+        Field::new("fee_ron", DataType::Utf8, true),
+        Field::new("fee_payer", DataType::Utf8, true),
+        Field::new("balance_after", DataType::Utf8, true),
+        Field::new("confirmations", DataType::UInt64, true),
+        Field::new("input", DataType::Utf8, true),
+        Field::new("output", DataType::Utf8, true),
+        Field::new("raw_logs", DataType::Utf8, true),
+        Field::new("wallet", DataType::Utf8, true),
+        Field::new("wallets", DataType::Utf8, true),
+    ])
+}
+
+fn text(value: Option<&Value>) -> Option<&str> {
+    value.and_then(Value::as_str)
+}
+
+/// Missing and `null` payloads are null, anything else is JSON encoded.
+fn json(value: Option<&Value>) -> Option<String> {
+    value.filter(|value| !value.is_null()).map(Value::to_string)
+}
+
+/// Collects serialized records into the columns of `schema`.
+pub struct Batch {
+    hash: StringBuilder,
+    block_number: UInt64Builder,
+    transaction_index: UInt64Builder,
+    nonce: UInt64Builder,
+    from: StringBuilder,
+    to: StringBuilder,
+    value: StringBuilder,
+    direction: StringBuilder,
+    counterparty: StringBuilder,
+    status: StringBuilder,
+    method: StringBuilder,
+    gas_used: UInt64Builder,
+    gas_price: UInt64Builder,
+    effective_gas_price: UInt64Builder,
+    fee_ron: StringBuilder,
+    fee_payer: StringBuilder,
+    balance_after: StringBuilder,
+    confirmations: UInt64Builder,
+    input: StringBuilder,
+    output: StringBuilder,
+    raw_logs: StringBuilder,
+    wallet: StringBuilder,
+    wallets: StringBuilder,
+    records: usize,
+}
+
+impl Batch {
+    pub fn new() -> Batch {
+        Batch {
+            hash: StringBuilder::new(),
+            block_number: UInt64Builder::new(),
+            transaction_index: UInt64Builder::new(),
+            nonce: UInt64Builder::new(),
+            from: StringBuilder::new(),
+            to: StringBuilder::new(),
+            value: StringBuilder::new(),
+            direction: StringBuilder::new(),
+            counterparty: StringBuilder::new(),
+            status: StringBuilder::new(),
+            method: StringBuilder::new(),
+            gas_used: UInt64Builder::new(),
+            gas_price: UInt64Builder::new(),
+            effective_gas_price: UInt64Builder::new(),
+            fee_ron: StringBuilder::new(),
+            fee_payer: StringBuilder::new(),
+            balance_after: StringBuilder::new(),
+            confirmations: UInt64Builder::new(),
+            input: StringBuilder::new(),
+            output: StringBuilder::new(),
+            raw_logs: StringBuilder::new(),
+            wallet: StringBuilder::new(),
+            wallets: StringBuilder::new(),
+            records: 0,
+        }
+    }
+
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    /// Adds a serialized record. `wallet` is the address (or pseudonym) the export belongs to and
+    /// its direction, unset in merged exports.
+    pub fn push(&mut self, record: &Value, wallet: Option<(&str, Direction)>) {
+        let (wallet, direction, counterparty) = csv::attribution(record, wallet);
+
+        self.hash.append_value(text(record.get("hash")).unwrap_or_default());
+        self.block_number.append_value(record.get("blockNumber").and_then(Value::as_u64).unwrap_or_default());
+        self.transaction_index.append_option(record.get("transactionIndex").and_then(Value::as_u64));
+        self.nonce.append_option(record.get("nonce").and_then(Value::as_u64));
+        self.from.append_value(text(record.get("from")).unwrap_or_default());
+        self.to.append_value(text(record.get("to")).unwrap_or_default());
+        self.value.append_option(text(record.get("value")));
+        self.direction.append_option(text(direction.as_ref()));
+        self.counterparty.append_option(text(counterparty));
+        self.status.append_option(text(record.get("status")));
+        self.method.append_option(record.get("input").and_then(filters::method_name));
+        self.gas_used.append_option(record.get("gasUsed").and_then(Value::as_u64));
+        self.gas_price.append_option(record.get("gasPrice").and_then(Value::as_u64));
+        self.effective_gas_price.append_option(record.get("effectiveGasPrice").and_then(Value::as_u64));
+        self.fee_ron.append_option(text(record.get("feeRon")));
+        self.fee_payer.append_option(text(record.get("feePayer")));
+        self.balance_after.append_option(text(record.get("balanceAfter")));
+        self.confirmations.append_option(record.get("confirmations").and_then(Value::as_u64));
+        self.input.append_option(json(record.get("input")));
+        self.output.append_option(json(record.get("output")));
+        self.raw_logs.append_option(json(record.get("rawLogs")));
+        self.wallet.append_option(wallet);
+        self.wallets.append_option(json(record.get("wallets")));
+        self.records += 1;
+    }
+
+    /// Returns the collected records as a record batch and starts an empty one.
+    pub fn finish(&mut self) -> RecordBatch {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.hash.finish()),
+            Arc::new(self.block_number.finish()),
+            Arc::new(self.transaction_index.finish()),
+            Arc::new(self.nonce.finish()),
+            Arc::new(self.from.finish()),
+            Arc::new(self.to.finish()),
+            Arc::new(self.value.finish()),
+            Arc::new(self.direction.finish()),
+            Arc::new(self.counterparty.finish()),
+            Arc::new(self.status.finish()),
+            Arc::new(self.method.finish()),
+            Arc::new(self.gas_used.finish()),
+            Arc::new(self.gas_price.finish()),
+            Arc::new(self.effective_gas_price.finish()),
+            Arc::new(self.fee_ron.finish()),
+            Arc::new(self.fee_payer.finish()),
+            Arc::new(self.balance_after.finish()),
+            Arc::new(self.confirmations.finish()),
+            Arc::new(self.input.finish()),
+            Arc::new(self.output.finish()),
+            Arc::new(self.raw_logs.finish()),
+            Arc::new(self.wallet.finish()),
+            Arc::new(self.wallets.finish()),
+        ];
+        self.records = 0;
+
+        RecordBatch::try_new(Arc::new(schema()), columns).unwrap()
+    }
+}
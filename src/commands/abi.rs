@@ -0,0 +1,139 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use web3::ethabi::Contract;
+use web3::types::Address;
+use crate::export::stream_records;
+use crate::provider::WalletDataProvider;
+use crate::{filters, normalize_address, provider, ArgParser};
+
+/// The ABI directory `wally decode` looks in, `--abi-dir`.
+pub fn dir() -> PathBuf {
+    PathBuf::from(ArgParser::split(&"--abi-dir".to_string()).unwrap_or_else(|| "abis".to_string()))
+}
+
+/// Where the ABI of `address` is kept in the ABI directory.
+pub fn path(address: &str) -> PathBuf {
+    dir().join(format!("{}.json", address.to_lowercase()))
+}
+
+/// The contracts of an export: the called contracts and the ones emitting receipt logs. Plain
+/// transfers to wallets are among them, the API has no ABI for those.
+fn contracts(path: &str) -> Result<BTreeSet<String>, String> {
+    let mut contracts = BTreeSet::new();
+
+    for record in stream_records::<serde_json::Value>(path)? {
+        let record = record.map_err(|error| format!("Could not parse {}: {}", path, error))?;
+
+        let to = record.get("to").and_then(serde_json::Value::as_str);
+        let logs = record.get("output").map(filters::receipt_logs).unwrap_or_default();
+
+        for address in to.into_iter().chain(logs.into_iter().filter_map(filters::log_address)) {
+            let address = normalize_address(address).to_lowercase();
+            if address.parse::<Address>().is_ok() {
+                contracts.insert(address);
+            }
+        }
+    }
+
+    Ok(contracts)
+}
+
+/// Fetches the ABI of `address` and writes it to `out`, checking that it parses as an ABI first.
+async fn fetch(rr: &dyn WalletDataProvider, address: &str, out: &Path) -> Result<(), String> {
+    let abi = rr.contract_abi(address).await?;
+    serde_json::from_value::<Contract>(abi.clone()).map_err(|error| format!("The ABI of {} can't be read: {}", address, error))?;
+
+    if let Some(parent) = out.parent() {
+        std::fs::create_dir_all(parent).map_err(|error| format!("Could not create {}: {}", parent.display(), error))?;
+    }
+    std::fs::write(out, serde_json::to_string_pretty(&abi).unwrap()).map_err(|error| format!("Could not write {}: {}", out.display(), error))
+}
+
+/// `wally abi ADDRESS [-o FILE]` fetches the verified ABI of a contract into the ABI directory
+/// (`abis/ADDRESS.json`, or `--abi-dir`) where `wally decode` finds it. `wally abi
+/// --all-from-export=FILE` fetches the ABIs of all contracts of an export that aren't there yet.
+pub async fn run(args: &[String]) -> i32 {
+    let mut args = args.to_vec();
+
+    // `-o FILE` as in `curl`.
+    let out = match args.iter().position(|arg| arg == "-o") {
+        Some(position) => {
+            let out = args.get(position + 1).cloned();
+            args.drain(position..(position + 2).min(args.len()));
+            out
+        }
+        None => ArgParser::split(&"--out".to_string())
+    };
+
+    let positional = super::positional(&args);
+    let from_export = ArgParser::split(&"--all-from-export".to_string());
+    let usage = "Usage: wally abi ADDRESS [-o FILE] | wally abi --all-from-export=FILE";
+
+    let rr = provider::from_args();
+    if !rr.capabilities().abis {
+        eprintln!("The {} provider doesn't deliver contract ABIs", rr.name());
+        return 2;
+    }
+
+    match (positional.as_slice(), from_export) {
+        ([address], None) => {
+            let address = normalize_address(address).to_lowercase();
+            if address.parse::<Address>().is_err() {
+                eprintln!("Could not parse address {}!", address);
+                return 2;
+            }
+
+            let out = out.map_or_else(|| path(&address), PathBuf::from);
+            match fetch(rr.as_ref(), &address, &out).await {
+                Ok(_) => {
+                    println!("The ABI of {} was saved to {}", address, out.display());
+                    0
+                }
+                Err(error) => {
+                    eprintln!("{}", error);
+                    1
+                }
+            }
+        }
+        ([], Some(export)) if out.is_none() => {
+            let contracts = match contracts(&export) {
+                Ok(contracts) => contracts,
+                Err(error) => {
+                    eprintln!("{}", error);
+                    return 1;
+                }
+            };
+
+            let (mut fetched, mut present, mut missing) = (0, 0, 0);
+
+            for address in &contracts {
+                let out = path(address);
+                if out.exists() {
+                    present += 1;
+                    continue;
+                }
+
+                match fetch(rr.as_ref(), address, &out).await {
+                    Ok(_) => {
+                        println!("{}: saved to {}", address, out.display());
+                        fetched += 1;
+                    }
+                    Err(error) => {
+                        println!("{}", error);
+                        missing += 1;
+                    }
+                }
+            }
+
+            println!(
+                "Fetched the ABIs of {} of the {} addresses of {}, {} were already in {} and {} could not be fetched",
+                fetched, contracts.len(), export, present, dir().display(), missing
+            );
+            0
+        }
+        _ => {
+            eprintln!("{}", usage);
+            2
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use indicatif::ProgressStyle;
+use serde::Serialize;
+use web3::types::Address;
+use crate::block::RRBlock;
+use crate::export::stream_records;
+use crate::{aliases, cache, csv, output, pipeline, provider, ArgParser, RRTransactionHash};
+
+const HEADER: &str = "blockNumber,timestamp,time,miner,transactionCount";
+
+/// What couldn't be fetched, written to `blocks.failures.json`.
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct BlockFailures {
+    /// Transactions whose block is unknown since they couldn't be fetched.
+    failed_transactions: Vec<RRTransactionHash>,
+    failed_blocks: Vec<u64>,
+}
+
+pub fn progress_bar(length: usize, message: &'static str) -> indicatif::ProgressBar {
+    let progress = indicatif::ProgressBar::new(length as u64);
+    progress.set_style(
+        ProgressStyle::with_template("{spinner}{bar:100.cyan/blue} {percent:>3}% | [{eta_precise}][{elapsed_precise}] ETA/Elapsed | {pos:>7}/{len:7} {msg}").unwrap()
+    );
+    progress.set_message(message);
+    progress
+}
+
+/// `wally blocks (--address=ADDRESS... | --from-export=FILE) [--format=json|csv] [--out=FILE]`
+/// fetches the timestamp, miner and number of transactions of every block the transactions of the
+/// wallets or of an export are in. The block numbers of wallets come from a fresh list of their
+/// transactions, which means fetching each of them. Blocks are cached in `--cache-dir`.
+pub async fn run(_args: &[String]) -> i32 {
+    let addresses = ArgParser::all("--address");
+    let from_export = ArgParser::split(&"--from-export".to_string());
+    let usage = "Usage: wally blocks (--address=ADDRESS | --from-export=FILE) [--format=json|csv] [--out=FILE]";
+
+    if addresses.is_empty() == from_export.is_none() {
+        eprintln!("{}", usage);
+        return 2;
+    }
+
+    let writer = output::Output::from_args();
+    let csv = match writer.format {
+        output::Format::Json => false,
+        output::Format::Csv(_) => true,
+        _ => {
+            eprintln!("wally blocks writes --format=json or --format=csv");
+            return 2;
+        }
+    };
+    let out = ArgParser::split(&"--out".to_string()).unwrap_or_else(|| format!("blocks.{}", if csv { "csv" } else { "json" }));
+
+    let max_attempts = pipeline::tx_max_attempts();
+    let interrupted = pipeline::interrupt_on_ctrl_c();
+    let mut failures = BlockFailures::default();
+    let mut numbers: BTreeSet<u64> = BTreeSet::new();
+
+    if let Some(path) = &from_export {
+        let records = match stream_records::<serde_json::Value>(path) {
+            Ok(records) => records,
+            Err(error) => {
+                eprintln!("{}", error);
+                return 1;
+            }
+        };
+
+        for record in records {
+            let record = match record {
+                Ok(record) => record,
+                Err(error) => {
+                    eprintln!("Could not parse {}: {}", path, error);
+                    return 1;
+                }
+            };
+            // Transactions that couldn't be fetched are exported with block 0.
+            match record.get("blockNumber").and_then(serde_json::Value::as_u64) {
+                Some(0) | None => {}
+                Some(number) => {
+                    numbers.insert(number);
+                }
+            }
+        }
+    }
+
+    let rr = provider::from_args();
+    if !rr.capabilities().blocks {
+        eprintln!("The {} provider doesn't deliver blocks", rr.name());
+        return 2;
+    }
+
+    if !ArgParser::flag("--skip-health-check") {
+        if let Err(error) = rr.health_check().await {
+            eprintln!("{}", error);
+            return 2;
+        }
+    }
+
+    if !addresses.is_empty() {
+        let mut hashes: BTreeSet<RRTransactionHash> = BTreeSet::new();
+
+        for address in &addresses {
+            let address = match aliases::WalletArg::parse(address) {
+                Ok(wallet) => wallet.address,
+                Err(error) => {
+                    eprintln!("{}", error);
+                    return 2;
+                }
+            };
+            if address.parse::<Address>().is_err() {
+                eprintln!("Could not parse address {}!", address);
+                return 2;
+            }
+
+            for list in [rr.list_sent(&address).await, rr.list_received(&address).await] {
+                for issue in &list.issues {
+                    println!("Warning: {}", issue);
+                }
+                for rejected in &list.rejected {
+                    println!("Warning: dropped the entry '{}' of {}: {}", rejected.entry, rejected.list, rejected.reason);
+                }
+                hashes.extend(list.transactions.iter().map(|hash| hash.to_lowercase()));
+            }
+        }
+
+        // Only the block numbers are needed, so nothing is decoded.
+        let options = pipeline::Options { max_attempts, decode_input: false, decode_output: false, raw_logs: false, max_blob_bytes: None, verify_hashes: false, decode_genes: false };
+        let progress = progress_bar(hashes.len(), "transactions");
+        let mut transactions = pipeline::spawn(rr.clone(), hashes.into_iter().collect(), options, interrupted.clone());
+
+        while let Some(fetched) = transactions.recv().await {
+            match fetched.fetch_failed() {
+                true => failures.failed_transactions.push(fetched.hash),
+                false => {
+                    numbers.insert(fetched.tx.block_number);
+                }
+            }
+            progress.inc(1);
+        }
+        progress.finish();
+    }
+
+    let progress = progress_bar(numbers.len(), "blocks");
+    let mut blocks: Vec<RRBlock> = vec![];
+    let mut cached = 0;
+
+    for number in numbers {
+        if let Some(block) = cache::load_block(number) {
+            blocks.push(block);
+            cached += 1;
+            progress.inc(1);
+            continue;
+        }
+        if interrupted.load(Ordering::SeqCst) {
+            break;
+        }
+
+        let attempts = AtomicUsize::new(0);
+        match pipeline::attempt(&format!("block {}", number), &attempts, max_attempts, || rr.get_block(number)).await {
+            Ok(block) => {
+                cache::store_block(&block);
+                blocks.push(block);
+            }
+            Err(_) => failures.failed_blocks.push(number)
+        }
+        progress.inc(1);
+    }
+    progress.finish();
+
+    if interrupted.load(Ordering::SeqCst) {
+        println!("Interrupted, only the blocks fetched so far are saved");
+    }
+
+    let contents = match csv {
+        true => std::iter::once(HEADER.to_string())
+            .chain(blocks.iter().map(|block| format!("{},{},{},{},{}", block.block_number, block.timestamp, block.time, csv::escape(&block.miner), block.transaction_count)))
+            .map(|line| line + "\n")
+            .collect(),
+        false => serde_json::to_string_pretty(&blocks).unwrap()
+    };
+    let written = writer.write(None, out, contents);
+    println!("{} blocks ({} from the cache) were saved to {}", blocks.len(), cached, written);
+
+    if failures.failed_transactions.is_empty() && failures.failed_blocks.is_empty() {
+        return 0;
+    }
+
+    let failure_file = writer.write(None, "blocks.failures.json".to_string(), serde_json::to_string(&failures).unwrap());
+    println!(
+        "{} transactions and {} blocks could not be fetched, they are listed in {}",
+        failures.failed_transactions.len(), failures.failed_blocks.len(), failure_file
+    );
+    1
+}
@@ -0,0 +1,54 @@
+use std::path::Path;
+use crate::checksum;
+
+/// `wally check FILE...` compares files with the checksums in their `{file}.sha256` sidecars. A
+/// sidecar may be passed instead of the file it belongs to. Exits with 0 when all files match.
+pub fn run(args: &[String]) -> i32 {
+    let positional = super::positional(args);
+
+    if positional.is_empty() {
+        eprintln!("Usage: wally check FILE...");
+        return 2;
+    }
+
+    let mut failed = 0;
+
+    for path in &positional {
+        let path = path.strip_suffix(".sha256").filter(|file| Path::new(file).exists()).unwrap_or(path);
+        let sidecar = checksum::sidecar_path(path);
+
+        let expected = match std::fs::read_to_string(&sidecar) {
+            Ok(contents) => checksum::parse_sidecar(&contents),
+            Err(error) => {
+                println!("{}: could not read {}: {}", path, sidecar, error);
+                failed += 1;
+                continue;
+            }
+        };
+        let Some(expected) = expected else {
+            println!("{}: {} does not hold a SHA-256 checksum", path, sidecar);
+            failed += 1;
+            continue;
+        };
+
+        match checksum::of_file(path) {
+            Ok(actual) if actual == expected => println!("{}: OK", path),
+            Ok(actual) => {
+                println!("{}: FAILED, the checksum is {} instead of {}", path, actual, expected);
+                failed += 1;
+            }
+            Err(error) => {
+                println!("{}: could not read it: {}", path, error);
+                failed += 1;
+            }
+        }
+    }
+
+    match failed {
+        0 => 0,
+        _ => {
+            println!("{} of {} files failed the check", failed, positional.len());
+            1
+        }
+    }
+}
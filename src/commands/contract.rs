@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use web3::ethabi::{self, ParamType, Token};
+use web3::types::Address;
+use crate::export::stream_records;
+use crate::filters::{self, SpamFilter};
+use crate::multicall::parse_hex;
+use crate::rpc::Rpc;
+use crate::{normalize_address, provider, registry, ArgParser};
+
+const NAME: &str = "0x06fdde03";
+const SYMBOL: &str = "0x95d89b41";
+const DECIMALS: &str = "0x313ce567";
+const SUPPORTS_INTERFACE: &str = "0x01ffc9a7";
+
+/// The ERC-165 interface IDs of the NFT standards.
+const ERC721: &str = "80ac58cd";
+const ERC1155: &str = "d9b67a26";
+
+/// How often an export refers to the contract.
+#[derive(Default)]
+struct References {
+    /// Transactions calling it.
+    calls: usize,
+    /// Transactions sent by it, which only happens for wallets.
+    sent: usize,
+    /// Receipt logs emitted by it.
+    logs: usize,
+    /// The transaction deploying it.
+    creation: Option<String>,
+}
+
+fn references(path: &str, address: &str) -> Result<References, String> {
+    let mut references = References::default();
+
+    for record in stream_records::<serde_json::Value>(path)? {
+        let record = record.map_err(|error| format!("Could not parse {}: {}", path, error))?;
+        let field = |key: &str| record.get(key).and_then(serde_json::Value::as_str).map(normalize_address);
+
+        if field("to").is_some_and(|to| to.eq_ignore_ascii_case(address)) {
+            references.calls += 1;
+        }
+        if field("from").is_some_and(|from| from.eq_ignore_ascii_case(address)) {
+            references.sent += 1;
+        }
+
+        let output = record.get("output");
+        references.logs += output.map(filters::receipt_logs).unwrap_or_default().into_iter()
+            .filter_map(filters::log_address)
+            .filter(|log_address| normalize_address(log_address).eq_ignore_ascii_case(address))
+            .count();
+
+        let deployed = output.and_then(|output| output.get("contractAddress").or_else(|| output.get("receipt").and_then(|receipt| receipt.get("contractAddress"))));
+        if deployed.and_then(serde_json::Value::as_str).is_some_and(|deployed| normalize_address(deployed).eq_ignore_ascii_case(address)) {
+            references.creation = field("hash");
+        }
+    }
+
+    Ok(references)
+}
+
+/// The result of a call without arguments, `None` if it reverts or returns nothing.
+async fn call(rpc: &Rpc, address: Address, selector: &str) -> Option<Vec<u8>> {
+    rpc.call(address, parse_hex(selector)?).await.ok().filter(|result| !result.is_empty())
+}
+
+/// A `string` result, or a `bytes32` one as some older tokens return for `name` and `symbol`.
+async fn text(rpc: &Rpc, address: Address, selector: &str) -> Option<String> {
+    let result = call(rpc, address, selector).await?;
+
+    match ethabi::decode(&[ParamType::String], &result).ok().and_then(|tokens| tokens.into_iter().next()) {
+        Some(Token::String(text)) => Some(text),
+        _ if result.len() == 32 => String::from_utf8(result.into_iter().take_while(|byte| *byte != 0).collect()).ok(),
+        _ => None
+    }.filter(|text| !text.is_empty())
+}
+
+async fn supports_interface(rpc: &Rpc, address: Address, interface: &str) -> bool {
+    let data = format!("{}{}{}", SUPPORTS_INTERFACE, interface, "0".repeat(56));
+
+    match call(rpc, address, &data).await {
+        Some(result) => result.len() == 32 && result[31] == 1,
+        None => false
+    }
+}
+
+/// The token standard of the contract with its name, symbol and decimals as far as it has them.
+async fn token(rpc: &Rpc, address: Address) -> Option<String> {
+    let standard = match (supports_interface(rpc, address, ERC721).await, supports_interface(rpc, address, ERC1155).await) {
+        (true, _) => "ERC-721",
+        (_, true) => "ERC-1155",
+        _ => "ERC-20"
+    };
+    let decimals = call(rpc, address, DECIMALS).await.filter(|result| result.len() == 32).map(|result| result[31]);
+
+    // Anything without an NFT interface and without decimals is no token.
+    if standard == "ERC-20" && decimals.is_none() {
+        return None;
+    }
+
+    let mut token = standard.to_string();
+    if let Some(name) = text(rpc, address, NAME).await {
+        token.push_str(&format!(" \"{}\"", name));
+    }
+    if let Some(symbol) = text(rpc, address, SYMBOL).await {
+        token.push_str(&format!(" ({})", symbol));
+    }
+    if let Some(decimals) = decimals.filter(|_| standard == "ERC-20") {
+        token.push_str(&format!(", {} decimals", decimals));
+    }
+
+    Some(token)
+}
+
+/// `wally contract ADDRESS [--export=FILE...] [--aliases=FILE] [--rpc-url=URL]` prints what wally
+/// knows about a contract: its label, whether there is an ABI, what kind of token it is, the
+/// transaction deploying it and how often the exports and the aliases refer to it.
+pub async fn run(args: &[String]) -> i32 {
+    let positional = super::positional(args);
+    let [address] = positional.as_slice() else {
+        eprintln!("Usage: wally contract ADDRESS [--export=FILE] [--aliases=FILE] [--rpc-url=URL]");
+        return 2;
+    };
+
+    let address = normalize_address(address).to_lowercase();
+    let Ok(parsed) = address.parse::<Address>() else {
+        eprintln!("Could not parse address {}!", address);
+        return 2;
+    };
+
+    let exports = ArgParser::all("--export");
+    let mut references = vec![];
+    for export in &exports {
+        match self::references(export, &address) {
+            Ok(found) => references.push((export, found)),
+            Err(error) => {
+                eprintln!("{}", error);
+                return 1;
+            }
+        }
+    }
+
+    let aliases: BTreeMap<String, String> = match ArgParser::split(&"--aliases".to_string()) {
+        None => BTreeMap::new(),
+        Some(path) => match std::fs::read_to_string(&path).map_err(|error| error.to_string()).and_then(|contents| serde_json::from_str(&contents).map_err(|error| error.to_string())) {
+            Ok(aliases) => aliases,
+            Err(error) => {
+                eprintln!("Could not read the aliases in {}: {}", path, error);
+                return 2;
+            }
+        }
+    };
+
+    let rr = provider::from_args();
+
+    println!("Contract {}", address);
+    println!("  Label:     {}", registry::label(&address).unwrap_or("-"));
+
+    let names: Vec<&String> = aliases.iter().filter(|(_, aliased)| normalize_address(aliased).eq_ignore_ascii_case(&address)).map(|(alias, _)| alias).collect();
+    if !names.is_empty() {
+        println!("  Aliases:   {}", names.iter().map(|name| name.as_str()).collect::<Vec<_>>().join(", "));
+    }
+    if SpamFilter::new(ArgParser::split(&"--spam-list".to_string())).is_listed(&address) {
+        println!("  Spam:      on the spam list");
+    }
+
+    match Rpc::from_args() {
+        Err(error) => println!("  Code:      unknown, {}", error),
+        Ok(rpc) => match rpc.code(parsed).await {
+            Err(error) => println!("  Code:      unknown, {}", error),
+            Ok(code) if code.is_empty() => println!("  Code:      none, {} is a wallet or not deployed yet", address),
+            Ok(code) => {
+                println!("  Code:      {} bytes", code.len());
+                println!("  Token:     {}", token(&rpc, parsed).await.unwrap_or_else(|| "-".to_string()));
+            }
+        }
+    }
+
+    let local = super::abi::path(&address);
+    let remote = match rr.capabilities().abis {
+        true => match rr.contract_abi(&address).await {
+            Ok(_) => "verified".to_string(),
+            Err(error) => error
+        },
+        false => format!("the {} provider doesn't deliver ABIs", rr.name())
+    };
+    match local.exists() {
+        true => println!("  ABI:       {}, remote: {}", local.display(), remote),
+        false => println!("  ABI:       not in {}, remote: {}", super::abi::dir().display(), remote)
+    }
+
+    let creation = references.iter().find_map(|(export, found)| found.creation.as_ref().map(|hash| format!("{} in {}", hash, export)));
+    match (creation, exports.is_empty()) {
+        (Some(creation), _) => println!("  Creation:  {}", creation),
+        (None, true) => println!("  Creation:  unknown, pass --export=FILE to look for it"),
+        (None, false) => println!("  Creation:  not in the exports")
+    }
+
+    for (export, found) in &references {
+        println!("  {}: {} calls, {} logs, {} transactions sent by it", export, found.calls, found.logs, found.sent);
+    }
+
+    0
+}
@@ -0,0 +1,144 @@
+use std::path::Path;
+use crate::export::read_records;
+use crate::filters::RecordFilters;
+use crate::{accounting, ctc, export_record, output, record_value, ArgParser, ExportCounts, RRDecodedTransaction, TxStatus};
+
+/// Flags of live exports which need more than the records of one export.
+const UNSUPPORTED: &[&str] = &["--anonymize", "--append", "--balance", "--group-by", "--merge", "--per-contract", "--shard"];
+
+/// `wally convert EXPORT [--format=FORMAT] [--out=STEM] [FILTERS]` writes an existing JSON export
+/// in another format, applying the filters of the command line, without any API requests. The
+/// records go through the writers of live exports, so the result is the same as exporting with
+/// these flags in the first place.
+pub fn run(args: &[String]) -> i32 {
+    let paths = super::positional(args);
+
+    let [path] = paths.as_slice() else {
+        eprintln!("Usage: wally convert EXPORT [--format=FORMAT] [--out=STEM] [--address=ADDRESS] [FILTERS]");
+        return 2;
+    };
+
+    if let Some(flag) = UNSUPPORTED.iter().find(|flag| ArgParser::flag(flag) || !ArgParser::all(flag).is_empty()) {
+        eprintln!("{} can not be used with wally convert", flag);
+        return 2;
+    }
+
+    let file_name = Path::new(path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let input_stem = super::stem(&file_name);
+
+    let wallet = match super::wallet(path, &input_stem) {
+        Ok(wallet) => wallet,
+        Err(error) => {
+            eprintln!("{}", error);
+            return 2;
+        }
+    };
+
+    let writer = output::Output::from_args();
+    let record_filters = RecordFilters::from_args();
+
+    if wallet.is_none() && writer.format.is_accounting() {
+        eprintln!("Could not tell which wallet {} belongs to, accounting exports are per wallet, pass --address=ADDRESS", path);
+        return 2;
+    }
+
+    let stem = ArgParser::split(&"--out".to_string()).unwrap_or(input_stem);
+    let directory = wallet.as_ref().map(|_| stem.as_str());
+    let output_path = writer.export_path(directory, &stem);
+
+    if std::fs::canonicalize(&output_path).ok().is_some_and(|output| std::fs::canonicalize(path).ok() == Some(output)) {
+        eprintln!("{} would be overwritten, pass --out=STEM or --out-dir=DIR", path);
+        return 2;
+    }
+
+    // The dates of accounting exports come from the block cache, convert makes no requests.
+    if writer.format.is_accounting() {
+        let numbers = read_records::<RRDecodedTransaction>(path).filter_map(Result::ok).map(|record| record.block_number);
+        if let Err(error) = accounting::cached_dates(numbers) {
+            eprintln!("{}", error);
+            return 1;
+        }
+    }
+
+    let mut counts = ExportCounts::default();
+    let mut read = 0;
+    let mut converted = 0;
+
+    let mut parsed = read_records::<RRDecodedTransaction>(path).map(|record| record.unwrap_or_else(|error| panic!("Could not parse {}: {}", path, error)));
+    let mut keep = |mut record: RRDecodedTransaction| -> Option<RRDecodedTransaction> {
+        read += 1;
+
+        let Some(record_wallet) = wallet.clone().or_else(|| record.wallet.clone()) else {
+            panic!("Could not tell which wallet {} belongs to, pass --address=ADDRESS", path);
+        };
+        record.direction = record.exported_direction(&record_wallet);
+
+        if record.status == Some(TxStatus::Failed) {
+            counts.reverted += 1;
+
+            if record_filters.exclude_failed {
+                return None;
+            }
+        }
+
+        match record_filters.check(&record, record.value, &record_wallet) {
+            Err(skip) => counts.skip(skip),
+            Ok(_) if record_filters.below_min_value(&record, record.value, &record_wallet) => counts.below_min_value += 1,
+            Ok(_) => {
+                converted += 1;
+                return Some(record);
+            }
+        }
+
+        None
+    };
+    let records = std::iter::from_fn(|| loop {
+        let record = parsed.next()?;
+        if let Some(record) = keep(record) {
+            return Some(record);
+        }
+    });
+
+    let written = match writer.format {
+        output::Format::Arrow => {
+            let mut export = writer.arrow_writer(directory, &stem);
+
+            for record in records {
+                export.push(&record_value(&record, None), wallet.as_deref().map(|wallet| (wallet, record.direction)));
+            }
+
+            export.finish()
+        }
+        _ => {
+            let mut export = writer.export_writer(directory, &stem);
+
+            for record in records {
+                export.push(&export_record(&record, None, &writer.format, wallet.as_deref()), record.block_number);
+            }
+
+            export.finish()
+        }
+    };
+
+    println!("Converted {} of {} records from {}, written to {}", converted, read, path, written);
+    match ctc::take_unvalued() {
+        0 => {}
+        unvalued => println!("{}", ctc::unvalued_warning(unvalued))
+    }
+    if record_filters.exclude_failed {
+        println!("Reverted transactions: {} (excluded)", counts.reverted);
+    }
+    let skipped = [
+        ("--skip-spam", counts.skipped_noise + counts.skipped_listed),
+        ("the counterparty filter", counts.skipped_counterparty),
+        ("--event", counts.skipped_event + counts.skipped_without_receipt),
+        ("--contract", counts.skipped_contract),
+        ("--filter", counts.skipped_expression),
+        ("--min-value", counts.below_min_value),
+    ];
+    for (filter, skipped) in skipped.iter().filter(|(_, skipped)| *skipped > 0) {
+        println!("Skipped by {}: {}", filter, skipped);
+    }
+
+    0
+}
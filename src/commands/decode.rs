@@ -0,0 +1,149 @@
+use std::io::{Read, Write};
+use serde_json::{json, Value};
+use web3::ethabi::{Contract, Function, Token};
+use web3::types::{Address, U256};
+use crate::multicall::{self, parse_hex, to_hex};
+use crate::{normalize_address, provider, registry, ArgParser};
+
+/// Loads an ABI, either a plain ABI array or a build artifact with an `abi` field.
+fn load_abi(path: &str) -> Result<Contract, String> {
+    let contents = std::fs::read_to_string(path).map_err(|error| format!("Could not read {}: {}", path, error))?;
+    let value: Value = serde_json::from_str(&contents).map_err(|error| format!("Could not parse {}: {}", path, error))?;
+    let abi = value.get("abi").cloned().unwrap_or(value);
+
+    serde_json::from_value(abi).map_err(|error| format!("{} is not an ABI: {}", path, error))
+}
+
+/// Uints are decimal strings as in the exports, ints are two's complement.
+fn token_value(token: &Token) -> Value {
+    match token {
+        Token::Address(address) => json!(format!("{:?}", address)),
+        Token::FixedBytes(bytes) | Token::Bytes(bytes) => json!(to_hex(bytes)),
+        Token::Uint(value) => json!(value.to_string()),
+        Token::Int(value) if value.bit(255) => json!(format!("-{}", (!*value).overflowing_add(U256::one()).0)),
+        Token::Int(value) => json!(value.to_string()),
+        Token::Bool(value) => json!(value),
+        Token::String(value) => json!(value),
+        Token::FixedArray(tokens) | Token::Array(tokens) | Token::Tuple(tokens) => Value::Array(tokens.iter().map(token_value).collect()),
+    }
+}
+
+/// Decodes `calldata` with the first function of the ABIs whose selector matches.
+fn decode_with_abis(calldata: &[u8], abis: &[Contract]) -> Option<Value> {
+    let selector = calldata.get(..4)?;
+    let function: &Function = abis.iter().flat_map(Contract::functions).find(|function| function.short_signature() == selector)?;
+    let tokens = function.decode_input(&calldata[4..]).ok()?;
+
+    let params: Vec<Value> = function.inputs.iter().zip(&tokens).map(|(param, token)| json!({
+        "name": param.name,
+        "type": param.kind.to_string(),
+        "value": token_value(token),
+    })).collect();
+
+    Some(json!({
+        "selector": to_hex(selector),
+        "name": function.name,
+        "signature": function.signature(),
+        "params": params,
+    }))
+}
+
+/// `wally decode --hash=HASH` prints the decoded method of a transaction as the API decodes it.
+/// `wally decode --data=CALLDATA [--to=ADDRESS] [--abi=FILE...]` decodes calldata locally with the
+/// ABI of the contract in the ABI directory, the ABIs and otherwise the built-in selectors,
+/// `--data=-` reads it from stdin. Exits with 1 if nothing knows the method, printing its selector.
+pub async fn run(args: &[String]) -> i32 {
+    let usage = "Usage: wally decode (--hash=HASH | --data=CALLDATA|- [--to=ADDRESS] [--abi=FILE])";
+    let hash = ArgParser::split(&"--hash".to_string());
+    let data = ArgParser::split(&"--data".to_string());
+
+    if !super::positional(args).is_empty() || hash.is_some() == data.is_some() {
+        eprintln!("{}", usage);
+        return 2;
+    }
+
+    if let Some(hash) = hash {
+        let rr = provider::from_args();
+        if !rr.capabilities().decode {
+            eprintln!("The {} provider doesn't decode transactions", rr.name());
+            return 2;
+        }
+
+        return match rr.decode_input(&hash.to_lowercase()).await {
+            Ok(Value::Null) => {
+                eprintln!("The API could not decode transaction {}", hash);
+                1
+            }
+            Ok(input) => {
+                // Fails once the output is piped into e.g. `head` and it has seen enough.
+                let _ = writeln!(std::io::stdout(), "{}", serde_json::to_string_pretty(&multicall::expand(input)).unwrap());
+                0
+            }
+            Err(error) => {
+                eprintln!("{}", error);
+                1
+            }
+        };
+    }
+
+    let data = match data.as_deref() {
+        Some("-") => {
+            let mut data = String::new();
+            if let Err(error) = std::io::stdin().read_to_string(&mut data) {
+                eprintln!("Could not read the calldata from stdin: {}", error);
+                return 2;
+            }
+            data.trim().to_string()
+        }
+        data => data.unwrap_or_default().to_string()
+    };
+    let Some(calldata) = parse_hex(&data).filter(|calldata| calldata.len() >= 4) else {
+        eprintln!("Invalid --data value {}, expected 0x prefixed hex calldata with at least a selector", data);
+        return 2;
+    };
+
+    let to = ArgParser::split(&"--to".to_string()).map(|address| normalize_address(&address).to_lowercase());
+    if let Some(to) = to.as_ref().filter(|to| to.parse::<Address>().is_err()) {
+        eprintln!("Could not parse address {}!", to);
+        return 2;
+    }
+
+    // The ABI of the contract from `wally abi` comes first.
+    let mut paths = ArgParser::all("--abi");
+    if let Some(path) = to.as_deref().map(super::abi::path).filter(|path| path.exists()) {
+        paths.insert(0, path.to_string_lossy().to_string());
+    }
+
+    let mut abis = vec![];
+    for path in paths {
+        match load_abi(&path) {
+            Ok(abi) => abis.push(abi),
+            Err(error) => {
+                eprintln!("{}", error);
+                return 2;
+            }
+        }
+    }
+
+    let mut decoded = decode_with_abis(&calldata, &abis).unwrap_or_else(|| multicall::decode(&calldata, None));
+    let selector = to_hex(&calldata[..4]);
+
+    if decoded.get("name").is_none() {
+        eprintln!("No ABI or built-in method has the selector {}, look it up in a signature database", selector);
+        return 1;
+    }
+
+    // The calldata was passed in, only the calldata of inner calls is printed.
+    if let Some(decoded) = decoded.as_object_mut() {
+        decoded.remove("data");
+    }
+    if let Some(to) = &to {
+        decoded["target"] = json!(to);
+        if let Some(label) = registry::label(to) {
+            decoded["label"] = json!(label);
+        }
+    }
+
+    let _ = writeln!(std::io::stdout(), "{}", serde_json::to_string_pretty(&decoded).unwrap());
+    0
+}
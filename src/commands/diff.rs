@@ -0,0 +1,161 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use serde::Serialize;
+use serde_json::Value;
+use crate::export::read_records;
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct ExportStats {
+    records: usize,
+    first_block: Option<u64>,
+    last_block: Option<u64>,
+}
+
+impl ExportStats {
+    fn add(&mut self, record: &Value) {
+        self.records += 1;
+
+        if let Some(block) = record.get("blockNumber").and_then(Value::as_u64) {
+            self.first_block = Some(self.first_block.map_or(block, |first| first.min(block)));
+            self.last_block = Some(self.last_block.map_or(block, |last| last.max(block)));
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ChangedRecord {
+    hash: String,
+    fields: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DiffReport {
+    old: ExportStats,
+    new: ExportStats,
+    only_in_old: Vec<String>,
+    only_in_new: Vec<String>,
+    changed: Vec<ChangedRecord>,
+}
+
+impl DiffReport {
+    fn is_identical(&self) -> bool {
+        self.only_in_old.is_empty() && self.only_in_new.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// A digest per field, which is all that needs to be kept of the old export while the new one is
+/// streamed.
+fn fingerprint(record: &Value) -> BTreeMap<String, u64> {
+    let mut fields = BTreeMap::new();
+
+    if let Value::Object(map) = record {
+        for (key, value) in map {
+            let mut hasher = DefaultHasher::new();
+            value.to_string().hash(&mut hasher);
+            fields.insert(key.clone(), hasher.finish());
+        }
+    }
+
+    fields
+}
+
+fn record_hash(record: &Value, path: &str) -> String {
+    record.get("hash")
+        .and_then(Value::as_str)
+        .unwrap_or_else(|| panic!("Record without hash in {}", path))
+        .to_lowercase()
+}
+
+fn diff(old_path: &str, new_path: &str) -> DiffReport {
+    let mut old = ExportStats::default();
+    let mut new = ExportStats::default();
+    let mut old_records: HashMap<String, BTreeMap<String, u64>> = HashMap::new();
+
+    for record in read_records::<Value>(old_path) {
+        let record = record.unwrap_or_else(|error| panic!("Could not parse {}: {}", old_path, error));
+        old.add(&record);
+        old_records.insert(record_hash(&record, old_path), fingerprint(&record));
+    }
+
+    let mut only_in_new = vec![];
+    let mut changed = vec![];
+
+    for record in read_records::<Value>(new_path) {
+        let record = record.unwrap_or_else(|error| panic!("Could not parse {}: {}", new_path, error));
+        new.add(&record);
+
+        let hash = record_hash(&record, new_path);
+
+        match old_records.remove(&hash) {
+            None => only_in_new.push(hash),
+            Some(old_fields) => {
+                let new_fields = fingerprint(&record);
+                let fields: Vec<String> = old_fields.keys()
+                    .chain(new_fields.keys().filter(|key| !old_fields.contains_key(*key)))
+                    .filter(|key| old_fields.get(*key) != new_fields.get(*key))
+                    .cloned()
+                    .collect();
+
+                if !fields.is_empty() {
+                    changed.push(ChangedRecord { hash, fields });
+                }
+            }
+        }
+    }
+
+    let mut only_in_old: Vec<String> = old_records.into_keys().collect();
+    only_in_old.sort();
+
+    DiffReport { old, new, only_in_old, only_in_new, changed }
+}
+
+fn print_stats(label: &str, stats: &ExportStats) {
+    match (stats.first_block, stats.last_block) {
+        (Some(first), Some(last)) => println!("{}: {} records, blocks {}–{}", label, stats.records, first, last),
+        _ => println!("{}: {} records", label, stats.records)
+    }
+}
+
+/// `wally diff OLD NEW [--json]` compares two exports. Exits with 1 when they differ.
+pub fn run(args: &[String]) -> i32 {
+    let paths = super::positional(args);
+
+    if paths.len() != 2 {
+        eprintln!("Usage: wally diff OLD_EXPORT NEW_EXPORT [--json]");
+        return 2;
+    }
+
+    let report = diff(paths[0], paths[1]);
+
+    if args.iter().any(|arg| arg == "--json") {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        print_stats(paths[0], &report.old);
+        print_stats(paths[1], &report.new);
+        println!("Record delta: {:+}", report.new.records as i64 - report.old.records as i64);
+
+        println!("\nOnly in {}: {}", paths[0], report.only_in_old.len());
+        for hash in &report.only_in_old {
+            println!("  {}", hash);
+        }
+
+        println!("\nOnly in {}: {}", paths[1], report.only_in_new.len());
+        for hash in &report.only_in_new {
+            println!("  {}", hash);
+        }
+
+        println!("\nChanged: {}", report.changed.len());
+        for record in &report.changed {
+            println!("  {} ({})", record.hash, record.fields.join(", "));
+        }
+    }
+
+    match report.is_identical() {
+        true => 0,
+        false => 1
+    }
+}
@@ -0,0 +1,40 @@
+/// `--csv-template` examples: what they are for, the header and the template.
+const TEMPLATES: &[(&str, &str, &str)] = &[
+    (
+        "RON transfers for a spreadsheet",
+        "Block,Direction,Value (RON),Counterparty,Transaction",
+        "{block_number},{direction},{value_ron},{counterparty},{hash}",
+    ),
+    (
+        "Fee ledger, sponsored transactions are marked in the last column",
+        "Transaction,Block,Fee (RON),Paid by",
+        "{hash},{block_number},{fee_ron},{fee_payer}",
+    ),
+    (
+        "Semicolon separated, for spreadsheets which expect semicolons",
+        "hash;from;to;value_ron;status",
+        "{hash};{from};{to};{value_ron};{status}",
+    ),
+    (
+        "Block explorer links",
+        "link,method",
+        "https://app.roninchain.com/tx/{hash},{method}",
+    ),
+];
+
+/// `wally examples` prints example `--csv-template` invocations for common targets.
+pub fn run(args: &[String]) -> i32 {
+    if !super::positional(args).is_empty() {
+        eprintln!("Usage: wally examples");
+        return 2;
+    }
+
+    for (index, (description, header, template)) in TEMPLATES.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+        println!("# {}", description);
+        println!("wally --format=csv --csv-template='{}' --csv-header='{}'", template, header);
+    }
+    0
+}
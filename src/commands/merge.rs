@@ -0,0 +1,432 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use std::path::Path;
+use serde_json::Value;
+use crate::export::{read_records, JsonArrayReader, FORMAT_VERSION};
+use crate::manifest::{Coverage, Manifest, ManifestSource};
+use crate::shard::Shard;
+use crate::{checksum, network, output, serialize_record, sort, ArgParser, Direction, RRDecodedTransaction};
+
+/// The order of exports, see `sort::SortKey`.
+type SortKey = (u64, Option<u64>, String);
+
+fn sort_key(record: &RRDecodedTransaction) -> SortKey {
+    (record.block_number, record.transaction_index, record.hash.to_lowercase())
+}
+
+/// The fields describing the wallets of a merged export, they don't make a record more complete.
+const ATTRIBUTION: &[&str] = &["wallet", "direction", "wallets", "directions", "balanceAfter"];
+
+/// The records of an export, checked to be in the order exports are written in.
+struct SortedExport<'a> {
+    path: &'a str,
+    records: JsonArrayReader<Box<dyn BufRead>, RRDecodedTransaction>,
+    last: Option<SortKey>,
+}
+
+impl<'a> SortedExport<'a> {
+    fn open(path: &'a str) -> SortedExport<'a> {
+        SortedExport { path, records: read_records(path), last: None }
+    }
+}
+
+impl Iterator for SortedExport<'_> {
+    type Item = RRDecodedTransaction;
+
+    /// Reads the next record. Exports are sorted, so they can be merged one record at a time.
+    /// Records are written back as they were read.
+    fn next(&mut self) -> Option<RRDecodedTransaction> {
+        let record = self.records.next()?.unwrap_or_else(|error| panic!("Could not parse {}: {}", self.path, error));
+        let key = sort_key(&record);
+
+        if self.last.as_ref().is_some_and(|last| *last > key) {
+            panic!("{} is not sorted by block, was it edited?", self.path);
+        }
+        self.last = Some(key);
+
+        Some(record)
+    }
+}
+
+struct ShardExport<'a> {
+    shard: Shard,
+    export: SortedExport<'a>,
+}
+
+impl ShardExport<'_> {
+    fn next(&mut self) -> Option<(SortKey, RRDecodedTransaction)> {
+        let record = self.export.next()?;
+        Some((sort_key(&record), record))
+    }
+}
+
+/// An export combined by `merge_exports`.
+struct SourceExport<'a> {
+    path: &'a str,
+    /// `None` for merged exports, whose records name their wallets.
+    wallet: Option<String>,
+    manifest: Option<Manifest>,
+    /// When the export was written, its manifest's `finishedAt` or else the modification time.
+    finished_at: Option<String>,
+}
+
+/// `wally merge EXPORT... [--out=FILE]` stitches the shard exports of `--shard` into one sorted
+/// export, see `merge_shards`, and combines any other exports into one, see `merge_exports`.
+pub fn run(args: &[String]) -> i32 {
+    let paths = super::positional(args);
+
+    if paths.is_empty() {
+        eprintln!("Usage: wally merge EXPORT... [--out=FILE]");
+        return 2;
+    }
+
+    match paths.iter().all(|path| Shard::from_file_name(&file_name(path)).is_some()) {
+        true => merge_shards(&paths),
+        false => merge_exports(&paths)
+    }
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+/// Merges the shards of one export into the export of a single run. Exits with 1 without writing
+/// it if a shard is missing or a transaction is in the wrong shard or in several.
+fn merge_shards(paths: &[&str]) -> i32 {
+    let mut stem = None;
+    let mut shards: Vec<ShardExport> = vec![];
+
+    for path in paths {
+        let file_name = file_name(path);
+        let (shard_stem, shard) = Shard::from_file_name(&file_name).unwrap();
+
+        if stem.get_or_insert_with(|| shard_stem.to_string()) != shard_stem {
+            eprintln!("{} belongs to the export {}, not {}", path, shard_stem, stem.unwrap());
+            return 2;
+        }
+        if let Some(other) = shards.iter().find(|other| other.shard.count != shard.count) {
+            eprintln!("{} is shard {} of {}, but {} is one of {}", path, shard.index, shard.count, other.export.path, other.shard.count);
+            return 2;
+        }
+        if let Some(other) = shards.iter().find(|other| other.shard == shard) {
+            eprintln!("{} and {} are both shard {} of {}", other.export.path, path, shard.index, shard.count);
+            return 2;
+        }
+
+        shards.push(ShardExport { shard, export: SortedExport::open(path) });
+    }
+
+    let count = shards[0].shard.count;
+    let missing: Vec<String> = (1..=count)
+        .filter(|index| !shards.iter().any(|shard| shard.shard.index == *index))
+        .map(|index| index.to_string())
+        .collect();
+
+    if !missing.is_empty() {
+        println!("Missing shards of {}: {}", count, missing.join(", "));
+        println!("\nThe export is NOT complete.");
+        return 1;
+    }
+
+    let out = ArgParser::split(&"--out".to_string()).unwrap_or_else(|| format!("{}.json", stem.unwrap()));
+    let temporary = format!("{}.tmp", out);
+    let mut file = BufWriter::new(File::create(&temporary).unwrap_or_else(|error| panic!("Could not create {}: {}", temporary, error)));
+
+    let mut heads: BinaryHeap<Reverse<(SortKey, usize)>> = BinaryHeap::new();
+    let mut pending: Vec<Option<RRDecodedTransaction>> = vec![];
+
+    for (position, shard) in shards.iter_mut().enumerate() {
+        let head = shard.next();
+        if let Some((key, _)) = &head {
+            heads.push(Reverse((key.clone(), position)));
+        }
+        pending.push(head.map(|(_, record)| record));
+    }
+
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut records = 0;
+    let mut misplaced = vec![];
+    let mut duplicates = vec![];
+
+    file.write_all(b"[").unwrap();
+
+    while let Some(Reverse((key, position))) = heads.pop() {
+        let record = pending[position].take().unwrap();
+        let (_, _, hash) = key;
+
+        if let Some((key, next)) = shards[position].next() {
+            heads.push(Reverse((key, position)));
+            pending[position] = Some(next);
+        }
+
+        if !shards[position].shard.contains(&hash) {
+            misplaced.push((hash.clone(), shards[position].export.path));
+        }
+        if !seen.insert(hash.clone()) {
+            duplicates.push(hash);
+            continue;
+        }
+
+        if records > 0 {
+            file.write_all(b",").unwrap();
+        }
+        serde_json::to_writer(&mut file, &record).unwrap();
+        records += 1;
+    }
+
+    file.write_all(b"]").unwrap();
+
+    println!("Merged transactions: {} from {} shards", records, count);
+
+    println!("\nIn the wrong shard: {}", misplaced.len());
+    for (hash, path) in &misplaced {
+        println!("  {} ({}, belongs to shard {})", hash, path, Shard::of(hash, count));
+    }
+
+    println!("\nIn several shards: {}", duplicates.len());
+    for hash in &duplicates {
+        println!("  {}", hash);
+    }
+
+    if !misplaced.is_empty() || !duplicates.is_empty() {
+        drop(file);
+        let _ = std::fs::remove_file(&temporary);
+        println!("\nThe shards don't fit together, {} was not written.", out);
+        return 1;
+    }
+
+    file.into_inner()
+        .map_err(|error| error.into_error())
+        .and_then(|file| file.sync_all())
+        .unwrap_or_else(|error| panic!("Could not write {}: {}", temporary, error));
+    std::fs::rename(&temporary, &out).unwrap_or_else(|error| panic!("Could not write {}: {}", out, error));
+
+    println!("\nThe merged export was saved to {}", out);
+    0
+}
+
+/// The number of fields a record has values for, so a record with decoded payloads or a receipt
+/// wins over one without.
+fn completeness(record: &RRDecodedTransaction) -> usize {
+    match serde_json::to_value(record).unwrap() {
+        Value::Object(fields) => fields.iter().filter(|(name, value)| !value.is_null() && !ATTRIBUTION.contains(&name.as_str())).count(),
+        _ => 0
+    }
+}
+
+/// The wallet a transaction of several wallets is attributed to, as in `merge_records`: the
+/// sender if it is one of them, else the first.
+fn attributed<'a>(wallets: &'a [String], from: &str) -> &'a String {
+    wallets.iter().find(|wallet| wallet.eq_ignore_ascii_case(from)).unwrap_or(&wallets[0])
+}
+
+/// One record of all copies of a transaction: the most complete one, of the most recent export if
+/// several are equally complete. With `annotate` it lists the wallets of all copies.
+fn merge_copies(copies: Vec<(usize, RRDecodedTransaction)>, sources: &[SourceExport], annotate: bool, aliases: &mut BTreeMap<String, String>) -> RRDecodedTransaction {
+    let mut wallets: Vec<String> = vec![];
+    let mut directions: BTreeMap<String, Direction> = BTreeMap::new();
+    let mut add = |wallet: &String, direction: Direction| {
+        if !wallets.iter().any(|known| known.eq_ignore_ascii_case(wallet)) {
+            wallets.push(wallet.clone());
+            directions.insert(wallet.clone(), direction);
+        }
+    };
+
+    for (index, copy) in &copies {
+        match &sources[*index].wallet {
+            Some(wallet) => add(wallet, copy.exported_direction(wallet)),
+            None => {
+                for wallet in &copy.wallets {
+                    add(wallet, copy.directions.get(wallet).copied().unwrap_or_default());
+                }
+                // The attribution of a merged export is an alias if it isn't one of its wallets.
+                if let Some(alias) = copy.wallet.as_ref().filter(|wallet| !copy.wallets.is_empty() && !copy.wallets.contains(wallet)) {
+                    aliases.entry(attributed(&copy.wallets, &copy.from).to_lowercase()).or_insert_with(|| alias.clone());
+                }
+            }
+        }
+    }
+
+    let (_, mut merged) = copies.into_iter()
+        .max_by_key(|(index, copy)| (completeness(copy), sources[*index].finished_at.clone()))
+        .unwrap();
+
+    if annotate && !wallets.is_empty() {
+        let wallet = attributed(&wallets, &merged.from).clone();
+        merged.direction = match wallets.len() {
+            1 => directions[&wallet],
+            _ => Direction::Both
+        };
+        merged.wallet = Some(aliases.get(&wallet.to_lowercase()).cloned().unwrap_or(wallet));
+        merged.wallets = wallets;
+        merged.directions = directions;
+        // The balance belongs to a single wallet.
+        merged.balance_after = None;
+    } else if let Some(direction) = directions.into_values().next() {
+        // The copy kept may be from an export written before records carried their direction.
+        merged.direction = direction;
+    }
+
+    merged
+}
+
+/// Combines exports of one or several wallets into one sorted export in which every transaction
+/// appears once, with a manifest listing the exports. The exports are streamed, only the hashes
+/// are kept in memory. Exits with 1 without writing anything if the exports are of different
+/// networks.
+fn merge_exports(paths: &[&str]) -> i32 {
+    let writer = output::Output::from_args();
+
+    if !matches!(writer.format, output::Format::Json) {
+        eprintln!("wally merge writes JSON exports, --format can not be used");
+        return 2;
+    }
+
+    let out = ArgParser::split(&"--out".to_string()).unwrap_or_else(|| "merged.json".to_string());
+    let stem = out.strip_suffix(".json").unwrap_or(&out).to_string();
+    let output_path = writer.export_path(None, &stem);
+
+    let mut sources: Vec<SourceExport> = vec![];
+
+    for path in paths {
+        if std::fs::canonicalize(&output_path).ok().is_some_and(|output| std::fs::canonicalize(path).ok() == Some(output)) {
+            eprintln!("{} would be overwritten, pass another --out=FILE", path);
+            return 2;
+        }
+
+        let source_stem = super::stem(&file_name(path));
+        let manifest = match Manifest::load(&Path::new(path).with_file_name(Manifest::file_name(&source_stem)).to_string_lossy()) {
+            Ok(manifest) => manifest,
+            Err(error) => {
+                eprintln!("{}", error);
+                return 1;
+            }
+        };
+        if let Some(manifest) = manifest.as_ref().filter(|manifest| manifest.format_version > FORMAT_VERSION) {
+            eprintln!("{} was written in format version {}, this version of wally reads up to {}", path, manifest.format_version, FORMAT_VERSION);
+            return 1;
+        }
+
+        let wallet = match super::wallet(path, &source_stem) {
+            Ok(wallet) => wallet,
+            Err(error) => {
+                eprintln!("{}", error);
+                return 2;
+            }
+        };
+        let finished_at = manifest.as_ref().map(|manifest| manifest.finished_at.clone()).or_else(|| {
+            let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+            Some(chrono::DateTime::<chrono::Utc>::from(modified).to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+        });
+
+        sources.push(SourceExport { path, wallet, manifest, finished_at });
+    }
+
+    let networks: Vec<(network::Network, &str)> = sources.iter()
+        .filter_map(|source| Some((source.manifest.as_ref()?.network, source.path)))
+        .collect();
+    if let Some((network, path)) = networks.first() {
+        if let Some((other, other_path)) = networks.iter().find(|(other, _)| other != network) {
+            eprintln!("{} is an export of {}, but {} one of {}, exports of different networks can't be merged", path, network.name(), other_path, other.name());
+            return 1;
+        }
+    }
+    for source in sources.iter().filter(|source| source.manifest.is_none()) {
+        println!("Note: {} has no manifest, its network could not be checked", source.path);
+    }
+
+    // Exports of a single wallet stay exports of that wallet.
+    let mut wallets: Vec<String> = sources.iter().filter_map(|source| source.wallet.as_deref().map(str::to_lowercase)).collect();
+    wallets.sort();
+    wallets.dedup();
+    let annotate = wallets.len() > 1 || sources.iter().any(|source| source.wallet.is_none());
+
+    let mut aliases: BTreeMap<String, String> = sources.iter()
+        .filter_map(|source| Some((source.wallet.as_ref()?.to_lowercase(), source.manifest.as_ref()?.alias.clone()?)))
+        .collect();
+
+    let mut records = sort::KWayMerge::new(sources.iter().map(|source| SortedExport::open(source.path)).collect()).peekable();
+    let mut export = writer.export_writer(None, &stem);
+    let mut read = vec![0; sources.len()];
+    let mut written: HashSet<String> = HashSet::new();
+    let mut coverage = Coverage::default();
+    let mut duplicates = 0;
+    let mut moved = vec![];
+
+    while let Some((index, record)) = records.next() {
+        read[index] += 1;
+        let hash = record.hash.to_lowercase();
+        let mut copies = vec![(index, record)];
+
+        // Copies of a transaction have the same sort key, so they are adjacent.
+        while let Some((index, copy)) = records.next_if(|(_, copy)| copy.hash.eq_ignore_ascii_case(&hash)) {
+            read[index] += 1;
+            copies.push((index, copy));
+        }
+
+        // A transaction in different blocks of the exports, e.g. after a reorg, is kept once.
+        if !written.insert(hash.clone()) {
+            moved.push(hash);
+            continue;
+        }
+        duplicates += copies.len() - 1;
+
+        let merged = merge_copies(copies, &sources, annotate, &mut aliases);
+        coverage.add(&merged);
+        export.push(&serialize_record(&merged, None), merged.block_number);
+    }
+
+    let output_path = export.finish();
+
+    let manifests: Vec<&Manifest> = sources.iter().filter_map(|source| source.manifest.as_ref()).collect();
+    let filters = manifests.first().map(|first| first.filters.clone()).filter(|filters| manifests.iter().all(|manifest| manifest.filters == *filters));
+    if filters.is_none() && !manifests.is_empty() {
+        println!("Note: the exports were written with different filters, see the sources of the manifest");
+    }
+
+    let mut manifest = Manifest {
+        format_version: FORMAT_VERSION,
+        tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        network: networks.first().map(|(network, _)| *network).unwrap_or_else(network::current),
+        address: match annotate {
+            true => None,
+            false => wallets.first().cloned()
+        },
+        alias: wallets.first().filter(|_| !annotate).and_then(|wallet| aliases.get(wallet).cloned()),
+        hosts: manifests.iter().flat_map(|manifest| manifest.hosts.clone()).collect::<BTreeSet<_>>().into_iter().collect(),
+        filters: filters.unwrap_or_default(),
+        flags: manifests.iter().flat_map(|manifest| manifest.flags.clone()).collect::<BTreeSet<_>>().into_iter().collect(),
+        coverage,
+        // The sources may have counted their confirmations from different blocks.
+        latest_block: None,
+        failed_transactions: manifests.iter().map(|manifest| manifest.failed_transactions).sum(),
+        started_at: manifests.iter().map(|manifest| manifest.started_at.clone()).min().unwrap_or_default(),
+        finished_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        files: vec![],
+        sources: sources.iter().zip(&read).map(|(source, records)| ManifestSource {
+            file: source.path.to_string(),
+            sha256: checksum::of_file(source.path).unwrap_or_else(|error| panic!("Could not read {}: {}", source.path, error)),
+            address: source.wallet.clone(),
+            records: *records,
+            filters: source.manifest.as_ref().map(|manifest| manifest.filters.clone()),
+            finished_at: source.finished_at.clone(),
+        }).collect(),
+    };
+    manifest.complete(&output::written(), None);
+    let manifest_path = writer.write(None, Manifest::file_name(&stem), serde_json::to_string_pretty(&manifest).unwrap());
+
+    println!("Merged transactions: {} from {} exports", manifest.coverage.records.total, sources.len());
+    for (source, records) in sources.iter().zip(&read) {
+        println!("  {}: {} records", source.path, records);
+    }
+    println!("\nIn several exports: {}", duplicates);
+    println!("In different blocks of the exports, kept once: {}", moved.len());
+    for hash in &moved {
+        println!("  {}", hash);
+    }
+
+    println!("\nThe merged export was saved to {}, its manifest to {}", output_path, manifest_path);
+    0
+}
@@ -0,0 +1,52 @@
+use std::path::Path;
+use web3::types::Address;
+use crate::manifest::Manifest;
+use crate::{aliases, normalize_address, ArgParser};
+
+pub mod abi;
+pub mod blocks;
+pub mod check;
+pub mod contract;
+pub mod convert;
+pub mod decode;
+pub mod diff;
+pub mod examples;
+pub mod merge;
+pub mod prune;
+pub mod schema;
+pub mod search;
+pub mod split;
+pub mod stats;
+pub mod tail;
+pub mod token_transfers;
+pub mod validate;
+pub mod verify;
+
+/// Returns the positional arguments of a subcommand, skipping flags.
+pub fn positional(args: &[String]) -> Vec<&str> {
+    args.iter().map(String::as_str).filter(|arg| !arg.starts_with("--")).collect()
+}
+
+/// The stem of an export file name, e.g. `0x...` for `0x....json.gz.age`.
+pub fn stem(file_name: &str) -> String {
+    let mut stem = file_name;
+    for extension in [".age", ".gz", ".json", ".ndjson"] {
+        stem = stem.strip_suffix(extension).unwrap_or(stem);
+    }
+    stem.to_string()
+}
+
+/// The wallet an export belongs to: `--address`, else the address of its manifest, else the file
+/// name if it is an address. `None` for merged exports, whose records name their wallet.
+pub fn wallet(path: &str, stem: &str) -> Result<Option<String>, String> {
+    if let Some(address) = ArgParser::all("--address").pop() {
+        return aliases::WalletArg::parse(&address).map(|wallet| Some(wallet.address));
+    }
+
+    let manifest = Path::new(path).with_file_name(Manifest::file_name(stem));
+    if let Some(manifest) = Manifest::load(&manifest.to_string_lossy())? {
+        return Ok(manifest.address.as_deref().map(normalize_address));
+    }
+
+    Ok(normalize_address(stem).parse::<Address>().is_ok().then(|| normalize_address(stem)))
+}
@@ -0,0 +1,150 @@
+use std::collections::HashSet;
+use std::path::Path;
+use serde_json::Value;
+use crate::export::read_records;
+use crate::filters::SpamFilter;
+use crate::manifest::{Coverage, Manifest};
+use crate::{output, serialize_record, ArgParser, RRDecodedTransaction};
+
+/// The cleanups of `wally prune`, applied in this order. A record counts for the first one
+/// removing it.
+const RULES: &[&str] = &["--drop-failed", "--drop-duplicates", "--drop-spam"];
+
+/// Whether a record is what an old version wrote for a transaction it could not fetch: without a
+/// block, sender or hash, or not even a transaction record.
+fn is_failed_fetch(record: Option<&RRDecodedTransaction>) -> bool {
+    match record {
+        None => true,
+        Some(record) => record.block_number == 0 || record.hash == "null" || record.from.is_empty() || record.from == "null"
+    }
+}
+
+/// `wally prune EXPORT --drop-failed --drop-duplicates --drop-spam (--out=FILE | --in-place)`
+/// removes junk records of older runs from an export and reports how many each rule removed. The
+/// export is only replaced with `--in-place`, through a temporary file like every output file.
+pub fn run(args: &[String]) -> i32 {
+    let paths = super::positional(args);
+
+    let [path] = paths.as_slice() else {
+        eprintln!("Usage: wally prune EXPORT [--drop-failed] [--drop-duplicates] [--drop-spam] (--out=FILE | --in-place)");
+        return 2;
+    };
+
+    let rules: Vec<&str> = RULES.iter().copied().filter(|rule| ArgParser::flag(rule)).collect();
+    if rules.is_empty() {
+        eprintln!("Nothing to prune, pass at least one of {}", RULES.join(", "));
+        return 2;
+    }
+
+    let in_place = ArgParser::flag("--in-place");
+    let writer = output::Output::from_args();
+
+    if ArgParser::split(&"--format".to_string()).is_some_and(|format| !format.eq_ignore_ascii_case("json")) {
+        eprintln!("wally prune writes JSON exports, --format can not be used");
+        return 2;
+    }
+    let out = match (ArgParser::split(&"--out".to_string()), in_place) {
+        (Some(_), true) => {
+            eprintln!("--out can not be combined with --in-place");
+            return 2;
+        }
+        (None, false) => {
+            eprintln!("Pass --out=FILE for the pruned export, or --in-place to replace {}", path);
+            return 2;
+        }
+        (Some(out), false) => out,
+        (None, true) => {
+            if !path.ends_with(".json") || writer.encryption.is_some() || writer.chunking.is_some() || writer.out_dir.is_some() {
+                eprintln!("--in-place only replaces plain JSON exports and can not be combined with --encrypt, --max-file-size, --chunk-size or --out-dir");
+                return 2;
+            }
+            path.to_string()
+        }
+    };
+
+    let file_name = Path::new(path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let stem = super::stem(&file_name);
+    let wallet = match super::wallet(path, &stem) {
+        Ok(wallet) => wallet,
+        Err(error) => {
+            eprintln!("{}", error);
+            return 2;
+        }
+    };
+    let spam_filter = SpamFilter::new(ArgParser::split(&"--spam-list".to_string()));
+
+    let mut removed = vec![0; rules.len()];
+    let mut hashes: HashSet<String> = HashSet::new();
+    let mut coverage = Coverage::default();
+    let mut read = 0;
+
+    let mut export = writer.export_writer(None, out.strip_suffix(".json").unwrap_or(&out));
+
+    for record in read_records::<Value>(path) {
+        let record = match record {
+            Ok(record) => record,
+            Err(error) => {
+                eprintln!("Could not parse {}: {}, nothing was written", path, error);
+                return 1;
+            }
+        };
+        read += 1;
+        let typed = serde_json::from_value::<RRDecodedTransaction>(record.clone()).ok();
+        let hash = record.get("hash").and_then(Value::as_str).unwrap_or_default().to_lowercase();
+
+        let rule = rules.iter().position(|rule| match *rule {
+            "--drop-failed" => is_failed_fetch(typed.as_ref()),
+            "--drop-duplicates" => !hash.is_empty() && hashes.contains(&hash),
+            _ => typed.as_ref().is_some_and(|typed| {
+                let record_wallet = wallet.as_deref().or(typed.wallet.as_deref()).unwrap_or_else(|| {
+                    panic!("Could not tell which wallet {} belongs to, --drop-spam needs --address=ADDRESS", path)
+                });
+                spam_filter.classify(typed, typed.value, record_wallet).is_some()
+            })
+        });
+        if let Some(rule) = rule {
+            removed[rule] += 1;
+            continue;
+        }
+
+        hashes.insert(hash);
+        // Records that aren't transaction records are kept as they are, with sorted keys.
+        match &typed {
+            Some(typed) => {
+                coverage.add(typed);
+                export.push(&serialize_record(typed, None), typed.block_number);
+            }
+            None => export.push(&record.to_string(), record.get("blockNumber").and_then(Value::as_u64).unwrap_or(0))
+        }
+    }
+
+    let written = export.finish();
+    let kept = read - removed.iter().sum::<usize>();
+    println!("Kept {} of {} records, written to {}", kept, read, written);
+    for (rule, removed) in rules.iter().zip(&removed) {
+        println!("  removed by {}: {}", rule, removed);
+    }
+
+    // The manifest of a replaced export describes the pruned records and their checksum.
+    let manifest_path = Path::new(path).with_file_name(Manifest::file_name(&stem)).to_string_lossy().to_string();
+    if in_place {
+        match Manifest::load(&manifest_path) {
+            Ok(Some(mut manifest)) => {
+                manifest.coverage = coverage;
+                // The export and its checksum sidecar were replaced.
+                let written = output::written();
+                for file in manifest.files.iter_mut() {
+                    if let Some(replaced) = written.iter().find(|replaced| Path::new(&replaced.path).file_name().is_some_and(|name| *name == *file.file)) {
+                        file.sha256 = replaced.sha256.clone();
+                    }
+                }
+                writer.write(None, manifest_path.clone(), serde_json::to_string_pretty(&manifest).unwrap());
+                println!("Updated {}", manifest_path);
+            }
+            Ok(None) => {}
+            Err(error) => eprintln!("{} was not updated: {}", manifest_path, error)
+        }
+    }
+
+    0
+}
@@ -0,0 +1,12 @@
+use crate::schema;
+
+/// `wally schema [FLAGS]` prints the JSON Schema of the records an export with `FLAGS` produces.
+pub fn run(args: &[String]) -> i32 {
+    if !super::positional(args).is_empty() {
+        eprintln!("Usage: wally schema [--raw-logs] [--no-decode-input] [--no-decode-output] [--merge]");
+        return 2;
+    }
+
+    println!("{}", serde_json::to_string_pretty(&schema::from_args()).unwrap());
+    0
+}
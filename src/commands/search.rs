@@ -0,0 +1,117 @@
+use std::io::Write;
+use std::path::Path;
+use crate::export::stream_records;
+use crate::{filters, normalize_address, ArgParser, RRDecodedTransaction};
+
+/// What `wally search` looks for, records have to match all of it.
+struct Query {
+    hash: Option<String>,
+    counterparty: Option<String>,
+    method: Option<String>,
+    /// Lowercase, compared with the lowercase decoded payloads.
+    text: Option<String>,
+}
+
+impl Query {
+    fn from_args() -> Query {
+        Query {
+            hash: ArgParser::split(&"--hash".to_string()),
+            counterparty: ArgParser::split(&"--counterparty".to_string()).map(|address| normalize_address(&address)),
+            method: ArgParser::split(&"--method".to_string()),
+            text: ArgParser::split(&"--text".to_string()).map(|text| text.to_lowercase()),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.hash.is_none() && self.counterparty.is_none() && self.method.is_none() && self.text.is_none()
+    }
+
+    fn matches(&self, record: &RRDecodedTransaction) -> bool {
+        self.hash.as_ref().is_none_or(|hash| record.hash.eq_ignore_ascii_case(hash))
+            && self.counterparty.as_ref().is_none_or(|address| record.from.eq_ignore_ascii_case(address) || record.to.eq_ignore_ascii_case(address))
+            && self.method.as_ref().is_none_or(|method| record.input.as_ref().and_then(filters::method_name).is_some_and(|name| name.eq_ignore_ascii_case(method)))
+            && self.text.as_ref().is_none_or(|text| {
+                let input = record.input.as_ref().map(|input| input.to_string());
+                let output = record.output.as_ref().map(|output| serde_json::to_string(output).unwrap());
+                [input, output].into_iter().flatten().any(|payload| payload.to_lowercase().contains(text))
+            })
+    }
+}
+
+/// `wally search EXPORT [--hash=HASH] [--counterparty=ADDRESS] [--method=NAME] [--text=TEXT] [--raw]
+/// [--count]` streams an export and prints the records matching all of the criteria as pretty
+/// JSON, or one per line with `--raw`. Exits with 0 if there were matches, 1 if not and 2 if the
+/// export can't be read.
+pub fn run(args: &[String]) -> i32 {
+    let paths = super::positional(args);
+    let query = Query::from_args();
+
+    let [path] = paths.as_slice() else {
+        eprintln!("Usage: wally search EXPORT [--hash=HASH] [--counterparty=ADDRESS] [--method=NAME] [--text=TEXT] [--raw] [--count]");
+        return 2;
+    };
+    if query.is_empty() {
+        eprintln!("Nothing to search for, pass at least one of --hash, --counterparty, --method or --text");
+        return 2;
+    }
+
+    let raw = ArgParser::flag("--raw");
+    let count = ArgParser::flag("--count");
+
+    let file_name = Path::new(path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let wallet = match super::wallet(path, &super::stem(&file_name)) {
+        Ok(wallet) => wallet,
+        Err(error) => {
+            eprintln!("{}", error);
+            return 2;
+        }
+    };
+
+    let records = match stream_records::<RRDecodedTransaction>(path) {
+        Ok(records) => records,
+        Err(error) => {
+            eprintln!("{}", error);
+            return 2;
+        }
+    };
+
+    let mut stdout = std::io::stdout().lock();
+    let mut matches = 0;
+
+    for record in records {
+        let mut record = match record {
+            Ok(record) => record,
+            Err(error) => {
+                eprintln!("Could not parse {}: {}", path, error);
+                return 2;
+            }
+        };
+        if !query.matches(&record) {
+            continue;
+        }
+        matches += 1;
+
+        if let Some(wallet) = &wallet {
+            record.direction = record.exported_direction(wallet);
+        }
+
+        let printed = match (count, raw) {
+            (true, _) => Ok(()),
+            (false, true) => writeln!(stdout, "{}", serde_json::to_string(&record).unwrap()),
+            (false, false) => writeln!(stdout, "{}", serde_json::to_string_pretty(&record).unwrap())
+        };
+        // Stop once the output is piped into e.g. `head` and it has seen enough.
+        if printed.is_err() {
+            return 0;
+        }
+    }
+
+    if count {
+        println!("{}", matches);
+    }
+
+    match matches {
+        0 => 1,
+        _ => 0
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use serde::Serialize;
+use crate::export::{read_records, FORMAT_VERSION};
+use crate::grouping::{self, Group, GroupBy};
+use crate::manifest::Manifest;
+use crate::network::{self, Network};
+use crate::sort::RecordSorter;
+use crate::{accounting, ctc, export_record, output, ArgParser, RRDecodedTransaction};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SplitFile {
+    group: String,
+    file: String,
+    records: usize,
+    first_block: Option<u64>,
+    last_block: Option<u64>,
+}
+
+/// The `index.json` of a split export, listing the file of every group.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SplitIndex {
+    format_version: u32,
+    network: Network,
+    source: String,
+    by: &'static str,
+    files: Vec<SplitFile>,
+}
+
+/// `wally split EXPORT --by=direction|method|counterparty|contract [--out=DIR]` writes the records
+/// of an existing export to one file per group, grouped as by `--group-by`, and an index of the
+/// files. Groups without records get no file.
+pub fn run(args: &[String]) -> i32 {
+    let paths = super::positional(args);
+    let usage = "Usage: wally split EXPORT --by=direction|method|counterparty|contract [--out=DIR]";
+
+    let [path] = paths.as_slice() else {
+        eprintln!("{}", usage);
+        return 2;
+    };
+
+    let group_by = match ArgParser::split(&"--by".to_string()).map(|by| by.to_lowercase()).as_deref() {
+        Some("direction") => GroupBy::Direction,
+        Some("method") => GroupBy::Method,
+        Some("counterparty") => GroupBy::Counterparty,
+        Some("contract") => GroupBy::Contract,
+        Some("month") => {
+            eprintln!("--by=month needs the block timestamps, which exports don't have");
+            return 2;
+        }
+        Some(by) => {
+            eprintln!("Invalid --by value {}, expected direction, method, counterparty or contract", by);
+            return 2;
+        }
+        None => {
+            eprintln!("{}", usage);
+            return 2;
+        }
+    };
+
+    let writer = output::Output::from_args();
+    if matches!(writer.format, output::Format::Arrow) {
+        eprintln!("wally split can not be combined with --format=arrow");
+        return 2;
+    }
+
+    let file_name = Path::new(path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let stem = super::stem(&file_name);
+    let manifest = match Manifest::load(&Path::new(path).with_file_name(Manifest::file_name(&stem)).to_string_lossy()) {
+        Ok(manifest) => manifest,
+        Err(error) => {
+            eprintln!("{}", error);
+            return 1;
+        }
+    };
+    if let Some(manifest) = manifest.as_ref().filter(|manifest| manifest.format_version > FORMAT_VERSION) {
+        eprintln!("{} was written in format version {}, this version of wally reads up to {}", path, manifest.format_version, FORMAT_VERSION);
+        return 1;
+    }
+
+    let wallet = match super::wallet(path, &stem) {
+        Ok(wallet) => wallet,
+        Err(error) => {
+            eprintln!("{}", error);
+            return 2;
+        }
+    };
+    if wallet.is_none() && writer.format.is_accounting() {
+        eprintln!("Accounting exports are per wallet, pass --address=ADDRESS");
+        return 2;
+    }
+
+    let out = ArgParser::split(&"--out".to_string()).unwrap_or_else(|| format!("{}.{}", stem, group_by.name()));
+
+    let mut groups: BTreeMap<String, Group> = BTreeMap::new();
+    let mut read = 0;
+
+    for record in read_records::<RRDecodedTransaction>(path) {
+        let mut record = match record {
+            Ok(record) => record,
+            Err(error) => {
+                eprintln!("Could not parse {}: {}, nothing was written", path, error);
+                return 1;
+            }
+        };
+        read += 1;
+
+        let Some(record_wallet) = wallet.clone().or_else(|| record.wallet.clone()) else {
+            eprintln!("Could not tell which wallet {} belongs to, pass --address=ADDRESS", path);
+            return 2;
+        };
+        record.direction = record.exported_direction(&record_wallet);
+
+        let group = groups.entry(group_by.key(&record, &record_wallet)).or_insert_with(|| Group { records: 0, sorter: RecordSorter::new() });
+        group.records += 1;
+        group.sorter.push(record);
+    }
+
+    // The dates of accounting exports come from the block cache, split makes no requests.
+    if writer.format.is_accounting() {
+        if let Err(error) = accounting::cached_dates(groups.values_mut().flat_map(|group| group.sorter.sorted()).map(|record| record.block_number)) {
+            eprintln!("{}, nothing was written", error);
+            return 1;
+        }
+    }
+
+    println!("Split {} records of {} by {} into {} files:", read, path, group_by.name(), groups.len());
+    let mut files = vec![];
+
+    for (key, group) in groups.iter_mut() {
+        let mut export = writer.export_writer(None, &format!("{}/{}.{}", out, stem, grouping::file_name(key)));
+        let mut first_block = None;
+        let mut last_block = None;
+
+        for record in group.sorter.sorted() {
+            first_block = first_block.or(Some(record.block_number));
+            last_block = Some(record.block_number);
+            export.push(&export_record(&record, None, &writer.format, wallet.as_deref()), record.block_number);
+        }
+
+        let written = export.finish();
+        println!("  {}: {} records in {}", key, group.records, written);
+
+        files.push(SplitFile {
+            group: key.clone(),
+            file: Path::new(&written).file_name().unwrap().to_string_lossy().to_string(),
+            records: group.records,
+            first_block,
+            last_block,
+        });
+    }
+    match ctc::take_unvalued() {
+        0 => {}
+        unvalued => println!("{}", ctc::unvalued_warning(unvalued))
+    }
+
+    let index = SplitIndex {
+        format_version: FORMAT_VERSION,
+        network: manifest.map_or_else(network::current, |manifest| manifest.network),
+        source: file_name,
+        by: group_by.name(),
+        files,
+    };
+    let index_path = writer.write(None, format!("{}/index.json", out), serde_json::to_string_pretty(&index).unwrap());
+
+    println!("The index was saved to {}", index_path);
+    0
+}
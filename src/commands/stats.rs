@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::path::Path;
+use serde::Serialize;
+use web3::types::U256;
+use crate::export::{self, FORMAT_VERSION};
+use crate::manifest::Manifest;
+use crate::{approvals, filters, largest, output, ArgParser, Direction, FeePayer, RRDecodedTransaction, TxStatus};
+
+/// The number of counterparties and methods listed by default.
+const TOP: usize = 10;
+
+#[derive(Serialize)]
+struct Count {
+    name: String,
+    transactions: usize,
+}
+
+/// The summary of an export, see `run`.
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct Stats {
+    wallet: Option<String>,
+    records: usize,
+    sent: usize,
+    received: usize,
+    /// Transactions in both the sent and the received list of the wallet.
+    both: usize,
+    first_block: Option<u64>,
+    last_block: Option<u64>,
+    reverted: usize,
+    /// Transactions the export could not fetch, according to its manifest.
+    failed_transactions: Option<usize>,
+    gas_used: u64,
+    /// The fees the wallet paid itself, in RON.
+    fees_paid: String,
+    sponsored: usize,
+    top_counterparties: Vec<Count>,
+    top_methods: Vec<Count>,
+    without_method: usize,
+}
+
+/// The `limit` names with the most transactions, ties in alphabetical order.
+fn top(counts: HashMap<String, usize>, limit: usize) -> Vec<Count> {
+    let mut counts: Vec<Count> = counts.into_iter().map(|(name, transactions)| Count { name, transactions }).collect();
+    counts.sort_by(|a, b| b.transactions.cmp(&a.transactions).then_with(|| a.name.cmp(&b.name)));
+    counts.truncate(limit);
+    counts
+}
+
+fn stats(path: &str, wallet: Option<String>, limit: usize) -> Result<Stats, String> {
+    let mut stats = Stats { wallet: wallet.clone(), ..Stats::default() };
+    let mut fees_paid = U256::zero();
+    let mut counterparties: HashMap<String, usize> = HashMap::new();
+    let mut methods: HashMap<String, usize> = HashMap::new();
+
+    for record in export::stream_records::<RRDecodedTransaction>(path)? {
+        let record = record.map_err(|error| format!("Could not parse {}: {}", path, error))?;
+        let record_wallet = wallet.clone().or_else(|| record.wallet.clone()).unwrap_or_default();
+
+        stats.records += 1;
+        stats.first_block = Some(stats.first_block.map_or(record.block_number, |first| first.min(record.block_number)));
+        stats.last_block = Some(stats.last_block.map_or(record.block_number, |last| last.max(record.block_number)));
+        stats.gas_used += record.gas_used.unwrap_or(0);
+
+        if record.status == Some(TxStatus::Failed) {
+            stats.reverted += 1;
+        }
+
+        match record.exported_direction(&record_wallet) {
+            Direction::Sent => stats.sent += 1,
+            Direction::Received => stats.received += 1,
+            Direction::Both => stats.both += 1,
+        }
+
+        // As in the summary of a run, the wallet pays the fees of what it sent unless a sponsor did.
+        if record.from.eq_ignore_ascii_case(&record_wallet) {
+            match record.fee_payer {
+                Some(FeePayer::Sponsored) => stats.sponsored += 1,
+                _ => {
+                    let gas_price = record.effective_gas_price.or(record.gas_price).unwrap_or(0);
+                    fees_paid += U256::from(record.gas_used.unwrap_or(0)) * U256::from(gas_price);
+                }
+            }
+        }
+
+        if !record_wallet.is_empty() {
+            *counterparties.entry(filters::address_key(filters::counterparty(&record, &record_wallet))).or_default() += 1;
+        }
+        match record.input.as_ref().and_then(filters::method_name) {
+            Some(method) => *methods.entry(method.to_string()).or_default() += 1,
+            None => stats.without_method += 1
+        }
+    }
+
+    stats.fees_paid = filters::format_units(fees_paid, filters::RON_DECIMALS);
+    stats.top_counterparties = top(counterparties, limit);
+    stats.top_methods = top(methods, limit);
+    Ok(stats)
+}
+
+fn print(path: &str, stats: &Stats) {
+    println!("Export: {}", path);
+    println!("Wallet: {}", stats.wallet.as_deref().unwrap_or("several (merged export)"));
+    println!(
+        "Transactions: {} ({} sent, {} received{})",
+        stats.records, stats.sent, stats.received, match stats.both { 0 => String::new(), both => format!(", {} sent and received", both) }
+    );
+    if let (Some(first), Some(last)) = (stats.first_block, stats.last_block) {
+        println!("Blocks: {}–{}", first, last);
+    }
+    println!("Reverted transactions: {}", stats.reverted);
+    if let Some(failed) = stats.failed_transactions {
+        println!("Transactions the export could not fetch: {}", failed);
+    }
+    println!("Gas used: {}", stats.gas_used);
+    println!("Fees paid: {} RON", stats.fees_paid);
+    println!("Sponsored transactions: {}", stats.sponsored);
+
+    println!("\nTop counterparties:");
+    for count in &stats.top_counterparties {
+        println!("  {}: {}", count.name, count.transactions);
+    }
+
+    println!("\nTop methods:");
+    for count in &stats.top_methods {
+        println!("  {}: {}", count.name, count.transactions);
+    }
+    println!("  (no decoded method): {}", stats.without_method);
+}
+
+/// `wally stats EXPORT [--json] [--top=N]` summarizes an export on disk without any API requests.
+/// `--report=approvals` adds the token approvals of the wallet, also written to
+/// `<stem>.approvals.json`, `--verify-allowances` checks them on chain. `--report=largest` adds the
+/// `--top` largest transactions in RON and each known token, also written to `<stem>.largest.json`.
+/// Exits with 1 if the export can't be read or was written in a newer format version.
+pub async fn run(args: &[String]) -> i32 {
+    let paths = super::positional(args);
+
+    let [path] = paths.as_slice() else {
+        eprintln!("Usage: wally stats EXPORT [--json] [--top=N] [--report=approvals [--verify-allowances]] [--report=largest]");
+        return 2;
+    };
+    if let Some(report) = ArgParser::all("--report").iter().find(|report| !["approvals", "largest"].contains(&report.as_str())) {
+        eprintln!("Invalid --report value {}, wally stats reports approvals or largest", report);
+        return 2;
+    }
+
+    let limit = match ArgParser::split(&"--top".to_string()) {
+        None => TOP,
+        Some(limit) => match limit.parse() {
+            Ok(limit) => limit,
+            Err(_) => {
+                eprintln!("Invalid --top value {}", limit);
+                return 2;
+            }
+        }
+    };
+
+    let file_name = Path::new(path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let stem = super::stem(&file_name);
+    let manifest = Path::new(path).with_file_name(Manifest::file_name(&stem));
+
+    let manifest = match Manifest::load(&manifest.to_string_lossy()) {
+        Ok(manifest) => manifest,
+        Err(error) => {
+            eprintln!("{}", error);
+            return 1;
+        }
+    };
+    if let Some(manifest) = manifest.as_ref().filter(|manifest| manifest.format_version > FORMAT_VERSION) {
+        eprintln!("{} was written in format version {}, this version of wally reads up to {}", path, manifest.format_version, FORMAT_VERSION);
+        return 1;
+    }
+
+    let result = super::wallet(path, &stem).and_then(|wallet| stats(path, wallet, limit));
+    let mut stats = match result {
+        Ok(stats) => stats,
+        Err(error) => {
+            eprintln!("{}", error);
+            return 1;
+        }
+    };
+    stats.failed_transactions = manifest.map(|manifest| manifest.failed_transactions);
+
+    let json = args.iter().any(|arg| arg == "--json");
+    match json {
+        true => println!("{}", serde_json::to_string_pretty(&stats).unwrap()),
+        false => print(path, &stats)
+    }
+
+    if approvals::report() {
+        let Some(records) = records(path) else {
+            return 1;
+        };
+        let mut report = approvals::scan(records, stats.wallet.as_deref());
+        approvals::verify_from_args(&mut report).await;
+        let file_name = output::Output::from_args().write(None, format!("{}.approvals.json", stem), serde_json::to_string_pretty(&report).unwrap());
+        print_report(json, approvals::lines(&report), format!("The approvals were saved to {}", file_name));
+    }
+
+    if largest::report() {
+        let Some(records) = records(path) else {
+            return 1;
+        };
+        let report = largest::scan(records, stats.wallet.as_deref(), limit);
+        let file_name = output::Output::from_args().write(None, format!("{}.largest.json", stem), serde_json::to_string_pretty(&report).unwrap());
+        print_report(json, largest::lines(&report), format!("The largest transactions were saved to {}", file_name));
+    }
+
+    0
+}
+
+/// The records of the export for a report. The export was read once already, records that don't
+/// parse were reported then.
+fn records(path: &str) -> Option<impl Iterator<Item = RRDecodedTransaction>> {
+    match export::stream_records::<RRDecodedTransaction>(path) {
+        Ok(records) => Some(records.filter_map(Result::ok)),
+        Err(error) => {
+            eprintln!("{}", error);
+            None
+        }
+    }
+}
+
+/// Prints a report after the stats. With `--json` only where it was saved goes to stderr, so the
+/// JSON on stdout stays parseable.
+fn print_report(json: bool, lines: Vec<String>, saved: String) {
+    match json {
+        true => eprintln!("{}", saved),
+        false => {
+            println!();
+            for line in lines {
+                println!("{}", line);
+            }
+            println!("{}", saved);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::{HashSet, VecDeque};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use crate::export::stream_records;
+use crate::{filters, registry, ArgParser, RRDecodedTransaction};
+
+/// The number of transactions shown by default.
+const LINES: usize = 10;
+
+/// How often `--follow` checks the export for new records.
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(2);
+
+const HEADER: [&str; 6] = ["BLOCK", "DIRECTION", "METHOD", "COUNTERPARTY", "VALUE (RON)", "HASH"];
+
+/// The cells of a transaction in the table. Exports have no block timestamps, so transactions are
+/// shown with their block.
+fn row(record: &RRDecodedTransaction, wallet: Option<&str>) -> [String; 6] {
+    let wallet = wallet.or(record.wallet.as_deref());
+
+    let direction = wallet.map_or(record.direction, |wallet| record.exported_direction(wallet));
+    let counterparty = match wallet {
+        Some(wallet) => filters::counterparty(record, wallet),
+        None => &record.to
+    };
+    let value = record.value_ron.clone()
+        .or_else(|| record.value.map(|value| filters::format_units(value, filters::RON_DECIMALS)))
+        .unwrap_or_else(|| "?".to_string());
+
+    [
+        record.block_number.to_string(),
+        serde_json::to_value(direction).ok().and_then(|direction| direction.as_str().map(str::to_string)).unwrap_or_default(),
+        record.input.as_ref().and_then(filters::method_name).unwrap_or("-").to_string(),
+        registry::label(counterparty).unwrap_or(counterparty).to_string(),
+        value,
+        record.hash.clone(),
+    ]
+}
+
+/// Pads the cells to the column widths, the last column isn't padded.
+fn line(cells: &[String; 6], widths: &[usize; 6]) -> String {
+    cells.iter().zip(widths).enumerate()
+        .map(|(column, (cell, width))| match column {
+            5 => cell.clone(),
+            _ => format!("{:<width$}", cell, width = width)
+        })
+        .collect::<Vec<_>>()
+        .join("  ")
+}
+
+fn modified(path: &str) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// `wally tail EXPORT [-n N | --lines=N] [--follow]` prints the most recent transactions of an
+/// export as a table. With `--follow` it keeps printing the transactions added to the export, e.g.
+/// by runs with `--append`, until interrupted.
+pub fn run(args: &[String]) -> i32 {
+    let mut args = args.to_vec();
+
+    // `-n N` as in `tail`.
+    let lines = match args.iter().position(|arg| arg == "-n") {
+        Some(position) => {
+            let value = args.get(position + 1).cloned().unwrap_or_default();
+            args.drain(position..(position + 2).min(args.len()));
+            Some(value)
+        }
+        None => ArgParser::split(&"--lines".to_string())
+    };
+    let lines = match lines {
+        None => LINES,
+        Some(lines) => match lines.parse() {
+            Ok(lines) => lines,
+            Err(_) => {
+                eprintln!("Invalid --lines value {}", lines);
+                return 2;
+            }
+        }
+    };
+
+    let positional = super::positional(&args);
+    let [path] = positional.as_slice() else {
+        eprintln!("Usage: wally tail EXPORT [-n N] [--follow]");
+        return 2;
+    };
+
+    let file_name = Path::new(path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let wallet = match super::wallet(path, &super::stem(&file_name)) {
+        Ok(wallet) => wallet,
+        Err(error) => {
+            eprintln!("{}", error);
+            return 2;
+        }
+    };
+
+    let records = match stream_records::<RRDecodedTransaction>(path) {
+        Ok(records) => records,
+        Err(error) => {
+            eprintln!("{}", error);
+            return 1;
+        }
+    };
+
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut last: VecDeque<RRDecodedTransaction> = VecDeque::new();
+
+    for record in records {
+        let record = match record {
+            Ok(record) => record,
+            Err(error) => {
+                eprintln!("Could not parse {}: {}", path, error);
+                return 1;
+            }
+        };
+        seen.insert(record.hash.to_lowercase());
+
+        last.push_back(record);
+        if last.len() > lines {
+            last.pop_front();
+        }
+    }
+
+    let rows: Vec<[String; 6]> = last.iter().map(|record| row(record, wallet.as_deref())).collect();
+    let mut widths = HEADER.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    let header = HEADER.map(str::to_string);
+    for cells in std::iter::once(&header).chain(&rows) {
+        // Stop once the output is piped into e.g. `head` and it has seen enough.
+        if writeln!(stdout, "{}", line(cells, &widths)).is_err() {
+            return 0;
+        }
+    }
+
+    if !ArgParser::flag("--follow") {
+        return 0;
+    }
+
+    let mut version = modified(path);
+    loop {
+        std::thread::sleep(FOLLOW_INTERVAL);
+
+        let current = modified(path);
+        if current == version {
+            continue;
+        }
+        let Ok(records) = stream_records::<RRDecodedTransaction>(path) else {
+            continue;
+        };
+
+        // The export may be read while it is being appended to, it is read again on the next check.
+        let mut complete = true;
+        for record in records {
+            let Ok(record) = record else {
+                complete = false;
+                break;
+            };
+            if seen.insert(record.hash.to_lowercase()) && writeln!(stdout, "{}", line(&row(&record, wallet.as_deref()), &widths)).is_err() {
+                return 0;
+            }
+        }
+        if complete {
+            version = current;
+        }
+    }
+}
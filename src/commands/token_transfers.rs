@@ -0,0 +1,252 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use serde::Serialize;
+use crate::filters::RecordFilters;
+use crate::provider::WalletDataProvider;
+use crate::transfers::{TokenStandard, TransferRecord, HEADER};
+use crate::{aliases, output, pipeline, provider, ArgParser, RRTransactionHash};
+
+/// A transfer both sources know, but with different amounts.
+#[derive(Serialize)]
+struct ValueMismatch {
+    endpoint: TransferRecord,
+    receipt: TransferRecord,
+}
+
+/// How the transfer endpoints and the receipts disagree, written to
+/// `<wallet>.transfers.reconcile.json`.
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct Reconciliation {
+    matched: usize,
+    only_in_endpoints: Vec<TransferRecord>,
+    only_in_receipts: Vec<TransferRecord>,
+    value_mismatches: Vec<ValueMismatch>,
+    /// Transactions whose receipt couldn't be fetched, their transfers are not compared.
+    failed_transactions: Vec<RRTransactionHash>,
+}
+
+impl Reconciliation {
+    fn is_clean(&self) -> bool {
+        self.only_in_endpoints.is_empty() && self.only_in_receipts.is_empty() && self.value_mismatches.is_empty() && self.failed_transactions.is_empty()
+    }
+}
+
+/// The command line filters that apply to transfers: the counterparty filters, `--contract` for
+/// the token and `--skip-spam` for tokens on the spam list.
+fn keep(filters: &RecordFilters, record: &TransferRecord) -> bool {
+    filters.counterparty.keeps(record.counterparty())
+        && filters.contract.as_ref().is_none_or(|contract| contract.contains(&record.token))
+        && filters.spam.as_ref().is_none_or(|spam| !spam.is_listed(&record.token))
+}
+
+/// Pairs the transfers of both sources by transaction, token, sender, recipient and token ID,
+/// then by amount. Unpaired transfers of a key present on both sides are value mismatches.
+fn compare(endpoints: &[TransferRecord], receipts: Vec<TransferRecord>, reconciliation: &mut Reconciliation) {
+    let mut pending: HashMap<_, Vec<TransferRecord>> = HashMap::new();
+    for record in receipts {
+        pending.entry(record.key()).or_default().push(record);
+    }
+
+    let mut unpaired = vec![];
+    for record in endpoints {
+        let candidates = pending.entry(record.key()).or_default();
+        match candidates.iter().position(|candidate| candidate.value == record.value) {
+            Some(position) => {
+                candidates.remove(position);
+                reconciliation.matched += 1;
+            }
+            None => unpaired.push(record.clone())
+        }
+    }
+
+    for record in unpaired {
+        match pending.get_mut(&record.key()).filter(|candidates| !candidates.is_empty()) {
+            Some(candidates) => reconciliation.value_mismatches.push(ValueMismatch { endpoint: record, receipt: candidates.remove(0) }),
+            None => reconciliation.only_in_endpoints.push(record)
+        }
+    }
+
+    let mut only_in_receipts: Vec<TransferRecord> = pending.into_values().flatten().collect();
+    only_in_receipts.sort_by(|a, b| (a.block_number, &a.transaction_hash, a.log_index).cmp(&(b.block_number, &b.transaction_hash, b.log_index)));
+    reconciliation.only_in_receipts = only_in_receipts;
+}
+
+/// Derives the transfers of the wallet from the receipts of all its transactions and of the
+/// transactions the endpoints name, then compares them with the endpoints.
+async fn reconcile(rr: Arc<dyn WalletDataProvider>, address: &str, endpoints: &[TransferRecord], keep: impl Fn(&TransferRecord) -> bool, interrupted: Arc<AtomicBool>) -> Reconciliation {
+    let mut hashes: BTreeSet<RRTransactionHash> = endpoints.iter().map(|record| record.transaction_hash.clone()).collect();
+    for list in [rr.list_sent(address).await, rr.list_received(address).await] {
+        for issue in &list.issues {
+            println!("Warning: {}", issue);
+        }
+        hashes.extend(list.transactions.iter().map(|hash| hash.to_lowercase()));
+    }
+
+    let options = pipeline::Options { max_attempts: pipeline::tx_max_attempts(), decode_input: false, decode_output: true, raw_logs: false, max_blob_bytes: None, verify_hashes: false, decode_genes: false };
+    let progress = super::blocks::progress_bar(hashes.len(), "receipts");
+    let mut transactions = pipeline::spawn(rr, hashes.into_iter().collect(), options, interrupted);
+
+    let mut reconciliation = Reconciliation::default();
+    let mut receipts = vec![];
+
+    while let Some(fetched) = transactions.recv().await {
+        match (&fetched.output, fetched.fetch_failed()) {
+            (Some(receipt), false) => receipts.extend(TransferRecord::from_receipt(&fetched.hash, fetched.tx.block_number, receipt, address).into_iter().filter(&keep)),
+            _ => reconciliation.failed_transactions.push(fetched.hash)
+        }
+        progress.inc(1);
+    }
+    progress.finish();
+
+    reconciliation.failed_transactions.sort();
+    let failed: BTreeSet<&RRTransactionHash> = reconciliation.failed_transactions.iter().collect();
+    let endpoints: Vec<TransferRecord> = endpoints.iter().filter(|record| !failed.contains(&record.transaction_hash)).cloned().collect();
+
+    compare(&endpoints, receipts, &mut reconciliation);
+    reconciliation
+}
+
+/// `wally token-transfers --address=ADDRESS... [--standard=erc20,erc721,erc1155]
+/// [--format=json|csv] [--reconcile]` lists the token transfers of wallets from the transfer
+/// endpoints instead of reading them from every receipt, writing `<wallet>.transfers.json`. The
+/// counterparty filters, `--contract` and `--skip-spam` apply. `--reconcile` compares them with
+/// the transfers in the receipts of the wallet's transactions and exits with 1 on discrepancies.
+pub async fn run(args: &[String]) -> i32 {
+    let usage = "Usage: wally token-transfers --address=ADDRESS [--standard=erc20,erc721,erc1155] [--format=json|csv] [--reconcile]";
+    let addresses = ArgParser::all("--address");
+
+    if addresses.is_empty() || !super::positional(args).is_empty() {
+        eprintln!("{}", usage);
+        return 2;
+    }
+
+    let writer = output::Output::from_args();
+    let csv = match writer.format {
+        output::Format::Json => false,
+        output::Format::Csv(_) => true,
+        _ => {
+            eprintln!("wally token-transfers writes --format=json or --format=csv");
+            return 2;
+        }
+    };
+
+    for option in ["--filter", "--event", "--event-topic", "--min-value"] {
+        if !ArgParser::all(option).is_empty() {
+            eprintln!("{} applies to transactions, not to token transfers", option);
+            return 2;
+        }
+    }
+
+    let standards: Vec<TokenStandard> = match ArgParser::all("--standard") {
+        standards if standards.is_empty() => TokenStandard::ALL.to_vec(),
+        standards => match standards.iter().flat_map(|standards| standards.split(',')).map(TokenStandard::parse).collect::<Result<BTreeSet<_>, _>>() {
+            Ok(standards) => standards.into_iter().collect(),
+            Err(error) => {
+                eprintln!("{}", error);
+                return 2;
+            }
+        }
+    };
+
+    let wallets = match addresses.iter().map(|address| aliases::WalletArg::parse(address)).collect::<Result<Vec<_>, _>>().and_then(aliases::deduplicate) {
+        Ok(wallets) => wallets,
+        Err(error) => {
+            eprintln!("{}", error);
+            return 2;
+        }
+    };
+
+    let filters = RecordFilters::from_args();
+    let reconcile = ArgParser::flag("--reconcile");
+    let interrupted = pipeline::interrupt_on_ctrl_c();
+
+    let rr = provider::from_args();
+    if !rr.capabilities().token_transfers {
+        eprintln!("The {} provider doesn't deliver token transfer lists", rr.name());
+        return 2;
+    }
+    if reconcile && !rr.capabilities().decode {
+        eprintln!("--reconcile needs the decoded receipts, which the {} provider doesn't deliver", rr.name());
+        return 2;
+    }
+
+    if !ArgParser::flag("--skip-health-check") {
+        if let Err(error) = rr.health_check().await {
+            eprintln!("{}", error);
+            return 2;
+        }
+    }
+
+    let mut code = 0;
+
+    for wallet in &wallets {
+        let address = wallet.address.to_lowercase();
+        let stem = wallet.alias.clone().unwrap_or_else(|| address.clone());
+        let mut records = vec![];
+        let mut complete = true;
+
+        for standard in &standards {
+            let list = match rr.token_transfers(*standard, &address).await {
+                Ok(list) => list,
+                Err(error) => {
+                    eprintln!("Could not list the {:?} transfers of {}: {}", standard, address, error);
+                    complete = false;
+                    continue;
+                }
+            };
+
+            for issue in &list.issues {
+                println!("Warning: {}", issue);
+            }
+            for entry in &list.transfers {
+                match TransferRecord::from_api(*standard, entry, &address) {
+                    Ok(record) => records.push(record),
+                    Err(reason) => println!("Warning: dropped the {:?} transfer {} of {}: {}", standard, entry, address, reason)
+                }
+            }
+        }
+
+        records.sort_by(|a, b| (a.block_number, &a.transaction_hash, a.log_index).cmp(&(b.block_number, &b.transaction_hash, b.log_index)));
+        let listed = records.len();
+        records.retain(|record| keep(&filters, record));
+
+        let contents = match csv {
+            true => std::iter::once(HEADER.to_string()).chain(records.iter().map(TransferRecord::csv_row)).map(|line| line + "\n").collect(),
+            false => serde_json::to_string_pretty(&records).unwrap()
+        };
+        let written = writer.write(Some(&stem), format!("{}.transfers.{}", stem, if csv { "csv" } else { "json" }), contents);
+        println!("{} of the {} token transfers of {} were saved to {}", records.len(), listed, address, written);
+
+        if !complete {
+            code = 1;
+            continue;
+        }
+        if !reconcile {
+            continue;
+        }
+
+        let reconciliation = self::reconcile(rr.clone(), &address, &records, |record| standards.contains(&record.standard) && keep(&filters, record), interrupted.clone()).await;
+        if interrupted.load(Ordering::SeqCst) {
+            println!("Interrupted, the transfers of {} were saved but not reconciled", address);
+            return 1;
+        }
+
+        println!(
+            "Reconciled with the receipts: {} transfers match, {} are only in the endpoints, {} only in the receipts and {} differ in value",
+            reconciliation.matched, reconciliation.only_in_endpoints.len(), reconciliation.only_in_receipts.len(), reconciliation.value_mismatches.len()
+        );
+
+        if !reconciliation.is_clean() {
+            let report = writer.write(Some(&stem), format!("{}.transfers.reconcile.json", stem), serde_json::to_string_pretty(&reconciliation).unwrap());
+            if !reconciliation.failed_transactions.is_empty() {
+                println!("{} transactions could not be fetched and were left out", reconciliation.failed_transactions.len());
+            }
+            println!("The discrepancies are listed in {}", report);
+            code = 1;
+        }
+    }
+
+    code
+}
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::path::Path;
+use jsonschema::JSONSchema;
+use serde_json::Value;
+use crate::export::{read_records, FORMAT_VERSION};
+use crate::manifest::Manifest;
+use crate::{checksum, filters, schema, ArgParser};
+
+/// Offenders listed per problem, the rest is only counted.
+const MAX_EXAMPLES: usize = 5;
+
+/// The exit code when there are warnings but nothing invalid.
+const WARNINGS: i32 = 3;
+
+/// The order of exports, see `sort::SortKey`.
+type SortKey = (u64, Option<u64>, String);
+
+/// A class of problems, counted with a few examples.
+struct Problem {
+    name: &'static str,
+    /// Whether the export is invalid because of it, or only suspicious.
+    invalid: bool,
+    count: usize,
+    examples: Vec<String>,
+}
+
+impl Problem {
+    fn new(name: &'static str, invalid: bool) -> Problem {
+        Problem { name, invalid, count: 0, examples: vec![] }
+    }
+
+    fn add(&mut self, example: impl FnOnce() -> String) {
+        self.count += 1;
+        if self.examples.len() < MAX_EXAMPLES {
+            self.examples.push(example());
+        }
+    }
+}
+
+fn field<'a>(record: &'a Value, key: &str) -> Option<&'a str> {
+    record.get(key).and_then(Value::as_str)
+}
+
+fn position((block, index, _): &SortKey) -> String {
+    match index {
+        Some(index) => format!("block {} index {}", block, index),
+        None => format!("block {}", block)
+    }
+}
+
+/// Whether the wallet sent or received the transaction or appears in its decoded payloads.
+fn involves(record: &Value, wallet: &str) -> bool {
+    ["from", "to", "input", "output", "rawLogs"].iter().any(|key| record.get(key).is_some_and(|value| filters::mentions(value, wallet)))
+}
+
+/// `wally validate EXPORT [--schema=FILE] [FLAGS]` checks an export for records failing a JSON
+/// Schema, by default the one `wally schema FLAGS` prints, duplicate hashes, records out of order
+/// or not involving the wallet, and disagreements with its checksums and manifest. Exits with 0
+/// when the export is clean, 3 when there are only warnings and 1 when it is invalid.
+pub fn run(args: &[String]) -> i32 {
+    let positional = super::positional(args);
+
+    if positional.len() != 1 {
+        eprintln!("Usage: wally validate EXPORT [--schema=FILE] [--address=ADDRESS] [--raw-logs] [--no-decode-input] [--no-decode-output] [--merge]");
+        return 2;
+    }
+
+    let path = positional[0];
+
+    let schema: Value = match ArgParser::split(&"--schema".to_string()) {
+        Some(schema_file) => {
+            let contents = std::fs::read_to_string(&schema_file).unwrap_or_else(|error| panic!("Could not read {}: {}", schema_file, error));
+            serde_json::from_str(&contents).unwrap_or_else(|error| panic!("Could not parse {}: {}", schema_file, error))
+        }
+        None => schema::from_args()
+    };
+
+    let compiled = match JSONSchema::compile(&schema) {
+        Ok(compiled) => compiled,
+        Err(error) => {
+            eprintln!("Invalid schema: {}", error);
+            return 2;
+        }
+    };
+
+    let file_name = Path::new(path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let stem = super::stem(&file_name);
+    let manifest_path = Path::new(path).with_file_name(Manifest::file_name(&stem)).to_string_lossy().to_string();
+
+    let mut unreadable = Problem::new("Unreadable files", true);
+    let mut invalid_records = Problem::new("Records failing the schema", true);
+    let mut duplicates = Problem::new("Duplicate hashes", true);
+    let mut unordered = Problem::new("Records out of block order", true);
+    let mut checksums = Problem::new("Checksum mismatches", true);
+    let mut manifest_mismatches = Problem::new("Disagreements with the manifest", true);
+    let mut unrelated = Problem::new("Records not involving the wallet", false);
+    let mut missing_files = Problem::new("Files of the manifest not found", false);
+
+    let manifest = Manifest::load(&manifest_path).unwrap_or_else(|error| {
+        unreadable.add(|| error);
+        None
+    });
+    let wallet = match super::wallet(path, &stem) {
+        Ok(wallet) => wallet,
+        // An unreadable manifest is reported with the other problems.
+        Err(_) if unreadable.count > 0 => None,
+        Err(error) => {
+            eprintln!("{}", error);
+            return 2;
+        }
+    };
+
+    let mut records = 0;
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut last: Option<SortKey> = None;
+    let mut first_block: Option<u64> = None;
+    let mut last_block: Option<u64> = None;
+
+    for record in read_records::<Value>(path) {
+        let record = match record {
+            Ok(record) => record,
+            Err(error) => {
+                unreadable.add(|| format!("after record {}: {}", records, error));
+                break;
+            }
+        };
+        records += 1;
+
+        let hash = field(&record, "hash").unwrap_or("without hash").to_lowercase();
+        let block = record.get("blockNumber").and_then(Value::as_u64).unwrap_or(0);
+
+        if let Err(errors) = compiled.validate(&record) {
+            let errors: Vec<String> = errors.map(|error| match error.instance_path.to_string().as_str() {
+                "" => error.to_string(),
+                path => format!("{}: {}", path, error)
+            }).collect();
+            invalid_records.add(|| format!("record {} ({}): {}", records, hash, errors.join("; ")));
+        }
+
+        match seen.get(&hash) {
+            Some(first) => duplicates.add(|| format!("{} (records {} and {})", hash, first, records)),
+            None => {
+                seen.insert(hash.clone(), records);
+            }
+        }
+
+        let key = (block, record.get("transactionIndex").and_then(Value::as_u64), hash.clone());
+        if last.as_ref().is_some_and(|last| *last > key) {
+            unordered.add(|| format!("record {} ({}) at {} follows {}", records, hash, position(&key), position(last.as_ref().unwrap())));
+        }
+        last = Some(key);
+
+        first_block = Some(first_block.map_or(block, |first| first.min(block)));
+        last_block = Some(last_block.map_or(block, |last| last.max(block)));
+
+        // Merged exports name the wallet of every record.
+        if let Some(wallet) = wallet.as_deref().or_else(|| field(&record, "wallet")) {
+            if !involves(&record, wallet) {
+                unrelated.add(|| format!("record {} ({}) from {} to {}", records, hash, field(&record, "from").unwrap_or("?"), field(&record, "to").unwrap_or("?")));
+            }
+        }
+    }
+
+    let sidecar = checksum::sidecar_path(path);
+    if let Ok(contents) = std::fs::read_to_string(&sidecar) {
+        let actual = checksum::of_file(path).ok();
+        if checksum::parse_sidecar(&contents) != actual {
+            checksums.add(|| format!("{} does not match {}", path, sidecar));
+        }
+    }
+
+    if let Some(manifest) = &manifest {
+        if manifest.format_version > FORMAT_VERSION {
+            manifest_mismatches.add(|| format!("format version {}, this version of wally reads up to {}", manifest.format_version, FORMAT_VERSION));
+        }
+        if manifest.coverage.records.total != records {
+            manifest_mismatches.add(|| format!("{} records according to the manifest, {} in the export", manifest.coverage.records.total, records));
+        }
+        if (manifest.coverage.first_block, manifest.coverage.last_block) != (first_block, last_block) {
+            manifest_mismatches.add(|| format!(
+                "blocks {:?}–{:?} according to the manifest, {:?}–{:?} in the export",
+                manifest.coverage.first_block, manifest.coverage.last_block, first_block, last_block
+            ));
+        }
+
+        for file in &manifest.files {
+            let file_path = Path::new(path).with_file_name(&file.file).to_string_lossy().to_string();
+            match checksum::of_file(&file_path) {
+                Ok(sha256) if sha256 == file.sha256 => {}
+                Ok(_) => checksums.add(|| format!("{} does not match the manifest", file_path)),
+                Err(_) => missing_files.add(|| file_path.clone())
+            }
+        }
+    }
+
+    println!("{}: {} records", path, records);
+    match &manifest {
+        Some(_) => println!("Manifest: {}", manifest_path),
+        None => println!("Manifest: none, its checks were skipped")
+    }
+
+    let problems = [unreadable, invalid_records, duplicates, unordered, checksums, manifest_mismatches, unrelated, missing_files];
+    for problem in &problems {
+        let severity = match problem.invalid {
+            true => "",
+            false => " (warning)"
+        };
+        println!("{}{}: {}", problem.name, severity, problem.count);
+        for example in &problem.examples {
+            println!("  {}", example);
+        }
+        if problem.count > problem.examples.len() {
+            println!("  ... and {} more", problem.count - problem.examples.len());
+        }
+    }
+
+    let invalid = problems.iter().any(|problem| problem.invalid && problem.count > 0);
+    let warnings = problems.iter().any(|problem| problem.count > 0);
+
+    match (invalid, warnings) {
+        (true, _) => {
+            println!("The export is invalid");
+            1
+        }
+        (false, true) => {
+            println!("The export is valid, with warnings");
+            WARNINGS
+        }
+        (false, false) => {
+            println!("The export is clean");
+            0
+        }
+    }
+}
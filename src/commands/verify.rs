@@ -0,0 +1,127 @@
+use std::collections::{HashMap, HashSet};
+use rand::seq::SliceRandom;
+use web3::types::Address;
+use crate::export::read_records;
+use crate::provider;
+use crate::{normalize_address, ArgParser, RRTransactionHash};
+
+const DEFAULT_SAMPLES: usize = 20;
+
+/// `wally verify EXPORT ADDRESS [--samples=N]` checks an export against the archive of the API.
+/// Exits with 0 when the export is complete and the spot check found no mismatches.
+pub async fn run(args: &[String]) -> i32 {
+    let positional = super::positional(args);
+
+    if positional.len() != 2 {
+        eprintln!("Usage: wally verify EXPORT ADDRESS [--samples=N] [--localhost[=PORT|URL]]");
+        return 2;
+    }
+
+    let path = positional[0];
+    let address = normalize_address(positional[1]);
+
+    if address.parse::<Address>().is_err() {
+        eprintln!("Could not parse address {}!", positional[1]);
+        return 2;
+    }
+
+    let samples: usize = match ArgParser::split(&"--samples".to_string()) {
+        None => DEFAULT_SAMPLES,
+        Some(samples) => samples.parse().unwrap_or_else(|_| panic!("Invalid --samples value {}", samples))
+    };
+
+    // Only hashes and block numbers are kept in memory.
+    let mut exported: HashMap<RRTransactionHash, u64> = HashMap::new();
+
+    for record in read_records::<serde_json::Value>(path) {
+        let record = record.unwrap_or_else(|error| panic!("Could not parse {}: {}", path, error));
+
+        if let Some(hash) = record.get("hash").and_then(serde_json::Value::as_str) {
+            let block = record.get("blockNumber").and_then(serde_json::Value::as_u64).unwrap_or(0);
+            exported.insert(hash.to_lowercase(), block);
+        }
+    }
+
+    let rr = provider::from_args();
+
+    if !ArgParser::flag("--skip-health-check") {
+        if let Err(error) = rr.health_check().await {
+            eprintln!("{}", error);
+            return 2;
+        }
+    }
+
+    let mut archived: HashSet<RRTransactionHash> = HashSet::new();
+
+    for list in [rr.list_sent(&address).await, rr.list_received(&address).await] {
+        for issue in &list.issues {
+            println!("Warning: {}", issue);
+        }
+        for rejected in &list.rejected {
+            println!("Warning: dropped the entry '{}' of {}: {}", rejected.entry, rejected.list, rejected.reason);
+        }
+        archived.extend(list.transactions.iter().map(|hash| hash.to_lowercase()));
+    }
+
+    let mut missing: Vec<&RRTransactionHash> = archived.iter().filter(|hash| !exported.contains_key(*hash)).collect();
+    let mut unknown: Vec<&RRTransactionHash> = exported.keys().filter(|hash| !archived.contains(*hash)).collect();
+    missing.sort();
+    unknown.sort();
+
+    // Self transfers are never exported, so they are not an incomplete export.
+    let mut self_transfers = 0;
+    let mut really_missing = vec![];
+
+    for hash in missing {
+        let tx = rr.get_transaction(hash).await.unwrap_or_else(|error| panic!("{}", error));
+        match tx.to == tx.from && tx.from.eq_ignore_ascii_case(&address) {
+            true => self_transfers += 1,
+            false => really_missing.push(hash)
+        }
+    }
+
+    let mut candidates: Vec<(&RRTransactionHash, &u64)> = exported.iter().filter(|(hash, _)| archived.contains(*hash)).collect();
+    candidates.sort();
+    let sample: Vec<_> = candidates.choose_multiple(&mut rand::thread_rng(), samples).collect();
+
+    let mut mismatches = vec![];
+    let mut unchecked = 0;
+
+    for (hash, block) in &sample {
+        let tx = rr.get_transaction(hash).await.unwrap_or_else(|error| panic!("{}", error));
+
+        if tx.to == "null" && tx.from == "null" {
+            unchecked += 1;
+        } else if tx.block_number != **block {
+            mismatches.push((hash, **block, tx.block_number));
+        }
+    }
+
+    println!("Exported transactions: {}\nArchived transactions: {}", exported.len(), archived.len());
+
+    println!("\nMissing from the export: {} (plus {} self transfers which are never exported)", really_missing.len(), self_transfers);
+    for hash in &really_missing {
+        println!("  {}", hash);
+    }
+
+    println!("\nNo longer returned by the API: {}", unknown.len());
+    for hash in &unknown {
+        println!("  {}", hash);
+    }
+
+    println!("\nSpot checked block numbers: {} ({} could not be retrieved)", sample.len(), unchecked);
+    for (hash, exported, actual) in &mismatches {
+        println!("  {}: exported {}, API {}", hash, exported, actual);
+    }
+
+    match really_missing.is_empty() && unknown.is_empty() && mismatches.is_empty() {
+        true => {
+            println!("\nThe export is complete.");
+            0
+        }
+        false => {
+            println!("\nThe export is NOT complete.");
+            1
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::sync::OnceLock;
+use crate::ArgParser;
+
+/// Whether responses are requested compressed, `--no-compression` turns it off.
+pub fn enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| !ArgParser::flag("--no-compression"))
+}
+
+/// A builder for the API clients. reqwest asks for gzip, brotli and deflate compressed responses
+/// and decompresses them as they arrive, unless `--no-compression` is passed.
+pub fn client() -> reqwest::ClientBuilder {
+    reqwest::Client::builder().gzip(enabled()).brotli(enabled()).deflate(enabled())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use super::*;
+
+    /// Answers one request with `body` gzip compressed, returning the URL and the request.
+    async fn gzip_server(body: &[u8]) -> (String, tokio::task::JoinHandle<String>) {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(body).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let request = tokio::spawn(async move {
+            let (mut connection, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let read = connection.read(&mut request).await.unwrap();
+
+            let head = format!("HTTP/1.1 200 OK\r\ncontent-encoding: gzip\r\ncontent-length: {}\r\n\r\n", compressed.len());
+            connection.write_all(&[head.as_bytes(), &compressed].concat()).await.unwrap();
+            String::from_utf8_lossy(&request[..read]).to_lowercase()
+        });
+
+        (url, request)
+    }
+
+    #[tokio::test]
+    async fn responses_are_requested_compressed_and_decompressed() {
+        let body = serde_json::json!({ "transactions": vec!["0x1"; 1000] }).to_string();
+        let (url, request) = gzip_server(body.as_bytes()).await;
+
+        let response = client().build().unwrap().get(url).send().await.unwrap();
+
+        assert_eq!(response.text().await.unwrap(), body);
+        assert!(request.await.unwrap().contains("accept-encoding: gzip, br, deflate"));
+    }
+}
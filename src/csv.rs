@@ -0,0 +1,341 @@
+use serde_json::Value;
+use crate::{filters, ArgParser, Direction};
+
+/// A column of the CSV export.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Hash,
+    BlockNumber,
+    TransactionIndex,
+    Nonce,
+    From,
+    To,
+    Value,
+    ValueRon,
+    Direction,
+    Counterparty,
+    Status,
+    Method,
+    GasUsed,
+    GasPrice,
+    EffectiveGasPrice,
+    FeeRon,
+    FeePayer,
+    BalanceAfter,
+    Confirmations,
+    Input,
+    Output,
+    RawLogs,
+    Wallet,
+    Wallets,
+}
+
+/// All columns by name, in the order of `--columns=all`.
+const COLUMNS: &[(&str, Column)] = &[
+    ("hash", Column::Hash),
+    ("block_number", Column::BlockNumber),
+    ("transaction_index", Column::TransactionIndex),
+    ("nonce", Column::Nonce),
+    ("from", Column::From),
+    ("to", Column::To),
+    ("value", Column::Value),
+    ("value_ron", Column::ValueRon),
+    ("direction", Column::Direction),
+    ("counterparty", Column::Counterparty),
+    ("status", Column::Status),
+    ("method", Column::Method),
+    ("gas_used", Column::GasUsed),
+    ("gas_price", Column::GasPrice),
+    ("effective_gas_price", Column::EffectiveGasPrice),
+    ("fee_ron", Column::FeeRon),
+    ("fee_payer", Column::FeePayer),
+    ("balance_after", Column::BalanceAfter),
+    ("confirmations", Column::Confirmations),
+    ("input", Column::Input),
+    ("output", Column::Output),
+    ("raw_logs", Column::RawLogs),
+    ("wallet", Column::Wallet),
+    ("wallets", Column::Wallets),
+];
+
+/// The columns without `--columns`. Existing spreadsheets rely on them, so they must not change.
+const DEFAULT_COLUMNS: &[Column] = &[
+    Column::Hash,
+    Column::BlockNumber,
+    Column::From,
+    Column::To,
+    Column::Direction,
+    Column::Status,
+    Column::Method,
+];
+
+/// The address on the other side of a serialized record.
+pub fn counterparty<'a>(record: &'a Value, wallet: &str) -> Option<&'a Value> {
+    let from = record.get("from").and_then(Value::as_str).unwrap_or_default();
+    record.get(if from.eq_ignore_ascii_case(wallet) { "to" } else { "from" })
+}
+
+/// How deep `--flatten-input` expands objects in parameters by default.
+const DEFAULT_FLATTEN_DEPTH: usize = 2;
+
+/// Quotes a field if it contains a separator, quote or line break.
+pub fn escape(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string()
+    }
+}
+
+/// Scalars are written as they are, `null` as an empty cell and anything else JSON encoded.
+fn cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Number(number)) => number.to_string(),
+        Some(Value::Bool(flag)) => flag.to_string(),
+        Some(value) => value.to_string()
+    }
+}
+
+/// Adds the cells of a decoded parameter at `path`. Objects are expanded into dotted paths up to
+/// `depth` levels, deeper objects and arrays stay JSON encoded in a single cell.
+fn flatten<'a>(path: String, value: &'a Value, depth: usize, cells: &mut Vec<(String, &'a Value)>) {
+    match value {
+        Value::Object(map) if depth > 0 && !map.is_empty() => {
+            for (key, value) in map {
+                flatten(format!("{}.{}", path, key), value, depth - 1, cells);
+            }
+        }
+        _ => cells.push((path, value))
+    }
+}
+
+fn column(name: &str) -> Option<Column> {
+    COLUMNS.iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(name))
+        .map(|(_, column)| *column)
+}
+
+fn column_names() -> String {
+    COLUMNS.iter().map(|(known, _)| *known).collect::<Vec<&str>>().join(", ")
+}
+
+fn parse(text: &str) -> Result<Vec<Column>, String> {
+    if text.trim().eq_ignore_ascii_case("all") {
+        return Ok(COLUMNS.iter().map(|(_, column)| *column).collect());
+    }
+
+    text.split(',').map(str::trim).filter(|name| !name.is_empty()).map(|name| {
+        column(name).ok_or_else(|| format!("Unknown column '{}' in --columns, expected all or some of {}", name, column_names()))
+    }).collect()
+}
+
+/// A piece of a `--csv-template` row.
+enum Part {
+    Text(String),
+    Field(Column),
+}
+
+/// Parses a `--csv-template` such as `{hash};{value_ron}`. Placeholders name columns, `{{` and
+/// `}}` are literal braces.
+fn parse_template(template: &str) -> Result<Vec<Part>, String> {
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                let field = column(name.trim()).ok_or_else(|| format!(
+                    "Unknown placeholder {{{}}} in --csv-template, expected one of {}", name, column_names()
+                ))?;
+
+                if !text.is_empty() {
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                }
+                parts.push(Part::Field(field));
+            }
+            '}' => return Err("Unmatched } in --csv-template, use }} for a literal brace".to_string()),
+            c => text.push(c)
+        }
+    }
+
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+    Ok(parts)
+}
+
+fn name(column: &Column) -> &'static str {
+    COLUMNS.iter().find(|(_, known)| known == column).unwrap().0
+}
+
+/// The selected columns of a CSV export.
+pub struct Columns {
+    columns: Vec<Column>,
+    /// The depth of `--flatten-input`, unset without it.
+    flatten_depth: Option<usize>,
+    /// The `param.*` columns found with `--flatten-input`, in the order of their first appearance.
+    params: Vec<String>,
+    /// Rows are formatted by `--csv-template` instead, with `--csv-header` as the header.
+    template: Option<(Vec<Part>, String)>,
+}
+
+impl Columns {
+    pub fn from_args() -> Columns {
+        let columns = match ArgParser::split(&"--columns".to_string()) {
+            None => DEFAULT_COLUMNS.to_vec(),
+            Some(columns) => parse(&columns).unwrap_or_else(|error| panic!("{}", error))
+        };
+
+        let flatten_depth = match ArgParser::split(&"--flatten-depth".to_string()) {
+            Some(depth) if ArgParser::flag("--flatten-input") => Some(depth.parse().unwrap_or_else(|_| panic!("Invalid --flatten-depth value {}", depth))),
+            Some(_) => panic!("--flatten-depth requires --flatten-input"),
+            None => ArgParser::flag("--flatten-input").then_some(DEFAULT_FLATTEN_DEPTH)
+        };
+
+        let template = ArgParser::all("--csv-template").pop().map(|template| {
+            let parts = parse_template(&template).unwrap_or_else(|error| panic!("{}", error));
+            if ArgParser::split(&"--columns".to_string()).is_some() {
+                panic!("--csv-template can not be combined with --columns");
+            }
+            if flatten_depth.is_some() {
+                panic!("--csv-template can not be combined with --flatten-input");
+            }
+
+            // Without --csv-header the header is the template with the column names filled in.
+            let header = ArgParser::all("--csv-header").pop().unwrap_or_else(|| parts.iter().map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Field(column) => name(column).to_string()
+            }).collect());
+
+            (parts, header)
+        });
+        if template.is_none() && !ArgParser::all("--csv-header").is_empty() {
+            panic!("--csv-header requires --csv-template");
+        }
+
+        Columns { columns, flatten_depth, params: vec![], template }
+    }
+
+    pub fn flattens_input(&self) -> bool {
+        self.flatten_depth.is_some()
+    }
+
+    fn input_cells<'a>(&self, input: Option<&'a Value>) -> Vec<(String, &'a Value)> {
+        let mut cells = vec![];
+
+        if let (Some(depth), Some(input)) = (self.flatten_depth, input) {
+            for (name, value) in filters::params(input) {
+                flatten(format!("param.{}", name), value, depth, &mut cells);
+            }
+        }
+
+        cells
+    }
+
+    /// Adds the parameter columns of a decoded input with `--flatten-input`. All records have to
+    /// be added before the header is written.
+    pub fn add_params(&mut self, input: Option<&Value>) {
+        for (path, _) in self.input_cells(input) {
+            if !self.params.contains(&path) {
+                self.params.push(path);
+            }
+        }
+    }
+
+    pub fn header(&self) -> String {
+        if let Some((_, header)) = &self.template {
+            return header.clone();
+        }
+
+        self.columns.iter()
+            .map(|column| name(column))
+            .chain(self.params.iter().map(String::as_str))
+            .map(escape)
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+
+    /// Formats a serialized record as a row. `wallet` is the address (or pseudonym) the export
+    /// belongs to and its direction, unset in merged exports which record the wallet of every
+    /// transaction.
+    pub fn row(&self, record: &Value, wallet: Option<(&str, Direction)>) -> String {
+        if let Some((parts, _)) = &self.template {
+            return parts.iter().map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Field(column) => escape(&field(*column, record, wallet))
+            }).collect();
+        }
+
+        let params = self.input_cells(record.get("input"));
+
+        let cells = self.columns.iter().map(|column| field(*column, record, wallet)).chain(self.params.iter().map(|path| {
+            cell(params.iter().find(|(param, _)| param == path).map(|(_, value)| *value))
+        }));
+
+        cells.map(|field| escape(&field)).collect::<Vec<String>>().join(",")
+    }
+}
+
+/// The wallet a serialized record belongs to, its direction and the counterparty, as passed or
+/// as recorded in merged exports. The attributed wallet of merged exports may be an alias, it is
+/// the sender if the sender is one of the wallets.
+pub fn attribution<'a>(record: &'a Value, wallet: Option<(&'a str, Direction)>) -> (Option<&'a str>, Option<Value>, Option<&'a Value>) {
+    match wallet {
+        Some((wallet, direction)) => (Some(wallet), serde_json::to_value(direction).ok(), counterparty(record, wallet)),
+        None => {
+            let from = record.get("from").and_then(Value::as_str).unwrap_or_default();
+            let sent = record.get("wallets").and_then(Value::as_array).is_some_and(|wallets| {
+                wallets.iter().filter_map(Value::as_str).any(|wallet| wallet.eq_ignore_ascii_case(from))
+            });
+            let counterparty = record.get("wallet").and_then(|_| record.get(if sent { "to" } else { "from" }));
+
+            (record.get("wallet").and_then(Value::as_str), record.get("direction").cloned(), counterparty)
+        }
+    }
+}
+
+/// The cell of a column for a serialized record.
+fn field(column: Column, record: &Value, wallet: Option<(&str, Direction)>) -> String {
+    let (wallet, direction, counterparty) = attribution(record, wallet);
+
+    match column {
+        Column::Hash => cell(record.get("hash")),
+        Column::BlockNumber => cell(record.get("blockNumber")),
+        Column::TransactionIndex => cell(record.get("transactionIndex")),
+        Column::Nonce => cell(record.get("nonce")),
+        Column::From => cell(record.get("from")),
+        Column::To => cell(record.get("to")),
+        Column::Value if filters::raw_amounts() => cell(record.get("value")),
+        Column::Value | Column::ValueRon => record.get("value")
+            .and_then(crate::parse_amount)
+            .map(|value| filters::format_units(value, filters::RON_DECIMALS))
+            .unwrap_or_default(),
+        Column::Direction => cell(direction.as_ref()),
+        Column::Counterparty => cell(counterparty),
+        Column::Status => cell(record.get("status")),
+        Column::Method => record.get("input").and_then(filters::method_name).unwrap_or_default().to_string(),
+        Column::GasUsed => cell(record.get("gasUsed")),
+        Column::GasPrice => cell(record.get("gasPrice")),
+        Column::EffectiveGasPrice => cell(record.get("effectiveGasPrice")),
+        Column::FeeRon => cell(record.get("feeRon")),
+        Column::FeePayer => cell(record.get("feePayer")),
+        Column::BalanceAfter => cell(record.get("balanceAfter")),
+        Column::Confirmations => cell(record.get("confirmations")),
+        Column::Input => cell(record.get("input")),
+        Column::Output => cell(record.get("output")),
+        Column::RawLogs => cell(record.get("rawLogs")),
+        Column::Wallet => wallet.unwrap_or_default().to_string(),
+        Column::Wallets => cell(record.get("wallets")),
+    }
+}
@@ -0,0 +1,145 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use serde_json::Value;
+use crate::accounting::{self, Event, Kind, Leg};
+use crate::csv::escape;
+
+/// The columns of the cryptotaxcalculator.io universal CSV import.
+pub const HEADER: &str = "Timestamp (UTC),Type,Base Currency,Base Amount,Quote Currency,Quote Amount,Fee Currency,Fee Amount,From,To,ID";
+
+/// The tickers cryptotaxcalculator.io expects for the well-known Ronin tokens, where they differ
+/// from the token symbol. Wrapped RON is its own currency, wrapping it is a trade.
+const TICKERS: &[(&str, &str)] = &[
+    ("WETH", "ETH"),
+];
+
+/// The transfers of NFTs and unknown tokens left out of the rows since the last `take_unvalued`.
+static UNVALUED: AtomicUsize = AtomicUsize::new(0);
+
+/// The transfers left out of the rows of the export written last, for its summary.
+pub fn take_unvalued() -> usize {
+    UNVALUED.swap(0, Ordering::Relaxed)
+}
+
+/// The summary line for `unvalued` transfers left out of an export.
+pub fn unvalued_warning(unvalued: usize) -> String {
+    format!("Warning: {} NFT or unknown token transfers are not in the export, add them in cryptotaxcalculator.io by hand", unvalued)
+}
+
+fn ticker(currency: &str) -> &str {
+    TICKERS.iter()
+        .find(|(symbol, _)| *symbol == currency)
+        .map_or(currency, |(_, ticker)| ticker)
+}
+
+struct Row<'a> {
+    kind: &'static str,
+    base: &'a Leg,
+    quote: Option<&'a Leg>,
+}
+
+/// Formats the accounting event of a serialized record as rows of the universal CSV, one per
+/// leg. A swap of one currency for another is a single `buy` row quoting what was paid, swaps
+/// moving more currencies become `buy` and `sell` rows which share the transaction hash as their
+/// ID. `Other` events, e.g. paying for an NFT, keep their legs of known currencies as `receive`
+/// and `send` rows, the NFT and unknown token transfers can't be valued and are only counted.
+/// Events without legs are written as a `fee` row if the wallet paid a fee and skipped otherwise,
+/// transactions without a block have no timestamp and no rows.
+pub fn rows(record: &Value, wallet: &str) -> String {
+    let Event { kind, incoming, outgoing, fee, hash, date, from, to, unvalued, .. } = accounting::event(record, wallet);
+    let Some(date) = date else {
+        return String::new();
+    };
+    UNVALUED.fetch_add(unvalued, Ordering::Relaxed);
+
+    let rows: Vec<Row> = match kind {
+        Kind::Trade if incoming.len() == 1 && outgoing.len() == 1 => {
+            vec![Row { kind: "buy", base: &incoming[0], quote: Some(&outgoing[0]) }]
+        }
+        Kind::Trade => incoming.iter().map(|leg| Row { kind: "buy", base: leg, quote: None })
+            .chain(outgoing.iter().map(|leg| Row { kind: "sell", base: leg, quote: None }))
+            .collect(),
+        Kind::Deposit | Kind::StakingReward => {
+            let kind = match kind {
+                Kind::StakingReward => "staking",
+                _ => "receive"
+            };
+            incoming.iter().map(|leg| Row { kind, base: leg, quote: None }).collect()
+        }
+        Kind::Withdrawal => outgoing.iter().map(|leg| Row { kind: "send", base: leg, quote: None }).collect(),
+        Kind::Other => incoming.iter().map(|leg| Row { kind: "receive", base: leg, quote: None })
+            .chain(outgoing.iter().map(|leg| Row { kind: "send", base: leg, quote: None }))
+            .collect()
+    };
+
+    let line = |kind: &str, base: (&str, String), quote: (&str, String), fee: Option<&str>| {
+        [
+            date.clone(),
+            kind.to_string(),
+            base.0.to_string(),
+            base.1,
+            quote.0.to_string(),
+            quote.1,
+            fee.map_or("", |_| accounting::RON).to_string(),
+            fee.unwrap_or_default().to_string(),
+            from.clone(),
+            to.clone(),
+            hash.clone(),
+        ].iter().map(|field| escape(field)).collect::<Vec<String>>().join(",")
+    };
+
+    match rows.is_empty() {
+        true => fee.as_deref()
+            .map(|fee| line("fee", (accounting::RON, fee.to_string()), ("", String::new()), None))
+            .unwrap_or_default(),
+        false => rows.iter().enumerate().map(|(index, row)| {
+            let quote = row.quote.map_or(("", String::new()), |quote| (ticker(quote.currency), quote.amount()));
+            line(row.kind, (ticker(row.base.currency), row.base.amount()), quote, fee.as_deref().filter(|_| index == 0))
+        }).collect::<Vec<String>>().join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::testing::{hash, FakeProvider, OTHER, WALLET};
+    use super::*;
+
+    const AXIE: &str = "0x32950db2a7164ae833121501c797d79e7b79d74c";
+    const AXS: &str = "0x97a9107c1793bc407d6f527b77e7fff4d812bece";
+    const WETH: &str = "0xc99a6a985ed2cac1ef41640596c5a5f9f4e19ef5";
+
+    fn transfer(token: &str, from: &str, to: &str, amount: Option<&str>) -> serde_json::Value {
+        let mut events = vec![json!({ "name": "_from", "value": from }), json!({ "name": "_to", "value": to })];
+        events.push(match amount {
+            Some(amount) => json!({ "name": "_value", "value": amount }),
+            None => json!({ "name": "_tokenId", "value": "42" })
+        });
+
+        json!({ "address": token, "name": "Transfer", "events": events })
+    }
+
+    #[tokio::test]
+    async fn mixed_legs_keep_the_valued_currencies() {
+        let provider = FakeProvider { blocks: [(7_400_100, 1_704_067_200)].into(), ..FakeProvider::default() };
+        accounting::look_up_dates(&provider, [7_400_100]).await;
+
+        // Buys an axie for RON and WETH and gets AXS back.
+        let record = json!({
+            "from": WALLET, "to": OTHER, "hash": hash(1), "blockNumber": 7_400_100, "value": "2000000000000000000", "feeRon": "0.001",
+            "output": { "status": true, "logs": [
+                transfer(WETH, WALLET, OTHER, Some("250000000000000000")),
+                transfer(AXS, OTHER, WALLET, Some("5000000000000000000")),
+                transfer(AXIE, OTHER, WALLET, None),
+            ] }
+        });
+
+        let rows = rows(&record, WALLET);
+        assert_eq!(rows.lines().collect::<Vec<&str>>(), [
+            format!("2024-01-01 00:00:00,receive,AXS,5,,,RON,0.001,{},{},{}", WALLET, OTHER, hash(1)),
+            format!("2024-01-01 00:00:00,send,RON,2,,,,,{},{},{}", WALLET, OTHER, hash(1)),
+            format!("2024-01-01 00:00:00,send,ETH,0.25,,,,,{},{},{}", WALLET, OTHER, hash(1)),
+        ]);
+        assert_eq!(take_unvalued(), 1);
+        assert_eq!(take_unvalued(), 0);
+    }
+}
@@ -0,0 +1,253 @@
+use std::time::Duration;
+use crate::postgres::{Config, Connection};
+use crate::{logging, ArgParser, RRDecodedTransaction};
+
+/// Records upserted in one statement and transaction.
+const BATCH_SIZE: usize = 500;
+
+/// Attempts to write the last batch at the end of the run, with a doubling delay in between.
+const FINAL_ATTEMPTS: u32 = 5;
+
+/// The table of `--postgres-url`, printed by `--print-schema`.
+pub const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS wally_transactions (
+    wallet text NOT NULL,
+    hash text NOT NULL,
+    block_number bigint NOT NULL,
+    transaction_index bigint,
+    nonce bigint,
+    from_address text NOT NULL,
+    to_address text NOT NULL,
+    direction text NOT NULL,
+    value numeric(78, 0),
+    status text,
+    gas_used bigint,
+    gas_price numeric(78, 0),
+    effective_gas_price numeric(78, 0),
+    fee_ron numeric,
+    fee_payer text,
+    confirmations bigint,
+    input jsonb,
+    output jsonb,
+    raw_logs jsonb,
+    record jsonb NOT NULL,
+    exported_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (wallet, hash)
+);
+CREATE INDEX IF NOT EXISTS wally_transactions_block_number ON wally_transactions (block_number);";
+
+/// The columns filled from a record, in the order of `row`, with their types. The values are sent
+/// as text and cast to these.
+const COLUMNS: &[(&str, &str)] = &[
+    ("wallet", "text"), ("hash", "text"), ("block_number", "bigint"), ("transaction_index", "bigint"), ("nonce", "bigint"),
+    ("from_address", "text"), ("to_address", "text"), ("direction", "text"), ("value", "numeric"), ("status", "text"),
+    ("gas_used", "bigint"), ("gas_price", "numeric"), ("effective_gas_price", "numeric"), ("fee_ron", "numeric"), ("fee_payer", "text"),
+    ("confirmations", "bigint"), ("input", "jsonb"), ("output", "jsonb"), ("raw_logs", "jsonb"), ("record", "jsonb"),
+];
+
+/// The database of `--postgres-url`.
+pub fn url_from_args() -> Option<String> {
+    ArgParser::split(&"--postgres-url".to_string())
+}
+
+/// Whether `--print-schema` was passed.
+pub fn print_schema() -> bool {
+    ArgParser::flag("--print-schema")
+}
+
+fn text<T: ToString>(value: Option<T>) -> Option<String> {
+    value.map(|value| value.to_string())
+}
+
+/// A JSON field as text, `null` becomes `NULL`.
+fn json(value: &serde_json::Value) -> Option<String> {
+    (!value.is_null()).then(|| value.to_string())
+}
+
+fn row(wallet: &str, record: &RRDecodedTransaction) -> Vec<Option<String>> {
+    let value = serde_json::to_value(record).unwrap();
+    let field = |name: &str| value.get(name).cloned().unwrap_or_default();
+
+    vec![
+        Some(wallet.to_string()),
+        Some(record.hash.clone()),
+        text(Some(record.block_number)),
+        text(record.transaction_index),
+        text(record.nonce),
+        Some(record.from.clone()),
+        Some(record.to.clone()),
+        serde_json::to_value(record.direction).unwrap().as_str().map(str::to_string),
+        text(record.value),
+        field("status").as_str().map(str::to_string),
+        text(record.gas_used),
+        text(record.gas_price),
+        text(record.effective_gas_price),
+        record.fee_ron.clone(),
+        field("feePayer").as_str().map(str::to_string),
+        text(record.confirmations.flatten()),
+        json(&field("input")),
+        json(&field("output")),
+        json(&field("rawLogs")),
+        Some(value.to_string()),
+    ]
+}
+
+/// Upserts the records of a run into `wally_transactions` in batches, each in a transaction. A
+/// batch that can't be written is kept and written along with the next one, reconnecting first,
+/// so a dropped connection doesn't lose records.
+pub struct PostgresSink {
+    config: Config,
+    connection: Option<Connection>,
+    pending: Vec<Vec<Option<String>>>,
+    written: usize,
+    /// Whether the last attempt failed, so a lasting outage is reported once.
+    failing: bool,
+}
+
+impl PostgresSink {
+    /// Connects and creates the table if it doesn't exist yet.
+    pub async fn connect(url: &str) -> Result<PostgresSink, String> {
+        let config = Config::parse(url)?;
+        let mut connection = Connection::connect(&config).await?;
+        connection.execute(SCHEMA).await.map_err(|error| format!("Could not create the table wally_transactions: {}", error))?;
+
+        Ok(PostgresSink { config, connection: Some(connection), pending: vec![], written: 0, failing: false })
+    }
+
+    /// Queues a record of `wallet`, writing the queue once a batch is full.
+    pub async fn push(&mut self, wallet: &str, record: &RRDecodedTransaction) {
+        self.pending.push(row(wallet, record));
+
+        // While the database is unreachable, reconnecting is only tried once per batch.
+        if self.pending.len().is_multiple_of(BATCH_SIZE) {
+            if let Err(error) = self.flush().await {
+                if !self.failing {
+                    let message = format!("Could not write to PostgreSQL, the records are kept and written later: {}", error);
+                    println!("{}", message);
+                    logging::warn(&message);
+                }
+                self.failing = true;
+            }
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), String> {
+        if self.connection.is_none() {
+            self.connection = Some(Connection::connect(&self.config).await?);
+        }
+        let connection = self.connection.as_mut().unwrap();
+        let pending = &self.pending;
+
+        let result = async {
+            connection.execute("BEGIN").await?;
+            for batch in pending.chunks(BATCH_SIZE) {
+                connection.execute_with(&upsert(batch.len()), &batch.concat()).await?;
+            }
+            connection.execute("COMMIT").await
+        }.await;
+
+        match result {
+            Ok(()) => {
+                self.written += self.pending.len();
+                self.pending.clear();
+                self.failing = false;
+                Ok(())
+            }
+            Err(error) => {
+                // The connection may be gone or stuck in the failed transaction.
+                self.connection = None;
+                Err(error)
+            }
+        }
+    }
+
+    /// Writes the remaining records, retrying a while. Returns the number of records written.
+    pub async fn finish(mut self) -> Result<usize, String> {
+        let mut delay = Duration::from_secs(1);
+
+        for attempt in 1..=FINAL_ATTEMPTS {
+            match self.flush().await {
+                Ok(()) => return Ok(self.written),
+                Err(error) if attempt == FINAL_ATTEMPTS => {
+                    return Err(format!("{} of {} records could not be written to PostgreSQL: {}", self.pending.len(), self.written + self.pending.len(), error));
+                }
+                Err(error) => {
+                    logging::warn(&format!("Could not write to PostgreSQL, retrying in {}s: {}", delay.as_secs(), error));
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+            }
+        }
+
+        unreachable!()
+    }
+}
+
+/// An upsert of `rows` records, replacing records exported before.
+fn upsert(rows: usize) -> String {
+    let values: Vec<String> = (0..rows).map(|row| {
+        let parameters: Vec<String> = COLUMNS.iter().enumerate().map(|(column, (_, kind))| format!("${}::{}", row * COLUMNS.len() + column + 1, kind)).collect();
+        format!("({})", parameters.join(", "))
+    }).collect();
+    let names: Vec<&str> = COLUMNS.iter().map(|(name, _)| *name).collect();
+    let updates: Vec<String> = names.iter().skip(2).map(|column| format!("{} = EXCLUDED.{}", column, column)).collect();
+
+    format!(
+        "INSERT INTO wally_transactions ({}) VALUES {} ON CONFLICT (wallet, hash) DO UPDATE SET {}, exported_at = now()",
+        names.join(", "), values.join(", "), updates.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::testing::{hash, record, WALLET};
+    use super::*;
+
+    #[test]
+    fn upsert_of_one_record() {
+        assert_eq!(upsert(1), "INSERT INTO wally_transactions (wallet, hash, block_number, transaction_index, nonce, from_address, to_address, \
+direction, value, status, gas_used, gas_price, effective_gas_price, fee_ron, fee_payer, confirmations, input, output, raw_logs, record) \
+VALUES ($1::text, $2::text, $3::bigint, $4::bigint, $5::bigint, $6::text, $7::text, $8::text, $9::numeric, $10::text, $11::bigint, \
+$12::numeric, $13::numeric, $14::numeric, $15::text, $16::bigint, $17::jsonb, $18::jsonb, $19::jsonb, $20::jsonb) \
+ON CONFLICT (wallet, hash) DO UPDATE SET block_number = EXCLUDED.block_number, transaction_index = EXCLUDED.transaction_index, \
+nonce = EXCLUDED.nonce, from_address = EXCLUDED.from_address, to_address = EXCLUDED.to_address, direction = EXCLUDED.direction, \
+value = EXCLUDED.value, status = EXCLUDED.status, gas_used = EXCLUDED.gas_used, gas_price = EXCLUDED.gas_price, \
+effective_gas_price = EXCLUDED.effective_gas_price, fee_ron = EXCLUDED.fee_ron, fee_payer = EXCLUDED.fee_payer, \
+confirmations = EXCLUDED.confirmations, input = EXCLUDED.input, output = EXCLUDED.output, raw_logs = EXCLUDED.raw_logs, \
+record = EXCLUDED.record, exported_at = now()");
+    }
+
+    #[test]
+    fn upsert_numbers_the_parameters_of_every_row() {
+        let sql = upsert(BATCH_SIZE);
+        let last = BATCH_SIZE * COLUMNS.len();
+
+        assert!(sql.contains("($21::text, $22::text, $23::bigint,"));
+        assert!(sql.contains(&format!("${}::jsonb) ON CONFLICT", last)));
+        assert!(!sql.contains(&format!("${}::", last + 1)));
+        assert_eq!(sql.matches("), (").count(), BATCH_SIZE - 1);
+    }
+
+    #[test]
+    fn every_column_of_the_schema_is_filled() {
+        for (name, kind) in COLUMNS {
+            let definition = SCHEMA.lines().find(|line| line.trim_start().starts_with(&format!("{} ", name))).unwrap_or_else(|| panic!("{} is not in the schema", name));
+            assert!(definition.contains(kind), "{} is not a {} column", name, kind);
+        }
+    }
+
+    #[test]
+    fn rows_match_the_columns() {
+        let record = record(1, 100, json!({ "value": "0xde0b6b3a7640000", "gasUsed": 21000, "input": null }));
+        let row = row(WALLET, &record);
+
+        assert_eq!(row.len(), COLUMNS.len());
+        assert_eq!(row[0].as_deref(), Some(WALLET));
+        assert_eq!(row[1], Some(hash(1)));
+        assert_eq!(row[2].as_deref(), Some("100"));
+        assert_eq!(row[8].as_deref(), Some("1000000000000000000"));
+        // `null` fields become `NULL`.
+        assert_eq!(row[16], None);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(row[19].as_deref().unwrap()).unwrap()["hash"], hash(1));
+    }
+}
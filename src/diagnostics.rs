@@ -0,0 +1,158 @@
+use std::error::Error;
+use crate::hosts;
+
+/// Why a request could not be sent, for messages that tell the user what to check.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConnectionProblem {
+    Refused,
+    Dns,
+    Tls,
+    Timeout,
+    Other,
+}
+
+/// Whether a response with `status` failed for reasons of the server, which another host or a
+/// later attempt may not have: server errors and rate limiting. Other statuses are answers.
+pub fn is_transient(status: reqwest::StatusCode) -> bool {
+    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+}
+
+/// The messages of `error` and all of its sources.
+fn chain<'a>(error: &'a (dyn Error + 'static)) -> impl Iterator<Item = &'a (dyn Error + 'static)> {
+    std::iter::successors(Some(error), |&error| error.source())
+}
+
+pub fn classify(error: &reqwest::Error) -> ConnectionProblem {
+    if error.is_timeout() {
+        return ConnectionProblem::Timeout;
+    }
+
+    for source in chain(error) {
+        if let Some(io) = source.downcast_ref::<std::io::Error>() {
+            match io.kind() {
+                std::io::ErrorKind::ConnectionRefused => return ConnectionProblem::Refused,
+                std::io::ErrorKind::TimedOut => return ConnectionProblem::Timeout,
+                _ => {}
+            }
+        }
+
+        let message = source.to_string().to_lowercase();
+        if message.contains("dns error") || message.contains("failed to lookup address") {
+            return ConnectionProblem::Dns;
+        }
+        if message.contains("certificate") || message.contains("ssl") || message.contains("tls") || message.contains("handshake") {
+            return ConnectionProblem::Tls;
+        }
+    }
+
+    ConnectionProblem::Other
+}
+
+/// The innermost cause of `error`, e.g. `Connection refused (os error 111)`.
+fn cause(error: &reqwest::Error) -> String {
+    chain(error).last().map(|source| source.to_string()).unwrap_or_default()
+}
+
+/// A message for a request to `host` that failed with `error`, with a hint on what to check.
+pub fn describe(error: &reqwest::Error, host: &str) -> String {
+    match classify(error) {
+        ConnectionProblem::Refused if hosts::localhost_from_args().is_some() => format!(
+            "connection refused by {}, is ronin.rest running? Try without --localhost to use the public API", host
+        ),
+        ConnectionProblem::Refused => format!("connection refused by {}, is the API running on this port?", host),
+        ConnectionProblem::Dns => format!("could not resolve the host name of {}, check the --host URL and your network ({})", host, cause(error)),
+        ConnectionProblem::Tls => format!(
+            "the TLS connection to {} failed, pass --ca-cert if its certificate is issued by an internal CA ({})", host, cause(error)
+        ),
+        ConnectionProblem::Timeout => format!("{} did not answer in time, it may be overloaded or unreachable", host),
+        ConnectionProblem::Other => error.to_string(),
+    }
+}
+
+/// Like `describe`, for the errors of the middleware client.
+pub fn describe_middleware(error: &reqwest_middleware::Error, host: &str) -> String {
+    match error {
+        reqwest_middleware::Error::Reqwest(error) => describe(error, host),
+        error => error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use reqwest::StatusCode;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+    use super::*;
+
+    #[test]
+    fn transient_statuses() {
+        for (status, transient) in [
+            (StatusCode::OK, false),
+            (StatusCode::NOT_MODIFIED, false),
+            (StatusCode::BAD_REQUEST, false),
+            (StatusCode::UNAUTHORIZED, false),
+            (StatusCode::NOT_FOUND, false),
+            (StatusCode::TOO_MANY_REQUESTS, true),
+            (StatusCode::INTERNAL_SERVER_ERROR, true),
+            (StatusCode::BAD_GATEWAY, true),
+            (StatusCode::SERVICE_UNAVAILABLE, true),
+            (StatusCode::GATEWAY_TIMEOUT, true),
+        ] {
+            assert_eq!(is_transient(status), transient, "{}", status);
+        }
+    }
+
+    /// The error of requesting `url`, which must fail.
+    async fn error(url: String) -> reqwest::Error {
+        let client = reqwest::Client::builder().timeout(Duration::from_millis(500)).build().unwrap();
+        client.get(url).send().await.unwrap_err()
+    }
+
+    /// A port nothing listens on.
+    async fn closed_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port()
+    }
+
+    /// A server which accepts connections and answers every one with `answer`, or never if it is
+    /// `None`.
+    async fn server(answer: Option<&'static [u8]>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut connections = vec![];
+            while let Ok((mut connection, _)) = listener.accept().await {
+                if let Some(answer) = answer {
+                    let _ = connection.write_all(answer).await;
+                }
+                connections.push(connection);
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn connection_problems() {
+        let plain_http = server(Some(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")).await;
+        let not_http = server(Some(b"220 mail.example.com ESMTP\r\n")).await;
+
+        for (url, problem) in [
+            (format!("http://127.0.0.1:{}/", closed_port().await), ConnectionProblem::Refused),
+            ("http://wally.invalid/".to_string(), ConnectionProblem::Dns),
+            (format!("https://127.0.0.1:{}/", plain_http), ConnectionProblem::Tls),
+            (format!("http://127.0.0.1:{}/", server(None).await), ConnectionProblem::Timeout),
+            (format!("http://127.0.0.1:{}/", not_http), ConnectionProblem::Other),
+        ] {
+            let error = error(url.clone()).await;
+            assert_eq!(classify(&error), problem, "{}: {:?}", url, error);
+        }
+    }
+
+    #[tokio::test]
+    async fn messages_name_the_host() {
+        let host = format!("http://127.0.0.1:{}", closed_port().await);
+        let message = describe(&error(format!("{}/", host)).await, &host);
+
+        assert_eq!(message, format!("connection refused by {}, is the API running on this port?", host));
+    }
+}
@@ -0,0 +1,107 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use age::secrecy::SecretString;
+use dialoguer::Password;
+use crate::ArgParser;
+
+/// Environment variable holding the passphrase for non-interactive runs.
+const PASSPHRASE_VARIABLE: &str = "WALLY_PASSPHRASE";
+
+/// How output files are encrypted. Both variants produce age files.
+pub enum Encryption {
+    Recipients(Vec<age::x25519::Recipient>),
+    Passphrase(SecretString),
+}
+
+/// The passphrase of the run, asked for once, e.g. when `--append` decrypts an export and
+/// encrypts it again. `confirm` asks twice if it hasn't been asked for yet.
+fn passphrase(confirm: bool) -> SecretString {
+    static PASSPHRASE: OnceLock<SecretString> = OnceLock::new();
+
+    PASSPHRASE.get_or_init(|| {
+        if let Ok(passphrase) = std::env::var(PASSPHRASE_VARIABLE) {
+            return SecretString::new(passphrase);
+        }
+
+        let mut prompt = Password::new();
+        prompt.with_prompt("Passphrase");
+
+        if confirm {
+            prompt.with_confirmation("Repeat passphrase", "The passphrases do not match!");
+        }
+
+        SecretString::new(prompt.interact().unwrap())
+    }).clone()
+}
+
+impl Encryption {
+    /// `--encrypt=RECIPIENT` (repeatable) encrypts to age public keys, `--encrypt-pass` asks for
+    /// a passphrase.
+    pub fn from_args() -> Option<Encryption> {
+        if ArgParser::flag("--encrypt-pass") {
+            return Some(Encryption::Passphrase(passphrase(true)));
+        }
+
+        let recipients: Vec<age::x25519::Recipient> = ArgParser::all("--encrypt").iter()
+            .map(|recipient| age::x25519::Recipient::from_str(recipient)
+                .unwrap_or_else(|error| panic!("Invalid age recipient {}: {}", recipient, error)))
+            .collect();
+
+        match recipients.is_empty() {
+            true => None,
+            false => Some(Encryption::Recipients(recipients))
+        }
+    }
+
+    /// Wraps a writer so everything written to it is encrypted. The returned writer must be
+    /// finished to write the final block.
+    pub fn wrap<W: Write>(&self, output: W) -> age::stream::StreamWriter<W> {
+        let encryptor = match self {
+            Encryption::Recipients(recipients) => age::Encryptor::with_recipients(
+                recipients.iter().map(|recipient| Box::new(recipient.clone()) as Box<dyn age::Recipient + Send>).collect()
+            ).unwrap(),
+            Encryption::Passphrase(passphrase) => age::Encryptor::with_user_passphrase(passphrase.clone())
+        };
+
+        encryptor.wrap_output(output).unwrap()
+    }
+}
+
+/// Reads the identities of an age identity file, one `AGE-SECRET-KEY-` per line.
+fn identities(path: &str) -> Vec<age::x25519::Identity> {
+    std::fs::read_to_string(path)
+        .unwrap_or_else(|error| panic!("Could not read identity file {}: {}", path, error))
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with("AGE-SECRET-KEY-"))
+        .map(|line| age::x25519::Identity::from_str(line).unwrap_or_else(|error| panic!("Invalid identity in {}: {}", path, error)))
+        .collect()
+}
+
+/// Decrypts an age encrypted export with the passphrase or the identity file given by
+/// `--identity=FILE`. Fails with a clear message if the key does not match.
+pub fn decrypt(file: File, path: &str) -> Box<dyn BufRead> {
+    let decryptor = age::Decryptor::new_buffered(BufReader::new(file))
+        .unwrap_or_else(|error| panic!("{} is not a valid age file: {}", path, error));
+
+    let reader: Box<dyn Read> = match decryptor {
+        age::Decryptor::Passphrase(decryptor) => Box::new(
+            decryptor.decrypt(&passphrase(false), None)
+                .unwrap_or_else(|error| panic!("Could not decrypt {}, is the passphrase correct? ({})", path, error))
+        ),
+        age::Decryptor::Recipients(decryptor) => {
+            let identity_file = ArgParser::split(&"--identity".to_string())
+                .unwrap_or_else(|| panic!("{} is encrypted to an age key, pass its identity with --identity=FILE", path));
+            let identities = identities(&identity_file);
+
+            Box::new(
+                decryptor.decrypt(identities.iter().map(|identity| identity as &dyn age::Identity))
+                    .unwrap_or_else(|error| panic!("Could not decrypt {} with the identities in {} ({})", path, identity_file, error))
+            )
+        }
+    };
+
+    Box::new(BufReader::new(reader))
+}
@@ -0,0 +1,59 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use crate::{filters, parse_quantity, registry, ArgParser, RRDecodedTransaction};
+
+/// The file of `--events-out`, written in addition to the export.
+pub fn file_name() -> Option<String> {
+    ArgParser::split(&"--events-out".to_string())
+}
+
+/// A log of a receipt, one per record of `--events-out`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventRecord {
+    pub transaction_hash: String,
+    pub block_number: u64,
+    pub log_index: u64,
+    /// The emitting contract.
+    pub address: Option<String>,
+    pub label: Option<&'static str>,
+    /// The event name, `null` if the decoder didn't recognize the log.
+    pub event: Option<String>,
+    /// The decoded parameters by name, `null` for undecoded logs.
+    pub params: Option<Map<String, Value>>,
+    /// The raw topics and data of undecoded logs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub topics: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+/// The logs of a transaction's receipt in order. Decoded logs and `--raw-logs` are both in receipt
+/// order, so an undecoded log takes its topics and data from the raw log at the same position if
+/// the decoder left them out.
+pub fn of(tx: &RRDecodedTransaction) -> Vec<EventRecord> {
+    let decoded = tx.output.as_ref().map(|receipt| receipt.logs.as_slice()).unwrap_or_default();
+    let raw = tx.raw_logs.as_ref().map(filters::receipt_logs).unwrap_or_default();
+
+    (0..decoded.len().max(raw.len())).map(|index| {
+        let log = decoded.get(index);
+        let raw_log = raw.get(index).copied();
+        let field = |key: &str| log.and_then(|log| log.raw.get(key)).or_else(|| raw_log.and_then(|raw| raw.get(key)));
+
+        let address = log.and_then(|log| log.address.clone()).or_else(|| raw_log.and_then(filters::log_address).map(str::to_string));
+        let event = log.and_then(|log| log.name.clone());
+        let undecoded = event.is_none();
+
+        EventRecord {
+            transaction_hash: tx.hash.clone(),
+            block_number: tx.block_number,
+            log_index: field("logIndex").and_then(parse_quantity).unwrap_or(index as u64),
+            label: address.as_deref().and_then(registry::label),
+            address,
+            params: log.filter(|_| !undecoded).map(|log| log.params.iter().cloned().collect()),
+            topics: field("topics").filter(|_| undecoded).cloned(),
+            data: field("data").filter(|_| undecoded).cloned(),
+            event,
+        }
+    }).collect()
+}
@@ -0,0 +1,96 @@
+use std::process::Command;
+use crate::{logging, ArgParser};
+
+/// The command of `--exec-after`, run once per exported wallet after its files were written.
+pub fn from_args() -> Option<String> {
+    let command = ArgParser::all("--exec-after").pop()?;
+    if command.contains("{summary}") && crate::summary_file_from_args().is_none() {
+        panic!("The --exec-after command uses {{summary}}, which requires --summary-json");
+    }
+
+    Some(command)
+}
+
+/// Whether a failing `--exec-after` command leaves the exit code of wally alone.
+pub fn ignore_failure() -> bool {
+    ArgParser::flag("--exec-after-ignore-failure")
+}
+
+/// Quotes `value` for the shell, so file names and aliases are passed as a single argument.
+fn quote(value: &str) -> String {
+    match cfg!(windows) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+/// Replaces the placeholders of `command`: `{output}` by the files of the export, each quoted on
+/// its own, `{address}` by the address and `{summary}` by the file of `--summary-json`.
+fn substitute(command: &str, files: &[String], address: &str, summary: Option<&str>) -> String {
+    let output = files.iter().map(|file| quote(file)).collect::<Vec<String>>().join(" ");
+
+    command.replace("{output}", &output)
+        .replace("{address}", &quote(address))
+        .replace("{summary}", &summary.map(quote).unwrap_or_default())
+}
+
+/// Runs `command` for the export of `address` in `files` in the shell, its output goes straight
+/// to the console. Returns the exit code of a failed command.
+pub fn run(command: &str, files: &[String], address: &str, summary: Option<&str>) -> Result<(), i32> {
+    let command = substitute(command, files, address, summary);
+
+    let mut shell = match cfg!(windows) {
+        true => {
+            let mut shell = Command::new("cmd");
+            shell.arg("/C").arg(&command);
+            shell
+        }
+        false => {
+            let mut shell = Command::new("sh");
+            shell.arg("-c").arg(&command);
+            shell
+        }
+    };
+
+    match shell.status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => {
+            let message = format!("--exec-after command `{}` failed with {}", command, status);
+            println!("{}", message);
+            logging::error(&message);
+            Err(status.code().unwrap_or(1))
+        }
+        Err(error) => {
+            let message = format!("Could not run the --exec-after command `{}`: {}", command, error);
+            println!("{}", message);
+            logging::error(&message);
+            Err(1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn placeholders_are_quoted() {
+        let files = ["0x11.json".to_string()];
+
+        assert_eq!(
+            substitute("python ingest.py {output} --wallet={address} --summary {summary}", &files, "0x11", Some("out/summary.json")),
+            "python ingest.py '0x11.json' --wallet='0x11' --summary 'out/summary.json'"
+        );
+        assert_eq!(substitute("cat {output}", &["it's.json".to_string()], "0x11", None), "cat 'it'\\''s.json'");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn every_file_is_an_argument() {
+        let files = ["out/0x11/a.json".to_string(), "out/0x11/b c.json".to_string()];
+
+        assert_eq!(substitute("ingest {output}", &files, "0x11", None), "ingest 'out/0x11/a.json' 'out/0x11/b c.json'");
+        assert_eq!(substitute("ingest {output}", &[], "0x11", None), "ingest ");
+    }
+}
@@ -0,0 +1,135 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use serde::de::DeserializeOwned;
+
+/// The version of the export format, recorded in the index files. Bumped whenever records change
+/// in a way older readers can't handle, e.g. a renamed or removed field. Added optional fields
+/// don't need a new version.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Reads the elements of a JSON array one at a time so exports of any size can be processed
+/// without holding the whole file in memory.
+pub struct JsonArrayReader<R: BufRead, T> {
+    reader: R,
+    started: bool,
+    finished: bool,
+    record: std::marker::PhantomData<T>,
+}
+
+impl<R: BufRead, T: DeserializeOwned> JsonArrayReader<R, T> {
+    pub fn new(reader: R) -> JsonArrayReader<R, T> {
+        JsonArrayReader {
+            reader,
+            started: false,
+            finished: false,
+            record: std::marker::PhantomData,
+        }
+    }
+
+    /// Skips whitespace and returns the next byte without consuming it.
+    fn peek(&mut self) -> std::io::Result<Option<u8>> {
+        loop {
+            let buffer = self.reader.fill_buf()?;
+            match buffer.first() {
+                None => return Ok(None),
+                Some(byte) if byte.is_ascii_whitespace() => self.reader.consume(1),
+                Some(byte) => return Ok(Some(*byte))
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: &[u8]) -> Result<u8, serde_json::Error> {
+        match self.peek().map_err(serde_json::Error::io)? {
+            Some(byte) if expected.contains(&byte) => {
+                self.reader.consume(1);
+                Ok(byte)
+            }
+            Some(byte) => Err(serde::de::Error::custom(format!("unexpected character '{}' in export", byte as char))),
+            None => Err(serde::de::Error::custom("unexpected end of export"))
+        }
+    }
+
+    fn next_record(&mut self) -> Result<Option<T>, serde_json::Error> {
+        if !self.started {
+            self.started = true;
+            self.expect(b"[")?;
+
+            if self.peek().map_err(serde_json::Error::io)? == Some(b']') {
+                self.reader.consume(1);
+                return Ok(None);
+            }
+        } else if self.expect(b",]")? == b']' {
+            return Ok(None);
+        }
+
+        // Records are objects, so the deserializer never reads past the closing brace.
+        let mut deserializer = serde_json::Deserializer::from_reader(&mut self.reader);
+        T::deserialize(&mut deserializer).map(Some)
+    }
+}
+
+impl<R: BufRead, T: DeserializeOwned> Iterator for JsonArrayReader<R, T> {
+    type Item = Result<T, serde_json::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let record = self.next_record();
+
+        if !matches!(record, Ok(Some(_))) {
+            self.finished = true;
+        }
+
+        record.transpose()
+    }
+}
+
+/// Opens an export for reading. Age encrypted exports are decrypted and gzip compressed ones
+/// decompressed on the fly.
+pub fn open(path: &str) -> Box<dyn BufRead> {
+    let file = File::open(path).unwrap_or_else(|error| panic!("Could not open {}: {}", path, error));
+
+    let mut reader: Box<dyn BufRead> = match path.ends_with(".age") {
+        true => crate::encryption::decrypt(file, path),
+        false => Box::new(BufReader::new(file))
+    };
+
+    match reader.fill_buf().map(|data| data.starts_with(&[0x1f, 0x8b])) {
+        Ok(true) => Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader))),
+        _ => reader
+    }
+}
+
+/// Opens an export and streams its records, see `open`.
+pub fn read_records<T: DeserializeOwned>(path: &str) -> JsonArrayReader<Box<dyn BufRead>, T> {
+    JsonArrayReader::new(open(path))
+}
+
+/// Streams the records of a JSON export or an NDJSON file, telling them apart by the first
+/// character, see `open`.
+pub fn stream_records<T: DeserializeOwned + 'static>(path: &str) -> Result<Box<dyn Iterator<Item = Result<T, String>>>, String> {
+    let mut reader = open(path);
+
+    let first = loop {
+        let buffer = reader.fill_buf().map_err(|error| format!("Could not read {}: {}", path, error))?;
+        match buffer.iter().position(|byte| !byte.is_ascii_whitespace()) {
+            _ if buffer.is_empty() => break None,
+            Some(position) => break Some(buffer[position]),
+            None => {
+                let length = buffer.len();
+                reader.consume(length);
+            }
+        }
+    };
+
+    match first {
+        Some(b'[') => Ok(Box::new(JsonArrayReader::new(reader).map(|record| record.map_err(|error| error.to_string())))),
+        Some(b'{') => Ok(Box::new(reader.lines()
+            .filter(|line| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+            .map(|line| line.map_err(|error| error.to_string()).and_then(|line| serde_json::from_str(&line).map_err(|error| error.to_string()))))),
+        Some(_) => Err(format!("{} is neither a JSON nor an NDJSON export", path)),
+        None => Err(format!("{} is empty", path))
+    }
+}
@@ -0,0 +1,427 @@
+use serde_json::Value;
+use web3::types::U256;
+use crate::filters::{self, MinValueFilter};
+use crate::{Direction, RRDecodedTransaction, TxStatus};
+
+/// A `--filter` expression such as `method=settleOrder AND (block>=20000000 OR NOT status=failed)`.
+pub enum Expression {
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Block(Comparison, u64),
+    Value(Comparison, U256),
+    Method(bool, String),
+    Counterparty(bool, String),
+    Status(bool, TxStatus),
+    Direction(bool, Direction),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn holds<T: Ord>(self, left: T, right: T) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq)]
+enum Token<'a> {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Operator(Comparison),
+    Word(&'a str),
+}
+
+fn describe(token: Option<&(usize, Token)>) -> String {
+    match token {
+        None => "the end".to_string(),
+        Some((_, Token::Open)) => "'('".to_string(),
+        Some((_, Token::Close)) => "')'".to_string(),
+        Some((_, Token::And)) => "'AND'".to_string(),
+        Some((_, Token::Or)) => "'OR'".to_string(),
+        Some((_, Token::Not)) => "'NOT'".to_string(),
+        Some((_, Token::Operator(_))) => "an operator".to_string(),
+        Some((_, Token::Word(word))) => format!("'{}'", word),
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token<'_>)>, (usize, String)> {
+    let mut tokens = vec![];
+    let mut chars = text.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push((start, if c == '(' { Token::Open } else { Token::Close }));
+            }
+            '=' | '!' | '<' | '>' => {
+                chars.next();
+                let equals = chars.next_if(|&(_, next)| next == '=').is_some();
+                let operator = match (c, equals) {
+                    ('=', _) => Comparison::Equal,
+                    ('!', true) => Comparison::NotEqual,
+                    ('<', false) => Comparison::Less,
+                    ('<', true) => Comparison::LessOrEqual,
+                    ('>', false) => Comparison::Greater,
+                    ('>', true) => Comparison::GreaterOrEqual,
+                    _ => return Err((start, "expected '=' after '!'".to_string()))
+                };
+                tokens.push((start, Token::Operator(operator)));
+            }
+            _ => {
+                let mut end = start;
+                while let Some(&(index, c)) = chars.peek() {
+                    if c.is_whitespace() || "()=!<>".contains(c) {
+                        break;
+                    }
+                    end = index + c.len_utf8();
+                    chars.next();
+                }
+
+                let word = &text[start..end];
+                tokens.push((start, match word.to_uppercase().as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Word(word)
+                }));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Vec<(usize, Token<'a>)>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    /// The offset of the current token in the expression, for error messages.
+    fn offset(&self) -> usize {
+        self.tokens.get(self.position).map(|(offset, _)| *offset).unwrap_or(self.text.len())
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, (usize, String)> {
+        Err((self.offset(), message))
+    }
+
+    fn or(&mut self) -> Result<Expression, (usize, String)> {
+        let mut left = self.and()?;
+
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            left = Expression::Or(Box::new(left), Box::new(self.and()?));
+        }
+
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expression, (usize, String)> {
+        let mut left = self.not()?;
+
+        while self.peek() == Some(&Token::And) {
+            self.position += 1;
+            left = Expression::And(Box::new(left), Box::new(self.not()?));
+        }
+
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expression, (usize, String)> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.position += 1;
+                Ok(Expression::Not(Box::new(self.not()?)))
+            }
+            _ => self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expression, (usize, String)> {
+        match self.peek() {
+            Some(Token::Open) => {
+                self.position += 1;
+                let expression = self.or()?;
+
+                match self.peek() {
+                    Some(Token::Close) => {
+                        self.position += 1;
+                        Ok(expression)
+                    }
+                    _ => self.error(format!("expected ')' but found {}", describe(self.tokens.get(self.position))))
+                }
+            }
+            Some(Token::Word(_)) => self.comparison(),
+            _ => self.error(format!("expected a comparison such as block>=123 but found {}", describe(self.tokens.get(self.position))))
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expression, (usize, String)> {
+        let field = match self.peek() {
+            Some(Token::Word(field)) => field.to_lowercase(),
+            _ => unreachable!()
+        };
+        let field_position = self.position;
+        self.position += 1;
+
+        let operator = match self.peek() {
+            Some(Token::Operator(operator)) => *operator,
+            _ => return self.error(format!("expected an operator after '{}' but found {}", field, describe(self.tokens.get(self.position))))
+        };
+        self.position += 1;
+
+        let value = match self.peek() {
+            Some(Token::Word(value)) => *value,
+            _ => return self.error(format!("expected a value after '{}' but found {}", field, describe(self.tokens.get(self.position))))
+        };
+        let equality = match operator {
+            Comparison::Equal => Some(true),
+            Comparison::NotEqual => Some(false),
+            _ => None
+        };
+
+        let expression = match (field.as_str(), equality) {
+            ("block", _) => Expression::Block(operator, value.parse().or_else(|_| self.error(format!("'{}' is not a block number", value)))?),
+            ("value", _) => {
+                let amount = value.strip_suffix("RON").or_else(|| value.strip_suffix("ron")).unwrap_or(value);
+                Expression::Value(operator, filters::parse_units(amount, filters::RON_DECIMALS).or_else(|error| self.error(format!("invalid value: {}", error)))?)
+            }
+            ("method", Some(equal)) => Expression::Method(equal, value.to_string()),
+            ("counterparty", Some(equal)) => Expression::Counterparty(equal, filters::address_key(value)),
+            ("status", Some(equal)) => Expression::Status(equal, match value.to_lowercase().as_str() {
+                "success" => TxStatus::Success,
+                "failed" => TxStatus::Failed,
+                _ => return self.error(format!("unknown status '{}', expected success or failed", value))
+            }),
+            ("direction", Some(equal)) => Expression::Direction(equal, match value.to_lowercase().as_str() {
+                "sent" => Direction::Sent,
+                "received" => Direction::Received,
+                "both" => Direction::Both,
+                _ => return self.error(format!("unknown direction '{}', expected sent, received or both", value))
+            }),
+            ("method" | "counterparty" | "status" | "direction", None) => {
+                self.position -= 1;
+                return self.error(format!("'{}' can only be compared with = or !=", field));
+            }
+            _ => {
+                self.position = field_position;
+                return self.error(format!(
+                    "unknown field '{}', expected block, value, method, counterparty, status or direction", field
+                ));
+            }
+        };
+        self.position += 1;
+
+        Ok(expression)
+    }
+}
+
+/// The names of the decoded method and of any decoded multicall inner calls.
+fn method_names(input: &Value) -> Vec<&str> {
+    let mut names: Vec<&str> = filters::method_name(input).into_iter().collect();
+
+    if let Some(calls) = input.get("calls").and_then(Value::as_array) {
+        names.extend(calls.iter().flat_map(method_names));
+    }
+
+    names
+}
+
+impl Expression {
+    /// Parses an expression. Errors show the expression with a marker below the offending token.
+    pub fn parse(text: &str) -> Result<Expression, String> {
+        let parsed = tokenize(text).and_then(|tokens| {
+            let mut parser = Parser { text, tokens, position: 0 };
+            let expression = parser.or()?;
+
+            match parser.position < parser.tokens.len() {
+                true => parser.error(format!("unexpected {}", describe(parser.tokens.get(parser.position)))),
+                false => Ok(expression)
+            }
+        });
+
+        parsed.map_err(|(offset, message)| {
+            format!("Invalid --filter: {}\n  {}\n  {}^", message, text, " ".repeat(text[..offset].chars().count()))
+        })
+    }
+
+    pub fn matches(&self, tx: &RRDecodedTransaction, value: Option<U256>, wallet: &str) -> bool {
+        match self {
+            Expression::And(left, right) => left.matches(tx, value, wallet) && right.matches(tx, value, wallet),
+            Expression::Or(left, right) => left.matches(tx, value, wallet) || right.matches(tx, value, wallet),
+            Expression::Not(expression) => !expression.matches(tx, value, wallet),
+            Expression::Block(comparison, block) => comparison.holds(tx.block_number, *block),
+            Expression::Value(comparison, amount) => comparison.holds(MinValueFilter::moved_value(tx, value, wallet), *amount),
+            Expression::Method(equal, method) => {
+                let found = tx.input.as_ref().is_some_and(|input| method_names(input).iter().any(|name| name.eq_ignore_ascii_case(method)));
+                found == *equal
+            }
+            Expression::Counterparty(equal, address) => (filters::address_key(filters::counterparty(tx, wallet)) == *address) == *equal,
+            Expression::Status(equal, status) => (tx.status == Some(*status)) == *equal,
+            Expression::Direction(equal, direction) => (tx.direction == *direction) == *equal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::testing::{record, OTHER, WALLET};
+    use super::*;
+
+    fn matches(text: &str, tx: &RRDecodedTransaction, value: Option<U256>) -> bool {
+        Expression::parse(text).unwrap_or_else(|error| panic!("{}", error)).matches(tx, value, WALLET)
+    }
+
+    /// The message of a parse error and the column of its marker.
+    fn error(text: &str) -> (String, usize) {
+        let error = Expression::parse(text).err().unwrap_or_else(|| panic!("'{}' should not parse", text));
+        let lines: Vec<&str> = error.lines().collect();
+        assert_eq!(lines.len(), 3, "{}", error);
+        assert_eq!(lines[1], format!("  {}", text));
+
+        let message = lines[0].strip_prefix("Invalid --filter: ").unwrap().to_string();
+        (message, lines[2].len() - "  ^".len())
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let tx = record(1, 1, json!({ "direction": "sent" }));
+
+        assert!(matches("block=1 OR block=2 AND direction=received", &tx, None));
+        assert!(!matches("(block=1 OR block=2) AND direction=received", &tx, None));
+        assert!(matches("direction=received AND block=2 OR block=1", &tx, None));
+    }
+
+    #[test]
+    fn not_applies_to_the_next_operand() {
+        let first = record(1, 1, json!({ "direction": "sent" }));
+        let second = record(2, 2, json!({ "direction": "sent" }));
+
+        assert!(!matches("NOT block=1 AND direction=sent", &first, None));
+        assert!(matches("NOT block=1 AND direction=sent", &second, None));
+        assert!(!matches("NOT (block=1 OR block=2)", &second, None));
+        assert!(matches("NOT NOT block=2", &second, None));
+        assert!(matches("not block=1 and (direction=received or block>1)", &second, None));
+    }
+
+    #[test]
+    fn compares_blocks() {
+        let tx = record(1, 100, json!({}));
+
+        assert!(matches("block=100", &tx, None));
+        assert!(matches("block!=99", &tx, None));
+        assert!(matches("block<101", &tx, None));
+        assert!(matches("block<=100", &tx, None));
+        assert!(matches("block>99", &tx, None));
+        assert!(matches("block>=100", &tx, None));
+        assert!(!matches("block<100", &tx, None));
+        assert!(!matches("block>100", &tx, None));
+    }
+
+    #[test]
+    fn compares_values_in_ron() {
+        let tx = record(1, 1, json!({}));
+        let value = Some(U256::from(2_000_000_000_000_000_000u64));
+
+        assert!(matches("value>=1.5RON", &tx, value));
+        assert!(matches("value=2", &tx, value));
+        assert!(!matches("value>2ron", &tx, value));
+        assert!(matches("value=0", &tx, None));
+    }
+
+    #[test]
+    fn compares_methods_including_multicall_calls() {
+        let tx = record(1, 1, json!({ "input": { "name": "multicall", "calls": [{ "name": "settleOrder" }] } }));
+
+        assert!(matches("method=multicall", &tx, None));
+        assert!(matches("method=SETTLEORDER", &tx, None));
+        assert!(matches("method!=transfer", &tx, None));
+        assert!(!matches("method=transfer", &tx, None));
+        assert!(!matches("method=transfer", &record(2, 1, json!({})), None));
+    }
+
+    #[test]
+    fn compares_counterparties_normalized() {
+        let sent = record(1, 1, json!({}));
+        let received = record(2, 1, json!({ "from": OTHER, "to": WALLET }));
+        let counterparty = format!("ronin:{}", OTHER[2..].to_uppercase());
+
+        assert!(matches(&format!("counterparty={}", counterparty), &sent, None));
+        assert!(matches(&format!("counterparty={}", OTHER), &received, None));
+        assert!(matches(&format!("counterparty!={}", WALLET), &received, None));
+    }
+
+    #[test]
+    fn compares_status_and_direction() {
+        let failed = record(1, 1, json!({ "status": "failed", "direction": "both" }));
+        let unknown = record(2, 1, json!({}));
+
+        assert!(matches("status=failed", &failed, None));
+        assert!(matches("status!=Success", &failed, None));
+        assert!(!matches("status=success", &unknown, None));
+        assert!(!matches("status=failed", &unknown, None));
+        assert!(matches("direction=both", &failed, None));
+        assert!(matches("direction!=sent", &failed, None));
+        assert!(matches("direction=sent", &unknown, None));
+    }
+
+    #[test]
+    fn reports_unbalanced_parentheses() {
+        assert_eq!(error("(block>1 OR block<0"), ("expected ')' but found the end".to_string(), 19));
+        assert_eq!(error("block>1)"), ("unexpected ')'".to_string(), 7));
+        assert_eq!(error("()"), ("expected a comparison such as block>=123 but found ')'".to_string(), 1));
+    }
+
+    #[test]
+    fn reports_unknown_fields_and_values() {
+        assert_eq!(
+            error("block>1 AND nonce=3"),
+            ("unknown field 'nonce', expected block, value, method, counterparty, status or direction".to_string(), 12)
+        );
+        assert_eq!(error("method>=transfer"), ("'method' can only be compared with = or !=".to_string(), 6));
+        assert_eq!(error("status=pending"), ("unknown status 'pending', expected success or failed".to_string(), 7));
+        assert_eq!(
+            error("direction=out"),
+            ("unknown direction 'out', expected sent, received or both".to_string(), 10)
+        );
+        assert_eq!(error("block=latest"), ("'latest' is not a block number".to_string(), 6));
+        assert_eq!(error("block"), ("expected an operator after 'block' but found the end".to_string(), 5));
+        assert_eq!(error("block!1"), ("expected '=' after '!'".to_string(), 5));
+    }
+
+    #[test]
+    fn reports_trailing_tokens() {
+        assert_eq!(error("block>1 block<2"), ("unexpected 'block'".to_string(), 8));
+        assert_eq!(error("block>1 AND"), ("expected a comparison such as block>=123 but found the end".to_string(), 11));
+    }
+}
@@ -0,0 +1,535 @@
+use std::collections::HashSet;
+use serde_json::Value;
+use web3::types::{Address, U256};
+use crate::expression::Expression;
+use crate::receipt::RRLog;
+use crate::{normalize_address, registry, ArgParser, RRDecodedTransaction};
+
+/// Known spam token and dusting contracts on Ronin. Extend it locally with `--spam-list=FILE`.
+const BUILTIN_SPAM_CONTRACTS: &[&str] = &[];
+
+const TRANSFER_EVENTS: &[&str] = &["Transfer", "TransferSingle", "TransferBatch"];
+
+pub const RON_DECIMALS: usize = 18;
+
+/// topic0 hashes of common events, to match event names against raw logs and topics against
+/// decoded logs.
+const EVENT_TOPICS: &[(&str, &str)] = &[
+    ("Transfer", "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"),
+    ("Approval", "0x8c5be1e5ebec7d5bd14f71427d1e84f3dd0314c0f7b2291e5b200ac8c7c3b925"),
+    ("ApprovalForAll", "0x17307eab39ab6107e8899845ad3d59bd9653f200f220920489ca2b5937696c31"),
+    ("Deposit", "0xe1fffcc4923d04b559f4d29a8bfc6cda04eb5b0d3c460751c2402c5c5cc9109c"),
+    ("Withdrawal", "0x7fcf532c15f0a6db0bd6d0e038bea71d30d808c7d98cb3bf7268a95bf5081b65"),
+];
+
+/// Returns the decoded logs of a receipt, which are either the receipt itself or nested below a
+/// `logs` key.
+pub fn receipt_logs(receipt: &Value) -> Vec<&Value> {
+    let logs = match receipt {
+        Value::Array(logs) => Some(logs),
+        _ => receipt.get("logs")
+            .or_else(|| receipt.get("receipt").and_then(|receipt| receipt.get("logs")))
+            .and_then(Value::as_array)
+    };
+
+    logs.map(|logs| logs.iter().collect()).unwrap_or_default()
+}
+
+pub fn log_name(log: &Value) -> Option<&str> {
+    log.get("name").or_else(|| log.get("event")).and_then(Value::as_str)
+}
+
+pub fn log_address(log: &Value) -> Option<&str> {
+    log.get("address").and_then(Value::as_str)
+}
+
+/// Returns the decoded parameters of a log or method as name/value pairs. Decoders either emit a
+/// list of `{name, value}` entries or a plain object.
+pub fn params(decoded: &Value) -> Vec<(&str, &Value)> {
+    let params = ["events", "params", "args", "inputs"].iter().find_map(|key| decoded.get(key));
+
+    match params {
+        Some(Value::Array(entries)) => entries.iter()
+            .filter_map(|entry| Some((entry.get("name")?.as_str()?, entry.get("value")?)))
+            .collect(),
+        Some(Value::Object(map)) => map.iter().map(|(name, value)| (name.as_str(), value)).collect(),
+        _ => vec![]
+    }
+}
+
+/// Returns the amount moved by a `Transfer` log if the wallet is on either side of it.
+fn transfer_amount(log: &RRLog, wallet: &str) -> Option<U256> {
+    let (from, to, amount) = log.transfer()?;
+
+    match from.eq_ignore_ascii_case(wallet) || to.eq_ignore_ascii_case(wallet) {
+        true => Some(amount),
+        false => None
+    }
+}
+
+/// The decoded logs of a transaction's receipt.
+fn decoded_logs(tx: &RRDecodedTransaction) -> impl Iterator<Item = &Value> {
+    tx.output.iter().flat_map(|receipt| receipt.logs.iter().map(|log| &log.raw))
+}
+
+/// Parses a decimal string such as `0.1` into the smallest unit of a token with `decimals`
+/// decimals, without going through floating point.
+pub fn parse_units(text: &str, decimals: usize) -> Result<U256, String> {
+    let (integer, fraction) = text.split_once('.').unwrap_or((text, ""));
+
+    if integer.is_empty() && fraction.is_empty() {
+        return Err(format!("'{}' is not a number", text));
+    }
+    if !integer.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+        return Err(format!("'{}' is not a non-negative decimal number", text));
+    }
+    if fraction.len() > decimals {
+        return Err(format!("'{}' has more than {} decimal places", text, decimals));
+    }
+
+    let digits = format!("{}{:0<width$}", integer, fraction, width = decimals);
+    U256::from_dec_str(&digits).map_err(|_| format!("'{}' is too large", text))
+}
+
+/// Formats an amount in the smallest unit of a token with `decimals` decimals as a decimal string
+/// such as `0.00042`, the inverse of `parse_units`.
+pub fn format_units(amount: U256, decimals: usize) -> String {
+    let digits = format!("{:0>width$}", amount.to_string(), width = decimals + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - decimals);
+
+    match fraction.trim_end_matches('0') {
+        "" => integer.to_string(),
+        fraction => format!("{}.{}", integer, fraction)
+    }
+}
+
+/// Whether `--raw-amounts` was passed, CSV exports and reports then show amounts in the smallest
+/// unit instead of formatted.
+pub fn raw_amounts() -> bool {
+    ArgParser::flag("--raw-amounts")
+}
+
+/// Returns the name of the decoded method, if the decoder recognized it.
+pub fn method_name(input: &Value) -> Option<&str> {
+    input.get("name")
+        .or_else(|| input.get("method"))
+        .and_then(Value::as_str)
+        .filter(|name| !name.is_empty())
+}
+
+/// Whether `address` appears anywhere inside a decoded value.
+pub fn mentions(value: &Value, address: &str) -> bool {
+    match value {
+        Value::String(text) => text.eq_ignore_ascii_case(address),
+        Value::Array(values) => values.iter().any(|value| mentions(value, address)),
+        Value::Object(map) => map.values().any(|value| mentions(value, address)),
+        _ => false
+    }
+}
+
+pub enum SpamReason {
+    /// The transaction involves a contract on the spam list.
+    Listed,
+    /// No value, no transfers involving the wallet and no recognized method.
+    Noise,
+}
+
+pub struct SpamFilter {
+    contracts: HashSet<String>,
+}
+
+/// Normalizes an address for comparisons: `ronin:` prefixes become `0x` and case is ignored.
+pub fn address_key(address: &str) -> String {
+    normalize_address(address.trim()).to_lowercase()
+}
+
+/// Reads a file with one address per line. Empty lines and lines starting with `#` are ignored.
+pub fn read_address_list(path: &str) -> Vec<String> {
+    let list = std::fs::read_to_string(path)
+        .unwrap_or_else(|error| panic!("Could not read address list {}: {}", path, error));
+
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(address_key)
+        .collect()
+}
+
+/// The other side of a transaction from the wallet's point of view.
+pub fn counterparty<'a>(tx: &'a RRDecodedTransaction, wallet: &str) -> &'a str {
+    match tx.from.eq_ignore_ascii_case(wallet) {
+        true => &tx.to,
+        false => &tx.from
+    }
+}
+
+impl SpamFilter {
+    /// Builds the filter from the built-in list, extended by an optional address list file.
+    pub fn new(list_file: Option<String>) -> SpamFilter {
+        SpamFilter::with_builtin(BUILTIN_SPAM_CONTRACTS, list_file)
+    }
+
+    fn with_builtin(builtin: &[&str], list_file: Option<String>) -> SpamFilter {
+        let mut contracts: HashSet<String> = builtin.iter().map(|address| address_key(address)).collect();
+
+        if let Some(list_file) = list_file {
+            contracts.extend(read_address_list(&list_file));
+        }
+
+        SpamFilter { contracts }
+    }
+
+    pub fn is_listed(&self, address: &str) -> bool {
+        self.contracts.contains(&address_key(address))
+    }
+
+    pub fn classify(&self, tx: &RRDecodedTransaction, value: Option<U256>, wallet: &str) -> Option<SpamReason> {
+        let logs = tx.output.as_ref().map(|receipt| receipt.logs.as_slice()).unwrap_or_default();
+
+        if self.is_listed(&tx.to) || logs.iter().filter_map(|log| log.address.as_deref()).any(|address| self.is_listed(address)) {
+            return Some(SpamReason::Listed);
+        }
+
+        let has_value = value.is_some_and(|value| !value.is_zero());
+        let has_transfer = logs.iter()
+            .filter(|log| log.name.as_deref().is_some_and(|name| TRANSFER_EVENTS.contains(&name)))
+            .any(|log| mentions(&log.raw, wallet));
+        let has_method = tx.input.as_ref().and_then(method_name).is_some();
+
+        match has_value || has_transfer || has_method {
+            true => None,
+            false => Some(SpamReason::Noise)
+        }
+    }
+}
+
+pub struct MinValueFilter {
+    threshold: U256,
+}
+
+impl MinValueFilter {
+    /// Parses thresholds such as `0.1RON`. USD thresholds require price enrichment, which is not
+    /// available, and are rejected.
+    pub fn parse(text: &str) -> Result<MinValueFilter, String> {
+        let text = text.trim();
+        let split = text.find(|c: char| c.is_ascii_alphabetic())
+            .ok_or_else(|| format!("Invalid --min-value '{}': missing unit, expected e.g. 0.1RON", text))?;
+        let (amount, unit) = text.split_at(split);
+
+        match unit.to_uppercase().as_str() {
+            "RON" => Ok(MinValueFilter {
+                threshold: parse_units(amount.trim(), RON_DECIMALS).map_err(|error| format!("Invalid --min-value: {}", error))?
+            }),
+            "USD" => Err("Invalid --min-value: USD thresholds require price enrichment, which is not available".to_string()),
+            _ => Err(format!("Invalid --min-value '{}': unknown unit '{}', expected RON", text, unit))
+        }
+    }
+
+    /// The RON moved by a transaction in either direction: its native value plus any WRON
+    /// transfers from or to the wallet. Fees are not counted.
+    pub fn moved_value(tx: &RRDecodedTransaction, value: Option<U256>, wallet: &str) -> U256 {
+        let wrapped = tx.output.iter().flat_map(|receipt| receipt.logs.iter())
+            // Wrapped RON moves RON-denominated value through `Transfer` logs.
+            .filter(|log| log.address.as_deref().and_then(registry::label) == Some("wron"))
+            .filter_map(|log| transfer_amount(log, wallet))
+            .fold(U256::zero(), |total, amount| total.saturating_add(amount));
+
+        value.unwrap_or_default().saturating_add(wrapped)
+    }
+
+    pub fn keep(&self, tx: &RRDecodedTransaction, value: Option<U256>, wallet: &str) -> bool {
+        MinValueFilter::moved_value(tx, value, wallet) >= self.threshold
+    }
+}
+
+/// Keeps transactions by their counterparty. Excluded counterparties always win over included
+/// ones, and an empty include list keeps everything that is not excluded.
+pub struct CounterpartyFilter {
+    include: HashSet<String>,
+    exclude: HashSet<String>,
+}
+
+impl CounterpartyFilter {
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> CounterpartyFilter {
+        CounterpartyFilter {
+            include: include.iter().map(|address| address_key(address)).collect(),
+            exclude: exclude.iter().map(|address| address_key(address)).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn keep(&self, tx: &RRDecodedTransaction, wallet: &str) -> bool {
+        self.keeps(counterparty(tx, wallet))
+    }
+
+    pub fn keeps(&self, counterparty: &str) -> bool {
+        let counterparty = address_key(counterparty);
+
+        if self.exclude.contains(&counterparty) {
+            return false;
+        }
+
+        self.include.is_empty() || self.include.contains(&counterparty)
+    }
+}
+
+/// Keeps transactions whose receipt contains a log of one of the given events, matched by name
+/// against decoded logs and by topic0 against raw logs.
+pub struct EventFilter {
+    names: HashSet<String>,
+    topics: HashSet<String>,
+}
+
+fn topic0(log: &Value) -> Option<&str> {
+    log.get("topics").and_then(Value::as_array).and_then(|topics| topics.first()).and_then(Value::as_str)
+}
+
+impl EventFilter {
+    pub fn new(names: Vec<String>, topics: Vec<String>) -> EventFilter {
+        let mut filter = EventFilter {
+            names: names.iter().map(|name| name.to_lowercase()).collect(),
+            topics: topics.iter().map(|topic| topic.to_lowercase()).collect(),
+        };
+
+        for (name, topic) in EVENT_TOPICS {
+            if filter.names.contains(&name.to_lowercase()) {
+                filter.topics.insert(topic.to_string());
+            }
+            if filter.topics.contains(*topic) {
+                filter.names.insert(name.to_lowercase());
+            }
+        }
+
+        filter
+    }
+
+    /// Whether the transaction has a matching log, or `None` when its receipt is not available.
+    pub fn matches(&self, tx: &RRDecodedTransaction) -> Option<bool> {
+        if tx.output.is_none() && tx.raw_logs.is_none() {
+            return None;
+        }
+
+        let raw = tx.raw_logs.as_ref().map(receipt_logs).unwrap_or_default();
+
+        Some(decoded_logs(tx).chain(raw).any(|log| {
+            log_name(log).is_some_and(|name| self.names.contains(&name.to_lowercase()))
+                || topic0(log).is_some_and(|topic| self.topics.contains(&topic.to_lowercase()))
+        }))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ContractMatch {
+    /// The transaction called the contract.
+    Direct,
+    /// The contract only emitted a log, e.g. behind a proxy or router.
+    Logs,
+}
+
+/// Keeps transactions interacting with one of the given contracts, directly or through logs.
+pub struct ContractFilter {
+    contracts: HashSet<String>,
+}
+
+impl ContractFilter {
+    pub fn new(contracts: Vec<String>) -> ContractFilter {
+        ContractFilter {
+            contracts: contracts.iter().map(|address| address_key(address)).collect(),
+        }
+    }
+
+    pub fn contains(&self, address: &str) -> bool {
+        self.contracts.contains(&address_key(address))
+    }
+
+    pub fn matches(&self, tx: &RRDecodedTransaction) -> Option<ContractMatch> {
+        // A contract creation has no recipient, it is direct for the deployed contract.
+        let created = tx.output.as_ref().and_then(|receipt| receipt.contract_address.as_deref());
+
+        if self.contracts.contains(&address_key(&tx.to)) || created.is_some_and(|address| self.contracts.contains(&address_key(address))) {
+            return Some(ContractMatch::Direct);
+        }
+
+        let raw = tx.raw_logs.as_ref().map(receipt_logs).unwrap_or_default();
+
+        match decoded_logs(tx).chain(raw).filter_map(log_address).any(|address| self.contracts.contains(&address_key(address))) {
+            true => Some(ContractMatch::Logs),
+            false => None
+        }
+    }
+}
+
+/// Why the filters of the command line left a record out.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Skip {
+    Listed,
+    Noise,
+    Counterparty,
+    /// `--event` needs the receipt, which the record doesn't have.
+    WithoutReceipt,
+    Event,
+    Contract,
+    Expression,
+}
+
+/// The record filters of the command line, applied the same way by exports and `wally convert`.
+pub struct RecordFilters {
+    pub exclude_failed: bool,
+    pub spam: Option<SpamFilter>,
+    pub min_value: Option<MinValueFilter>,
+    pub counterparty: CounterpartyFilter,
+    pub contract: Option<ContractFilter>,
+    /// Several --filter expressions must all match.
+    pub expressions: Vec<Expression>,
+    pub event: Option<EventFilter>,
+}
+
+impl RecordFilters {
+    pub fn from_args() -> RecordFilters {
+        let spam = match ArgParser::flag("--skip-spam") {
+            true => Some(SpamFilter::new(ArgParser::split(&"--spam-list".to_string()))),
+            false => None
+        };
+        let min_value = ArgParser::split(&"--min-value".to_string()).map(|threshold| {
+            MinValueFilter::parse(&threshold).unwrap_or_else(|error| panic!("{}", error))
+        });
+        let counterparty = {
+            let mut include = ArgParser::all("--counterparty");
+            let mut exclude = ArgParser::all("--exclude-counterparty");
+
+            if let Some(file) = ArgParser::split(&"--counterparty-file".to_string()) {
+                include.extend(read_address_list(&file));
+            }
+            if let Some(file) = ArgParser::split(&"--exclude-file".to_string()) {
+                exclude.extend(read_address_list(&file));
+            }
+
+            CounterpartyFilter::new(include, exclude)
+        };
+        let contract = match ArgParser::all("--contract") {
+            contracts if contracts.is_empty() => None,
+            contracts => Some(ContractFilter::new(contracts.iter().map(|contract| match normalize_address(contract).parse::<Address>() {
+                Ok(_) => normalize_address(contract),
+                Err(_) => panic!("Could not parse contract address {}!", contract)
+            }).collect()))
+        };
+        let expressions = ArgParser::all("--filter").iter().map(|text| {
+            Expression::parse(text).unwrap_or_else(|error| panic!("{}", error))
+        }).collect();
+        let event = {
+            let names = ArgParser::all("--event");
+            let topics = ArgParser::all("--event-topic");
+
+            match names.is_empty() && topics.is_empty() {
+                true => None,
+                false => Some(EventFilter::new(names, topics))
+            }
+        };
+
+        RecordFilters { exclude_failed: ArgParser::flag("--exclude-failed"), spam, min_value, counterparty, contract, expressions, event }
+    }
+
+    /// Applies the filters in order, the first one rejecting the record decides why it is
+    /// skipped. A kept record comes with how it matched `--contract`. `--exclude-failed` and
+    /// `--min-value` are checked separately, before and after the matches are counted.
+    pub fn check(&self, tx: &RRDecodedTransaction, value: Option<U256>, wallet: &str) -> Result<Option<ContractMatch>, Skip> {
+        let event_match = self.event.as_ref().map(|filter| filter.matches(tx));
+        let contract_match = self.contract.as_ref().map(|filter| filter.matches(tx));
+
+        match self.spam.as_ref().and_then(|filter| filter.classify(tx, value, wallet)) {
+            Some(SpamReason::Listed) => Err(Skip::Listed),
+            Some(SpamReason::Noise) => Err(Skip::Noise),
+            None if !self.counterparty.keep(tx, wallet) => Err(Skip::Counterparty),
+            None if event_match == Some(None) => Err(Skip::WithoutReceipt),
+            None if event_match == Some(Some(false)) => Err(Skip::Event),
+            None if contract_match == Some(None) => Err(Skip::Contract),
+            None if !self.expressions.iter().all(|expression| expression.matches(tx, value, wallet)) => Err(Skip::Expression),
+            None => Ok(contract_match.flatten())
+        }
+    }
+
+    pub fn below_min_value(&self, tx: &RRDecodedTransaction, value: Option<U256>, wallet: &str) -> bool {
+        !self.min_value.as_ref().is_none_or(|filter| filter.keep(tx, value, wallet))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::testing::{record, OTHER, WALLET};
+    use super::*;
+
+    const THIRD: &str = "0x3333333333333333333333333333333333333333";
+
+    fn filter(include: &[&str], exclude: &[&str]) -> CounterpartyFilter {
+        CounterpartyFilter::new(include.iter().map(|address| address.to_string()).collect(), exclude.iter().map(|address| address.to_string()).collect())
+    }
+
+    #[test]
+    fn empty_filter_keeps_everything() {
+        let filter = filter(&[], &[]);
+        assert!(filter.is_empty());
+        assert!(filter.keeps(OTHER));
+        assert!(filter.keeps(THIRD));
+    }
+
+    #[test]
+    fn include_keeps_only_listed_counterparties() {
+        let filter = filter(&[OTHER], &[]);
+        assert!(filter.keeps(OTHER));
+        assert!(!filter.keeps(THIRD));
+    }
+
+    #[test]
+    fn exclude_drops_only_listed_counterparties() {
+        let filter = filter(&[], &[OTHER]);
+        assert!(!filter.keeps(OTHER));
+        assert!(filter.keeps(THIRD));
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        let filter = filter(&[OTHER, THIRD], &[OTHER]);
+        assert!(!filter.keeps(OTHER));
+        assert!(filter.keeps(THIRD));
+    }
+
+    #[test]
+    fn addresses_compare_normalized() {
+        let filter = filter(&[&OTHER.to_uppercase().replace("0X", "0x")], &[&THIRD.replace("0x", "ronin:")]);
+        assert!(filter.keeps(OTHER));
+        assert!(!filter.keeps(&THIRD.to_uppercase().replace("0X", "0x")));
+    }
+
+    #[test]
+    fn keep_uses_the_counterparty_of_the_wallet() {
+        let filter = filter(&[], &[THIRD]);
+        let sent = record(1, 100, json!({ "to": THIRD }));
+        let received = record(2, 100, json!({ "from": THIRD, "to": WALLET }));
+        let unrelated = record(3, 100, json!({}));
+
+        assert!(!filter.keep(&sent, WALLET));
+        assert!(!filter.keep(&received, WALLET));
+        assert!(filter.keep(&unrelated, WALLET));
+    }
+
+    #[test]
+    fn spam_list_extends_the_builtin_list() {
+        let directory = tempfile::tempdir().unwrap();
+        let list = directory.path().join("spam.txt");
+        std::fs::write(&list, format!("# airdrops\n\n{}\n", OTHER.replace("0x", "ronin:"))).unwrap();
+
+        let builtin = THIRD.to_uppercase().replace("0X", "0x");
+        let filter = SpamFilter::with_builtin(&[&builtin], Some(list.display().to_string()));
+        assert!(filter.is_listed(THIRD));
+        assert!(filter.is_listed(&OTHER.to_uppercase().replace("0X", "0x")));
+        assert!(!filter.is_listed(WALLET));
+
+        let builtin_only = SpamFilter::with_builtin(&[&builtin], None);
+        assert!(builtin_only.is_listed(THIRD));
+        assert!(!builtin_only.is_listed(OTHER));
+    }
+}
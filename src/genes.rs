@@ -0,0 +1,126 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use serde_json::{json, Map, Value};
+use web3::types::U256;
+use crate::provider::WalletDataProvider;
+use crate::receipt::{self, RRLog, RRReceipt};
+use crate::{logging, parse_amount, registry, ArgParser};
+
+/// The classes by their 4 bit gene.
+const CLASSES: &[(u64, &str)] = &[
+    (0b0000, "beast"),
+    (0b0001, "bug"),
+    (0b0010, "bird"),
+    (0b0011, "plant"),
+    (0b0100, "aquatic"),
+    (0b0101, "reptile"),
+    (0b1000, "mech"),
+    (0b1001, "dawn"),
+    (0b1010, "dusk"),
+];
+
+/// The body parts in the order of their genes, 32 bits each after the first 64 bits.
+const PARTS: [&str; 6] = ["eyes", "mouth", "ears", "horn", "back", "tail"];
+
+/// Whether Axie transfers are enriched with the decoded genes, `--decode-genes`.
+pub fn enabled() -> bool {
+    ArgParser::flag("--decode-genes")
+}
+
+/// `length` bits of the 256 bit `genes` starting at bit `start`, counted from the most significant.
+fn bits(genes: U256, start: usize, length: usize) -> u64 {
+    ((genes >> (256 - start - length)) & ((U256::one() << length) - 1)).low_u64()
+}
+
+fn class(gene: u64) -> Result<&'static str, String> {
+    CLASSES.iter().find(|(code, _)| *code == gene).map(|(_, class)| *class).ok_or_else(|| format!("unknown class gene {:04b}", gene))
+}
+
+/// The class of a part gene and the number of the part within the class.
+fn part_gene(genes: U256, start: usize) -> Result<Value, String> {
+    Ok(json!({ "class": class(bits(genes, start, 4))?, "part": bits(genes, start + 4, 6) }))
+}
+
+/// Decodes the 256 bit genes of an axie into its class, the dominant and recessive genes of its
+/// parts and its purity, the number of dominant parts of its own class. Part names aren't resolved,
+/// a part is its class and number within the class. The 512 bit genes of newer axies aren't
+/// supported.
+pub fn decode(genes: &str) -> Result<Value, String> {
+    let hex = genes.strip_prefix("0x").ok_or_else(|| format!("genes {} are not hex", genes))?.trim_start_matches('0');
+    let hex = if hex.is_empty() { "0" } else { hex };
+    if hex.len() > 64 {
+        return Err(format!("only 256 bit genes are supported, got {} bits", hex.len() * 4));
+    }
+    let genes = U256::from_str_radix(hex, 16).map_err(|error| format!("could not parse the genes {}: {}", genes, error))?;
+
+    let axie_class = class(bits(genes, 0, 4))?;
+    let mut parts = Map::new();
+    let mut purity = 0;
+
+    for (index, part) in PARTS.iter().enumerate() {
+        // 2 bits of skin, then the dominant, first and second recessive genes of 10 bits.
+        let start = 64 + index * 32 + 2;
+        let dominant = part_gene(genes, start)?;
+        if dominant["class"] == axie_class {
+            purity += 1;
+        }
+        parts.insert(part.to_string(), json!({
+            "dominant": dominant,
+            "r1": part_gene(genes, start + 10)?,
+            "r2": part_gene(genes, start + 20)?,
+        }));
+    }
+
+    Ok(json!({ "class": axie_class, "purity": purity, "parts": parts }))
+}
+
+/// The token ID of an ERC-721 `Transfer` log of the Axie contract.
+fn token_id(log: &RRLog) -> Option<U256> {
+    if log.name.as_deref() != Some("Transfer") || log.address.as_deref().and_then(registry::label) != Some("axie") {
+        return None;
+    }
+
+    log.params.iter()
+        .find(|(name, _)| matches!(name.trim_start_matches('_').to_lowercase().as_str(), "tokenid" | "id"))
+        .and_then(|(_, value)| parse_amount(value))
+}
+
+/// Attaches an `axie` object with the token ID and the decoded genes to the Axie transfers of
+/// `receipt`. `cache` keeps the genes by token ID for the run, since an axie is often transferred
+/// more than once. If the genes can't be fetched or decoded the object only has the token ID.
+pub async fn attach(receipt: &mut RRReceipt, rr: &dyn WalletDataProvider, cache: &mut HashMap<U256, Option<Value>>) {
+    let token_ids: Vec<Option<U256>> = receipt.logs.iter().map(token_id).collect();
+    if token_ids.iter().all(Option::is_none) {
+        return;
+    }
+
+    let mut axies = vec![];
+    for token_id in token_ids {
+        let Some(token_id) = token_id else {
+            axies.push(None);
+            continue;
+        };
+
+        if let Entry::Vacant(entry) = cache.entry(token_id) {
+            let decoded = rr.axie_genes(&token_id.to_string()).await.and_then(|genes| decode(&genes));
+            if let Err(error) = &decoded {
+                logging::warn(&format!("Could not decode the genes of axie {}: {}", token_id, error));
+            }
+            entry.insert(decoded.ok());
+        }
+
+        let mut axie = json!({ "id": token_id.to_string() });
+        if let Some(Value::Object(decoded)) = &cache[&token_id] {
+            axie.as_object_mut().unwrap().extend(decoded.clone());
+        }
+        axies.push(Some(axie));
+    }
+
+    receipt.edit(|raw| {
+        for (log, axie) in receipt::logs_mut(raw).into_iter().flatten().zip(axies) {
+            if let (Some(log), Some(axie)) = (log.as_object_mut(), axie) {
+                log.insert("axie".to_string(), axie);
+            }
+        }
+    });
+}
@@ -0,0 +1,332 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
+use reqwest_retry::policies::ExponentialBackoff;
+use reqwest_retry::RetryTransientMiddleware;
+use serde_json::{json, Value};
+use web3::types::U256;
+use crate::block::RRBlock;
+use crate::provider::{Capabilities, WalletDataProvider};
+use crate::receipt::RRReceipt;
+use crate::transfers::{TokenStandard, TransferList};
+use crate::{compression, hashes, logging, parse_amount, parse_quantity, pipeline, traffic, ArgParser, RRTransaction, RRTransactionDict, RRTransactionHash, DEFAULT_USER_AGENT};
+
+/// Transactions requested per page unless `--page-size` is passed.
+const PAGE_SIZE: usize = 100;
+
+/// The queries run against `--graphql-url`, replaceable with `--graphql-query=NAME=FILE`. The
+/// result of a query is its first field, see `GraphQl::query`.
+const QUERIES: &[(&str, &str)] = &[
+    ("transactions", "query Transactions($address: String!, $direction: String!, $first: Int!, $after: String) {
+  transactions(address: $address, direction: $direction, first: $first, after: $after) {
+    totalCount
+    pageInfo { hasNextPage endCursor }
+    nodes { hash }
+  }
+}"),
+    ("transaction", "query Transaction($hash: String!) {
+  transaction(hash: $hash) { hash from to blockNumber nonce transactionIndex value gasPrice payer }
+}"),
+    ("receipt", "query Receipt($hash: String!) {
+  receipt(hash: $hash) { status gasUsed effectiveGasPrice contractAddress logs }
+}"),
+    ("decodedInput", "query DecodedInput($hash: String!) {
+  decodedInput(hash: $hash)
+}"),
+    ("latestBlock", "query LatestBlock {
+  latestBlock { number }
+}"),
+    ("balance", "query Balance($address: String!) {
+  balance(address: $address)
+}"),
+];
+
+/// The queries of the run, the defaults of `QUERIES` with the files of `--graphql-query`.
+fn queries_from_args() -> BTreeMap<&'static str, String> {
+    let mut queries: BTreeMap<&'static str, String> = QUERIES.iter().map(|(name, query)| (*name, query.to_string())).collect();
+
+    for value in ArgParser::all("--graphql-query") {
+        let (name, path) = value.split_once('=').unwrap_or_else(|| panic!("Invalid --graphql-query value {}, expected NAME=FILE", value));
+        let names: Vec<&str> = QUERIES.iter().map(|(name, _)| *name).collect();
+        let name = names.iter().find(|known| **known == name).unwrap_or_else(|| {
+            panic!("Invalid --graphql-query name {}, expected one of {}", name, names.join(", "))
+        });
+        let query = std::fs::read_to_string(path).unwrap_or_else(|error| panic!("Could not read the --graphql-query file {}: {}", path, error));
+
+        queries.insert(name, query);
+    }
+
+    queries
+}
+
+/// The hashes of a page of `transactions`, either the `nodes` or the `edges` of a connection.
+/// Nodes may be objects with a `hash` or the hashes themselves.
+fn page_hashes(page: &Value) -> Result<Vec<String>, String> {
+    let nodes: Vec<&Value> = match (page.get("nodes").and_then(Value::as_array), page.get("edges").and_then(Value::as_array)) {
+        (Some(nodes), _) => nodes.iter().collect(),
+        (None, Some(edges)) => edges.iter().filter_map(|edge| edge.get("node")).collect(),
+        (None, None) => return Err(format!("expected a connection with nodes or edges, got {}", page))
+    };
+
+    nodes.into_iter().map(|node| node.get("hash").unwrap_or(node).as_str().map(str::to_string).ok_or_else(|| format!("expected a hash, got {}", node))).collect()
+}
+
+/// A GraphQL indexer at `--graphql-url`, selected with `--provider=graphql`. Transaction lists
+/// are paged with the cursors of the `pageInfo`, and all results are read into the same structs
+/// as the ones of ronin.rest so every format and report works unchanged.
+pub struct GraphQl {
+    url: String,
+    queries: BTreeMap<&'static str, String>,
+    client: ClientWithMiddleware,
+    /// The same connection pool without retries, for the health check.
+    http: reqwest::Client,
+    page_size: usize,
+    requests: AtomicU64,
+}
+
+impl GraphQl {
+    pub fn from_args() -> GraphQl {
+        let url = ArgParser::all("--graphql-url").pop().unwrap_or_else(|| panic!("--provider=graphql requires --graphql-url"));
+        if !ArgParser::all("--host").is_empty() || ArgParser::flag("--localhost") || ArgParser::split(&"--unix-socket".to_string()).is_some() {
+            panic!("--provider=graphql can not be combined with --host, --localhost or --unix-socket");
+        }
+        let page_size = match ArgParser::split(&"--page-size".to_string()) {
+            Some(page_size) => match page_size.parse::<usize>() {
+                Ok(page_size) if page_size > 0 => page_size,
+                _ => panic!("Could not parse --page-size={}, expected a positive number of transactions", page_size)
+            },
+            None => PAGE_SIZE
+        };
+
+        println!(">> Using the GraphQL API at {} <<", url);
+
+        let http = compression::client().build().expect("Could not create the HTTP client!");
+        let max_retries = match pipeline::tx_max_attempts() {
+            None => 25,
+            Some(_) => 3
+        };
+
+        GraphQl {
+            client: ClientBuilder::new(http.clone()).with(
+                RetryTransientMiddleware::new_with_policy(
+                    ExponentialBackoff {
+                        max_n_retries: max_retries,
+                        min_retry_interval: Duration::from_secs(1),
+                        max_retry_interval: Duration::from_secs(15),
+                        backoff_exponent: 2
+                    }
+                )
+            ).with(traffic::Counter).build(),
+            http,
+            url,
+            queries: queries_from_args(),
+            page_size,
+            requests: AtomicU64::new(0),
+        }
+    }
+
+    /// Runs the query `name` and returns its result, the first field of `data`. A response with
+    /// `errors` fails even if it carries partial data, an export must not silently miss fields.
+    async fn query(&self, name: &str, variables: Value) -> Result<Value, String> {
+        let path = format!("/graphql/{}", name);
+        let body = json!({ "query": self.queries[name], "variables": variables }).to_string();
+
+        traffic::requested(&path);
+        self.requests.fetch_add(1, Ordering::Relaxed);
+
+        let mut extensions = task_local_extensions::Extensions::new();
+        extensions.insert(traffic::ApiPath(path.clone()));
+
+        let response = self.client.post(&self.url)
+            .header("user-agent", DEFAULT_USER_AGENT)
+            .header("content-type", "application/json")
+            .body(body)
+            .send_with_extensions(&mut extensions).await
+            .map_err(|error| format!("Could not run the GraphQL query {}: {}", name, error))?;
+
+        let status = response.status();
+        let text = response.text().await.map_err(|error| format!("Could not read the response to the GraphQL query {}: {}", name, error))?;
+        traffic::downloaded(&path, text.len());
+
+        // Servers answer errors with 200 or 400, both with an `errors` list.
+        let response: Value = serde_json::from_str(&text).map_err(|_| format!("Unexpected response ({}) to the GraphQL query {}", status, name))?;
+
+        if let Some(errors) = response.get("errors").and_then(Value::as_array).filter(|errors| !errors.is_empty()) {
+            let messages: Vec<String> = errors.iter().map(|error| match error.get("message").and_then(Value::as_str) {
+                Some(message) => message.to_string(),
+                None => error.to_string()
+            }).collect();
+            return Err(format!("The GraphQL query {} failed: {}", name, messages.join("; ")));
+        }
+
+        response.get("data").and_then(Value::as_object).and_then(|data| data.values().next()).cloned()
+            .ok_or_else(|| format!("The response to the GraphQL query {} has no data", name))
+    }
+
+    /// Fetches the hashes sent or received by `address`, page by page. Pages are deduplicated and
+    /// a cursor that repeats or goes missing is reported in `issues`.
+    async fn transaction_list(&self, list: &str, direction: &str, address: &str) -> RRTransactionDict {
+        let progress = indicatif::ProgressBar::new_spinner();
+        let mut data = RRTransactionDict::default();
+        let mut seen: HashSet<RRTransactionHash> = HashSet::new();
+        let mut cursors: HashSet<String> = HashSet::new();
+        let mut after: Option<String> = None;
+        let mut entries = vec![];
+
+        for page in 1.. {
+            let variables = json!({ "address": address, "direction": direction, "first": self.page_size, "after": after });
+            let result = self.query("transactions", variables).await.unwrap_or_else(|error| panic!("{}", error));
+            let hashes = page_hashes(&result).unwrap_or_else(|error| panic!("Could not parse page {} of {} for {}: {}", page, list, address, error));
+
+            data.total = data.total.or(result.get("totalCount").and_then(Value::as_u64).map(|total| total as usize));
+
+            for hash in hashes {
+                if seen.insert(hash.to_lowercase()) {
+                    entries.push(hash);
+                }
+            }
+
+            progress.set_message(format!("{} {}: {} transactions ({} pages)", list, address, entries.len(), page));
+            progress.tick();
+
+            let page_info = result.get("pageInfo");
+            if !page_info.and_then(|info| info.get("hasNextPage")).and_then(Value::as_bool).unwrap_or(false) {
+                break;
+            }
+            match page_info.and_then(|info| info.get("endCursor")).and_then(Value::as_str) {
+                Some(cursor) if cursors.insert(cursor.to_string()) => after = Some(cursor.to_string()),
+                Some(cursor) => {
+                    data.issues.push(format!("{}: page {} repeated the cursor {}, the list may be incomplete", list, page, cursor));
+                    break;
+                }
+                None => {
+                    data.issues.push(format!("{}: page {} has a next page but no end cursor, the list may be incomplete", list, page));
+                    break;
+                }
+            }
+        }
+
+        progress.finish_and_clear();
+
+        if let Some(total) = data.total {
+            if total != entries.len() {
+                data.issues.push(format!("{}: the API reported {} transactions but {} were received", list, total, entries.len()));
+            }
+        }
+
+        (data.transactions, data.rejected) = hashes::sanitize(list, entries);
+        for rejected in &data.rejected {
+            logging::warn(&format!("Dropped the entry '{}' of {} for {}: {}", rejected.entry, list, address, rejected.reason));
+        }
+
+        data
+    }
+}
+
+#[async_trait::async_trait]
+impl WalletDataProvider for GraphQl {
+    fn name(&self) -> &'static str {
+        "graphql"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { decode: true, raw_logs: false, transaction_fields: false, blocks: false, genes: false, abis: false, token_transfers: false }
+    }
+
+    /// Checks that the URL answers `{ __typename }`, which every GraphQL server does.
+    async fn health_check(&self) -> Result<(), String> {
+        let path = "/graphql/__typename";
+        traffic::requested(path);
+        traffic::attempted(path);
+
+        let response = self.http.post(&self.url)
+            .header("user-agent", DEFAULT_USER_AGENT)
+            .header("content-type", "application/json")
+            .body(json!({ "query": "{ __typename }" }).to_string())
+            .timeout(crate::HEALTH_CHECK_TIMEOUT)
+            .send().await
+            .map_err(|error| format!("Could not reach {}: {}", self.url, crate::diagnostics::describe(&error, &self.url)))?;
+
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        traffic::downloaded(path, body.len());
+
+        match serde_json::from_str::<Value>(&body).ok().and_then(|body| body.pointer("/data/__typename").cloned()) {
+            Some(Value::String(_)) => Ok(()),
+            _ => Err(format!("{} does not look like a GraphQL API: unexpected response ({})", self.url, status))
+        }
+    }
+
+    fn active_host(&self) -> &str {
+        &self.url
+    }
+
+    fn host_requests(&self) -> Vec<(&str, u64)> {
+        vec![(self.url.as_str(), self.requests.load(Ordering::Relaxed))]
+    }
+
+    async fn list_sent(&self, address: &str) -> RRTransactionDict {
+        self.transaction_list("listSentTransactions", "sent", address).await
+    }
+
+    async fn list_received(&self, address: &str) -> RRTransactionDict {
+        self.transaction_list("listReceivedTransactions", "received", address).await
+    }
+
+    /// Fetches a transaction. Transactions the indexer has no details for are `RRTransaction::unavailable`.
+    async fn get_transaction(&self, hash: &RRTransactionHash) -> Result<RRTransaction, String> {
+        let result = self.query("transaction", json!({ "hash": hash })).await?;
+
+        Ok(serde_json::from_value(result).unwrap_or_else(|_| RRTransaction::unavailable()))
+    }
+
+    async fn get_transaction_fields(&self, _hash: &RRTransactionHash) -> Result<(RRTransaction, Value), String> {
+        Err("The graphql provider doesn't deliver all fields of a transaction".to_string())
+    }
+
+    async fn decode_input(&self, hash: &RRTransactionHash) -> Result<Value, String> {
+        self.query("decodedInput", json!({ "hash": hash })).await
+    }
+
+    async fn decode_receipt(&self, hash: &RRTransactionHash) -> Result<RRReceipt, String> {
+        let result = self.query("receipt", json!({ "hash": hash })).await?;
+
+        serde_json::from_value(result).map_err(|error| format!("Could not parse the decoded receipt of {}: {}", hash, error))
+    }
+
+    async fn raw_logs(&self, _hash: &RRTransactionHash) -> Result<Value, String> {
+        Err("The graphql provider doesn't deliver raw logs".to_string())
+    }
+
+    async fn get_block(&self, _number: u64) -> Result<RRBlock, String> {
+        Err("The graphql provider doesn't deliver blocks".to_string())
+    }
+
+    async fn axie_genes(&self, _token_id: &str) -> Result<String, String> {
+        Err("The graphql provider doesn't deliver axie genes".to_string())
+    }
+
+    async fn contract_abi(&self, _address: &str) -> Result<Value, String> {
+        Err("The graphql provider doesn't deliver contract ABIs".to_string())
+    }
+
+    async fn token_transfers(&self, _standard: TokenStandard, _address: &str) -> Result<TransferList, String> {
+        Err("The graphql provider doesn't deliver token transfer lists".to_string())
+    }
+
+    /// The number of the latest block, a quantity or an object with a `number` or `blockNumber`.
+    async fn latest_block(&self) -> Result<u64, String> {
+        let result = self.query("latestBlock", json!({})).await?;
+        let number = result.get("number").or_else(|| result.get("blockNumber")).unwrap_or(&result);
+
+        parse_quantity(number).ok_or_else(|| format!("Could not parse the latest block number {}", result))
+    }
+
+    /// The current RON balance of `address` in wei.
+    async fn balance(&self, address: &str) -> Result<U256, String> {
+        let result = self.query("balance", json!({ "address": address })).await?;
+
+        parse_amount(result.get("balance").unwrap_or(&result)).ok_or_else(|| format!("Could not parse the balance {} of {}", result, address))
+    }
+}
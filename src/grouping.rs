@@ -0,0 +1,231 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use serde::Serialize;
+use serde_json::Value;
+use crate::anonymize::Anonymizer;
+use crate::export::FORMAT_VERSION;
+use crate::network::{self, Network};
+use crate::output::Output;
+use crate::sort::{KWayMerge, RecordSorter};
+use crate::{filters, registry};
+use crate::{serialize_record, ArgParser, RRDecodedTransaction};
+
+/// The group of transactions without a decoded method.
+const UNKNOWN: &str = "unknown";
+
+/// The counterparty group of transactions from the wallet to itself. Self transfers are not
+/// exported, but the addresses of a record may still only differ in their case.
+const SELF: &str = "self";
+
+/// The counterparty group of transactions deploying a contract, which have no recipient.
+const CONTRACT_CREATION: &str = "contract-creation";
+
+/// The per-contract file of the contracts with fewer transactions than `--min-group-size`.
+const OTHER: &str = "other";
+
+/// Names of per-contract files which aren't available to contracts.
+const RESERVED: &[&str] = &[OTHER, "index"];
+
+/// How `--group-by` splits an export.
+pub enum GroupBy {
+    Method,
+    Counterparty,
+    /// Like `Counterparty`, but always keyed by the address, for `--per-contract`.
+    Contract,
+    /// By `sent` and `received`, for `wally split`.
+    Direction,
+}
+
+impl GroupBy {
+    pub fn from_args() -> Option<GroupBy> {
+        ArgParser::split(&"--group-by".to_string()).map(|group_by| match group_by.to_lowercase().as_str() {
+            "method" => GroupBy::Method,
+            "counterparty" => GroupBy::Counterparty,
+            _ => panic!("Invalid --group-by value {}, expected method or counterparty", group_by)
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GroupBy::Method => "method",
+            GroupBy::Counterparty => "counterparty",
+            GroupBy::Contract => "contract",
+            GroupBy::Direction => "direction",
+        }
+    }
+
+    /// Counterparties are keyed by their registry label if known, by their address otherwise.
+    /// Directions are those of the attributed wallet in merged exports.
+    pub fn key(&self, record: &RRDecodedTransaction, wallet: &str) -> String {
+        match self {
+            GroupBy::Method => record.input.as_ref()
+                .and_then(filters::method_name)
+                .unwrap_or(UNKNOWN)
+                .to_string(),
+            GroupBy::Counterparty => {
+                let key = counterparty_key(record, wallet);
+                registry::label(&key).map_or(key, str::to_string)
+            }
+            GroupBy::Contract => counterparty_key(record, wallet),
+            GroupBy::Direction => match serde_json::to_value(record.direction) {
+                Ok(Value::String(direction)) => direction,
+                _ => unreachable!("Directions serialize to strings")
+            },
+        }
+    }
+}
+
+/// The lowercase address on the other side of a transaction, or its special group.
+fn counterparty_key(record: &RRDecodedTransaction, wallet: &str) -> String {
+    let counterparty = filters::counterparty(record, wallet);
+
+    match counterparty {
+        "" | "null" => CONTRACT_CREATION.to_string(),
+        _ if counterparty.eq_ignore_ascii_case(wallet) => SELF.to_string(),
+        _ => counterparty.to_lowercase()
+    }
+}
+
+/// The sorted transactions of one group.
+pub struct Group {
+    pub records: usize,
+    pub sorter: RecordSorter,
+}
+
+/// Distributes the records of `wallet` into their groups, ordered by the group key. Address keys
+/// are replaced by their pseudonym in anonymized exports.
+pub fn group(
+    records: impl Iterator<Item = RRDecodedTransaction>,
+    group_by: &GroupBy,
+    wallet: &str,
+    mut anonymizer: Option<&mut Anonymizer>,
+) -> BTreeMap<String, Group> {
+    let mut groups: BTreeMap<String, Group> = BTreeMap::new();
+
+    for record in records {
+        let mut key = group_by.key(&record, wallet);
+        if let Some(anonymizer) = anonymizer.as_mut() {
+            if let Value::String(pseudonym) = anonymizer.anonymize(Value::String(key.clone())) {
+                key = pseudonym;
+            }
+        }
+
+        let group = groups.entry(key).or_insert_with(|| Group { records: 0, sorter: RecordSorter::new() });
+        group.records += 1;
+        group.sorter.push(record);
+    }
+
+    groups
+}
+
+/// Turns a group key into something usable in a file name.
+pub fn file_name(key: &str) -> String {
+    key.chars().map(|c| match c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+        true => c,
+        false => '_'
+    }).collect()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ContractFile {
+    file: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<&'static str>,
+    addresses: Vec<String>,
+    records: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ContractIndex {
+    format_version: u32,
+    network: Network,
+    files: Vec<ContractFile>,
+}
+
+/// Writes the transactions of a wallet to one NDJSON file per contract with `--per-contract`.
+pub struct PerContract {
+    /// Contracts with fewer transactions are merged into `other.ndjson`.
+    min_group_size: usize,
+}
+
+impl PerContract {
+    pub fn from_args() -> Option<PerContract> {
+        let min_group_size = ArgParser::split(&"--min-group-size".to_string()).map(|size| {
+            size.parse().unwrap_or_else(|_| panic!("Invalid --min-group-size value {}", size))
+        });
+
+        match ArgParser::flag("--per-contract") {
+            true => Some(PerContract { min_group_size: min_group_size.unwrap_or(0) }),
+            false if min_group_size.is_some() => panic!("--min-group-size requires --per-contract"),
+            false => None
+        }
+    }
+
+    /// Writes the groups of `GroupBy::Contract` to `{stem}.contracts/`, one file per contract named
+    /// by its label or else its address, and an `index.json` mapping the files to the addresses.
+    /// Returns the path of the index.
+    pub fn write(&self, output: &Output, stem: &str, groups: BTreeMap<String, Group>, mut anonymizer: Option<&mut Anonymizer>) -> String {
+        let (mut small, mut large): (Vec<_>, Vec<_>) = groups.into_iter()
+            .partition(|(_, group)| group.records < self.min_group_size);
+
+        // Labels may be shared by several contracts, those files get the address appended.
+        let base_name = |key: &str| file_name(registry::label(key).unwrap_or(key)).to_lowercase();
+        let mut uses: HashMap<String, usize> = HashMap::new();
+        for (key, _) in &large {
+            *uses.entry(base_name(key)).or_default() += 1;
+        }
+
+        let directory = format!("{}.contracts", stem);
+        let mut files = vec![];
+
+        for (key, group) in large.iter_mut() {
+            let base = base_name(key);
+            let name = match uses[&base] > 1 || RESERVED.contains(&base.as_str()) {
+                true => format!("{}-{}", base, file_name(key)),
+                false => base
+            };
+
+            let mut file = output.ndjson_writer(Some(stem), &format!("{}/{}.ndjson", directory, name));
+            for record in group.sorter.sorted() {
+                file.push(&serialize_record(&record, anonymizer.as_deref_mut()));
+            }
+
+            files.push(ContractFile {
+                file: file_name_of(&file.finish()),
+                label: registry::label(key),
+                addresses: addresses(key),
+                records: group.records,
+            });
+        }
+
+        if !small.is_empty() {
+            let mut file = output.ndjson_writer(Some(stem), &format!("{}/{}.ndjson", directory, OTHER));
+            for (_, record) in KWayMerge::new(small.iter_mut().map(|(_, group)| group.sorter.sorted()).collect()) {
+                file.push(&serialize_record(&record, anonymizer.as_deref_mut()));
+            }
+
+            files.push(ContractFile {
+                file: file_name_of(&file.finish()),
+                label: None,
+                addresses: small.iter().flat_map(|(key, _)| addresses(key)).collect(),
+                records: small.iter().map(|(_, group)| group.records).sum(),
+            });
+        }
+
+        output.write(Some(stem), format!("{}/index.json", directory), serde_json::to_string_pretty(&ContractIndex { format_version: FORMAT_VERSION, network: network::current(), files }).unwrap())
+    }
+}
+
+/// The addresses behind a group key, none for the special groups.
+fn addresses(key: &str) -> Vec<String> {
+    match key {
+        SELF | CONTRACT_CREATION => vec![],
+        _ => vec![key.to_string()]
+    }
+}
+
+fn file_name_of(path: &str) -> String {
+    Path::new(path).file_name().unwrap().to_string_lossy().to_string()
+}
@@ -0,0 +1,53 @@
+use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use crate::RRTransactionHash;
+
+/// An entry of a transaction list that is not a transaction hash, or one already listed.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RejectedHash {
+    /// The list it came from, e.g. `listSentTransactions`.
+    pub list: String,
+    pub entry: String,
+    pub reason: String,
+}
+
+/// Checks that `entry` is a 0x prefixed 32 byte hex string, returning it in lower case.
+pub fn validate(entry: &str) -> Result<RRTransactionHash, &'static str> {
+    if entry.is_empty() {
+        return Err("empty");
+    }
+
+    let Some(digits) = entry.strip_prefix("0x").or_else(|| entry.strip_prefix("0X")) else {
+        return Err("no 0x prefix");
+    };
+
+    if digits.len() != 64 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err("not 32 bytes of hex");
+    }
+
+    Ok(entry.to_lowercase())
+}
+
+/// Normalizes the hashes of `list`, dropping malformed and repeated entries. Hashes end up in
+/// URLs, so nothing else is passed on.
+pub fn sanitize(list: &str, entries: Vec<String>) -> (Vec<RRTransactionHash>, Vec<RejectedHash>) {
+    let mut hashes = vec![];
+    let mut rejected = vec![];
+    let mut seen: HashSet<RRTransactionHash> = HashSet::new();
+
+    for entry in entries {
+        let reason = match validate(&entry) {
+            Ok(hash) if seen.insert(hash.clone()) => {
+                hashes.push(hash);
+                continue;
+            }
+            Ok(_) => "listed twice",
+            Err(reason) => reason
+        };
+
+        rejected.push(RejectedHash { list: list.to_string(), entry, reason: reason.to_string() });
+    }
+
+    (hashes, rejected)
+}
@@ -0,0 +1,122 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::{logging, ArgParser};
+
+/// Consecutive failed requests after which a host is skipped.
+const FAILOVER_THRESHOLD: usize = 3;
+
+/// How long a failed host is skipped before it is tried again.
+const FAILBACK_COOLDOWN: Duration = Duration::from_secs(60);
+
+pub struct Host {
+    pub url: String,
+    requests: AtomicU64,
+    consecutive_failures: AtomicUsize,
+    cooldown_until: Mutex<Option<Instant>>,
+}
+
+impl Host {
+    fn is_cooling_down(&self) -> bool {
+        self.cooldown_until.lock().unwrap().is_some_and(|until| Instant::now() < until)
+    }
+}
+
+/// A prioritized list of API hosts. Requests go to the first healthy host, a host failing
+/// repeatedly is skipped for a cooldown after which it is preferred again.
+pub struct HostPool {
+    hosts: Vec<Host>,
+    active: AtomicUsize,
+}
+
+impl HostPool {
+    pub fn new(urls: Vec<String>) -> HostPool {
+        HostPool {
+            hosts: urls.into_iter().map(|url| Host {
+                url: url.trim_end_matches('/').to_string(),
+                requests: AtomicU64::new(0),
+                consecutive_failures: AtomicUsize::new(0),
+                cooldown_until: Mutex::new(None),
+            }).collect(),
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    /// The hosts in the order they should be tried for the next request: healthy hosts by priority,
+    /// then hosts in their cooldown as a last resort.
+    pub fn candidates(&self) -> Vec<&Host> {
+        let (healthy, cooling): (Vec<&Host>, Vec<&Host>) = self.hosts.iter().partition(|host| !host.is_cooling_down());
+        healthy.into_iter().chain(cooling).collect()
+    }
+
+    pub fn started(&self, host: &Host) {
+        host.requests.fetch_add(1, Ordering::Relaxed);
+
+        let mut cooldown_until = host.cooldown_until.lock().unwrap();
+        if cooldown_until.is_some_and(|until| Instant::now() >= until) {
+            *cooldown_until = None;
+            println!("Retrying API host {} after its cooldown", host.url);
+            logging::info(&format!("Retrying API host {} after its cooldown", host.url));
+        }
+
+        let index = self.hosts.iter().position(|candidate| std::ptr::eq(candidate, host)).unwrap();
+        self.active.store(index, Ordering::SeqCst);
+    }
+
+    pub fn succeeded(&self, host: &Host) {
+        host.consecutive_failures.store(0, Ordering::SeqCst);
+    }
+
+    pub fn failed(&self, host: &Host, error: &str) {
+        let failures = host.consecutive_failures.fetch_add(1, Ordering::SeqCst) + 1;
+
+        if failures >= FAILOVER_THRESHOLD && self.hosts.len() > 1 {
+            *host.cooldown_until.lock().unwrap() = Some(Instant::now() + FAILBACK_COOLDOWN);
+            host.consecutive_failures.store(0, Ordering::SeqCst);
+            let message = format!(
+                "API host {} failed {} requests in a row ({}), skipping it for {}s",
+                host.url, failures, error, FAILBACK_COOLDOWN.as_secs()
+            );
+            println!("{}", message);
+            logging::warn(&message);
+        }
+    }
+
+    pub fn active(&self) -> &str {
+        &self.hosts[self.active.load(Ordering::SeqCst)].url
+    }
+
+    pub fn urls(&self) -> impl Iterator<Item = &str> {
+        self.hosts.iter().map(|host| host.url.as_str())
+    }
+
+    /// The number of requests sent to each host.
+    pub fn requests(&self) -> Vec<(&str, u64)> {
+        self.hosts.iter().map(|host| (host.url.as_str(), host.requests.load(Ordering::Relaxed))).collect()
+    }
+}
+
+/// The local instance used by `--localhost`, without a value on the default port.
+const DEFAULT_LOCALHOST: &str = "http://localhost:3000";
+
+/// The host of `--localhost`, `--localhost=PORT` or `--localhost=URL`. `--localhost=1` is the
+/// spelling from before the flag took a value and means the default as well.
+pub fn localhost_from_args() -> Option<String> {
+    let value = match ArgParser::split(&"--localhost".to_string()) {
+        Some(value) => value,
+        None if ArgParser::flag("--localhost") => return Some(DEFAULT_LOCALHOST.to_string()),
+        None => return None
+    };
+
+    if value == "1" || value == "true" {
+        return Some(DEFAULT_LOCALHOST.to_string());
+    }
+    if let Some(port) = value.parse::<u16>().ok().filter(|port| *port > 0) {
+        return Some(format!("http://localhost:{}", port));
+    }
+
+    match reqwest::Url::parse(&value) {
+        Ok(url) if (url.scheme() == "http" || url.scheme() == "https") && url.host_str().is_some() => Some(value.trim_end_matches('/').to_string()),
+        _ => panic!("Invalid --localhost value {}, expected a port like 8080 or a URL like https://127.0.0.1:8443", value)
+    }
+}
@@ -0,0 +1,271 @@
+use serde_json::Value;
+use web3::signing::keccak256;
+use web3::types::U256;
+use crate::multicall::{parse_hex, to_hex};
+use crate::{parse_amount, ArgParser};
+
+/// Whether `--verify-hashes` was passed.
+pub fn enabled() -> bool {
+    ArgParser::flag("--verify-hashes")
+}
+
+/// The outcome of recomputing the hash of a transaction from its fields.
+pub enum Verification {
+    Verified,
+    /// The hash of the fields the API returned, which differs from the requested one.
+    Mismatch(String),
+    /// The API did not return the fields needed to recompute the hash.
+    Unverifiable(String),
+}
+
+/// An RLP string or list, see the Ethereum yellow paper, appendix B.
+enum Rlp {
+    Bytes(Vec<u8>),
+    List(Vec<Rlp>),
+}
+
+fn length_prefix(offset: u8, length: usize) -> Vec<u8> {
+    if length < 56 {
+        return vec![offset + length as u8];
+    }
+
+    let length = (length as u64).to_be_bytes();
+    let length = &length[length.iter().position(|byte| *byte != 0).unwrap()..];
+    [&[offset + 55 + length.len() as u8], length].concat()
+}
+
+impl Rlp {
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Rlp::Bytes(bytes) if bytes.len() == 1 && bytes[0] < 0x80 => bytes.clone(),
+            Rlp::Bytes(bytes) => [length_prefix(0x80, bytes.len()), bytes.clone()].concat(),
+            Rlp::List(items) => {
+                let payload: Vec<u8> = items.iter().flat_map(Rlp::encode).collect();
+                [length_prefix(0xc0, payload.len()), payload].concat()
+            }
+        }
+    }
+
+    /// A number, big-endian without leading zeros.
+    fn number(value: U256) -> Rlp {
+        let mut bytes = [0u8; 32];
+        value.to_big_endian(&mut bytes);
+        Rlp::Bytes(bytes[(value.leading_zeros() / 8) as usize..].to_vec())
+    }
+}
+
+/// The fields of a transaction as returned by getTransaction.
+struct Fields<'a>(&'a Value);
+
+impl Fields<'_> {
+    fn get(&self, names: &[&str]) -> Option<&Value> {
+        names.iter().find_map(|name| self.0.get(*name).filter(|value| !value.is_null()))
+    }
+
+    fn number(&self, names: &[&str]) -> Result<Rlp, String> {
+        let value = self.get(names).ok_or_else(|| format!("no {}", names[0]))?;
+        parse_amount(value).map(Rlp::number).ok_or_else(|| format!("invalid {} {}", names[0], value))
+    }
+
+    fn bytes(&self, names: &[&str]) -> Result<Rlp, String> {
+        let value = self.get(names).ok_or_else(|| format!("no {}", names[0]))?;
+        value.as_str().and_then(parse_hex).map(Rlp::Bytes).ok_or_else(|| format!("invalid {} {}", names[0], value))
+    }
+
+    /// The recipient, empty for contract creations.
+    fn to(&self) -> Result<Rlp, String> {
+        match self.get(&["to"]) {
+            None => Ok(Rlp::Bytes(vec![])),
+            Some(_) => self.bytes(&["to"])
+        }
+    }
+
+    fn access_list(&self) -> Result<Rlp, String> {
+        let entries = match self.get(&["accessList"]) {
+            None => return Ok(Rlp::List(vec![])),
+            Some(entries) => entries.as_array().ok_or("invalid accessList")?
+        };
+
+        entries.iter().map(|entry| {
+            let entry = Fields(entry);
+            let keys = entry.get(&["storageKeys"]).and_then(Value::as_array).ok_or("invalid accessList")?;
+            let keys = keys.iter().map(|key| key.as_str().and_then(parse_hex).map(Rlp::Bytes).ok_or("invalid accessList"))
+                .collect::<Result<Vec<Rlp>, &str>>()?;
+
+            Ok(Rlp::List(vec![entry.bytes(&["address"])?, Rlp::List(keys)]))
+        }).collect::<Result<Vec<Rlp>, String>>().map(Rlp::List)
+    }
+
+    /// The signature, `v` is the y parity in typed transactions.
+    fn signature(&self, typed: bool) -> Result<Vec<Rlp>, String> {
+        let v = match typed {
+            true => self.number(&["yParity", "v"])?,
+            false => self.number(&["v"])?
+        };
+
+        Ok(vec![v, self.number(&["r"])?, self.number(&["s"])?])
+    }
+
+    /// The signed transaction as it was broadcast, legacy or typed (EIP-2718).
+    fn encode(&self) -> Result<Vec<u8>, String> {
+        let kind = match self.get(&["type"]) {
+            None => 0,
+            Some(kind) => crate::parse_quantity(kind).ok_or_else(|| format!("invalid type {}", kind))?
+        };
+
+        let (mut items, typed) = match kind {
+            0 => (vec![
+                self.number(&["nonce"])?, self.number(&["gasPrice"])?, self.number(&["gas"])?, self.to()?,
+                self.number(&["value"])?, self.bytes(&["input", "data"])?,
+            ], false),
+            1 => (vec![
+                self.number(&["chainId"])?, self.number(&["nonce"])?, self.number(&["gasPrice"])?, self.number(&["gas"])?,
+                self.to()?, self.number(&["value"])?, self.bytes(&["input", "data"])?, self.access_list()?,
+            ], true),
+            2 => (vec![
+                self.number(&["chainId"])?, self.number(&["nonce"])?, self.number(&["maxPriorityFeePerGas"])?,
+                self.number(&["maxFeePerGas"])?, self.number(&["gas"])?, self.to()?, self.number(&["value"])?,
+                self.bytes(&["input", "data"])?, self.access_list()?,
+            ], true),
+            kind => return Err(format!("transaction type {:#x} is not supported", kind))
+        };
+        items.extend(self.signature(typed)?);
+
+        let list = Rlp::List(items).encode();
+        Ok(match typed {
+            true => [vec![kind as u8], list].concat(),
+            false => list
+        })
+    }
+}
+
+/// Recomputes the hash of `tx`, the getTransaction response for `hash`. The signed transaction is
+/// taken from the `raw` field if the API sends it, otherwise it is encoded from the fields.
+pub fn verify(hash: &str, tx: &Value) -> Verification {
+    let fields = Fields(tx);
+    let encoded = match fields.get(&["raw"]).and_then(Value::as_str) {
+        Some(raw) => parse_hex(raw).ok_or_else(|| format!("invalid raw transaction {}", raw)),
+        None => fields.encode()
+    };
+
+    match encoded {
+        Ok(encoded) => {
+            let computed = to_hex(&keccak256(&encoded));
+            match computed.eq_ignore_ascii_case(hash) {
+                true => Verification::Verified,
+                false => Verification::Mismatch(computed)
+            }
+        }
+        Err(reason) => Verification::Unverifiable(reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    const WRON: &str = "0xe514d9deb7966c8be0ca922de8a064264ea6bcd4";
+    /// `transfer(0x2222…, 0.5 WRON)`.
+    const TRANSFER: &str = "0xa9059cbb000000000000000000000000222222222222222222222222222222222222222200000000000000000000000000000000000000000000000006f05b59d3b20000";
+
+    // Transactions on Ronin mainnet (chain ID 2020) signed with the private key 0x4646…46 by the
+    // independent signer of the web3 crate, and their hashes.
+    const LEGACY_HASH: &str = "0x261221654b6cad0500e6cc8e67370b4f84077a1cfc8bf00543e8db9fd7c0d63f";
+    const EIP2930_HASH: &str = "0xe9ee40b02e483fb310f0aaf55b1cbf885c0b7011dd082ca5408ddba6f92aba84";
+    const EIP1559_HASH: &str = "0x6726b6582a4857d4831abd8887d78ea611251f26c56c02ba362ad85fb92195f0";
+
+    fn access_list() -> Value {
+        json!([{
+            "address": WRON,
+            "storageKeys": [
+                "0x0000000000000000000000000000000000000000000000000000000000000001",
+                "0x0000000000000000000000000000000000000000000000000000000000000002"
+            ]
+        }])
+    }
+
+    fn legacy() -> Value {
+        json!({
+            "nonce": "0x9", "gasPrice": "0x4a817c800", "gas": "0xc738", "to": WRON, "value": "0x0", "input": TRANSFER, "v": "0xfec",
+            "r": "0x8f2275c12b4a73ff5cfa94710afd3cb9c0dbccfc641c160848e05c50d420bf4a",
+            "s": "0x4fd92b9a90fd76d6ce6e177df5f7a728ab745d70308ee7847981313f4f7bb8b5"
+        })
+    }
+
+    fn eip2930() -> Value {
+        json!({
+            "type": "0x1", "chainId": "0x7e4", "nonce": "0xa", "gasPrice": "0x4a817c800", "gas": "0xea60", "to": WRON,
+            "value": "0x14d1120d7b160000", "input": "0x", "accessList": access_list(), "yParity": "0x1", "v": "0x1",
+            "r": "0xa8bd526beb908bea31529f824f99b9c657c874afc81dd26b88d709efd53cc1c1",
+            "s": "0x49bedacd0458e0dee9df40ce0ddbdc33894ff0ed18d3c72c37e52ab544803a80"
+        })
+    }
+
+    fn eip1559() -> Value {
+        json!({
+            "type": "0x2", "chainId": "0x7e4", "nonce": "0xb", "maxPriorityFeePerGas": "0x3b9aca00", "maxFeePerGas": "0x6fc23ac00",
+            "gasPrice": "0x6fc23ac00", "gas": "0x13880", "to": WRON, "value": "0x0", "input": TRANSFER, "accessList": access_list(), "v": "0x1",
+            "r": "0x515eb4d909d8d2ebf92116df1976fd22981fb4dd331a4887402a32f507f0d777",
+            "s": "0x6e16e42988216b207291753942537817e19b4985d10b9b4d9953aa0a06e4e709"
+        })
+    }
+
+    fn verified(hash: &str, tx: &Value) -> bool {
+        matches!(verify(hash, tx), Verification::Verified)
+    }
+
+    #[test]
+    fn encodes_the_eip155_example() {
+        // The signed transaction of the example in EIP-155.
+        let tx = json!({
+            "nonce": 9, "gasPrice": "20000000000", "gas": 21000, "to": "0x3535353535353535353535353535353535353535",
+            "value": "1000000000000000000", "input": "0x", "v": 37,
+            "r": "18515461264373351373200002665853028612451056578545711640558177340181847433846",
+            "s": "46948507304638947509940763649030358759909902576025900602547168820602576006531"
+        });
+        let signed = "0xf86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83";
+
+        assert_eq!(to_hex(&Fields(&tx).encode().unwrap()), signed);
+    }
+
+    #[test]
+    fn recomputes_legacy_hashes() {
+        assert!(verified(LEGACY_HASH, &legacy()));
+    }
+
+    #[test]
+    fn recomputes_eip2930_hashes() {
+        assert!(verified(EIP2930_HASH, &eip2930()));
+    }
+
+    #[test]
+    fn recomputes_eip1559_hashes() {
+        assert!(verified(EIP1559_HASH, &eip1559()));
+    }
+
+    #[test]
+    fn reports_tampered_fields_as_mismatches() {
+        let tampered = [
+            (LEGACY_HASH, "value", json!("0x1")),
+            (EIP2930_HASH, "to", json!("0x2222222222222222222222222222222222222222")),
+            (EIP1559_HASH, "maxFeePerGas", json!("0x6fc23ac01")),
+        ];
+
+        for ((hash, field, value), mut tx) in tampered.into_iter().zip([legacy(), eip2930(), eip1559()]) {
+            tx[field] = value;
+            match verify(hash, &tx) {
+                Verification::Mismatch(computed) => assert_ne!(computed, hash, "{}", field),
+                _ => panic!("changing {} of {} should be a mismatch", field, hash)
+            }
+        }
+    }
+
+    #[test]
+    fn missing_fields_are_unverifiable() {
+        let mut tx = eip1559();
+        tx.as_object_mut().unwrap().remove("r");
+
+        assert!(matches!(verify(EIP1559_HASH, &tx), Verification::Unverifiable(reason) if reason == "no r"));
+    }
+}
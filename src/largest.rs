@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+use serde::Serialize;
+use web3::types::U256;
+use crate::transfers::{TokenStandard, TransferRecord};
+use crate::{block_times, filters, registry, ArgParser, RRDecodedTransaction, TxStatus};
+
+/// The number of transactions listed per currency by default.
+pub const TOP: usize = 10;
+
+/// Whether `--report=largest` was passed.
+pub fn report() -> bool {
+    ArgParser::all("--report").iter().any(|report| report == "largest")
+}
+
+/// The number of transactions to list per currency, `--top=N`.
+pub fn limit() -> usize {
+    match ArgParser::split(&"--top".to_string()) {
+        None => TOP,
+        Some(limit) => limit.parse().unwrap_or_else(|_| panic!("Invalid --top value {}", limit))
+    }
+}
+
+/// A transaction among the largest of a currency.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    pub transaction_hash: String,
+    pub block_number: u64,
+    /// The time of the block, if it was looked up or is in the block cache.
+    pub time: Option<String>,
+    pub wallet: String,
+    pub direction: &'static str,
+    pub counterparty: String,
+    pub counterparty_label: Option<&'static str>,
+    /// The amount in the smallest unit as a decimal string.
+    pub value: String,
+    /// `value` divided by the decimals of the currency, `value` remains authoritative.
+    pub amount_formatted: String,
+    #[serde(skip)]
+    amount: U256,
+}
+
+/// The largest transactions in RON or in one token.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ranking {
+    /// The token contract, `null` for RON.
+    pub token: Option<String>,
+    pub ticker: &'static str,
+    /// The transactions that moved any of it.
+    pub transactions: usize,
+    pub largest: Vec<Entry>,
+}
+
+impl Ranking {
+    fn new(token: Option<String>, ticker: &'static str) -> Ranking {
+        Ranking { token, ticker, transactions: 0, largest: vec![] }
+    }
+
+    /// Largest first, equal amounts in chain order.
+    fn rank(&mut self, limit: usize) {
+        self.largest.sort_by(|a, b| b.amount.cmp(&a.amount).then_with(|| (a.block_number, &a.transaction_hash).cmp(&(b.block_number, &b.transaction_hash))));
+        self.largest.truncate(limit);
+    }
+
+    fn push(&mut self, entry: Entry, limit: usize) {
+        self.transactions += 1;
+        self.largest.push(entry);
+        // Records are streamed, only the candidates are kept.
+        if self.largest.len() > limit.max(1) * 4 {
+            self.rank(limit);
+        }
+    }
+}
+
+/// The largest transactions of an export, written to `<wallet>.largest.json`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub ron: Ranking,
+    /// The tokens with known decimals the wallet moved, by ticker.
+    pub tokens: Vec<Ranking>,
+    /// Reverted transactions, which moved nothing.
+    pub reverted: usize,
+    /// Transactions whose RON value is unknown, usually because they couldn't be fetched.
+    pub unknown_value: usize,
+    /// Transactions without a decoded receipt, whose token transfers are unknown.
+    pub without_receipt: usize,
+}
+
+/// Ranks the transactions of the wallets by the RON they moved and, separately, by the amount of
+/// each token with known decimals they moved from or to the wallet. A transaction with several
+/// transfers of a token ranks by the largest one. Only the `limit` largest are kept per currency,
+/// equal amounts in chain order. `wallet` is `None` for merged exports, whose records name their
+/// wallet.
+pub fn scan(records: impl Iterator<Item = RRDecodedTransaction>, wallet: Option<&str>, limit: usize) -> Report {
+    let mut report = Report { ron: Ranking::new(None, "RON"), tokens: vec![], reverted: 0, unknown_value: 0, without_receipt: 0 };
+    let mut tokens: BTreeMap<String, Ranking> = BTreeMap::new();
+
+    for record in records {
+        if record.status == Some(TxStatus::Failed) {
+            report.reverted += 1;
+            continue;
+        }
+
+        let owner = wallet.map(str::to_string).or_else(|| record.wallet.clone()).unwrap_or_default();
+        let hash = record.hash.to_lowercase();
+        let direction = match (record.from.eq_ignore_ascii_case(&owner), record.to.eq_ignore_ascii_case(&owner)) {
+            (true, true) => "both",
+            (true, false) => "sent",
+            _ => "received"
+        };
+
+        match record.value {
+            None => report.unknown_value += 1,
+            Some(value) if value.is_zero() => {}
+            Some(value) => {
+                let counterparty = filters::counterparty(&record, &owner).to_lowercase();
+                report.ron.push(Entry {
+                    transaction_hash: hash.clone(),
+                    block_number: record.block_number,
+                    time: None,
+                    wallet: owner.to_lowercase(),
+                    direction,
+                    counterparty_label: registry::label(&counterparty),
+                    counterparty,
+                    value: value.to_string(),
+                    amount_formatted: filters::format_units(value, filters::RON_DECIMALS),
+                    amount: value,
+                }, limit);
+            }
+        }
+
+        let Some(receipt) = &record.output else {
+            report.without_receipt += 1;
+            continue;
+        };
+
+        let mut transfers: BTreeMap<String, (U256, TransferRecord)> = BTreeMap::new();
+        for transfer in TransferRecord::from_receipt(&hash, record.block_number, receipt, &owner) {
+            let Ok(amount) = U256::from_dec_str(&transfer.value) else {
+                continue;
+            };
+            if transfer.standard != TokenStandard::Erc20 || amount.is_zero() || registry::token(&transfer.token).is_none() {
+                continue;
+            }
+            if transfers.get(&transfer.token).is_none_or(|(largest, _)| amount > *largest) {
+                transfers.insert(transfer.token.clone(), (amount, transfer));
+            }
+        }
+
+        for (token, (amount, transfer)) in transfers {
+            let (ticker, decimals) = registry::token(&token).unwrap();
+            let counterparty = transfer.counterparty().to_string();
+            tokens.entry(token.clone()).or_insert_with(|| Ranking::new(Some(token), ticker)).push(Entry {
+                transaction_hash: hash.clone(),
+                block_number: record.block_number,
+                time: None,
+                wallet: owner.to_lowercase(),
+                direction: transfer.direction,
+                counterparty_label: registry::label(&counterparty),
+                counterparty,
+                value: transfer.value,
+                amount_formatted: filters::format_units(amount, decimals),
+                amount,
+            }, limit);
+        }
+    }
+
+    report.tokens = tokens.into_values().collect();
+    report.tokens.sort_by_key(|ranking| ranking.ticker);
+    for ranking in std::iter::once(&mut report.ron).chain(report.tokens.iter_mut()) {
+        ranking.rank(limit);
+        for entry in ranking.largest.iter_mut() {
+            entry.time = block_times::get(entry.block_number).map(|(_, time)| time);
+        }
+    }
+    report
+}
+
+impl Report {
+    /// The listed transactions of all currencies.
+    pub fn entries(&mut self) -> impl Iterator<Item = &mut Entry> {
+        std::iter::once(&mut self.ron).chain(self.tokens.iter_mut()).flat_map(|ranking| ranking.largest.iter_mut())
+    }
+}
+
+/// The report as printed at the end of a run and by `wally stats`.
+pub fn lines(report: &Report) -> Vec<String> {
+    let mut lines = vec![];
+
+    for ranking in std::iter::once(&report.ron).chain(report.tokens.iter()) {
+        lines.push(format!("Largest {} transactions ({} of {}):", ranking.ticker, ranking.largest.len(), ranking.transactions));
+        for (rank, entry) in ranking.largest.iter().enumerate() {
+            let counterparty = match entry.counterparty_label {
+                Some(label) => format!("{} ({})", label, entry.counterparty),
+                None => entry.counterparty.clone()
+            };
+            let direction = match entry.direction {
+                "sent" => "sent to",
+                "received" => "received from",
+                _ => "sent and received by"
+            };
+            lines.push(format!(
+                "  {}. {} {} {} {} in {} (block {}{})",
+                rank + 1, entry.amount_formatted, ranking.ticker, direction, counterparty,
+                entry.transaction_hash, entry.block_number, entry.time.as_ref().map(|time| format!(", {}", time)).unwrap_or_default()
+            ));
+        }
+    }
+
+    if report.unknown_value + report.without_receipt > 0 {
+        lines.push(format!(
+            "  Left out: {} transactions with an unknown RON value, {} without a decoded receipt for their token transfers",
+            report.unknown_value, report.without_receipt
+        ));
+    }
+
+    lines
+}
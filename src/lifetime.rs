@@ -0,0 +1,273 @@
+use std::collections::{BTreeMap, HashMap};
+use serde::Serialize;
+use crate::{block_times, Direction, RRDecodedTransaction, TxStatus};
+
+/// A transaction with its time, if the time of its block is known.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Activity {
+    pub hash: String,
+    pub block_number: u64,
+    pub timestamp: Option<u64>,
+    pub time: Option<String>,
+}
+
+/// The longest time between two consecutive transactions.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Gap {
+    pub seconds: u64,
+    pub after: Activity,
+    pub before: Activity,
+}
+
+#[derive(Serialize)]
+pub struct Busiest {
+    pub period: String,
+    pub transactions: usize,
+}
+
+/// What the exported transactions tell about the lifetime of a wallet. The export has no
+/// timestamps, they come from the block cache of `wally blocks` and the blocks looked up during
+/// the run. Everything depending on time only counts the transactions with a known block time.
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Lifetime {
+    pub transactions: usize,
+    pub sent: usize,
+    pub received: usize,
+    /// Transactions in both the sent and the received list of the wallet.
+    pub both: usize,
+    pub reverted: usize,
+    /// Transactions that couldn't be fetched, which are exported without a block.
+    pub failed: usize,
+    pub first: Option<Activity>,
+    pub last: Option<Activity>,
+    pub without_timestamp: usize,
+    /// Distinct UTC days with a transaction.
+    pub days_active: Option<usize>,
+    pub longest_gap: Option<Gap>,
+    pub busiest_day: Option<Busiest>,
+    pub busiest_month: Option<Busiest>,
+}
+
+/// The period with the most transactions, the earliest one on ties.
+fn busiest(counts: BTreeMap<String, usize>) -> Option<Busiest> {
+    let most = counts.values().copied().max()?;
+    counts.into_iter().find(|(_, transactions)| *transactions == most).map(|(period, transactions)| Busiest { period, transactions })
+}
+
+fn format_duration(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{} seconds", seconds),
+        60..=3599 => format!("{:.1} minutes", seconds as f64 / 60.0),
+        3600..=86399 => format!("{:.1} hours", seconds as f64 / 3600.0),
+        _ => format!("{:.1} days", seconds as f64 / 86400.0)
+    }
+}
+
+/// Walks the sorted records of a wallet once.
+pub fn compute(records: impl Iterator<Item = RRDecodedTransaction>) -> Lifetime {
+    from_records(records, block_times::get)
+}
+
+/// `compute` with the timestamp and time of a block from `block_time`.
+fn from_records(records: impl Iterator<Item = RRDecodedTransaction>, mut block_time: impl FnMut(u64) -> Option<(u64, String)>) -> Lifetime {
+    let mut lifetime = Lifetime::default();
+    let mut blocks: HashMap<u64, Option<(u64, String)>> = HashMap::new();
+    let mut timed: Vec<Activity> = vec![];
+
+    for record in records {
+        lifetime.transactions += 1;
+        match record.direction {
+            Direction::Sent => lifetime.sent += 1,
+            Direction::Received => lifetime.received += 1,
+            Direction::Both => lifetime.both += 1,
+        }
+        if record.status == Some(TxStatus::Failed) {
+            lifetime.reverted += 1;
+        }
+        if record.block_number == 0 {
+            lifetime.failed += 1;
+            continue;
+        }
+
+        let time = blocks.entry(record.block_number)
+            .or_insert_with(|| block_time(record.block_number))
+            .clone();
+        let activity = Activity { hash: record.hash.clone(), block_number: record.block_number, timestamp: time.as_ref().map(|(timestamp, _)| *timestamp), time: time.map(|(_, time)| time) };
+
+        match activity.timestamp.is_some() {
+            true => timed.push(activity.clone()),
+            false => lifetime.without_timestamp += 1
+        }
+        if lifetime.first.is_none() {
+            lifetime.first = Some(activity.clone());
+        }
+        lifetime.last = Some(activity);
+    }
+
+    if timed.is_empty() {
+        return lifetime;
+    }
+
+    let mut days: BTreeMap<String, usize> = BTreeMap::new();
+    let mut months: BTreeMap<String, usize> = BTreeMap::new();
+    for activity in &timed {
+        // Cached times are RFC 3339 in UTC, starting with the date.
+        let time = activity.time.as_deref().unwrap_or_default();
+        *days.entry(time.chars().take(10).collect()).or_default() += 1;
+        *months.entry(time.chars().take(7).collect()).or_default() += 1;
+    }
+
+    lifetime.longest_gap = timed.windows(2)
+        .map(|pair| (pair[1].timestamp.unwrap_or_default().saturating_sub(pair[0].timestamp.unwrap_or_default()), pair))
+        .filter(|(seconds, _)| *seconds > 0)
+        // The earliest of equally long gaps.
+        .rev()
+        .max_by_key(|(seconds, _)| *seconds)
+        .map(|(seconds, pair)| Gap { seconds, after: pair[0].clone(), before: pair[1].clone() });
+    lifetime.days_active = Some(days.len());
+    lifetime.busiest_day = busiest(days);
+    lifetime.busiest_month = busiest(months);
+    lifetime
+}
+
+/// The lifetime facts of the summary.
+pub fn lines(lifetime: &Lifetime) -> Vec<String> {
+    // Reverted transactions have a line of their own.
+    let mut lines = vec![format!(
+        "Transactions: {} ({} sent, {} received{}{})",
+        lifetime.transactions, lifetime.sent, lifetime.received,
+        match lifetime.both { 0 => String::new(), both => format!(", {} sent and received", both) },
+        match lifetime.failed { 0 => String::new(), failed => format!(", {} could not be fetched", failed) }
+    )];
+
+    let describe = |activity: &Activity| format!(
+        "{} in block {}{}", activity.hash, activity.block_number, activity.time.as_ref().map(|time| format!(" at {}", time)).unwrap_or_default()
+    );
+    if let (Some(first), Some(last)) = (&lifetime.first, &lifetime.last) {
+        lines.push(format!("First transaction: {}", describe(first)));
+        lines.push(format!("Last transaction: {}", describe(last)));
+    }
+
+    match lifetime.days_active {
+        None if lifetime.first.is_some() => lines.push("No block times are cached, run `wally blocks --from-export=FILE --cache-dir=DIR` and pass the --cache-dir for the days active and gaps".to_string()),
+        None => {}
+        Some(days_active) => {
+            lines.push(format!("Days active: {}", days_active));
+            if let Some(gap) = &lifetime.longest_gap {
+                lines.push(format!("Longest inactivity: {} between {} and {}", format_duration(gap.seconds), gap.after.hash, gap.before.hash));
+            }
+            if let (Some(day), Some(month)) = (&lifetime.busiest_day, &lifetime.busiest_month) {
+                lines.push(format!("Busiest day: {} ({} transactions), busiest month: {} ({} transactions)", day.period, day.transactions, month.period, month.transactions));
+            }
+            if lifetime.without_timestamp > 0 {
+                lines.push(format!("  {} transactions without a cached block time are left out of these", lifetime.without_timestamp));
+            }
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::testing::{hash, record};
+    use super::*;
+
+    /// Blocks 100 to 199 are cached, block `n` is `n` hours after midnight of 2024-01-01.
+    fn block_time(number: u64) -> Option<(u64, String)> {
+        let time = chrono::DateTime::from_timestamp(1_704_067_200 + (number as i64 - 100) * 3600, 0)?;
+        (100..200).contains(&number).then(|| (time.timestamp() as u64, time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)))
+    }
+
+    /// An export of the wallet: the `(n, block)` of its transactions, with `fields` for some of them.
+    fn export(transactions: &[(u64, u64)], fields: impl Fn(u64) -> serde_json::Value) -> Vec<RRDecodedTransaction> {
+        transactions.iter().map(|(n, block)| record(*n, *block, fields(*n))).collect()
+    }
+
+    #[test]
+    fn counts_the_directions_and_failures() {
+        let records = export(&[(1, 0), (2, 100), (3, 101), (4, 102), (5, 103)], |n| match n {
+            3 => json!({ "direction": "received" }),
+            4 => json!({ "direction": "both", "status": "failed" }),
+            _ => json!({})
+        });
+        let lifetime = from_records(records.into_iter(), block_time);
+
+        assert_eq!((lifetime.transactions, lifetime.sent, lifetime.received, lifetime.both), (5, 3, 1, 1));
+        assert_eq!(lifetime.reverted, 1);
+        // Transactions without a block are left out of the first and last.
+        assert_eq!(lifetime.failed, 1);
+        assert_eq!(lifetime.first.unwrap().hash, hash(2));
+        assert_eq!(lifetime.last.unwrap().hash, hash(5));
+    }
+
+    #[test]
+    fn activity_over_time() {
+        // Two hours after the first, then a day later, then three on 2024-01-03.
+        let records = export(&[(1, 100), (2, 102), (3, 126), (4, 150), (5, 151), (6, 151)], |_| json!({}));
+        let lifetime = from_records(records.into_iter(), block_time);
+
+        assert_eq!(lifetime.first.as_ref().unwrap().time.as_deref(), Some("2024-01-01T00:00:00Z"));
+        assert_eq!(lifetime.last.as_ref().unwrap().time.as_deref(), Some("2024-01-03T03:00:00Z"));
+        assert_eq!(lifetime.days_active, Some(3));
+
+        // Two gaps of 24 hours, the earlier one is reported.
+        let gap = lifetime.longest_gap.unwrap();
+        assert_eq!(gap.seconds, 24 * 3600);
+        assert_eq!((gap.after.hash, gap.before.hash), (hash(2), hash(3)));
+
+        let day = lifetime.busiest_day.unwrap();
+        assert_eq!((day.period.as_str(), day.transactions), ("2024-01-03", 3));
+        let month = lifetime.busiest_month.unwrap();
+        assert_eq!((month.period.as_str(), month.transactions), ("2024-01", 6));
+    }
+
+    #[test]
+    fn uncached_blocks_are_left_out_of_the_times() {
+        let records = export(&[(1, 100), (2, 120), (3, 250)], |_| json!({}));
+        let lifetime = from_records(records.into_iter(), block_time);
+
+        assert_eq!(lifetime.without_timestamp, 1);
+        assert_eq!(lifetime.days_active, Some(1));
+        assert_eq!(lifetime.last.as_ref().unwrap().timestamp, None);
+        assert_eq!(lines(&lifetime), vec![
+            "Transactions: 3 (3 sent, 0 received)".to_string(),
+            format!("First transaction: {} in block 100 at 2024-01-01T00:00:00Z", hash(1)),
+            format!("Last transaction: {} in block 250", hash(3)),
+            "Days active: 1".to_string(),
+            format!("Longest inactivity: 20.0 hours between {} and {}", hash(1), hash(2)),
+            "Busiest day: 2024-01-01 (2 transactions), busiest month: 2024-01 (2 transactions)".to_string(),
+            "  1 transactions without a cached block time are left out of these".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn without_cached_blocks() {
+        let records = export(&[(1, 300), (2, 301)], |_| json!({}));
+        let lifetime = from_records(records.into_iter(), block_time);
+
+        assert_eq!(lifetime.days_active, None);
+        assert!(lifetime.longest_gap.is_none() && lifetime.busiest_day.is_none());
+        assert!(lines(&lifetime).last().unwrap().starts_with("No block times are cached"));
+    }
+
+    #[test]
+    fn an_empty_export() {
+        let lifetime = from_records(std::iter::empty(), block_time);
+
+        assert!(lifetime.first.is_none());
+        assert_eq!(lines(&lifetime), vec!["Transactions: 0 (0 sent, 0 received)"]);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(format_duration(59), "59 seconds");
+        assert_eq!(format_duration(90), "1.5 minutes");
+        assert_eq!(format_duration(5400), "1.5 hours");
+        assert_eq!(format_duration(86400 * 3), "3.0 days");
+    }
+}
@@ -0,0 +1,87 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::time::{Duration, Instant};
+use crate::ArgParser;
+
+/// How often a locked output is checked with `--wait-lock`.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long to wait for a locked output, `--wait-lock=SECONDS`. Runs fail right away without it.
+pub fn wait_from_args() -> Option<Duration> {
+    ArgParser::split(&"--wait-lock".to_string()).map(|seconds| {
+        Duration::from_secs(seconds.parse().unwrap_or_else(|_| panic!("Invalid --wait-lock value {}, expected a number of seconds", seconds)))
+    })
+}
+
+/// An advisory lock on an output file, held in `{output}.lock` which records the PID of the run.
+/// The operating system releases it when the run ends in any way, the lock file is removed when
+/// the run finishes or panics.
+pub struct OutputLock {
+    path: String,
+    // Dropped after the lock file was removed, which releases the lock.
+    _file: File,
+}
+
+#[cfg(unix)]
+fn same_file(file: &File, path: &str) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(locked), Ok(current)) => locked.dev() == current.dev() && locked.ino() == current.ino(),
+        _ => false
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(_: &File, path: &str) -> bool {
+    std::path::Path::new(path).exists()
+}
+
+impl OutputLock {
+    /// Locks `output`, waiting up to `wait` for a concurrent run to finish.
+    pub fn acquire(output: &str, wait: Option<Duration>) -> Result<OutputLock, String> {
+        let path = format!("{}.lock", output);
+        let deadline = wait.map(|wait| Instant::now() + wait);
+        let mut waiting = false;
+
+        loop {
+            let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)
+                .map_err(|error| format!("Could not create {}: {}", path, error))?;
+
+            match file.try_lock() {
+                // The previous holder may have removed the file between opening and locking it.
+                Ok(()) if !same_file(&file, &path) => continue,
+                Ok(()) => {
+                    file.set_len(0).and_then(|_| write!(file, "{}", std::process::id()))
+                        .map_err(|error| format!("Could not write {}: {}", path, error))?;
+
+                    return Ok(OutputLock { path, _file: file });
+                }
+                Err(TryLockError::WouldBlock) => {
+                    let holder = std::fs::read_to_string(&path).unwrap_or_default();
+
+                    match deadline {
+                        Some(deadline) if Instant::now() < deadline => {
+                            if !waiting {
+                                println!("{} is in use by another run (PID {}), waiting for it to finish", output, holder.trim());
+                                waiting = true;
+                            }
+                            std::thread::sleep(POLL_INTERVAL);
+                        }
+                        _ => return Err(format!(
+                            "{} is in use by another run (PID {}), see {}. Pass --wait-lock=SECONDS to wait for it",
+                            output, holder.trim(), path
+                        ))
+                    }
+                }
+                Err(TryLockError::Error(error)) => return Err(format!("Could not lock {}: {}", path, error))
+            }
+        }
+    }
+}
+
+impl Drop for OutputLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,100 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::{Mutex, OnceLock};
+use chrono::SecondsFormat;
+use serde::Serialize;
+use crate::ArgParser;
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn tag(&self) -> &'static str {
+        match self {
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+}
+
+enum LogFormat {
+    Text,
+    Json,
+}
+
+struct LogFile {
+    file: File,
+    format: LogFormat,
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    time: String,
+    level: Level,
+    message: &'a str,
+}
+
+/// The log of `--log-file`, opened once. Successive runs append to the same file.
+fn log_file() -> Option<&'static Mutex<LogFile>> {
+    static LOG_FILE: OnceLock<Option<Mutex<LogFile>>> = OnceLock::new();
+
+    LOG_FILE.get_or_init(|| {
+        let format = match ArgParser::split(&"--log-format".to_string()).as_deref() {
+            None | Some("text") => LogFormat::Text,
+            Some("json") => LogFormat::Json,
+            Some(format) => panic!("Invalid --log-format value {}, expected text or json", format)
+        };
+
+        let path = match ArgParser::split(&"--log-file".to_string()) {
+            Some(path) => path,
+            None if ArgParser::split(&"--log-format".to_string()).is_some() => panic!("--log-format requires --log-file"),
+            None => return None
+        };
+        let file = OpenOptions::new().create(true).append(true).open(&path)
+            .unwrap_or_else(|error| panic!("Could not open the log file {}: {}", path, error));
+
+        Some(Mutex::new(LogFile { file, format }))
+    }).as_ref()
+}
+
+/// Opens the log file early so a bad `--log-file` fails before any work is done.
+pub fn init() {
+    log_file();
+}
+
+/// Writes a line to the log file, if any. The console output is not affected.
+pub fn write(level: Level, message: &str) {
+    let Some(log_file) = log_file() else {
+        return;
+    };
+
+    let time = chrono::Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    let message = message.trim();
+    let mut log_file = log_file.lock().unwrap();
+
+    let line = match log_file.format {
+        LogFormat::Text => format!("{} {} {}\n", time, level.tag(), message),
+        LogFormat::Json => format!("{}\n", serde_json::to_string(&JsonLine { time, level, message }).unwrap()),
+    };
+
+    // A full disk must not end the export.
+    let _ = log_file.file.write_all(line.as_bytes());
+}
+
+pub fn info(message: &str) {
+    write(Level::Info, message);
+}
+
+pub fn warn(message: &str) {
+    write(Level::Warn, message);
+}
+
+pub fn error(message: &str) {
+    write(Level::Error, message);
+}
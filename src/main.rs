@@ -1,19 +1,126 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::Ordering;
 use std::time::Duration;
 use dialoguer::Input;
 use indicatif::ProgressStyle;
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use reqwest_retry::policies::ExponentialBackoff;
 use reqwest_retry::RetryTransientMiddleware;
-use serde::{Deserialize, Serialize};
-use web3::types::Address;
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+use web3::types::{Address, U256};
+use receipt::RRReceipt;
+
+/// Prints a line of the final summary and writes it to the `--log-file`.
+macro_rules! summary {
+    ($($arg:tt)*) => {{
+        let line = format!($($arg)*);
+        println!("{}", line);
+        logging::info(&line);
+    }};
+}
+
+mod accounting;
+mod aliases;
+mod anonymize;
+mod append;
+mod approvals;
+mod balance;
+mod blobs;
+mod block;
+mod block_times;
+mod breaker;
+mod cache;
+mod canonical;
+mod checksum;
+mod columnar;
+mod cointracking;
+mod commands;
+mod compression;
+mod csv;
+mod database;
+mod ctc;
+mod diagnostics;
+mod encryption;
+mod events;
+mod exec;
+mod export;
+mod expression;
+mod filters;
+mod genes;
+mod graphql;
+mod grouping;
+mod hashes;
+mod hosts;
+mod integrity;
+mod largest;
+mod lifetime;
+mod lock;
+mod logging;
+mod manifest;
+mod multicall;
+mod network;
+mod notify;
+mod output;
+mod pipeline;
+mod postgres;
+mod provider;
+mod receipt;
+mod registry;
+mod rpc;
+mod schema;
+mod shard;
+mod skymavis;
+mod sort;
+#[cfg(test)]
+mod testing;
+mod traffic;
+mod transfers;
+mod unix_socket;
+mod upload;
 
 const DEFAULT_USER_AGENT: &str = "ronin/wally0.1.0 See: https://github.com/wehmoen/ronin-wally";
 
 type RRTransactionHash = String;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Default)]
 struct RRTransactionDict {
     transactions: Vec<RRTransactionHash>,
+    /// The total number of transactions, if the API reports it.
+    #[serde(default, skip_serializing)]
+    total: Option<usize>,
+    /// Problems with the page sequence of a paginated list.
+    #[serde(skip)]
+    issues: Vec<String>,
+    /// Entries which are not transaction hashes, see `hashes::sanitize`.
+    #[serde(skip)]
+    rejected: Vec<hashes::RejectedHash>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum Direction {
+    #[default]
+    Sent,
+    Received,
+    Both,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum TxStatus {
+    Success,
+    Failed,
+}
+
+/// Who paid the fee of a transaction. Transactions of Ronin's free gas program are paid by a
+/// sponsor, they cost the sender nothing even though gas was used.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum FeePayer {
+    #[serde(rename = "self")]
+    Sender,
+    Sponsored,
 }
 
 #[derive(Serialize, Deserialize, Default)]
@@ -22,215 +129,2278 @@ struct RRTransaction {
     from: String,
     to: String,
     hash: String,
+    #[serde(deserialize_with = "deserialize_quantity")]
     block_number: u64,
+    #[serde(default, deserialize_with = "deserialize_optional_quantity")]
+    nonce: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_optional_quantity")]
+    transaction_index: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_optional_amount")]
+    value: Option<U256>,
+    #[serde(default, deserialize_with = "deserialize_optional_quantity")]
+    gas_price: Option<u64>,
+    /// The account paying the fee of a sponsored transaction.
+    #[serde(default)]
+    payer: Option<String>,
+}
+
+impl RRTransaction {
+    fn unavailable() -> RRTransaction {
+        RRTransaction {
+            from: "null".to_string(),
+            to: "null".to_string(),
+            hash: "null".to_string(),
+            block_number: 0,
+            nonce: None,
+            transaction_index: None,
+            value: None,
+            gas_price: None,
+            payer: None,
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 struct RRDecodedTransaction {
     from: String,
     to: String,
     hash: RRTransactionHash,
     block_number: u64,
+    #[serde(default)]
+    nonce: Option<u64>,
+    #[serde(default)]
+    transaction_index: Option<u64>,
+    /// Whether the hash was in the sent or the received list of the wallet, or in both. In merged
+    /// exports the direction for `wallet`, `both` if several of the exported wallets are involved.
+    #[serde(default)]
+    direction: Direction,
+    /// The native RON value moved, in wei as a decimal string.
+    #[serde(default, serialize_with = "serialize_optional_amount", deserialize_with = "deserialize_optional_amount")]
+    #[schemars(with = "Option<String>")]
+    value: Option<U256>,
+    /// `value` in RON as a decimal string, `value` remains authoritative.
+    #[serde(default)]
+    value_ron: Option<String>,
+    #[serde(default)]
+    status: Option<TxStatus>,
+    /// Gas used according to the receipt.
+    #[serde(default)]
+    gas_used: Option<u64>,
+    /// Gas price offered by the transaction, in wei.
+    #[serde(default)]
+    gas_price: Option<u64>,
+    /// Gas price actually paid according to the receipt, in wei.
+    #[serde(default)]
+    effective_gas_price: Option<u64>,
+    /// `gas_used` times the effective (or else the offered) gas price, as a decimal string in RON.
+    #[serde(default)]
+    fee_ron: Option<String>,
+    /// Whether the sender paid `fee_ron` or a sponsor did, `null` if neither the payer nor the gas
+    /// price is known.
+    #[serde(default)]
+    fee_payer: Option<FeePayer>,
     input: Option<serde_json::Value>,
-    output: Option<serde_json::Value>,
+    #[schemars(with = "Option<serde_json::Value>")]
+    output: Option<RRReceipt>,
+    /// The number of strings in `input` and `output` cut short by `--max-blob-bytes`, only set if
+    /// there were any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    truncated_blobs: Option<usize>,
+    /// The undecoded receipt logs (`address`, `topics`, `data`), only set with `--raw-logs`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    raw_logs: Option<serde_json::Value>,
+    /// The number of blocks on top of the transaction's block at the start of the run, only set
+    /// with `--confirmations`. `null` if the block of the transaction is unknown.
+    #[serde(default, deserialize_with = "deserialize_some", skip_serializing_if = "Option::is_none")]
+    confirmations: Option<Option<u64>>,
+    /// The reconstructed RON balance of the wallet after the transaction, only set with
+    /// `--balance` and not in merged exports.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    balance_after: Option<String>,
+    /// The exported wallet the transaction belongs to, only set in merged exports. It is the sender
+    /// if the sender is one of the wallets and the recipient otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    wallet: Option<String>,
+    /// The exported wallets involved in the transaction, only set in merged exports.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    wallets: Vec<String>,
+    /// The direction of the transaction for each of the wallets, only set in merged exports.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    directions: BTreeMap<String, Direction>,
+}
+
+impl RRDecodedTransaction {
+    /// The direction of a record read from an export of `wallet`. Exports written before records
+    /// carried their direction read as `sent`, but sent transactions are always from the wallet.
+    fn exported_direction(&self, wallet: &str) -> Direction {
+        match self.direction {
+            Direction::Sent if self.wallets.is_empty() && !self.from.eq_ignore_ascii_case(wallet) => Direction::Received,
+            direction => direction
+        }
+    }
+}
+
+/// A wallet in the file of `--summary-json`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WalletSummary<'a> {
+    address: String,
+    alias: Option<String>,
+    output: String,
+    /// In RON.
+    fees_paid: String,
+    lifetime: Option<&'a lifetime::Lifetime>,
+}
+
+/// A transaction of a `--hashes-only` export.
+#[derive(Serialize)]
+struct RRHashEntry<'a> {
+    hash: &'a str,
+    direction: Direction,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct RRFailureReport {
+    failed_transactions: Vec<RRTransactionHash>,
+    nonce_issues: Vec<String>,
+    #[serde(default)]
+    list_issues: Vec<String>,
+    /// Transactions whose fields don't hash to the requested hash, from `--verify-hashes`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    hash_mismatches: Vec<String>,
+    /// Malformed or repeated entries dropped from the transaction lists.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    rejected_hashes: Vec<hashes::RejectedHash>,
+}
+
+impl RRFailureReport {
+    fn is_empty(&self) -> bool {
+        self.failed_transactions.is_empty() && self.nonce_issues.is_empty() && self.list_issues.is_empty() && self.hash_mismatches.is_empty()
+            && self.rejected_hashes.is_empty()
+    }
+}
+
+#[derive(Default)]
+struct ExportCounts {
+    reverted: usize,
+    skipped_noise: usize,
+    skipped_listed: usize,
+    below_min_value: usize,
+    skipped_counterparty: usize,
+    skipped_event: usize,
+    skipped_without_receipt: usize,
+    skipped_contract: usize,
+    contract_direct: usize,
+    contract_logs: usize,
+    skipped_expression: usize,
+    /// Fees of exported transactions sent and paid by the wallet, in wei, and of reverted ones
+    /// left out by `--exclude-failed`.
+    fees_paid: U256,
+    sponsored: usize,
+    /// Gas used by transactions of the wallet paid by a sponsor, counted like `fees_paid`.
+    sponsored_gas: u64,
+    hashes_verified: usize,
+    hashes_unverifiable: usize,
+    /// NFT and unknown token transfers left out of a `--format=ctc` export.
+    unvalued_transfers: usize,
+}
+
+impl ExportCounts {
+    fn skip(&mut self, skip: filters::Skip) {
+        let count = match skip {
+            filters::Skip::Listed => &mut self.skipped_listed,
+            filters::Skip::Noise => &mut self.skipped_noise,
+            filters::Skip::Counterparty => &mut self.skipped_counterparty,
+            filters::Skip::WithoutReceipt => &mut self.skipped_without_receipt,
+            filters::Skip::Event => &mut self.skipped_event,
+            filters::Skip::Contract => &mut self.skipped_contract,
+            filters::Skip::Expression => &mut self.skipped_expression,
+        };
+        *count += 1;
+    }
+
+    /// Counts the fee of a transaction sent by the wallet, or its gas if a sponsor paid it.
+    fn paid(&mut self, fee_payer: Option<FeePayer>, fee: Option<U256>, gas_used: Option<u64>) {
+        match fee_payer {
+            Some(FeePayer::Sponsored) => {
+                self.sponsored += 1;
+                self.sponsored_gas += gas_used.unwrap_or(0);
+            }
+            _ => self.fees_paid += fee.unwrap_or_default()
+        }
+    }
+
+    /// Counts a reverted transaction, returning whether it is left out of the export. With
+    /// `--exclude-failed` it never reaches the records, but its gas was spent all the same, so the
+    /// fee of a transaction the wallet `sent` is counted here.
+    fn reverted(&mut self, exclude_failed: bool, sent: bool, fee_payer: Option<FeePayer>, fee: Option<U256>, gas_used: Option<u64>) -> bool {
+        self.reverted += 1;
+        if exclude_failed && sent {
+            self.paid(fee_payer, fee, gas_used);
+        }
+        exclude_failed
+    }
+}
+
+/// The transactions and bookkeeping of a single exported wallet.
+struct WalletExport {
+    address: String,
+    /// The name passed with `--address=ALIAS=ADDRESS`.
+    alias: Option<String>,
+    /// Output file names are derived from this, it is the alias or the address unless anonymized.
+    file_stem: String,
+    hashes: Vec<RRTransactionHash>,
+    directions: HashMap<RRTransactionHash, Direction>,
+    records: sort::RecordSorter,
+    failures: RRFailureReport,
+    sent_nonces: Vec<u64>,
+    counts: ExportCounts,
+    /// Transactions per group with `--group-by`.
+    groups: Vec<(String, usize)>,
+    /// The reconstructed RON balance with `--balance`.
+    balance: Option<balance::Reconstruction>,
+    /// The token approvals with `--report=approvals`.
+    approvals: Option<approvals::Report>,
+    /// The largest transactions with `--report=largest`.
+    largest: Option<largest::Report>,
+    /// The first and last activity and the like, from the sorted records.
+    lifetime: Option<lifetime::Lifetime>,
+    /// The previous export new records are appended to with `--append`.
+    existing: Option<append::Existing>,
+    /// The manifest of the previous export with `--append`.
+    previous_manifest: Option<manifest::Manifest>,
+    coverage: manifest::Coverage,
+}
+
+impl WalletExport {
+    fn new(address: String, alias: Option<String>, sent: RRTransactionDict, received: RRTransactionDict) -> WalletExport {
+        let mut failures = RRFailureReport::default();
+        failures.list_issues.extend(sent.issues);
+        failures.list_issues.extend(received.issues);
+        failures.rejected_hashes.extend(sent.rejected);
+        failures.rejected_hashes.extend(received.rejected);
+
+        let mut hashes: Vec<RRTransactionHash> = vec![];
+        let mut directions: HashMap<RRTransactionHash, Direction> = HashMap::new();
+
+        for hash in sent.transactions {
+            if directions.insert(hash.clone(), Direction::Sent).is_none() {
+                hashes.push(hash);
+            }
+        }
+
+        for hash in received.transactions {
+            match directions.get_mut(&hash) {
+                Some(direction) => {
+                    if *direction == Direction::Sent {
+                        *direction = Direction::Both;
+                    }
+                }
+                None => {
+                    directions.insert(hash.clone(), Direction::Received);
+                    hashes.push(hash);
+                }
+            }
+        }
+
+        WalletExport {
+            file_stem: alias.clone().unwrap_or_else(|| address.clone()),
+            address,
+            alias,
+            hashes,
+            directions,
+            records: sort::RecordSorter::bounded(),
+            failures,
+            sent_nonces: vec![],
+            counts: ExportCounts::default(),
+            groups: vec![],
+            balance: None,
+            approvals: None,
+            largest: None,
+            lifetime: None,
+            existing: None,
+            previous_manifest: None,
+            coverage: manifest::Coverage::default(),
+        }
+    }
+
+    /// The wallet in progress messages and summaries, e.g. `treasury (0x…)`.
+    fn label(&self) -> String {
+        match &self.alias {
+            Some(alias) => format!("{} ({})", alias, self.address),
+            None => self.address.clone()
+        }
+    }
+
+    /// The wallet as it appears in the records: its pseudonym if anonymized, the address
+    /// otherwise. Aliases and pseudonyms are never combined.
+    fn record_wallet(&self) -> &str {
+        match self.alias {
+            Some(_) => &self.address,
+            None => &self.file_stem
+        }
+    }
+
+    fn failure_file_name(&self) -> String {
+        format!("{}.failures.json", shard::stem(&self.file_stem))
+    }
+}
+
+fn serialize_record(record: &RRDecodedTransaction, anonymizer: Option<&mut anonymize::Anonymizer>) -> String {
+    match (anonymizer, canonical::enabled()) {
+        (anonymizer, true) => canonical::to_string(&record_value(record, anonymizer)),
+        (Some(anonymizer), false) => serde_json::to_string(&anonymizer.anonymize(serde_json::to_value(record).unwrap())).unwrap(),
+        (None, false) => serde_json::to_string(record).unwrap()
+    }
+}
+
+/// Serializes a record for an export in the selected format. `wallet` is the address or pseudonym
+/// of the exported wallet, unset in merged exports.
+fn export_record(record: &RRDecodedTransaction, anonymizer: Option<&mut anonymize::Anonymizer>, format: &output::Format, wallet: Option<&str>) -> String {
+    match format {
+        output::Format::Json => serialize_record(record, anonymizer),
+        output::Format::Csv(columns) => columns.row(&record_value(record, anonymizer), wallet.map(|wallet| (wallet, record.direction))),
+        output::Format::Arrow => unreachable!("Arrow exports are written by ArrowWriter"),
+        output::Format::CoinTracking => cointracking::rows(&record_value(record, anonymizer), wallet.expect("Accounting exports are per wallet")),
+        output::Format::Ctc => ctc::rows(&record_value(record, anonymizer), wallet.expect("Accounting exports are per wallet")),
+    }
+}
+
+/// The serialized record, anonymized if requested.
+fn record_value(record: &RRDecodedTransaction, anonymizer: Option<&mut anonymize::Anonymizer>) -> serde_json::Value {
+    let value = serde_json::to_value(record).unwrap();
+
+    match anonymizer {
+        Some(anonymizer) => anonymizer.anonymize(value),
+        None => value
+    }
+}
+
+/// Combines the sorted exports of several wallets into one sorted stream in which every
+/// transaction appears once, annotated with the wallets involved and their direction and
+/// attributed to one of them.
+fn merge_records(wallets: &mut [WalletExport]) -> impl Iterator<Item = RRDecodedTransaction> + '_ {
+    let addresses: Vec<String> = wallets.iter().map(|wallet| wallet.address.clone()).collect();
+    let aliases: Vec<Option<String>> = wallets.iter().map(|wallet| wallet.alias.clone()).collect();
+    let mut records = sort::KWayMerge::new(wallets.iter_mut().map(|wallet| wallet.records.sorted()).collect()).peekable();
+
+    std::iter::from_fn(move || {
+        let (index, mut merged) = records.next()?;
+        // The balance belongs to a single wallet.
+        merged.balance_after = None;
+        merged.wallets.push(addresses[index].clone());
+        merged.directions.insert(addresses[index].clone(), merged.direction);
+
+        // The same transaction has the same sort key in every wallet, so duplicates are adjacent.
+        while let Some((index, record)) = records.next_if(|(_, record)| record.hash == merged.hash) {
+            merged.wallets.push(addresses[index].clone());
+            merged.directions.insert(addresses[index].clone(), record.direction);
+        }
+
+        let wallet = merged.wallets.iter().find(|wallet| wallet.eq_ignore_ascii_case(&merged.from)).unwrap_or(&merged.wallets[0]).clone();
+        merged.direction = match merged.wallets.len() {
+            1 => merged.directions[&wallet],
+            _ => Direction::Both
+        };
+        // Aliased wallets are attributed by their alias.
+        let index = addresses.iter().position(|address| *address == wallet).unwrap();
+        merged.wallet = Some(aliases[index].clone().unwrap_or(wallet));
+
+        Some(merged)
+    })
+}
+
+/// Parses a JSON-RPC style quantity which may be encoded as a number, a decimal string or a
+/// 0x-prefixed hex string.
+fn parse_quantity(value: &serde_json::Value) -> Option<u64> {
+    match value {
+        serde_json::Value::Number(number) => number.as_u64(),
+        serde_json::Value::String(text) => match text.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => text.parse().ok()
+        },
+        _ => None
+    }
+}
+
+/// Parses a wei amount which, unlike a quantity, may exceed `u64`.
+fn parse_amount(value: &serde_json::Value) -> Option<U256> {
+    match value {
+        serde_json::Value::Number(number) => number.as_u64().map(U256::from),
+        serde_json::Value::String(text) => match text.strip_prefix("0x") {
+            Some(hex) => U256::from_str_radix(hex, 16).ok(),
+            None => U256::from_dec_str(text).ok()
+        },
+        _ => None
+    }
+}
+
+fn deserialize_quantity<'de, D>(deserializer: D) -> Result<u64, D::Error> where D: Deserializer<'de> {
+    let value = serde_json::Value::deserialize(deserializer)?;
+    parse_quantity(&value).ok_or_else(|| serde::de::Error::custom(format!("invalid quantity: {}", value)))
+}
+
+fn deserialize_optional_quantity<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error> where D: Deserializer<'de> {
+    let value = serde_json::Value::deserialize(deserializer)?;
+    if value.is_null() {
+        return Ok(None);
+    }
+    parse_quantity(&value).map(Some).ok_or_else(|| serde::de::Error::custom(format!("invalid quantity: {}", value)))
+}
+
+/// Distinguishes a `null` field from a missing one, for `Option<Option<T>>` fields.
+fn deserialize_some<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error> where D: Deserializer<'de>, T: Deserialize<'de> {
+    T::deserialize(deserializer).map(Some)
+}
+
+/// The fee in wei: the gas used times the effective (or else the offered) gas price, `None` if
+/// the receipt could not be fetched.
+fn fee(tx: &RRTransaction, receipt: Option<&RRReceipt>) -> Option<U256> {
+    let gas_used = receipt.and_then(|receipt| receipt.gas_used);
+    let effective_gas_price = receipt.and_then(|receipt| receipt.effective_gas_price);
+
+    gas_used.zip(effective_gas_price.or(tx.gas_price)).map(|(gas_used, gas_price)| U256::from(gas_used) * U256::from(gas_price))
+}
+
+/// Who paid the fee: a sponsor if the transaction or its receipt names a payer other than the
+/// sender, or if the paid gas price is zero.
+fn fee_payer(tx: &RRTransaction, receipt: Option<&RRReceipt>) -> Option<FeePayer> {
+    let payer = tx.payer.as_deref().or_else(|| receipt.and_then(|receipt| receipt.field("payer")).and_then(serde_json::Value::as_str));
+    let effective_gas_price = receipt.and_then(|receipt| receipt.effective_gas_price);
+
+    match (payer, effective_gas_price.or(tx.gas_price)) {
+        (Some(payer), _) if !payer.eq_ignore_ascii_case(&tx.from) => Some(FeePayer::Sponsored),
+        (_, Some(0)) => Some(FeePayer::Sponsored),
+        (Some(_), _) | (_, Some(_)) => Some(FeePayer::Sender),
+        (None, None) => None
+    }
+}
+
+fn serialize_optional_amount<S>(value: &Option<U256>, serializer: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
+    match value {
+        Some(value) => serializer.serialize_some(&value.to_string()),
+        None => serializer.serialize_none()
+    }
+}
+
+fn deserialize_optional_amount<'de, D>(deserializer: D) -> Result<Option<U256>, D::Error> where D: Deserializer<'de> {
+    let value = serde_json::Value::deserialize(deserializer)?;
+    if value.is_null() {
+        return Ok(None);
+    }
+    parse_amount(&value).map(Some).ok_or_else(|| serde::de::Error::custom(format!("invalid amount: {}", value)))
+}
+
+fn count_word(count: usize) -> String {
+    match count {
+        2 => "two".to_string(),
+        3 => "three".to_string(),
+        4 => "four".to_string(),
+        5 => "five".to_string(),
+        _ => count.to_string()
+    }
+}
+
+/// Reports missing and duplicated nonces. A wallet's outgoing nonces are expected to run
+/// from 0 without holes, so anything else means the export is missing sent transactions.
+fn nonce_issues(nonces: &[u64]) -> Vec<String> {
+    let mut sorted = nonces.to_vec();
+    sorted.sort_unstable();
+
+    let mut issues = vec![];
+    let mut expected: u64 = 0;
+
+    for group in sorted.chunk_by(|a, b| a == b) {
+        let nonce = group[0];
+        if nonce > expected {
+            if nonce - 1 == expected {
+                issues.push(format!("nonce {} missing", expected));
+            } else {
+                issues.push(format!("nonce {}–{} missing", expected, nonce - 1));
+            }
+        }
+        if group.len() > 1 {
+            issues.push(format!("{} transactions with nonce {}", count_word(group.len()), nonce));
+        }
+        expected = nonce + 1;
+    }
+
+    issues
 }
 
 fn normalize_address(input: &str) -> String {
     input.replace("ronin:", "0x")
 }
 
+/// The file of `--summary-json`, `summary.json` if no name is given.
+fn summary_file_from_args() -> Option<String> {
+    ArgParser::split(&"--summary-json".to_string()).or_else(|| ArgParser::flag("--summary-json").then(|| "summary.json".to_string()))
+}
+
+/// Time the health check waits for a host before reporting it unreachable.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+/// The page size of the token transfer lists without `--page-size`.
+const TRANSFER_PAGE_SIZE: usize = 100;
+
+/// The request of the health check, the sent transactions of the zero address.
+fn health_check_path() -> String {
+    format!("/archive/listSentTransactions/{:?}", Address::zero())
+}
+
 struct RoninRest {
-    address: String,
-    host: String,
+    hosts: hosts::HostPool,
     client: ClientWithMiddleware,
+    /// The same connection pool without retries, for requests that should fail fast.
+    http: ClientWithMiddleware,
 }
 
 impl RoninRest {
-    pub fn new(address: String) -> RoninRest {
+    /// Requests are sent over `socket` instead of to the hosts if it is set, see `--unix-socket`.
+    pub fn new(client: reqwest::Client, hosts: Vec<String>, max_retries: u32, max_outage: Duration, socket: Option<unix_socket::UnixSocket>) -> RoninRest {
+        let transport = |builder: ClientBuilder| match socket.clone() {
+            Some(socket) => builder.with(socket),
+            None => builder
+        };
+        let http = transport(ClientBuilder::new(client.clone())).build();
+        let probe_urls = hosts.iter().map(|host| format!("{}{}", host.trim_end_matches('/'), health_check_path())).collect();
+        let breaker = breaker::CircuitBreaker::new(http.clone(), probe_urls, max_outage);
+
         RoninRest {
-            address,
-            host: "https://ronin.rest".into(),
-            client: ClientBuilder::new(reqwest::Client::new()).with(
+            http,
+            client: transport(ClientBuilder::new(client).with(
                 RetryTransientMiddleware::new_with_policy(
                     ExponentialBackoff {
-                        max_n_retries: 25,
+                        max_n_retries: max_retries,
                         min_retry_interval: Duration::from_secs(1),
                         max_retry_interval: Duration::from_secs(15),
                         backoff_exponent: 2
                     }
                 )
-            ).build(),
+            ).with(breaker).with(traffic::Counter)).build(),
+            hosts: hosts::HostPool::new(hosts),
         }
     }
 
-    pub async fn sent_transactions(&self) -> RRTransactionDict {
-        let data: RRTransactionDict = serde_json::from_str(
-            &self.client.get(format!("{}/archive/listSentTransactions/{}", self.host, self.address)).header("user-agent", DEFAULT_USER_AGENT).send().await.unwrap().text().await.unwrap()
-        ).unwrap();
+    /// Creates a client for the hosts and connection pool settings selected on the command line.
+    /// The client is shared by all requests of a run so connections are reused.
+    pub fn from_args() -> RoninRest {
+        let mut client = compression::client();
 
-        data
+        if let Some(max_idle) = ArgParser::split(&"--pool-max-idle".to_string()) {
+            client = client.pool_max_idle_per_host(
+                max_idle.parse().unwrap_or_else(|_| panic!("Could not parse --pool-max-idle={}, expected a number of connections", max_idle))
+            );
+        }
+        if let Some(idle_timeout) = ArgParser::split(&"--pool-idle-timeout".to_string()) {
+            client = client.pool_idle_timeout(Duration::from_secs(
+                idle_timeout.parse().unwrap_or_else(|_| panic!("Could not parse --pool-idle-timeout={}, expected a number of seconds", idle_timeout))
+            ));
+        }
+        if ArgParser::flag("--http2") {
+            client = client.http2_prior_knowledge();
+        }
+
+        let mut hosts = ArgParser::all("--host");
+        let mut socket = None;
+
+        if network::current() != network::Network::Mainnet {
+            println!(">> Exporting from the {} network <<", network::current().name());
+        }
+
+        if let Some(path) = unix_socket::from_args() {
+            if !hosts.is_empty() || hosts::localhost_from_args().is_some() {
+                panic!("--unix-socket can not be combined with --host or --localhost");
+            }
+            println!(">> Using the API at the unix socket {} <<", path);
+            socket = Some(unix_socket::connect(&path, ArgParser::flag("--http2")).unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                notify::failed(&error);
+                std::process::exit(1);
+            }));
+            hosts = vec![unix_socket::HOST.to_string()];
+        } else if let Some(localhost) = hosts::localhost_from_args() {
+            println!(">> !! USING LOCALHOST FOR API CALLS: {} !! <<", localhost);
+            hosts = vec![localhost];
+        } else if hosts.is_empty() {
+            hosts.push(network::current().default_host().to_string());
+        } else {
+            println!(">> Using API hosts: {} <<", hosts.join(", "));
+        }
+
+        if let Some(path) = ArgParser::split(&"--ca-cert".to_string()) {
+            let pem = std::fs::read(&path).unwrap_or_else(|error| panic!("Could not read the CA certificate {}: {}", path, error));
+            let certificate = reqwest::Certificate::from_pem(&pem).unwrap_or_else(|error| panic!("Invalid --ca-cert {}, expected a PEM certificate: {}", path, error));
+            client = client.add_root_certificate(certificate);
+        }
+        if ArgParser::flag("--insecure") {
+            if let Some(host) = hosts.iter().find(|host| network::is_public_host(host)) {
+                panic!("--insecure can not be used with the public API {}, it is only meant for self-hosted instances", host);
+            }
+            println!(">> !! TLS CERTIFICATES ARE NOT VERIFIED (--insecure), ANYONE ON THE NETWORK CAN TAMPER WITH THE EXPORT !! <<");
+            client = client.danger_accept_invalid_certs(true);
+        }
+
+        // With several hosts a failing host is given up on quickly in favor of the next, with
+        // --tx-max-attempts a failing transaction is retried by the pipeline instead.
+        let max_retries = match (hosts.len(), pipeline::tx_max_attempts()) {
+            (1, None) => 25,
+            (1, Some(_)) => 3,
+            _ => 2
+        };
+
+        RoninRest::new(client.build().expect("Could not create the HTTP client!"), hosts, max_retries, breaker::max_outage_from_args(), socket)
     }
-    pub async fn received_transactions(&self) -> RRTransactionDict {
-        let data: RRTransactionDict = serde_json::from_str(
-            &self.client.get(format!("{}/archive/listReceivedTransactions/{}", self.host, self.address)).header("user-agent", DEFAULT_USER_AGENT).send().await.unwrap().text().await.unwrap()
-        ).unwrap();
 
-        data
+    async fn get(&self, path: String) -> String {
+        self.try_get(path).await.unwrap_or_else(|error| panic!("{}", error))
     }
 
-    pub async fn decode_method(&self, hash: &RRTransactionHash) -> serde_json::Value {
-        let data: serde_json::Value = serde_json::from_str(
-            &self.client.get(format!("{}/ronin/decodeTransaction/{}", self.host, hash)).header("user-agent", DEFAULT_USER_AGENT).send().await.unwrap().text().await.unwrap()
-        ).unwrap();
+    /// Requests `path` from the first healthy host, failing over to the next host on errors.
+    async fn try_get(&self, path: String) -> Result<String, String> {
+        self.send(path, reqwest::header::HeaderMap::new()).await.map(|(_, _, body)| body)
+    }
 
-        data
+    /// Requests `path` with `headers`, returning the status and headers along with the body.
+    async fn send(&self, path: String, headers: reqwest::header::HeaderMap) -> Result<(reqwest::StatusCode, reqwest::header::HeaderMap, String), String> {
+        let mut last_error = String::new();
+
+        for host in self.hosts.candidates() {
+            self.hosts.started(host);
+            traffic::requested(&path);
+
+            let mut extensions = task_local_extensions::Extensions::new();
+            extensions.insert(traffic::ApiPath(path.clone()));
+
+            let response = self.client.get(format!("{}{}", host.url, path)).header("user-agent", DEFAULT_USER_AGENT).headers(headers.clone()).send_with_extensions(&mut extensions).await;
+            let body = match response {
+                Ok(response) if diagnostics::is_transient(response.status()) => Err(format!("HTTP {}", response.status())),
+                Ok(response) => {
+                    let (status, headers) = (response.status(), response.headers().clone());
+                    response.text().await.map(|body| (status, headers, body)).map_err(|error| format!("Could not read the response: {}", error))
+                }
+                Err(error) => Err(diagnostics::describe_middleware(&error, &host.url))
+            };
+
+            let body = body.inspect(|(_, _, body)| traffic::downloaded(&path, body.len()));
+
+            match body {
+                Ok(body) => {
+                    self.hosts.succeeded(host);
+                    return Ok(body);
+                }
+                Err(error) => {
+                    self.hosts.failed(host, &error);
+                    last_error = error;
+                }
+            }
+        }
+
+        Err(format!("Could not request {} from any API host: {}", path, last_error))
     }
 
-    pub async fn decode_receipt(&self, hash: &RRTransactionHash) -> serde_json::Value {
-        let data: serde_json::Value = serde_json::from_str(
-            &self.client.get(format!("{}/ronin/decodeTransactionReceipt/{}", self.host, hash)).header("user-agent", DEFAULT_USER_AGENT).send().await.unwrap().text().await.unwrap()
-        ).unwrap();
+    /// Fetches a transaction list of the archive, keeping only well-formed hashes, see
+    /// `hashes::sanitize`.
+    async fn transaction_list(&self, list: &str, address: &str) -> RRTransactionDict {
+        let mut data = self.fetch_transaction_list(list, address).await;
+        (data.transactions, data.rejected) = hashes::sanitize(list, std::mem::take(&mut data.transactions));
+
+        for rejected in &data.rejected {
+            logging::warn(&format!("Dropped the entry '{}' of {} for {}: {}", rejected.entry, list, address, rejected.reason));
+        }
 
         data
     }
 
-    pub async fn transaction(&self, hash: &RRTransactionHash) -> RRTransaction {
-        let data: RRTransaction = serde_json::from_str(
-            &self.client.get(format!("{}/ronin/getTransaction/{}", self.host, hash)).header("user-agent", DEFAULT_USER_AGENT).send().await.unwrap().text().await.unwrap()
-        ).unwrap_or(RRTransaction {
-            from: "null".to_string(),
-            to: "null".to_string(),
-            hash: "null".to_string(),
-            block_number: 0
+    /// Requests the list at `path`. With `--cache-dir` the list is kept along with its `ETag` or
+    /// `Last-Modified`, and the next run only downloads it again if it changed. APIs without
+    /// validators are asked for the whole list every time.
+    async fn get_list(&self, path: String) -> String {
+        let Some(cache_path) = cache::CachedList::path(&path) else {
+            return self.get(path).await;
+        };
+        let cached = cache::CachedList::load(&cache_path);
+        let headers = cached.as_ref().map(cache::CachedList::conditional_headers).unwrap_or_default();
+
+        let (status, headers, body) = self.send(path, headers).await.unwrap_or_else(|error| panic!("{}", error));
+
+        match (status, cached) {
+            (reqwest::StatusCode::NOT_MODIFIED, Some(cached)) => {
+                cache::CachedList::not_modified();
+                cached.body
+            }
+            _ => {
+                match cache::CachedList::from_response(&headers, body.clone()) {
+                    Some(list) => list.store(&cache_path),
+                    None => {
+                        let _ = std::fs::remove_file(&cache_path);
+                    }
+                }
+                body
+            }
+        }
+    }
+
+    /// Fetches a transaction list of the archive, page by page with `--page-size`. Pages are
+    /// deduplicated and an inconsistent page sequence is reported in `issues`.
+    async fn fetch_transaction_list(&self, list: &str, address: &str) -> RRTransactionDict {
+        let path = format!("/archive/{}/{}", list, address);
+        let page_size = ArgParser::split(&"--page-size".to_string()).map(|page_size| match page_size.parse::<usize>() {
+            Ok(page_size) if page_size > 0 => page_size,
+            _ => panic!("Could not parse --page-size={}, expected a positive number of transactions", page_size)
         });
 
-        data
+        let page_size = match page_size {
+            Some(page_size) => page_size,
+            None => return serde_json::from_str(&self.get_list(path).await).unwrap()
+        };
+
+        collect_pages(list, address, page_size, |offset, limit| self.get(format!("{}?offset={}&limit={}", path, offset, limit))).await
     }
 }
 
-struct ArgParser {}
-impl ArgParser {
-    fn parse() -> Vec<String> {
-        std::env::args().collect()
-    }
+/// Collects a transaction list page by page, `request(offset, limit)` fetches one of them. Pages
+/// are deduplicated and an inconsistent page sequence is reported in `issues`.
+async fn collect_pages<F, R>(list: &str, address: &str, page_size: usize, mut request: F) -> RRTransactionDict
+    where F: FnMut(usize, usize) -> R, R: std::future::Future<Output = String> {
+    let progress = indicatif::ProgressBar::new_spinner();
+    let mut data = RRTransactionDict::default();
+    let mut seen: HashSet<RRTransactionHash> = HashSet::new();
 
-    fn split(param: &String) -> Option<String> {
+    for page in 0.. {
+        let offset = page * page_size;
+        let body = request(offset, page_size).await;
+        let response: RRTransactionDict = serde_json::from_str(&body).unwrap_or_else(|error| {
+            panic!("Could not parse page {} of {} for {}: {}", page + 1, list, address, error)
+        });
+        let received = response.transactions.len();
+        let mut duplicates = 0;
 
-        let args: Vec<String> = ArgParser::parse();
+        data.total = data.total.or(response.total);
 
-        for arg in args {
-            if arg.starts_with(param) {
-                let kv: Vec<&str> = arg.split('=').collect();
-                if kv.len() == 2 {
-                    return Some(kv[1].to_string())
-                }
+        for hash in response.transactions {
+            match seen.insert(hash.to_lowercase()) {
+                true => data.transactions.push(hash),
+                false => duplicates += 1
+            }
+        }
+
+        progress.set_message(format!("{} {}: {} transactions ({} pages)", list, address, data.transactions.len(), page + 1));
+        progress.tick();
+
+        if received > page_size {
+            if page > 0 {
+                data.issues.push(format!("{}: page {} returned {} transactions, more than the page size {}", list, page + 1, received, page_size));
             }
+            // The API ignores the pagination parameters and returned the whole list.
+            break;
+        }
+        if received > 0 && duplicates == received {
+            data.issues.push(format!("{}: page {} at offset {} only repeated earlier transactions, the list may have changed while paging", list, page + 1, offset));
+            break;
         }
+        if received < page_size {
+            break;
+        }
+    }
 
-        None
+    progress.finish_and_clear();
+
+    if let Some(total) = data.total {
+        if total != data.transactions.len() {
+            data.issues.push(format!("{}: the API reported {} transactions but {} were received", list, total, data.transactions.len()));
+        }
     }
+
+    data
 }
 
-#[tokio::main]
-async fn main() {
+#[async_trait::async_trait]
+impl provider::WalletDataProvider for RoninRest {
+    fn name(&self) -> &'static str {
+        "ronin-rest"
+    }
 
-    let use_localhost = match ArgParser::split(&"--localhost".to_string()) {
-        None => false,
-        Some(_) => true
-    };
+    fn capabilities(&self) -> provider::Capabilities {
+        provider::Capabilities { decode: true, raw_logs: true, transaction_fields: true, blocks: true, genes: true, abis: true, token_transfers: true }
+    }
 
-    let address: String = match ArgParser::split(&"--address".to_string()) {
-        None => {
-            normalize_address(
-                &Input::new()
-                    .with_prompt("Please enter your Ronin address")
-                    .validate_with(|input: &String| -> Result<(), &str> {
-                        let address = normalize_address(input).as_str().parse::<Address>();
-                        match address {
-                            Ok(_) => Ok(()),
-                            Err(_) => Err("Failed to parse your address!")
-                        }
-                    })
-                    .interact()
-                    .unwrap()
-            )
-        },
-        Some(passed_address) => {
-            let address = normalize_address(&passed_address).as_str().parse::<Address>();
-            match address {
-                Ok(_) => normalize_address(&passed_address),
-                Err(_) => {
-                    panic!("Could not parse address!");
+    /// Checks that at least one host is reachable and answers like a ronin.rest instance, reporting
+    /// its version if it sends one. Unreachable hosts are reported but skipped by the failover anyway.
+    async fn health_check(&self) -> Result<(), String> {
+        let mut errors = vec![];
+
+        for host in self.hosts.urls() {
+            let path = health_check_path();
+            let url = format!("{}{}", host, path);
+
+            traffic::requested(&path);
+            traffic::attempted(&path);
+
+            let response = match self.http.get(&url).header("user-agent", DEFAULT_USER_AGENT).timeout(HEALTH_CHECK_TIMEOUT).send().await {
+                Ok(response) => response,
+                Err(error) => {
+                    errors.push(format!("Could not reach {}: {}", host, diagnostics::describe_middleware(&error, host)));
+                    continue;
                 }
+            };
+
+            let version = response.headers().get("x-api-version").and_then(|version| version.to_str().ok()).map(str::to_string);
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            traffic::downloaded(&path, body.len());
+
+            let shape_ok = status.is_success() && serde_json::from_str::<RRTransactionDict>(&body).is_ok();
+
+            if !shape_ok {
+                errors.push(format!("{} does not look like a ronin.rest instance: unexpected response ({}) from {}", host, status, url));
+                continue;
             }
+
+            if let Some(version) = version {
+                println!(">> {} runs API version {} <<", host, version);
+            }
+
+            for error in errors.drain(..) {
+                println!("{}", error);
+            }
+
+            return Ok(());
         }
-    };
 
+        Err(errors.join("\n"))
+    }
+
+    /// The host that served the latest request.
+    fn active_host(&self) -> &str {
+        self.hosts.active()
+    }
 
-    let mut rr = RoninRest::new(address);
+    fn host_requests(&self) -> Vec<(&str, u64)> {
+        self.hosts.requests()
+    }
 
-    if use_localhost {
-        println!(">> !! USING LOCALHOST FOR API CALLS !! <<");
-        rr.host = "http://localhost:3000".to_string();
+    async fn list_sent(&self, address: &str) -> RRTransactionDict {
+        self.transaction_list("listSentTransactions", address).await
     }
 
-    let mut sent: RRTransactionDict = rr.sent_transactions().await;
-    let mut received: RRTransactionDict = rr.received_transactions().await;
+    async fn list_received(&self, address: &str) -> RRTransactionDict {
+        self.transaction_list("listReceivedTransactions", address).await
+    }
 
-    let mut total: Vec<RRTransactionHash> = vec![];
+    async fn decode_input(&self, hash: &RRTransactionHash) -> Result<serde_json::Value, String> {
+        let body = self.try_get(format!("/ronin/decodeTransaction/{}", hash)).await?;
 
-    println!("Sent Transactions: {}\nReceived Transactions: {}\nAddress: {}", sent.transactions.len(), received.transactions.len(), rr.address);
+        serde_json::from_str(&body).map_err(|error| format!("Could not parse the decoded transaction {}: {}", hash, error))
+    }
 
-    total.append(&mut sent.transactions);
-    total.append(&mut received.transactions);
+    async fn decode_receipt(&self, hash: &RRTransactionHash) -> Result<RRReceipt, String> {
+        let body = self.try_get(format!("/ronin/decodeTransactionReceipt/{}", hash)).await?;
 
-    total.dedup();
+        serde_json::from_str(&body).map_err(|error| format!("Could not parse the decoded receipt of {}: {}", hash, error))
+    }
 
-    let progress = indicatif::ProgressBar::new(total.len() as u64);
-    progress.set_style(
-        ProgressStyle::with_template("{spinner}{bar:100.cyan/blue} {percent:>3}% | [{eta_precise}][{elapsed_precise}] ETA/Elapsed | {pos:>7}/{len:7} {msg}").unwrap()
-    );
+    /// The number of the latest block, a quantity or an object with a `blockNumber`.
+    async fn latest_block(&self) -> Result<u64, String> {
+        let body = self.try_get("/ronin/getBlockNumber".to_string()).await?;
+        let value: serde_json::Value = serde_json::from_str(&body).map_err(|error| format!("Could not parse the latest block number: {}", error))?;
 
-    let mut account_data: Vec<RRDecodedTransaction> = vec![];
+        parse_quantity(value.get("blockNumber").unwrap_or(&value)).ok_or_else(|| format!("Could not parse the latest block number {}", value))
+    }
 
-    for hash in total {
-        let tx = rr.transaction(&hash).await;
+    async fn get_block(&self, number: u64) -> Result<block::RRBlock, String> {
+        let body = self.try_get(format!("/ronin/getBlock/{}", number)).await?;
+        let value: serde_json::Value = serde_json::from_str(&body).map_err(|error| format!("Could not parse block {}: {}", number, error))?;
 
-        if tx.to == "null" && tx.from == "null" {
-            println!("Failed to retrieve transaction details: {}", &hash)
-        }
+        block::RRBlock::from_value(number, &value)
+    }
 
-        if tx.to != tx.from {
-            account_data.push(
-                RRDecodedTransaction {
-                    from: tx.from,
-                    input: Some(rr.decode_method(&hash).await),
-                    output: Some(rr.decode_receipt(&hash).await),
-                    hash: hash.clone(),
-                    to: tx.to,
-                    block_number: tx.block_number,
-                }
-            );
-        }
+    /// The genes of an axie, a hex string or an object with `genes`.
+    async fn axie_genes(&self, token_id: &str) -> Result<String, String> {
+        let body = self.try_get(format!("/axie/getAxie/{}", token_id)).await?;
+        let value: serde_json::Value = serde_json::from_str(&body).map_err(|error| format!("Could not parse axie {}: {}", token_id, error))?;
 
-        progress.inc(1);
-        progress.set_message(hash);
+        value.get("genes").unwrap_or(&value).as_str().map(str::to_string).ok_or_else(|| format!("Axie {} has no genes", token_id))
     }
 
-    progress.set_message("Saving...");
+    /// The verified ABI of a contract, an array or an object with `abi`, which may also be the ABI
+    /// as a JSON string.
+    async fn contract_abi(&self, address: &str) -> Result<serde_json::Value, String> {
+        let body = self.try_get(format!("/ronin/getContractAbi/{}", address)).await?;
+        let value: serde_json::Value = serde_json::from_str(&body).map_err(|error| format!("Could not parse the ABI of {}: {}", address, error))?;
 
-    account_data.sort_by(|a, b| {
-        a.block_number.cmp(&b.block_number)
-    });
+        let abi = match value.get("abi").cloned().unwrap_or(value) {
+            serde_json::Value::String(abi) => serde_json::from_str(&abi).map_err(|error| format!("Could not parse the ABI of {}: {}", address, error))?,
+            abi => abi
+        };
+        match abi.is_array() {
+            true => Ok(abi),
+            false => Err(format!("{} has no verified ABI", address))
+        }
+    }
 
-    let output_file_name = format!("{}.json", rr.address);
+    /// The token transfers of `address`, paged with `--page-size` like the transaction lists but
+    /// always in pages since the lists of active wallets are large. Pages are `{"transfers": [...]}`
+    /// with an optional `total`, or plain arrays.
+    async fn token_transfers(&self, standard: transfers::TokenStandard, address: &str) -> Result<transfers::TransferList, String> {
+        let path = format!("/archive/list{}Transfers/{}", standard.endpoint_name(), address);
+        let page_size = ArgParser::split(&"--page-size".to_string()).map_or(TRANSFER_PAGE_SIZE, |page_size| match page_size.parse::<usize>() {
+            Ok(page_size) if page_size > 0 => page_size,
+            _ => panic!("Could not parse --page-size={}, expected a positive number of transactions", page_size)
+        });
 
-    std::fs::write(&output_file_name, serde_json::to_string(&account_data).unwrap()).unwrap();
+        let mut list = transfers::TransferList::default();
+        let mut seen: HashSet<String> = HashSet::new();
 
-    progress.set_message("FINISH!");
+        for page in 0.. {
+            let offset = page * page_size;
+            let body = self.try_get(format!("{}?offset={}&limit={}", path, offset, page_size)).await?;
+            let response: serde_json::Value = serde_json::from_str(&body)
+                .map_err(|error| format!("Could not parse page {} of the {} transfers of {}: {}", page + 1, standard.endpoint_name(), address, error))?;
 
-    progress.finish();
+            list.total = list.total.or_else(|| response.get("total").and_then(parse_quantity).map(|total| total as usize));
+            let entries = match response.get("transfers").unwrap_or(&response) {
+                serde_json::Value::Array(entries) => entries.clone(),
+                serde_json::Value::Null => vec![],
+                _ => return Err(format!("Page {} of the {} transfers of {} has no transfer list", page + 1, standard.endpoint_name(), address))
+            };
+            let received = entries.len();
+            let mut duplicates = 0;
+
+            for entry in entries {
+                // Entries are told apart by their transaction and log, or else as a whole.
+                let key = match (entry.get("transactionHash").or_else(|| entry.get("hash")), entry.get("logIndex")) {
+                    (Some(hash), Some(index)) => format!("{}:{}", hash.to_string().to_lowercase(), index),
+                    _ => entry.to_string()
+                };
+                match seen.insert(key) {
+                    true => list.transfers.push(entry),
+                    false => duplicates += 1
+                }
+            }
 
-    println!("The output was saved to {}", &output_file_name);
+            if received > page_size {
+                if page > 0 {
+                    list.issues.push(format!("{} transfers: page {} returned {} transfers, more than the page size {}", standard.endpoint_name(), page + 1, received, page_size));
+                }
+                break;
+            }
+            if received > 0 && duplicates == received {
+                list.issues.push(format!("{} transfers: page {} at offset {} only repeated earlier transfers, the list may have changed while paging", standard.endpoint_name(), page + 1, offset));
+                break;
+            }
+            if received < page_size {
+                break;
+            }
+        }
+
+        if let Some(total) = list.total.filter(|total| *total != list.transfers.len()) {
+            list.issues.push(format!("{} transfers: the API reported {} transfers but {} were received", standard.endpoint_name(), total, list.transfers.len()));
+        }
+
+        Ok(list)
+    }
+
+    /// The current RON balance of `address` in wei.
+    async fn balance(&self, address: &str) -> Result<U256, String> {
+        let body = self.try_get(format!("/ronin/getBalance/{}", address)).await?;
+        let value: serde_json::Value = serde_json::from_str(&body).map_err(|error| format!("Could not parse the balance of {}: {}", address, error))?;
+
+        parse_amount(value.get("balance").unwrap_or(&value)).ok_or_else(|| format!("Could not parse the balance {} of {}", value, address))
+    }
+
+    /// The logs of the undecoded receipt, including events the decoder doesn't recognize.
+    async fn raw_logs(&self, hash: &RRTransactionHash) -> Result<serde_json::Value, String> {
+        let body = self.try_get(format!("/ronin/getTransactionReceipt/{}", hash)).await?;
+        let receipt: serde_json::Value = serde_json::from_str(&body).map_err(|error| format!("Could not parse the receipt of {}: {}", hash, error))?;
+
+        let logs = receipt.get("logs").and_then(serde_json::Value::as_array).map(|logs| logs.iter().map(|log| serde_json::json!({
+            "address": log.get("address"),
+            "topics": log.get("topics"),
+            "data": log.get("data"),
+        })).collect()).unwrap_or_default();
+
+        Ok(serde_json::Value::Array(logs))
+    }
+
+    /// Fetches a transaction. Transactions the API has no details for are `RRTransaction::unavailable`.
+    async fn get_transaction(&self, hash: &RRTransactionHash) -> Result<RRTransaction, String> {
+        self.get_transaction_fields(hash).await.map(|(tx, _)| tx)
+    }
+
+    /// Fetches a transaction along with all fields of the response, e.g. the signature.
+    async fn get_transaction_fields(&self, hash: &RRTransactionHash) -> Result<(RRTransaction, serde_json::Value), String> {
+        let body = self.try_get(format!("/ronin/getTransaction/{}", hash)).await?;
+        let fields: serde_json::Value = serde_json::from_str(&body).unwrap_or_default();
+
+        Ok((serde_json::from_value(fields.clone()).unwrap_or_else(|_| RRTransaction::unavailable()), fields))
+    }
+}
+
+struct ArgParser {}
+impl ArgParser {
+    fn parse() -> Vec<String> {
+        std::env::args().collect()
+    }
+
+    fn flag(param: &str) -> bool {
+        ArgParser::parse().iter().any(|arg| arg == param)
+    }
+
+    /// Returns the values of a flag that may be passed multiple times.
+    fn all(param: &str) -> Vec<String> {
+        let prefix = format!("{}=", param);
+
+        ArgParser::parse().iter()
+            .filter_map(|arg| arg.strip_prefix(&prefix).map(str::to_string))
+            .collect()
+    }
+
+    /// Returns the value of `param=VALUE`, everything after the first `=`.
+    #[allow(clippy::ptr_arg)]
+    fn split(param: &String) -> Option<String> {
+        ArgParser::value(&ArgParser::parse(), param)
+    }
+
+    fn value(args: &[String], param: &str) -> Option<String> {
+        args.iter()
+            .filter_map(|arg| arg.split_once('='))
+            .find(|(name, _)| *name == param)
+            .map(|(_, value)| value.to_string())
+    }
+}
+
+#[tokio::main]
+async fn main() {
+
+    let args = ArgParser::parse();
+
+    match args.get(1).map(String::as_str) {
+        Some("abi") => std::process::exit(commands::abi::run(&args[2..]).await),
+        Some("blocks") => std::process::exit(commands::blocks::run(&args[2..]).await),
+        Some("check") | Some("--check") => std::process::exit(commands::check::run(&args[2..])),
+        Some("contract") => std::process::exit(commands::contract::run(&args[2..]).await),
+        Some("convert") => std::process::exit(commands::convert::run(&args[2..])),
+        Some("decode") => std::process::exit(commands::decode::run(&args[2..]).await),
+        Some("diff") => std::process::exit(commands::diff::run(&args[2..])),
+        Some("examples") => std::process::exit(commands::examples::run(&args[2..])),
+        Some("merge") => std::process::exit(commands::merge::run(&args[2..])),
+        Some("prune") => std::process::exit(commands::prune::run(&args[2..])),
+        Some("verify") => std::process::exit(commands::verify::run(&args[2..]).await),
+        Some("schema") => std::process::exit(commands::schema::run(&args[2..])),
+        Some("search") => std::process::exit(commands::search::run(&args[2..])),
+        Some("split") => std::process::exit(commands::split::run(&args[2..])),
+        Some("stats") => std::process::exit(commands::stats::run(&args[2..]).await),
+        Some("tail") => std::process::exit(commands::tail::run(&args[2..])),
+        Some("token-transfers") => std::process::exit(commands::token_transfers::run(&args[2..]).await),
+        Some("validate") => std::process::exit(commands::validate::run(&args[2..])),
+        _ => {}
+    }
+
+    if database::print_schema() {
+        println!("{}", database::SCHEMA);
+        return;
+    }
+
+    logging::init();
+    notify::init();
+    let exec_after = exec::from_args();
+
+    let started_at = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let filters = manifest::filters();
+
+    let record_filters = filters::RecordFilters::from_args();
+    let exclude_failed = record_filters.exclude_failed;
+
+    let mut pipeline_options = pipeline::Options::from_args();
+
+    if exclude_failed && !pipeline_options.decode_output {
+        panic!("--exclude-failed needs the decoded receipt and can't be combined with --no-decode-output!");
+    }
+    if events::file_name().is_some() {
+        if !pipeline_options.decode_output && !pipeline_options.raw_logs {
+            panic!("--events-out needs the decoded receipt or --raw-logs and can't be combined with --no-decode-output alone!");
+        }
+        if ArgParser::flag("--hashes-only") {
+            panic!("--events-out can not be combined with --hashes-only");
+        }
+    }
+
+    let mut addresses: Vec<aliases::WalletArg> = ArgParser::all("--address").iter().map(|passed_address| {
+        aliases::WalletArg::parse(passed_address).unwrap_or_else(|error| panic!("{}", error))
+    }).collect();
+
+    if addresses.is_empty() {
+        let input: String = Input::new()
+            .with_prompt("Please enter your Ronin address")
+            .validate_with(|input: &String| -> Result<(), String> {
+                aliases::WalletArg::parse(input).map(|_| ())
+            })
+            .interact()
+            .unwrap();
+
+        addresses.push(aliases::WalletArg::parse(&input).unwrap());
+    }
+
+    let addresses = aliases::deduplicate(addresses).unwrap_or_else(|error| panic!("{}", error));
+    logging::info(&format!("Export of {} started", addresses.iter().map(|wallet| wallet.address.as_str()).collect::<Vec<&str>>().join(", ")));
+    notify::set_addresses(addresses.iter().map(|wallet| wallet.address.clone()).collect());
+    let alias_mapping = aliases::mapping(&addresses);
+
+    // Pseudonyms replace the wallets everywhere, an alias would give them away.
+    if !alias_mapping.is_empty() && ArgParser::flag("--anonymize") {
+        panic!("Wallet aliases can not be combined with --anonymize");
+    }
+
+    let merge_file_name = ArgParser::split(&"--merge".to_string())
+        .or_else(|| ArgParser::flag("--merge").then(|| "merged.json".to_string()));
+
+    if let Some(schema_file) = ArgParser::split(&"--emit-schema".to_string()) {
+        let schema = schema::export_schema(&pipeline_options, &schema::Fields::from_args());
+        std::fs::write(&schema_file, serde_json::to_string_pretty(&schema).unwrap())
+            .unwrap_or_else(|error| panic!("Could not write {}: {}", schema_file, error));
+    }
+
+    // Asks for the passphrase before any API calls are made.
+    let mut writer = output::Output::from_args();
+
+    let group_by = grouping::GroupBy::from_args();
+    let per_contract = grouping::PerContract::from_args();
+    if group_by.is_some() && matches!(writer.format, output::Format::Arrow) {
+        panic!("--group-by can not be combined with --format=arrow");
+    }
+    if group_by.is_some() && writer.out_dir.is_none() {
+        if writer.chunking.is_some() {
+            panic!("--group-by can only be combined with --max-file-size or --chunk-size when using --out-dir");
+        }
+        if let output::Format::Csv(_) | output::Format::CoinTracking | output::Format::Ctc = writer.format {
+            panic!("--group-by can only be combined with --format={} when using --out-dir", ArgParser::split(&"--format".to_string()).unwrap());
+        }
+    }
+    // Parsed before anything is fetched, so an invalid limit fails right away.
+    sort::memory_limit();
+    if canonical::enabled() && !matches!(writer.format, output::Format::Json) {
+        panic!("--canonical requires --format=json");
+    }
+    if merge_file_name.is_some() && writer.format.is_accounting() {
+        panic!("--merge can not be combined with --format={}, accounting exports are per wallet", ArgParser::split(&"--format".to_string()).unwrap());
+    }
+    // Shards are stitched together by `wally merge`, which reads plain JSON exports.
+    if shard::current().is_some() {
+        if !matches!(writer.format, output::Format::Json) {
+            panic!("--shard can only be combined with --format=json");
+        }
+        if writer.encryption.is_some() || writer.chunking.is_some() {
+            panic!("--shard can not be combined with --encrypt, --max-file-size or --chunk-size");
+        }
+        if merge_file_name.is_some() || group_by.is_some() || per_contract.is_some() {
+            panic!("--shard can not be combined with --merge, --group-by or --per-contract");
+        }
+        // The balance depends on every transaction before it.
+        if balance::enabled() {
+            panic!("--shard can not be combined with --balance or --report=balance");
+        }
+    }
+    // Appending extends the flat JSON export of every wallet in place.
+    if append::enabled() {
+        if !matches!(writer.format, output::Format::Json) {
+            panic!("--append can only be combined with --format=json");
+        }
+        if writer.chunking.is_some() || writer.out_dir.is_some() {
+            panic!("--append can not be combined with --max-file-size, --chunk-size or --out-dir");
+        }
+        if merge_file_name.is_some() || group_by.is_some() || per_contract.is_some() || shard::current().is_some() {
+            panic!("--append can not be combined with --merge, --group-by, --per-contract or --shard");
+        }
+        // Pseudonyms and balances depend on all transactions of the run.
+        if ArgParser::flag("--anonymize") || balance::enabled() || ArgParser::flag("--hashes-only") {
+            panic!("--append can not be combined with --anonymize, --balance, --report=balance or --hashes-only");
+        }
+    }
+
+    // Approvals are only revoked by later transactions, and name the wallet.
+    if approvals::report() && (ArgParser::flag("--anonymize") || append::enabled() || shard::current().is_some()) {
+        panic!("--report=approvals can not be combined with --anonymize, --append or --shard, run `wally stats EXPORT --report=approvals` on the complete export instead");
+    }
+    if largest::report() && (ArgParser::flag("--anonymize") || append::enabled() || shard::current().is_some()) {
+        panic!("--report=largest can not be combined with --anonymize, --append or --shard, run `wally stats EXPORT --report=largest` on the complete export instead");
+    }
+
+    // Records are written to the database as they are fetched, before pseudonyms and balances exist.
+    let postgres_url = database::url_from_args();
+    if postgres_url.is_some() && (ArgParser::flag("--anonymize") || balance::enabled() || ArgParser::flag("--hashes-only")) {
+        panic!("--postgres-url can not be combined with --anonymize, --balance, --report=balance or --hashes-only");
+    }
+
+    // Overlapping runs for the same wallet would overwrite each other's files.
+    let wait_lock = lock::wait_from_args();
+    let locks: Vec<lock::OutputLock> = addresses.iter().map(|wallet| {
+        let stem = wallet.alias.as_ref().unwrap_or(&wallet.address);
+        lock::OutputLock::acquire(&writer.export_path(Some(stem), &shard::stem(stem)), wait_lock).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            notify::failed(&error);
+            std::process::exit(1);
+        })
+    }).collect();
+
+    let uploader = upload::Uploader::from_args();
+    let mut upload_failed = false;
+
+    let rr = provider::from_args();
+    provider::restrict(&mut pipeline_options, rr.as_ref());
+
+    if exclude_failed && !pipeline_options.decode_output {
+        panic!("--exclude-failed needs the decoded receipt, which the {} provider can't deliver!", rr.name());
+    }
+
+    if !ArgParser::flag("--skip-health-check") {
+        if let Err(error) = rr.health_check().await {
+            eprintln!("{}", error);
+            notify::failed(&error);
+            drop(locks);
+            std::process::exit(1);
+        }
+    }
+
+    let mut postgres = match &postgres_url {
+        Some(url) => match database::PostgresSink::connect(url).await {
+            Ok(postgres) => Some(postgres),
+            Err(error) => {
+                eprintln!("{}", error);
+                notify::failed(&error);
+                drop(locks);
+                std::process::exit(1);
+            }
+        },
+        None => None
+    };
+
+    let latest_block = match ArgParser::flag("--confirmations") {
+        true => match rr.latest_block().await {
+            Ok(latest_block) => {
+                println!("Confirmations are counted from the latest block {}", latest_block);
+                Some(latest_block)
+            }
+            Err(error) => panic!("Could not get the latest block for --confirmations: {}", error)
+        },
+        false => None
+    };
+
+    let mut wallets: Vec<WalletExport> = vec![];
+    let mut total: Vec<RRTransactionHash> = vec![];
+    let mut seen: HashSet<RRTransactionHash> = HashSet::new();
+
+    for aliases::WalletArg { address, alias } in addresses {
+        let sent: RRTransactionDict = rr.list_sent(&address).await;
+        let received: RRTransactionDict = rr.list_received(&address).await;
+
+        let (sent_count, received_count) = (sent.transactions.len(), received.transactions.len());
+        let mut wallet = WalletExport::new(address, alias, sent, received);
+
+        println!("Sent Transactions: {}\nReceived Transactions: {}\nAddress: {}", sent_count, received_count, wallet.label());
+
+        if let Some(shard) = shard::current() {
+            let transactions = wallet.hashes.len();
+            wallet.hashes.retain(|hash| shard.contains(hash));
+            println!("Shard {} of {}: {} of {} transactions", shard.index, shard.count, wallet.hashes.len(), transactions);
+        }
+
+        if append::enabled() {
+            let path = writer.export_path(Some(&wallet.file_stem), &wallet.file_stem);
+
+            if let Some(existing) = append::load(&path, &wallet.address).unwrap_or_else(|error| panic!("{}", error)) {
+                wallet.hashes.retain(|hash| !existing.hashes.contains(&hash.to_lowercase()));
+                wallet.sent_nonces.extend(&existing.sent_nonces);
+                wallet.coverage = existing.coverage.clone();
+                println!("Appending to {}: {} transactions already exported, {} new", path, existing.records, wallet.hashes.len());
+                wallet.existing = Some(existing);
+
+                // Records of differently filtered runs can't be told apart once merged.
+                let manifest_path = writer.path(Some(&wallet.file_stem), &manifest::Manifest::file_name(&wallet.file_stem));
+                let previous = manifest::Manifest::load(&manifest_path).unwrap_or_else(|error| panic!("{}", error));
+                if let Some(Err(message)) = previous.as_ref().map(|previous| append::check_manifest(&manifest_path, previous, &wallet.address)) {
+                    eprintln!("{}\nRun the export again without --append", message);
+                    notify::failed(&message);
+                    drop(locks);
+                    std::process::exit(1);
+                }
+                let differences = previous.as_ref().map(|previous| manifest::differences(&previous.filters, &filters)).unwrap_or_default();
+
+                if !differences.is_empty() {
+                    let message = format!("The filters differ from the ones of the export in {}:\n  {}", manifest_path, differences.join("\n  "));
+
+                    if !ArgParser::flag("--force") {
+                        eprintln!("{}\nRun the export again without --append, or pass --force to append anyway", message);
+                        notify::failed(&message);
+                        drop(locks);
+                        std::process::exit(1);
+                    }
+
+                    println!("Warning: {}\nAppending anyway because of --force", message);
+                    logging::warn(&message);
+                }
+                wallet.previous_manifest = previous;
+            }
+        }
+
+        // Transactions between several of the exported wallets are only fetched once.
+        for hash in &wallet.hashes {
+            if seen.insert(hash.clone()) {
+                total.push(hash.clone());
+            }
+        }
+
+        wallets.push(wallet);
+    }
+
+    if ArgParser::flag("--hashes-only") {
+        let mut anonymizer = ArgParser::flag("--anonymize").then(|| anonymize::Anonymizer::new(&wallets.iter().map(|wallet| wallet.address.clone()).collect::<Vec<String>>()));
+        let mut notified = vec![];
+
+        for wallet in wallets.iter_mut() {
+            if let Some(anonymizer) = anonymizer.as_mut() {
+                wallet.file_stem = anonymizer.pseudonym(&wallet.address);
+            }
+
+            let entries: Vec<RRHashEntry> = wallet.hashes.iter().map(|hash| RRHashEntry {
+                hash,
+                direction: wallet.directions[hash],
+            }).collect();
+            let file_name = writer.write(Some(&wallet.file_stem), format!("{}.hashes.json", shard::stem(&wallet.file_stem)), serde_json::to_string(&entries).unwrap());
+
+            println!("The {} transaction hashes of {} were saved to {}", entries.len(), wallet.file_stem, file_name);
+            notified.push(notify::NotifiedWallet { address: wallet.address.clone(), alias: wallet.alias.clone(), transactions: entries.len(), files: vec![file_name] });
+        }
+
+        if let Some(anonymizer) = anonymizer {
+            let mapping_file_name = writer.write(None, "anonymize-mapping.json".to_string(), serde_json::to_string_pretty(&anonymizer.mapping()).unwrap());
+            println!("\nThe pseudonym mapping was saved to {}. Keep it private, it reveals all anonymized addresses!", mapping_file_name);
+        }
+
+        if !alias_mapping.is_empty() {
+            let alias_file_name = writer.write(None, "aliases.json".to_string(), serde_json::to_string_pretty(&alias_mapping).unwrap());
+            println!("\nThe wallet aliases were saved to {}", alias_file_name);
+        }
+
+        notify::completed(&notified);
+        return;
+    }
+
+    let progress = indicatif::ProgressBar::new(total.len() as u64);
+    progress.set_style(
+        ProgressStyle::with_template("{spinner}{bar:100.cyan/blue} {percent:>3}% | [{eta_precise}][{elapsed_precise}] ETA/Elapsed | {pos:>7}/{len:7} {msg}").unwrap()
+    );
+
+    let interrupted = pipeline::interrupt_on_ctrl_c();
+    let mut pipeline = pipeline::spawn(rr.clone(), total, pipeline_options, interrupted.clone());
+
+    while let Some(fetched) = pipeline.recv().await {
+        let fetch_failed = fetched.fetch_failed();
+        let pipeline::Fetched { hash, tx, output, status, input, raw_logs, truncated_blobs, error, verification } = fetched;
+        let output = output.map(RRReceipt::with_formatted_amounts);
+
+        // Gas fields of the receipt are null if the receipt could not be fetched.
+        let gas_used = output.as_ref().and_then(|receipt| receipt.gas_used);
+        let effective_gas_price = output.as_ref().and_then(|receipt| receipt.effective_gas_price);
+        let fee = fee(&tx, output.as_ref());
+        let fee_ron = fee.map(|fee| filters::format_units(fee, filters::RON_DECIMALS));
+        let fee_payer = fee_payer(&tx, output.as_ref());
+
+        match error {
+            Some(error) => {
+                let message = format!("Could not retrieve all details of transaction {}: {}", &hash, error);
+                println!("{}", message);
+                logging::error(&message);
+            }
+            None if fetch_failed => {
+                let message = format!("Failed to retrieve transaction details: {}", &hash);
+                println!("{}", message);
+                logging::error(&message);
+            }
+            None => {}
+        }
+
+        match &verification {
+            Some(integrity::Verification::Mismatch(computed)) => {
+                let message = format!("The fields of transaction {} returned by the API hash to {}", hash, computed);
+                println!("{}", message);
+                logging::error(&message);
+            }
+            Some(integrity::Verification::Unverifiable(reason)) => logging::info(&format!("Could not verify the hash of {}: {}", hash, reason)),
+            _ => {}
+        }
+
+        for wallet in wallets.iter_mut() {
+            let direction = match wallet.directions.get(&hash) {
+                Some(direction) => *direction,
+                None => continue
+            };
+
+            if fetch_failed {
+                wallet.failures.failed_transactions.push(hash.clone());
+            }
+
+            match &verification {
+                Some(integrity::Verification::Verified) => wallet.counts.hashes_verified += 1,
+                Some(integrity::Verification::Mismatch(computed)) => {
+                    wallet.failures.hash_mismatches.push(format!("{}: the fields returned by the API hash to {}", hash, computed));
+                }
+                Some(integrity::Verification::Unverifiable(_)) => wallet.counts.hashes_unverifiable += 1,
+                None => {}
+            }
+
+            // Nonces are collected before any filtering: self transfers, reverted and filtered
+            // transactions are not exported but still consume a nonce.
+            if direction != Direction::Received && tx.from.eq_ignore_ascii_case(&wallet.address) {
+                wallet.sent_nonces.extend(tx.nonce);
+            }
+
+            // Self transfers are not exported.
+            if tx.to != tx.from {
+                let sent = tx.from.eq_ignore_ascii_case(&wallet.address);
+                if status == Some(TxStatus::Failed) && wallet.counts.reverted(exclude_failed, sent, fee_payer, fee, gas_used) {
+                    continue;
+                }
+
+                let record = RRDecodedTransaction {
+                    from: tx.from.clone(),
+                    input: input.clone(),
+                    output: output.clone(),
+                    truncated_blobs: (truncated_blobs > 0).then_some(truncated_blobs),
+                    raw_logs: raw_logs.clone(),
+                    hash: hash.clone(),
+                    to: tx.to.clone(),
+                    block_number: tx.block_number,
+                    nonce: tx.nonce,
+                    transaction_index: tx.transaction_index,
+                    direction,
+                    value: tx.value,
+                    value_ron: tx.value.map(|value| filters::format_units(value, filters::RON_DECIMALS)),
+                    status,
+                    gas_used,
+                    gas_price: tx.gas_price,
+                    effective_gas_price,
+                    fee_ron: fee_ron.clone(),
+                    fee_payer,
+                    confirmations: latest_block.map(|latest| match tx.block_number {
+                        0 => None,
+                        block => Some(latest.saturating_sub(block))
+                    }),
+                    balance_after: None,
+                    wallet: None,
+                    wallets: vec![],
+                    directions: BTreeMap::new(),
+                };
+
+                match record_filters.check(&record, tx.value, &wallet.address) {
+                    Err(skip) => wallet.counts.skip(skip),
+                    Ok(contract_match) => {
+                        match contract_match {
+                            Some(filters::ContractMatch::Direct) => wallet.counts.contract_direct += 1,
+                            Some(filters::ContractMatch::Logs) => wallet.counts.contract_logs += 1,
+                            None => {}
+                        }
+
+                        if record_filters.below_min_value(&record, tx.value, &wallet.address) {
+                            wallet.counts.below_min_value += 1;
+                            continue;
+                        }
+
+                        if record.from.eq_ignore_ascii_case(&wallet.address) {
+                            wallet.counts.paid(fee_payer, fee, gas_used);
+                        }
+
+                        wallet.coverage.add(&record);
+                        if let Some(postgres) = postgres.as_mut() {
+                            postgres.push(&wallet.address, &record).await;
+                        }
+                        wallet.records.push(record);
+                    }
+                }
+            }
+        }
+
+        progress.inc(1);
+        progress.set_message(hash);
+    }
+
+    // Also written when the run is interrupted, the records are complete either way.
+    let postgres_result = match postgres {
+        Some(postgres) => Some(postgres.finish().await),
+        None => None
+    };
+
+    if breaker::aborted() {
+        progress.abandon_with_message("API outage!");
+        println!("The export was given up during an API outage, existing output files were left untouched. Run it again once the API is back.");
+        logging::error("The export was given up during an API outage, existing output files were left untouched.");
+        notify::failed("The export was given up during an API outage");
+        drop(wallets);
+        drop(locks);
+        std::process::exit(1);
+    }
+
+    if interrupted.load(Ordering::SeqCst) {
+        progress.abandon_with_message("Interrupted!");
+        println!("The export was interrupted, existing output files were left untouched.");
+        logging::warn("The export was interrupted, existing output files were left untouched.");
+        notify::interrupted();
+        drop(wallets);
+        drop(locks);
+        std::process::exit(130);
+    }
+
+    progress.set_message("Saving...");
+
+    if let output::Format::Csv(columns) = &mut writer.format {
+        if columns.flattens_input() {
+            for wallet in wallets.iter_mut() {
+                for record in wallet.records.sorted() {
+                    columns.add_params(record.input.as_ref());
+                }
+            }
+        }
+    }
+
+    if balance::enabled() {
+        for wallet in wallets.iter_mut() {
+            let (records, reconstruction) = balance::reconstruct(wallet.records.sorted(), &wallet.address);
+            wallet.records = records;
+            wallet.balance = Some(reconstruction);
+        }
+    }
+
+    if writer.format.is_accounting() {
+        for wallet in wallets.iter_mut() {
+            accounting::look_up_dates(rr.as_ref(), wallet.records.sorted().map(|record| record.block_number)).await;
+        }
+    }
+
+    for wallet in wallets.iter_mut() {
+        wallet.lifetime = Some(lifetime::compute(wallet.records.sorted()));
+    }
+
+    if approvals::report() {
+        for wallet in wallets.iter_mut() {
+            let mut report = approvals::scan(wallet.records.sorted(), Some(&wallet.address));
+
+            block_times::fetch(rr.as_ref(), report.approvals.iter().map(|approval| approval.block_number)).await;
+            for approval in report.approvals.iter_mut() {
+                approval.time = block_times::get(approval.block_number).map(|(_, time)| time);
+            }
+
+            approvals::verify_from_args(&mut report).await;
+            wallet.approvals = Some(report);
+        }
+    }
+
+    if largest::report() {
+        let limit = largest::limit();
+        for wallet in wallets.iter_mut() {
+            let mut report = largest::scan(wallet.records.sorted(), Some(&wallet.address), limit);
+
+            let numbers: Vec<u64> = report.entries().map(|entry| entry.block_number).collect();
+            block_times::fetch(rr.as_ref(), numbers).await;
+            for entry in report.entries() {
+                entry.time = block_times::get(entry.block_number).map(|(_, time)| time);
+            }
+
+            wallet.largest = Some(report);
+        }
+    }
+
+    let mut anonymizer = match ArgParser::flag("--anonymize") {
+        true => Some(anonymize::Anonymizer::new(&wallets.iter().map(|wallet| wallet.address.clone()).collect::<Vec<String>>())),
+        false => None
+    };
+
+    let mut output_file_names: Vec<String> = vec![];
+    let mut output_paths: Vec<Vec<String>> = vec![];
+    let mut failure_file_names: Vec<Option<String>> = vec![];
+    let mut contract_index_names: Vec<Option<String>> = vec![];
+
+    for wallet in wallets.iter_mut() {
+        if let Some(anonymizer) = anonymizer.as_mut() {
+            wallet.file_stem = anonymizer.pseudonym(&wallet.address);
+        }
+        let record_wallet = wallet.record_wallet().to_string();
+        let written = output::written().len();
+        let mut appended = None;
+
+        let paths = match &group_by {
+            None if matches!(writer.format, output::Format::Arrow) => {
+                let mut export = writer.arrow_writer(Some(&wallet.file_stem), &wallet.file_stem);
+
+                for record in wallet.records.sorted() {
+                    export.push(&record_value(&record, anonymizer.as_mut()), Some((&record_wallet, record.direction)));
+                }
+
+                vec![export.finish()]
+            }
+            None if wallet.existing.is_some() => {
+                let existing = wallet.existing.take().unwrap();
+                let mut export = writer.append_writer(&existing.path, existing.records);
+
+                for record in wallet.records.sorted() {
+                    export.push(&serialize_record(&record, None));
+                }
+
+                let (path, records) = export.finish();
+                appended = Some(records);
+                vec![path]
+            }
+            None => {
+                let mut export = writer.export_writer(Some(&wallet.file_stem), &shard::stem(&wallet.file_stem));
+
+                for record in wallet.records.sorted() {
+                    export.push(&export_record(&record, anonymizer.as_mut(), &writer.format, Some(&record_wallet)), record.block_number);
+                }
+
+                vec![export.finish()]
+            }
+            Some(group_by) => {
+                let mut groups = grouping::group(wallet.records.sorted(), group_by, &wallet.address, anonymizer.as_mut());
+                wallet.groups = groups.iter().map(|(key, group)| (key.clone(), group.records)).collect();
+
+                match writer.out_dir {
+                    // One file per group in the wallet's directory.
+                    Some(_) => {
+                        let mut paths = vec![];
+
+                        for (key, group) in groups.iter_mut() {
+                            let stem = format!("{}.{}", wallet.file_stem, grouping::file_name(key));
+                            let mut export = writer.export_writer(Some(&wallet.file_stem), &stem);
+
+                            for record in group.sorter.sorted() {
+                                export.push(&export_record(&record, anonymizer.as_mut(), &writer.format, Some(&record_wallet)), record.block_number);
+                            }
+
+                            paths.push(export.finish());
+                        }
+
+                        paths
+                    }
+                    None => {
+                        let mut export = writer.grouped_writer(Some(&wallet.file_stem), &wallet.file_stem);
+
+                        for (key, group) in groups.iter_mut() {
+                            export.group(key);
+
+                            for record in group.sorter.sorted() {
+                                export.push(&serialize_record(&record, anonymizer.as_mut()));
+                            }
+                        }
+
+                        vec![export.finish()]
+                    }
+                }
+            }
+        };
+
+        output_file_names.push(match (paths.as_slice(), appended) {
+            ([], _) => "no files, there were no transactions to export".to_string(),
+            ([path], Some(appended)) => format!("{} ({} transactions appended)", path, appended),
+            (paths, _) => paths.join(", ")
+        });
+        output_paths.push(paths);
+        wallet.counts.unvalued_transfers = ctc::take_unvalued();
+
+        contract_index_names.push(per_contract.as_ref().map(|per_contract| {
+            let groups = grouping::group(wallet.records.sorted(), &grouping::GroupBy::Contract, &wallet.address, anonymizer.as_mut());
+            per_contract.write(&writer, &wallet.file_stem, groups, anonymizer.as_mut())
+        }));
+
+        // A shard only sees some of the nonces, the gaps are the other shards.
+        if shard::current().is_none() {
+            wallet.failures.nonce_issues = nonce_issues(&wallet.sent_nonces);
+        }
+
+        failure_file_names.push(match wallet.failures.is_empty() {
+            true => None,
+            false => Some(writer.write(Some(&wallet.file_stem), wallet.failure_file_name(), serde_json::to_string(&wallet.failures).unwrap()))
+        });
+
+        let mut manifest = manifest::Manifest {
+            format_version: export::FORMAT_VERSION,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            network: network::current(),
+            // An anonymized manifest must not reveal the address.
+            address: Some(record_wallet),
+            alias: wallet.alias.clone(),
+            hosts: rr.host_requests().into_iter().filter(|(_, requests)| *requests > 0).map(|(host, _)| host.to_string()).collect(),
+            filters: filters.clone(),
+            flags: manifest::flags(),
+            coverage: wallet.coverage.clone(),
+            latest_block,
+            failed_transactions: wallet.failures.failed_transactions.len(),
+            started_at: started_at.clone(),
+            finished_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            files: vec![],
+            sources: vec![],
+        };
+        manifest.complete(&output::written()[written..], wallet.previous_manifest.as_ref());
+        writer.write(Some(&wallet.file_stem), manifest::Manifest::file_name(&shard::stem(&wallet.file_stem)), serde_json::to_string_pretty(&manifest).unwrap());
+    }
+
+    // The transactions of the merged export per wallet and how many of them involve several wallets.
+    let mut attribution: HashMap<String, (usize, usize)> = HashMap::new();
+    let mut attribute = |record: &RRDecodedTransaction| {
+        if let Some(wallet) = &record.wallet {
+            let (transactions, shared) = attribution.entry(wallet.clone()).or_default();
+            *transactions += 1;
+            if record.wallets.len() > 1 {
+                *shared += 1;
+            }
+        }
+    };
+
+    let merge_file_name = match merge_file_name {
+        Some(merge_file_name) => {
+            let stem = merge_file_name.strip_suffix(&format!(".{}", writer.format.extension())).unwrap_or(&merge_file_name);
+
+            match writer.format {
+                output::Format::Arrow => {
+                    let mut export = writer.arrow_writer(None, stem);
+
+                    for record in merge_records(&mut wallets) {
+                        attribute(&record);
+                        export.push(&record_value(&record, anonymizer.as_mut()), None);
+                    }
+
+                    Some(export.finish())
+                }
+                _ => {
+                    let mut export = writer.export_writer(None, stem);
+
+                    for record in merge_records(&mut wallets) {
+                        attribute(&record);
+                        export.push(&export_record(&record, anonymizer.as_mut(), &writer.format, None), record.block_number);
+                    }
+
+                    Some(export.finish())
+                }
+            }
+        }
+        None => None
+    };
+
+    // A transaction of several wallets has its logs written once.
+    let events_file_name = events::file_name().map(|file_name| {
+        let mut export = writer.json_writer(None, &file_name);
+        let mut undecoded = 0;
+
+        for record in merge_records(&mut wallets) {
+            for event in events::of(&record) {
+                undecoded += usize::from(event.event.is_none());
+                let event = serde_json::to_value(&event).unwrap();
+                let event = match anonymizer.as_mut() {
+                    Some(anonymizer) => anonymizer.anonymize(event),
+                    None => event
+                };
+                export.push(&serde_json::to_string(&event).unwrap());
+            }
+        }
+
+        let (path, events) = export.finish();
+        (path, events, undecoded)
+    });
+
+    let mapping_file_name = anonymizer.as_ref().map(|anonymizer| {
+        writer.write(None, "anonymize-mapping.json".to_string(), serde_json::to_string_pretty(&anonymizer.mapping()).unwrap())
+    });
+    // The files of aliased wallets are named by the alias, the mapping keeps them interpretable.
+    let alias_file_name = (!alias_mapping.is_empty()).then(|| {
+        writer.write(None, "aliases.json".to_string(), serde_json::to_string_pretty(&alias_mapping).unwrap())
+    });
+
+    progress.set_message("FINISH!");
+
+    progress.finish();
+
+    let mut notified = vec![];
+
+    let mut summaries = vec![];
+
+    for ((((wallet, output_file_name), paths), failure_file_name), contract_index_name) in wallets.iter().zip(output_file_names).zip(output_paths).zip(failure_file_names).zip(contract_index_names) {
+        if wallets.len() > 1 {
+            summary!("\nAddress: {}", wallet.label());
+        }
+
+        summary!("The output was saved to {}", output_file_name);
+        notified.push(notify::NotifiedWallet { address: wallet.address.clone(), alias: wallet.alias.clone(), transactions: wallet.records.len(), files: paths });
+
+        if let Some(lifetime) = &wallet.lifetime {
+            for line in lifetime::lines(lifetime) {
+                summary!("{}", line);
+            }
+        }
+        summaries.push(WalletSummary {
+            address: wallet.record_wallet().to_string(),
+            alias: wallet.alias.clone(),
+            output: output_file_name.clone(),
+            fees_paid: filters::format_units(wallet.counts.fees_paid, filters::RON_DECIMALS),
+            lifetime: wallet.lifetime.as_ref(),
+        });
+
+        if let Some(reconstruction) = &wallet.balance {
+            if let Some(negative) = &reconstruction.first_negative {
+                summary!(
+                    "Warning: the reconstructed RON balance is negative ({}) after transaction {} in block {}, transactions adding RON, e.g. internal transactions, are probably missing",
+                    balance::format_ron(negative.balance), negative.hash, negative.block
+                );
+            }
+
+            if balance::report() {
+                summary!("RON balance reconstructed from {} transactions:", reconstruction.transactions);
+                summary!("  start: 0");
+                if let Some(last) = &reconstruction.last {
+                    summary!("  now (after block {}): {}", last.block, balance::report_amount(last.balance));
+                }
+
+                match rr.balance(&wallet.address).await {
+                    Ok(live) => {
+                        let live = live.low_u128() as balance::Wei;
+                        summary!("  live: {} (difference {})", balance::report_amount(live), balance::report_amount(live - reconstruction.balance()));
+                    }
+                    Err(error) => summary!("  live: unknown ({})", error)
+                }
+            }
+        }
+
+        if let Some(report) = &wallet.approvals {
+            for line in approvals::lines(report) {
+                summary!("{}", line);
+            }
+            let file_name = writer.write(Some(&wallet.file_stem), format!("{}.approvals.json", wallet.file_stem), serde_json::to_string_pretty(report).unwrap());
+            summary!("The approvals were saved to {}", file_name);
+        }
+
+        if let Some(report) = &wallet.largest {
+            for line in largest::lines(report) {
+                summary!("{}", line);
+            }
+            let file_name = writer.write(Some(&wallet.file_stem), format!("{}.largest.json", wallet.file_stem), serde_json::to_string_pretty(report).unwrap());
+            summary!("The largest transactions were saved to {}", file_name);
+        }
+
+        if let Some(contract_index_name) = contract_index_name {
+            summary!("The per-contract files are listed in {}", contract_index_name);
+        }
+
+        if let Some(group_by) = &group_by {
+            summary!("Transactions per {}:", group_by.name());
+            for (key, records) in &wallet.groups {
+                summary!("  {}: {}", key, records);
+            }
+        }
+
+        if exclude_failed {
+            summary!("Reverted transactions: {} (excluded from the export)", wallet.counts.reverted);
+        } else {
+            summary!("Reverted transactions: {}", wallet.counts.reverted);
+        }
+
+        summary!("Fees paid: {} RON", filters::format_units(wallet.counts.fees_paid, filters::RON_DECIMALS));
+        if wallet.counts.sponsored > 0 {
+            summary!("Sponsored transactions: {} ({} gas paid by a sponsor, not included in the fees paid)", wallet.counts.sponsored, wallet.counts.sponsored_gas);
+        }
+
+        if wallet.counts.unvalued_transfers > 0 {
+            summary!("{}", ctc::unvalued_warning(wallet.counts.unvalued_transfers));
+        }
+
+        if integrity::enabled() {
+            summary!(
+                "Verified transaction hashes: {} ({} mismatches, {} without the fields to recompute them)",
+                wallet.counts.hashes_verified, wallet.failures.hash_mismatches.len(), wallet.counts.hashes_unverifiable
+            );
+        }
+
+        if record_filters.spam.is_some() {
+            summary!(
+                "Skipped spam transactions: {} ({} without value, transfers or known method, {} involving listed spam contracts)",
+                wallet.counts.skipped_noise + wallet.counts.skipped_listed, wallet.counts.skipped_noise, wallet.counts.skipped_listed
+            );
+        }
+
+        if !record_filters.counterparty.is_empty() {
+            summary!("Skipped transactions by counterparty filter: {}", wallet.counts.skipped_counterparty);
+        }
+
+        if record_filters.event.is_some() {
+            summary!(
+                "Skipped transactions by event filter: {} ({} without a receipt)",
+                wallet.counts.skipped_event + wallet.counts.skipped_without_receipt, wallet.counts.skipped_without_receipt
+            );
+        }
+
+        if record_filters.contract.is_some() {
+            summary!(
+                "Skipped transactions by contract filter: {} (matches: {} direct calls, {} through logs)",
+                wallet.counts.skipped_contract, wallet.counts.contract_direct, wallet.counts.contract_logs
+            );
+        }
+
+        if !record_filters.expressions.is_empty() {
+            summary!("Skipped transactions by --filter: {}", wallet.counts.skipped_expression);
+        }
+
+        if record_filters.min_value.is_some() {
+            summary!("Skipped transactions below --min-value: {}", wallet.counts.below_min_value);
+        }
+
+        if !wallet.failures.list_issues.is_empty() {
+            summary!("The transaction lists may be incomplete:");
+            for issue in &wallet.failures.list_issues {
+                summary!("  {}", issue);
+            }
+        }
+
+        if !wallet.failures.rejected_hashes.is_empty() {
+            summary!("Malformed or repeated entries dropped from the transaction lists: {} (listed in the failure report)", wallet.failures.rejected_hashes.len());
+        }
+
+        if !wallet.failures.nonce_issues.is_empty() {
+            summary!("Nonce issues in sent transactions:");
+            for issue in &wallet.failures.nonce_issues {
+                summary!("  {}", issue);
+            }
+        }
+
+        if let Some(failure_file_name) = failure_file_name {
+            summary!("The failure report was saved to {}", failure_file_name);
+        }
+    }
+
+    let summary_file_name = summary_file_from_args().map(|summary_file_name| {
+        let summary_file_name = writer.write(None, summary_file_name, serde_json::to_string_pretty(&serde_json::json!({ "wallets": summaries, "traffic": traffic::summary() })).unwrap());
+        summary!("\nThe summary was saved to {}", summary_file_name);
+        summary_file_name
+    });
+
+    if let Some(merge_file_name) = &merge_file_name {
+        summary!("\nThe merged output was saved to {}", merge_file_name);
+
+        for wallet in &wallets {
+            let (transactions, shared) = attribution.get(wallet.alias.as_ref().unwrap_or(&wallet.address)).copied().unwrap_or_default();
+            summary!("  {}: {} transactions ({} with another exported wallet)", wallet.label(), transactions, shared);
+        }
+    }
+
+    if let Some((events_file_name, events, undecoded)) = &events_file_name {
+        summary!("\nThe {} event logs were saved to {} ({} not decoded)", events, events_file_name, undecoded);
+    }
+
+    if let Some(mapping_file_name) = mapping_file_name {
+        summary!("\nThe pseudonym mapping was saved to {}. Keep it private, it reveals all anonymized addresses!", mapping_file_name);
+    }
+
+    if let Some(alias_file_name) = alias_file_name {
+        summary!("\nThe wallet aliases were saved to {}", alias_file_name);
+    }
+
+    // Removes the runs spilled to disk, the exits below would leave them behind.
+    drop(wallets);
+
+    let host_requests = rr.host_requests();
+
+    if host_requests.len() > 1 {
+        summary!("\nActive API host: {}", rr.active_host());
+        for (host, requests) in host_requests {
+            summary!("  {}: {} requests", host, requests);
+        }
+    }
+
+    let total = traffic::total();
+
+    summary!("\nAPI requests: {} ({} retries), {} downloaded", total.requests, total.retries(), traffic::format_bytes(total.bytes));
+    for (endpoint, traffic) in traffic::report() {
+        summary!("  {}: {} requests, {} retries, {}", endpoint, traffic.requests, traffic.retries(), traffic::format_bytes(traffic.bytes));
+    }
+    let (conditional, not_modified) = cache::list_requests();
+    if conditional > 0 {
+        summary!("Transaction lists unchanged since the last run: {} of {} (304 Not Modified)", not_modified, conditional);
+    }
+    if let Some(limit) = ArgParser::split(&"--max-memory".to_string()) {
+        match sort::spilled() {
+            (0, _) => summary!("Records spilled to disk (--max-memory={}): none", limit),
+            (runs, bytes) => summary!("Records spilled to disk (--max-memory={}): {} sorted runs, {}", limit, runs, traffic::format_bytes(bytes as u64))
+        }
+    }
+
+
+    match &postgres_result {
+        Some(Ok(records)) => summary!("Records written to PostgreSQL: {}", records),
+        Some(Err(error)) => {
+            println!("{}", error);
+            logging::error(error);
+        }
+        None => {}
+    }
+
+    if let Some(uploader) = &uploader {
+        for path in output::written().into_iter().map(|file| file.path) {
+            match uploader.upload(&path).await {
+                Ok(url) => summary!("Uploaded {} to {}", path, url),
+                Err(error) => {
+                    let message = format!("Could not upload {}, the local file was kept: {}", path, error);
+                    summary!("{}", message);
+                    logging::error(&message);
+                    upload_failed = true;
+                }
+            }
+        }
+
+        if upload_failed {
+            notify::failed("The upload of the export failed");
+            drop(locks);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(Err(error)) = &postgres_result {
+        notify::failed(error);
+        drop(locks);
+        std::process::exit(1);
+    }
+
+    // The hook runs once everything was written and the summary is complete.
+    if let Some(command) = &exec_after {
+        for wallet in &notified {
+            if let Err(code) = exec::run(command, &wallet.files, &wallet.address, summary_file_name.as_deref()) {
+                if !exec::ignore_failure() {
+                    notify::failed(&format!("The --exec-after command failed for {}", wallet.address));
+                    drop(locks);
+                    std::process::exit(code);
+                }
+            }
+        }
+    }
+
+    notify::completed(&notified);
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use crate::provider::WalletDataProvider;
+    use crate::testing::{hash, record, transaction, FakeProvider, OTHER, WALLET};
+    use super::*;
+
+    /// The transaction and the decoded receipt of a fixture.
+    fn fixture(fixture: &str) -> (RRTransaction, RRReceipt) {
+        let fixture: Value = serde_json::from_str(fixture).unwrap();
+        (serde_json::from_value(fixture["transaction"].clone()).unwrap(), RRReceipt::from_value(fixture["receipt"].clone()))
+    }
+
+    #[test]
+    fn reads_values_of_exactly_the_named_flag() {
+        let args: Vec<String> = ["wally", "--notify-url=https://example.com/hook?token=a=b", "--out=exports/q1", "--out-dir=x"]
+            .iter().map(|arg| arg.to_string()).collect();
+
+        assert_eq!(ArgParser::value(&args, "--notify-url"), Some("https://example.com/hook?token=a=b".to_string()));
+        assert_eq!(ArgParser::value(&args, "--notify"), None);
+        assert_eq!(ArgParser::value(&args, "--out"), Some("exports/q1".to_string()));
+        assert_eq!(ArgParser::value(&args, "--out-dir"), Some("x".to_string()));
+        assert_eq!(ArgParser::value(&args, "--format"), None);
+    }
+
+    #[test]
+    fn sponsored_transaction() {
+        let (tx, receipt) = fixture(include_str!("../tests/fixtures/sponsored.json"));
+
+        assert!(fee_payer(&tx, Some(&receipt)) == Some(FeePayer::Sponsored));
+        // The gas is still reported, the sponsor paid it.
+        assert_eq!(fee(&tx, Some(&receipt)), Some(U256::from(21_000u64 * 20_000_000_000)));
+    }
+
+    #[test]
+    fn self_paid_transaction() {
+        let (tx, receipt) = fixture(include_str!("../tests/fixtures/self_paid.json"));
+
+        assert!(fee_payer(&tx, Some(&receipt)) == Some(FeePayer::Sender));
+        assert_eq!(fee(&tx, Some(&receipt)).map(|fee| filters::format_units(fee, filters::RON_DECIMALS)).as_deref(), Some("0.00042"));
+    }
+
+    #[test]
+    fn excluded_reverted_transactions_count_towards_the_fees() {
+        let mut fixture: Value = serde_json::from_str(include_str!("../tests/fixtures/self_paid.json")).unwrap();
+        fixture["receipt"]["status"] = json!(false);
+        let tx: RRTransaction = serde_json::from_value(fixture["transaction"].clone()).unwrap();
+        let receipt = RRReceipt::from_value(fixture["receipt"].clone());
+        assert!(receipt.status == Some(TxStatus::Failed));
+
+        let mut counts = ExportCounts::default();
+        let excluded = counts.reverted(true, true, fee_payer(&tx, Some(&receipt)), fee(&tx, Some(&receipt)), receipt.gas_used);
+
+        assert!(excluded);
+        assert_eq!(counts.reverted, 1);
+        assert_eq!(counts.fees_paid, U256::from(420_000_000_000_000u64));
+
+        // Kept in the export, the fee is counted along with the record.
+        let mut counts = ExportCounts::default();
+        assert!(!counts.reverted(false, true, Some(FeePayer::Sender), fee(&tx, Some(&receipt)), receipt.gas_used));
+        assert_eq!(counts.fees_paid, U256::zero());
+
+        // Received transactions were paid by someone else.
+        let mut counts = ExportCounts::default();
+        counts.reverted(true, false, Some(FeePayer::Sender), fee(&tx, Some(&receipt)), receipt.gas_used);
+        assert_eq!(counts.fees_paid, U256::zero());
+    }
+
+    #[test]
+    fn excluded_sponsored_transactions_count_their_gas() {
+        let mut counts = ExportCounts::default();
+        counts.reverted(true, true, Some(FeePayer::Sponsored), None, Some(21_000));
+
+        assert_eq!((counts.sponsored, counts.sponsored_gas), (1, 21_000));
+    }
+
+    #[test]
+    fn sponsorship_markers() {
+        let (mut tx, mut receipt) = fixture(include_str!("../tests/fixtures/self_paid.json"));
+
+        // The sender named as the payer pays itself.
+        tx.payer = Some(tx.from.to_uppercase());
+        assert!(fee_payer(&tx, Some(&receipt)) == Some(FeePayer::Sender));
+
+        // A payer in the receipt.
+        tx.payer = None;
+        receipt.edit(|raw| raw["payer"] = json!("0x3333333333333333333333333333333333333333"));
+        assert!(fee_payer(&tx, Some(&receipt)) == Some(FeePayer::Sponsored));
+
+        // A zero gas price.
+        receipt.edit(|raw| {
+            raw.as_object_mut().unwrap().remove("payer");
+            raw["effectiveGasPrice"] = json!("0x0");
+        });
+        assert!(fee_payer(&tx, Some(&receipt)) == Some(FeePayer::Sponsored));
+        assert_eq!(fee(&tx, Some(&receipt)), Some(U256::zero()));
+
+        // Nothing is known without a receipt and a gas price.
+        tx.gas_price = None;
+        assert!(fee_payer(&tx, None).is_none());
+        assert_eq!(fee(&tx, None), None);
+    }
+
+    /// A fake whose sent list of `WALLET` is `pages`, requested in pages of `page_size`.
+    fn paged(page_size: usize, pages: Vec<Vec<u64>>) -> FakeProvider {
+        let pages = pages.into_iter().map(|page| page.into_iter().map(hash).collect()).collect();
+        FakeProvider { sent: HashMap::from([(WALLET.to_string(), pages)]), page_size: Some(page_size), ..FakeProvider::default() }
+    }
+
+    #[tokio::test]
+    async fn pages_are_collected_until_a_short_page() {
+        let provider = paged(2, vec![vec![1, 2], vec![3, 4], vec![5]]);
+        let list = provider.list_sent(WALLET).await;
+
+        assert_eq!(list.transactions, (1..=5).map(hash).collect::<Vec<_>>());
+        assert!(list.issues.is_empty());
+        assert_eq!(provider.host_requests(), vec![("memory", 3)]);
+    }
+
+    #[tokio::test]
+    async fn an_empty_last_page_ends_the_list() {
+        let provider = paged(2, vec![vec![1, 2], vec![3, 4]]);
+        let list = provider.list_sent(WALLET).await;
+
+        assert_eq!(list.transactions.len(), 4);
+        assert_eq!(provider.host_requests(), vec![("memory", 3)]);
+    }
+
+    #[tokio::test]
+    async fn overlapping_pages_are_deduplicated() {
+        // A transaction arriving while paging shifts the list by one.
+        let provider = paged(2, vec![vec![1, 2], vec![2, 3], vec![4]]);
+        let list = provider.list_sent(WALLET).await;
+
+        assert_eq!(list.transactions, (1..=4).map(hash).collect::<Vec<_>>());
+        assert!(list.issues.is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_page_of_repeats_is_reported() {
+        let provider = paged(2, vec![vec![1, 2], vec![1, 2], vec![3]]);
+        let list = provider.list_sent(WALLET).await;
+
+        assert_eq!(list.transactions, vec![hash(1), hash(2)]);
+        assert_eq!(list.issues, vec!["listSentTransactions: page 2 at offset 2 only repeated earlier transactions, the list may have changed while paging"]);
+    }
+
+    #[tokio::test]
+    async fn an_api_ignoring_the_page_size_returns_the_whole_list() {
+        let provider = paged(2, vec![vec![1, 2, 3, 4, 5]]);
+        let list = provider.list_sent(WALLET).await;
+
+        assert_eq!(list.transactions.len(), 5);
+        assert!(list.issues.is_empty());
+        assert_eq!(provider.host_requests(), vec![("memory", 1)]);
+    }
+
+    #[tokio::test]
+    async fn sent_and_received_lists_are_merged() {
+        let provider = FakeProvider {
+            sent: HashMap::from([(WALLET.to_string(), vec![vec![hash(1), hash(2)]])]),
+            received: HashMap::from([(WALLET.to_string(), vec![vec![hash(2), hash(3)]])]),
+            ..FakeProvider::default()
+        };
+        let sent = provider.list_sent(WALLET).await;
+        let received = provider.list_received(WALLET).await;
+        let wallet = WalletExport::new(WALLET.to_string(), None, sent, received);
+
+        assert_eq!(wallet.hashes, vec![hash(1), hash(2), hash(3)]);
+        assert!(wallet.directions[&hash(1)] == Direction::Sent);
+        assert!(wallet.directions[&hash(2)] == Direction::Both);
+        assert!(wallet.directions[&hash(3)] == Direction::Received);
+    }
+
+    #[test]
+    fn wallets_are_merged_into_one_record_per_transaction() {
+        let mut wallets = [
+            WalletExport::new(WALLET.to_string(), Some("treasury".to_string()), RRTransactionDict::default(), RRTransactionDict::default()),
+            WalletExport::new(OTHER.to_string(), None, RRTransactionDict::default(), RRTransactionDict::default()),
+        ];
+        wallets[0].records.push(record(1, 10, json!({})));
+        wallets[0].records.push(record(2, 20, json!({})));
+        wallets[1].records.push(record(2, 20, json!({ "direction": "received" })));
+        wallets[1].records.push(record(3, 30, json!({ "from": OTHER, "to": WALLET })));
+
+        let merged: Vec<RRDecodedTransaction> = merge_records(&mut wallets).collect();
+
+        assert_eq!(merged.iter().map(|record| record.hash.clone()).collect::<Vec<_>>(), vec![hash(1), hash(2), hash(3)]);
+        assert_eq!(merged[0].wallets, vec![WALLET.to_string()]);
+        assert_eq!(merged[0].wallet.as_deref(), Some("treasury"));
+        assert_eq!(merged[1].wallets, vec![WALLET.to_string(), OTHER.to_string()]);
+        assert!(merged[1].direction == Direction::Both);
+        assert!(merged[1].directions[OTHER] == Direction::Received);
+        // Attributed to the sender.
+        assert_eq!(merged[1].wallet.as_deref(), Some("treasury"));
+        assert_eq!(merged[2].wallet.as_deref(), Some(OTHER));
+    }
+
+    #[tokio::test]
+    async fn the_pipeline_fetches_through_the_provider() {
+        let provider = FakeProvider {
+            transactions: (1..=100).map(|n| (hash(n), transaction(n, WALLET, OTHER))).collect(),
+            ..FakeProvider::default()
+        };
+        let mut options = pipeline::Options { max_attempts: Some(2), decode_input: true, decode_output: true, raw_logs: false, max_blob_bytes: None, verify_hashes: false, decode_genes: false };
+        // The fake can't decode, the export goes ahead without the decoded fields.
+        provider::restrict(&mut options, &provider);
+        assert!(!options.decode_input && !options.decode_output);
+
+        // One more than the fake knows, it is given up after two attempts.
+        let hashes: Vec<RRTransactionHash> = (1..=101).map(hash).collect();
+        let mut pipeline = pipeline::spawn(std::sync::Arc::new(provider), hashes.clone(), options, Default::default());
+
+        let mut fetched = vec![];
+        while let Some(transaction) = pipeline.recv().await {
+            fetched.push(transaction);
+        }
+
+        assert_eq!(fetched.iter().map(|fetched| fetched.hash.clone()).collect::<Vec<_>>(), hashes);
+        assert!(fetched[..100].iter().all(|fetched| fetched.error.is_none() && fetched.output.is_none() && fetched.tx.block_number > 0));
+        assert!(fetched[100].fetch_failed());
+    }
 }
@@ -0,0 +1,202 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::network::Network;
+use crate::output::WrittenFile;
+use crate::{checksum, ArgParser, RRDecodedTransaction, TxStatus};
+
+/// Flags which decide what is exported, an export only stays consistent if all runs that wrote
+/// to it used the same ones.
+const FILTER_FLAGS: &[&str] = &[
+    "--network",
+    "--exclude-failed",
+    "--skip-spam",
+    "--spam-list",
+    "--min-value",
+    "--counterparty",
+    "--exclude-counterparty",
+    "--counterparty-file",
+    "--exclude-file",
+    "--contract",
+    "--filter",
+    "--event",
+    "--event-topic",
+    "--no-decode-input",
+    "--no-decode-output",
+    "--raw-logs",
+    "--max-blob-bytes",
+    "--confirmations",
+];
+
+/// The filter flags of this run and their values, sorted as their order doesn't matter. Flags
+/// without a value map to an empty list.
+pub fn filters() -> BTreeMap<String, Vec<String>> {
+    let mut filters: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for arg in ArgParser::parse().iter().skip(1) {
+        let (flag, value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag, Some(value)),
+            None => (arg.as_str(), None)
+        };
+
+        if FILTER_FLAGS.contains(&flag) {
+            let values = filters.entry(flag.to_string()).or_default();
+            values.extend(value.map(str::to_string));
+            values.sort();
+        }
+    }
+
+    filters
+}
+
+/// The names of all flags passed, without their values which may hold secrets such as the URL
+/// of `--notify-url`.
+pub fn flags() -> Vec<String> {
+    let mut flags: Vec<String> = ArgParser::parse().iter().skip(1)
+        .filter(|arg| arg.starts_with("--"))
+        .map(|arg| arg.split('=').next().unwrap().to_string())
+        .filter(|flag| flag != "--address")
+        .collect();
+    flags.sort();
+    flags.dedup();
+
+    flags
+}
+
+/// Exported records by the status of their transaction.
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordCounts {
+    pub total: usize,
+    pub successful: usize,
+    pub reverted: usize,
+    /// Records without a receipt to tell.
+    pub unknown: usize,
+}
+
+/// The exported records of a wallet and the blocks they span.
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Coverage {
+    pub first_block: Option<u64>,
+    pub last_block: Option<u64>,
+    pub records: RecordCounts,
+}
+
+impl Coverage {
+    pub fn add(&mut self, record: &RRDecodedTransaction) {
+        self.first_block = Some(self.first_block.map_or(record.block_number, |first| first.min(record.block_number)));
+        self.last_block = Some(self.last_block.map_or(record.block_number, |last| last.max(record.block_number)));
+
+        self.records.total += 1;
+        match record.status {
+            Some(TxStatus::Success) => self.records.successful += 1,
+            Some(TxStatus::Failed) => self.records.reverted += 1,
+            None => self.records.unknown += 1,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ManifestFile {
+    pub file: String,
+    pub sha256: String,
+}
+
+/// An export combined by `wally merge`.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestSource {
+    pub file: String,
+    pub sha256: String,
+    /// The wallet of the export, `null` for merged exports.
+    pub address: Option<String>,
+    pub records: usize,
+    /// The filters of the export according to its manifest, `null` without one.
+    pub filters: Option<BTreeMap<String, Vec<String>>>,
+    pub finished_at: Option<String>,
+}
+
+/// Where an export came from, written to `{wallet}.manifest.json` next to it.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub format_version: u32,
+    pub tool_version: String,
+    pub network: Network,
+    /// The exported wallet, `null` for exports of several wallets combined by `wally merge`.
+    pub address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// The API hosts which served requests.
+    pub hosts: Vec<String>,
+    pub filters: BTreeMap<String, Vec<String>>,
+    pub flags: Vec<String>,
+    #[serde(flatten)]
+    pub coverage: Coverage,
+    /// The latest block of the chain the confirmations of `--confirmations` are counted from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latest_block: Option<u64>,
+    /// Transactions which could not be fetched, see the failure report.
+    pub failed_transactions: usize,
+    pub started_at: String,
+    pub finished_at: String,
+    pub files: Vec<ManifestFile>,
+    /// The exports combined by `wally merge`, only set in its manifests.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<ManifestSource>,
+}
+
+impl Manifest {
+    pub fn file_name(stem: &str) -> String {
+        format!("{}.manifest.json", stem)
+    }
+
+    /// Reads the manifest at `path`, `None` if there is none.
+    pub fn load(path: &str) -> Result<Option<Manifest>, String> {
+        if !Path::new(path).exists() {
+            return Ok(None);
+        }
+
+        let contents = std::fs::read_to_string(path).map_err(|error| format!("Could not read {}: {}", path, error))?;
+        serde_json::from_str(&contents).map(Some).map_err(|error| format!("Could not parse {}: {}", path, error))
+    }
+
+    /// Fills in the files written for the wallet, their checksum sidecars are left out. With
+    /// `previous`, the manifest of the export appended to, its failures and files are kept.
+    pub fn complete(&mut self, written: &[WrittenFile], previous: Option<&Manifest>) {
+        self.files = written.iter()
+            .filter(|file| !file.path.ends_with(&checksum::sidecar_path("")))
+            .map(|file| ManifestFile {
+                file: Path::new(&file.path).file_name().unwrap().to_string_lossy().to_string(),
+                sha256: file.sha256.clone(),
+            })
+            .collect();
+
+        if let Some(previous) = previous {
+            self.failed_transactions += previous.failed_transactions;
+
+            for file in &previous.files {
+                if !self.files.iter().any(|written| written.file == file.file) {
+                    self.files.push(file.clone());
+                }
+            }
+        }
+    }
+}
+
+/// The differences between the filters of a previous run and this one, for messages.
+pub fn differences(previous: &BTreeMap<String, Vec<String>>, current: &BTreeMap<String, Vec<String>>) -> Vec<String> {
+    let describe = |values: Option<&Vec<String>>| match values {
+        None => "not passed".to_string(),
+        Some(values) if values.is_empty() => "passed".to_string(),
+        Some(values) => values.join(", ")
+    };
+
+    previous.keys().chain(current.keys())
+        .collect::<BTreeSet<&String>>()
+        .into_iter()
+        .filter(|flag| previous.get(*flag) != current.get(*flag))
+        .map(|flag| format!("{}: {} before, {} now", flag, describe(previous.get(flag)), describe(current.get(flag))))
+        .collect()
+}
@@ -0,0 +1,186 @@
+use serde_json::{json, Value};
+use web3::types::U256;
+use crate::filters;
+
+/// Decoded methods whose parameters are batches of inner calls.
+const WRAPPERS: &[&str] = &["multicall", "aggregate", "aggregate3", "tryaggregate", "blockandaggregate", "tryblockandaggregate"];
+
+/// Nested multicalls deeper than this are left undecoded.
+const MAX_DEPTH: usize = 4;
+
+#[derive(Clone, Copy)]
+enum Param {
+    Address,
+    Uint,
+    Bool,
+    BytesArray,
+}
+
+/// Parameter names and types of a method.
+type Signature = &'static [(&'static str, Param)];
+
+/// Selectors of the inner calls that can be decoded locally.
+const SELECTORS: &[(&str, &str, Signature)] = &[
+    ("a9059cbb", "transfer", &[("to", Param::Address), ("value", Param::Uint)]),
+    ("095ea7b3", "approve", &[("spender", Param::Address), ("value", Param::Uint)]),
+    ("23b872dd", "transferFrom", &[("from", Param::Address), ("to", Param::Address), ("value", Param::Uint)]),
+    ("42842e0e", "safeTransferFrom", &[("from", Param::Address), ("to", Param::Address), ("tokenId", Param::Uint)]),
+    ("a22cb465", "setApprovalForAll", &[("operator", Param::Address), ("approved", Param::Bool)]),
+    ("2e1a7d4d", "withdraw", &[("wad", Param::Uint)]),
+    ("d0e30db0", "deposit", &[]),
+    ("ac9650d8", "multicall", &[("data", Param::BytesArray)]),
+    ("5ae401dc", "multicall", &[("deadline", Param::Uint), ("data", Param::BytesArray)]),
+];
+
+pub fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    let digits = text.strip_prefix("0x")?;
+
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..digits.len()).step_by(2).map(|index| u8::from_str_radix(digits.get(index..index + 2)?, 16).ok()).collect()
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    format!("0x{}", bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+}
+
+/// ABI encoded call arguments, i.e. the calldata after the selector.
+struct Arguments<'a>(&'a [u8]);
+
+impl<'a> Arguments<'a> {
+    fn word(&self, offset: usize) -> Option<&'a [u8]> {
+        self.0.get(offset..offset.checked_add(32)?)
+    }
+
+    fn usize(&self, offset: usize) -> Option<usize> {
+        let word = U256::from_big_endian(self.word(offset)?);
+        match word <= U256::from(self.0.len()) {
+            true => Some(word.as_usize()),
+            false => None
+        }
+    }
+
+    fn bytes_array(&self, offset: usize) -> Option<Vec<&'a [u8]>> {
+        let start = self.usize(offset)?;
+        let count = self.usize(start)?;
+        let elements = start + 32;
+
+        (0..count).map(|index| {
+            let element = elements + self.usize(elements + index * 32)?;
+            let length = self.usize(element)?;
+            self.0.get(element + 32..element + 32 + length)
+        }).collect()
+    }
+}
+
+/// Decodes one inner call with the built-in selectors. Unknown or malformed calls are kept as their
+/// selector and raw calldata.
+fn decode_call(calldata: &[u8], target: Option<&str>, depth: usize, ancestors: &mut Vec<Vec<u8>>) -> Value {
+    let selector = calldata.get(..4).map(to_hex);
+    let mut call = json!({
+        "selector": selector,
+        "data": to_hex(calldata),
+    });
+
+    if let Some(target) = target {
+        call["target"] = json!(target);
+    }
+
+    let known = selector.as_deref().and_then(|selector| SELECTORS.iter().find(|(known, _, _)| selector[2..] == **known));
+
+    let (name, signature) = match known {
+        Some((_, name, signature)) => (*name, *signature),
+        None => return call
+    };
+
+    let arguments = Arguments(&calldata[4..]);
+    let mut params = vec![];
+    let mut calls = vec![];
+
+    for (index, (param, kind)) in signature.iter().enumerate() {
+        let offset = index * 32;
+        let value = match kind {
+            Param::Address => arguments.word(offset).map(|word| json!(to_hex(&word[12..]))),
+            Param::Uint => arguments.word(offset).map(|word| json!(U256::from_big_endian(word).to_string())),
+            Param::Bool => arguments.word(offset).map(|word| json!(word[31] != 0)),
+            Param::BytesArray => arguments.bytes_array(offset).map(|blobs| {
+                ancestors.push(calldata.to_vec());
+                calls.extend(blobs.iter().map(|blob| expand_call(blob, None, depth + 1, ancestors)));
+                ancestors.pop();
+
+                json!(blobs.iter().map(|blob| to_hex(blob)).collect::<Vec<String>>())
+            }),
+        };
+
+        match value {
+            Some(value) => params.push(json!({"name": param, "value": value})),
+            None => return call
+        }
+    }
+
+    call["name"] = json!(name);
+    call["params"] = json!(params);
+
+    if !calls.is_empty() {
+        call["calls"] = json!(calls);
+    }
+
+    call
+}
+
+fn expand_call(calldata: &[u8], target: Option<&str>, depth: usize, ancestors: &mut Vec<Vec<u8>>) -> Value {
+    match depth > MAX_DEPTH || ancestors.iter().any(|ancestor| ancestor == calldata) {
+        true => json!({"data": to_hex(calldata)}),
+        false => decode_call(calldata, target, depth, ancestors)
+    }
+}
+
+/// Decodes calldata with the built-in selectors, including the inner calls of multicalls. Calldata
+/// without a known selector is only its selector and data.
+pub fn decode(calldata: &[u8], target: Option<&str>) -> Value {
+    expand_call(calldata, target, 0, &mut vec![])
+}
+
+/// Finds the calldata and target of an inner call, which is either a bare `bytes` blob, a
+/// `(target, [allowFailure,] callData)` tuple or an object with `target` and `callData`.
+fn inner_call(value: &Value) -> Option<(Option<String>, Vec<u8>)> {
+    match value {
+        Value::String(blob) => Some((None, parse_hex(blob)?)),
+        Value::Array(fields) => {
+            let target = fields.iter().filter_map(Value::as_str).find(|field| field.len() == 42);
+            let blob = fields.iter().filter_map(Value::as_str).rfind(|field| Some(*field) != target)?;
+            Some((target.map(str::to_string), parse_hex(blob)?))
+        }
+        Value::Object(fields) => {
+            let blob = fields.get("callData").or_else(|| fields.get("data"))?.as_str()?;
+            Some((fields.get("target").and_then(Value::as_str).map(str::to_string), parse_hex(blob)?))
+        }
+        _ => None
+    }
+}
+
+/// Decodes the inner calls of a known multicall wrapper into a nested `calls` array of the decoded
+/// input, so filters looking at methods and addresses see the inner calls as well.
+pub fn expand(mut input: Value) -> Value {
+    let is_wrapper = filters::method_name(&input)
+        .is_some_and(|name| WRAPPERS.contains(&name.to_lowercase().as_str()));
+
+    if !is_wrapper {
+        return input;
+    }
+
+    let calls: Vec<Value> = filters::params(&input).iter()
+        .filter_map(|(_, value)| value.as_array())
+        .flatten()
+        .filter_map(inner_call)
+        .map(|(target, calldata)| expand_call(&calldata, target.as_deref(), 1, &mut vec![]))
+        .collect();
+
+    if !calls.is_empty() {
+        input["calls"] = json!(calls);
+    }
+
+    input
+}
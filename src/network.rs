@@ -0,0 +1,67 @@
+use std::sync::OnceLock;
+use serde::{Deserialize, Serialize};
+use crate::ArgParser;
+
+/// The Ronin network exported from, `--network`.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    Mainnet,
+    /// The Saigon testnet.
+    Saigon,
+}
+
+impl Network {
+    fn from_args() -> Network {
+        match ArgParser::split(&"--network".to_string()).as_deref().map(str::to_lowercase).as_deref() {
+            None | Some("mainnet") => Network::Mainnet,
+            Some("saigon") => Network::Saigon,
+            Some(network) => panic!("Invalid --network value {}, expected mainnet or saigon", network)
+        }
+    }
+
+    /// The public ronin.rest instance of the network, used without `--host`.
+    pub fn default_host(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "https://ronin.rest",
+            Network::Saigon => "https://saigon.ronin.rest",
+        }
+    }
+
+    /// The public JSON-RPC endpoint of the network, used without `--rpc-url`.
+    pub fn default_rpc_url(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "https://api.roninchain.com/rpc",
+            Network::Saigon => "https://saigon-testnet.roninchain.com/rpc",
+        }
+    }
+
+    /// The block explorer of the network.
+    pub fn explorer_url(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "https://app.roninchain.com",
+            Network::Saigon => "https://saigon-app.roninchain.com",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "mainnet",
+            Network::Saigon => "saigon",
+        }
+    }
+}
+
+/// The network of this run. The contract registry depends on it, so it is parsed once.
+pub fn current() -> Network {
+    static NETWORK: OnceLock<Network> = OnceLock::new();
+    *NETWORK.get_or_init(Network::from_args)
+}
+
+/// Whether `url` points to one of the public ronin.rest instances.
+pub fn is_public_host(url: &str) -> bool {
+    match reqwest::Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_lowercase)) {
+        Some(host) => host == "ronin.rest" || host.ends_with(".ronin.rest"),
+        None => false
+    }
+}
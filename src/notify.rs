@@ -0,0 +1,224 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use crate::{logging, ArgParser, DEFAULT_USER_AGENT};
+
+/// How long a webhook may take before the notification is given up.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Completed,
+    Failed,
+    Interrupted,
+}
+
+/// An exported wallet in the notification.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotifiedWallet {
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    pub transactions: usize,
+    /// The paths of the export, several if each group got its own file.
+    pub files: Vec<String>,
+}
+
+/// The JSON posted to `--notify-url`. `text` is a one line summary, which is what Slack and
+/// Slack compatible webhooks display.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Notification<'a> {
+    status: Status,
+    text: String,
+    addresses: Vec<String>,
+    duration_seconds: u64,
+    wallets: &'a [NotifiedWallet],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+struct Targets {
+    desktop: bool,
+    url: Option<String>,
+    started: Instant,
+    addresses: Mutex<Vec<String>>,
+}
+
+fn targets() -> &'static Targets {
+    static TARGETS: OnceLock<Targets> = OnceLock::new();
+
+    TARGETS.get_or_init(|| {
+        let desktop = ArgParser::split(&"--notify".to_string()).is_some_and(|notify| match notify.as_str() {
+            "desktop" => true,
+            notify => panic!("Invalid --notify value {}, expected desktop", notify)
+        });
+        let url = ArgParser::split(&"--notify-url".to_string()).inspect(|url| {
+            if reqwest::Url::parse(url).is_err() {
+                panic!("Invalid --notify-url value {}, expected a URL", url);
+            }
+        });
+
+        Targets { desktop, url, started: Instant::now(), addresses: Mutex::new(vec![]) }
+    })
+}
+
+/// Parses the notification flags and, if any is set, reports panics as a failed run. Called at the
+/// start of the run, which is where the duration is measured from.
+pub fn init() {
+    let targets = targets();
+
+    if targets.desktop || targets.url.is_some() {
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            default_hook(info);
+
+            let message = match (info.payload().downcast_ref::<&str>(), info.payload().downcast_ref::<String>()) {
+                (Some(message), _) => message.to_string(),
+                (_, Some(message)) => message.clone(),
+                _ => "wally panicked".to_string()
+            };
+            failed(&message);
+        }));
+    }
+}
+
+/// The wallets of the run, for notifications of runs that fail before anything was exported.
+pub fn set_addresses(addresses: Vec<String>) {
+    *targets().addresses.lock().unwrap() = addresses;
+}
+
+pub fn completed(wallets: &[NotifiedWallet]) {
+    send(Status::Completed, wallets, None);
+}
+
+pub fn failed(error: &str) {
+    send(Status::Failed, &[], Some(error));
+}
+
+pub fn interrupted() {
+    send(Status::Interrupted, &[], None);
+}
+
+fn report(message: &str) {
+    println!("{}", message);
+    logging::warn(message);
+}
+
+/// Sends the notification once, later calls are ignored. Failures are reported and otherwise
+/// ignored, a notification never changes the outcome of the run.
+fn send(status: Status, wallets: &[NotifiedWallet], error: Option<&str>) {
+    static SENT: AtomicBool = AtomicBool::new(false);
+
+    let targets = targets();
+    if (!targets.desktop && targets.url.is_none()) || SENT.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let addresses = targets.addresses.lock().map(|addresses| addresses.clone()).unwrap_or_default();
+    let duration_seconds = targets.started.elapsed().as_secs();
+    let text = match status {
+        Status::Completed => format!(
+            "wally exported {} transactions of {} in {}s",
+            wallets.iter().map(|wallet| wallet.transactions).sum::<usize>(), addresses.join(", "), duration_seconds
+        ),
+        Status::Failed => format!("wally failed to export {} after {}s: {}", addresses.join(", "), duration_seconds, error.unwrap_or_default()),
+        Status::Interrupted => format!("The export of {} was interrupted after {}s", addresses.join(", "), duration_seconds),
+    };
+
+    if targets.desktop {
+        if let Err(error) = desktop(&text) {
+            report(&format!("Could not show the desktop notification: {}", error));
+        }
+    }
+
+    if let Some(url) = &targets.url {
+        let body = serde_json::to_string(&Notification { status, text, addresses, duration_seconds, wallets, error }).unwrap();
+
+        // On a thread of its own, so it also works from the panic hook, inside or outside the runtime.
+        let url = url.clone();
+        let result = std::thread::spawn(move || webhook(&url, body)).join().unwrap_or_else(|_| Err("the request panicked".to_string()));
+
+        if let Err(error) = result {
+            report(&format!("Could not send the notification to --notify-url: {}", error));
+        }
+    }
+}
+
+fn webhook(url: &str, body: String) -> Result<(), String> {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().map_err(|error| error.to_string())?;
+
+    runtime.block_on(async {
+        let response = reqwest::Client::new().post(url)
+            .header("user-agent", DEFAULT_USER_AGENT)
+            .header("content-type", "application/json")
+            .timeout(WEBHOOK_TIMEOUT)
+            .body(body)
+            .send().await
+            .map_err(|error| error.to_string())?;
+
+        match response.status().is_success() {
+            true => Ok(()),
+            false => Err(format!("HTTP {}", response.status()))
+        }
+    })
+}
+
+/// Shows `text` with the notification tool of the system.
+fn desktop(text: &str) -> Result<(), String> {
+    let mut command = match std::env::consts::OS {
+        "macos" => {
+            let mut command = std::process::Command::new("osascript");
+            command.arg("-e").arg(format!("display notification {:?} with title \"wally\"", text));
+            command
+        }
+        "windows" => return Err("desktop notifications are not supported on Windows".to_string()),
+        _ => {
+            let mut command = std::process::Command::new("notify-send");
+            command.arg("wally").arg(text);
+            command
+        }
+    };
+
+    match command.status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("{:?} exited with {}", command.get_program(), status)),
+        Err(error) => Err(format!("could not run {:?}: {}", command.get_program(), error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn payload_is_the_documented_json() {
+        let wallets = [
+            NotifiedWallet { address: "0x1111".to_string(), alias: Some("treasury".to_string()), transactions: 42, files: vec!["treasury.json".to_string()] },
+            NotifiedWallet { address: "0x2222".to_string(), alias: None, transactions: 0, files: vec![] },
+        ];
+        let notification = Notification {
+            status: Status::Completed,
+            text: "wally exported 42 transactions of 0x1111, 0x2222 in 95s".to_string(),
+            addresses: vec!["0x1111".to_string(), "0x2222".to_string()],
+            duration_seconds: 95,
+            wallets: &wallets,
+            error: None,
+        };
+
+        assert_eq!(serde_json::to_value(&notification).unwrap(), json!({
+            "status": "completed",
+            "text": "wally exported 42 transactions of 0x1111, 0x2222 in 95s",
+            "addresses": ["0x1111", "0x2222"],
+            "durationSeconds": 95,
+            "wallets": [
+                { "address": "0x1111", "alias": "treasury", "transactions": 42, "files": ["treasury.json"] },
+                { "address": "0x2222", "transactions": 0, "files": [] }
+            ]
+        }));
+    }
+}
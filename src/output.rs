@@ -0,0 +1,770 @@
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use arrow_ipc::writer::FileWriter;
+use serde::Serialize;
+use serde_json::Value;
+use crate::checksum::{self, HashingWriter};
+use crate::columnar::{self, Batch};
+use crate::{cointracking, ctc};
+use crate::csv::Columns;
+use crate::encryption::Encryption;
+use crate::export::FORMAT_VERSION;
+use crate::network::{self, Network};
+use crate::{ArgParser, Direction};
+
+/// The file format of exports.
+pub enum Format {
+    Json,
+    Csv(Columns),
+    /// Arrow IPC files, also known as Feather v2, written by `ArrowWriter`.
+    Arrow,
+    /// The CoinTracking CSV import, one or more rows per accounting event.
+    CoinTracking,
+    /// The cryptotaxcalculator.io universal CSV import, one row per leg of an accounting event.
+    Ctc,
+}
+
+impl Format {
+    fn from_args() -> Format {
+        let format = match ArgParser::split(&"--format".to_string()).as_deref().map(str::to_lowercase).as_deref() {
+            None | Some("json") => Format::Json,
+            Some("csv") => Format::Csv(Columns::from_args()),
+            Some("arrow") => Format::Arrow,
+            Some("cointracking") => Format::CoinTracking,
+            Some("ctc") => Format::Ctc,
+            Some(format) => panic!("Invalid --format value {}, expected json, csv, arrow, cointracking or ctc", format)
+        };
+
+        if !matches!(format, Format::Csv(_)) {
+            for flag in ["--columns", "--flatten-input", "--flatten-depth", "--csv-template", "--csv-header"] {
+                if !ArgParser::all(flag).is_empty() || ArgParser::flag(flag) {
+                    panic!("{} requires --format=csv", flag);
+                }
+            }
+        }
+
+        format
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Csv(_) => "csv",
+            Format::Arrow => "arrow",
+            Format::CoinTracking | Format::Ctc => "csv",
+        }
+    }
+
+    /// Whether this is an accounting export, which classifies transactions from the point of
+    /// view of a single wallet.
+    pub fn is_accounting(&self) -> bool {
+        matches!(self, Format::CoinTracking | Format::Ctc)
+    }
+}
+
+/// How exports are split into several files.
+pub enum Chunking {
+    Records(usize),
+    Bytes(usize),
+}
+
+/// Parses sizes such as `100MB`. Units are binary, i.e. `1KB` is 1024 bytes.
+pub(crate) fn parse_size(text: &str) -> Result<usize, String> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+
+    let number: usize = number.parse().map_err(|_| format!("Invalid size '{}', expected e.g. 100MB", text))?;
+    let multiplier: usize = match unit.trim().to_uppercase().as_str() {
+        "" | "B" => 1,
+        "KB" => 1 << 10,
+        "MB" => 1 << 20,
+        "GB" => 1 << 30,
+        unit => return Err(format!("Invalid size '{}': unknown unit '{}', expected B, KB, MB or GB", text, unit))
+    };
+
+    Ok(number * multiplier)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ChunkIndexEntry {
+    file: String,
+    records: usize,
+    first_block: Option<u64>,
+    last_block: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ChunkIndex {
+    format_version: u32,
+    network: Network,
+    chunks: Vec<ChunkIndexEntry>,
+}
+
+/// How output files are written.
+pub struct Output {
+    pub encryption: Option<Encryption>,
+    /// Number of previous versions kept when an existing file is overwritten.
+    pub backups: usize,
+    /// Root of the `<wallet>/<date>/` layout, files are written to the working directory if unset.
+    pub out_dir: Option<PathBuf>,
+    pub date: String,
+    pub chunking: Option<Chunking>,
+    pub format: Format,
+}
+
+impl Output {
+    pub fn from_args() -> Output {
+        let output = Output {
+            encryption: Encryption::from_args(),
+            backups: match ArgParser::split(&"--backup".to_string()) {
+                None => 0,
+                Some(backups) => backups.parse().unwrap_or_else(|_| panic!("Invalid --backup value {}", backups))
+            },
+            out_dir: ArgParser::split(&"--out-dir".to_string()).map(PathBuf::from),
+            date: chrono::Utc::now().format("%Y-%m-%d").to_string(),
+            chunking: match (ArgParser::split(&"--max-file-size".to_string()), ArgParser::split(&"--chunk-size".to_string())) {
+                (Some(_), Some(_)) => panic!("--max-file-size and --chunk-size can not be combined"),
+                (Some(size), None) => Some(Chunking::Bytes(parse_size(&size).unwrap_or_else(|error| panic!("{}", error)))),
+                (None, Some(records)) => match records.parse() {
+                    Ok(0) | Err(_) => panic!("Invalid --chunk-size value {}", records),
+                    Ok(records) => Some(Chunking::Records(records))
+                },
+                (None, None) => None
+            },
+            format: Format::from_args(),
+        };
+
+        if matches!(output.format, Format::Arrow) && output.chunking.is_some() {
+            panic!("--format=arrow can not be combined with --max-file-size or --chunk-size");
+        }
+
+        output
+    }
+
+    /// Starts writing an export of `{stem}.json`, or `{stem}.part001.json`, ... when chunking
+    /// is enabled. CSV exports use the `.csv` extension and repeat the header in every chunk.
+    pub fn export_writer(&self, wallet: Option<&str>, stem: &str) -> ExportWriter<'_> {
+        ExportWriter {
+            output: self,
+            wallet: wallet.map(str::to_string),
+            stem: stem.to_string(),
+            current: None,
+            chunks: vec![],
+            paths: vec![],
+        }
+    }
+
+    /// Starts writing `{stem}.json` as a JSON object with one array of records per group.
+    pub fn grouped_writer(&self, wallet: Option<&str>, stem: &str) -> GroupedWriter {
+        let mut file = self.create(wallet, &format!("{}.json", stem));
+        file.sink.write_all(b"{").unwrap();
+
+        GroupedWriter { file, groups: 0, records: 0 }
+    }
+
+    /// Starts writing an export of `{stem}.arrow` with `--format=arrow`.
+    pub fn arrow_writer(&self, wallet: Option<&str>, stem: &str) -> ArrowWriter {
+        let PendingFile { path, temporary, sink, backups } = self.create(wallet, &format!("{}.arrow", stem));
+        let writer = FileWriter::try_new(sink, &columnar::schema()).unwrap_or_else(|error| panic!("Could not write {}: {}", temporary, error));
+
+        ArrowWriter { path, temporary, backups, writer, batch: Batch::new() }
+    }
+
+    /// The path an export of `{stem}.json` is written to, e.g. to look for a previous export.
+    pub fn export_path(&self, wallet: Option<&str>, stem: &str) -> String {
+        let path = self.path(wallet, &format!("{}.{}", stem, self.format.extension()));
+
+        match self.encryption {
+            Some(_) => format!("{}.age", path),
+            None => path
+        }
+    }
+
+    /// Starts appending records to the existing JSON export at `path` holding `records` records,
+    /// see `--append`. Only the closing bracket of a plain export is replaced. Encrypted exports
+    /// can't be extended in place, their records are decrypted into a new encrypted file.
+    pub fn append_writer(&self, path: &str, records: usize) -> AppendWriter {
+        let target = match self.encryption {
+            Some(_) => AppendTarget::Rewritten(Box::new(self.reencrypt(path))),
+            None => AppendTarget::InPlace(open_in_place(path))
+        };
+
+        AppendWriter { path: path.to_string(), target, records, appended: 0 }
+    }
+
+    /// Copies the decrypted records of the encrypted export at `path` into a new encrypted file,
+    /// leaving out the closing bracket.
+    fn reencrypt(&self, path: &str) -> PendingFile {
+        let mut file = self.create_at(path.to_string());
+        let mut reader = crate::export::open(path);
+        // What may be the closing bracket and the whitespace around it is held back.
+        let mut held: Vec<u8> = vec![];
+
+        loop {
+            let chunk = reader.fill_buf().unwrap_or_else(|error| panic!("Could not read {}: {}", path, error));
+            if chunk.is_empty() {
+                break;
+            }
+            held.extend_from_slice(chunk);
+            let length = chunk.len();
+            reader.consume(length);
+
+            let written = match held.iter().rposition(|byte| !byte.is_ascii_whitespace()) {
+                Some(last) if held[last] == b']' => last,
+                Some(last) => last + 1,
+                None => 0
+            };
+            file.sink.write_all(&held[..written]).unwrap();
+            held.drain(..written);
+        }
+
+        if held.first() != Some(&b']') {
+            panic!("Could not append to {}, it does not end with a JSON array", path);
+        }
+
+        file
+    }
+
+    /// Starts writing a newline delimited JSON file with one record per line.
+    pub fn ndjson_writer(&self, wallet: Option<&str>, file_name: &str) -> NdjsonWriter {
+        NdjsonWriter { file: self.create(wallet, file_name) }
+    }
+
+    /// Starts writing a JSON array of records to `file_name`, whatever the export format.
+    pub fn json_writer(&self, wallet: Option<&str>, file_name: &str) -> JsonWriter {
+        let mut file = self.create(wallet, file_name);
+        file.sink.write_all(b"[").unwrap();
+
+        JsonWriter { file, records: 0 }
+    }
+
+    /// Resolves where a file goes. With `--out-dir`, files of a wallet are written to
+    /// `<out-dir>/<wallet>/<date>/` and files concerning the whole run to `<out-dir>/<date>/`.
+    pub fn path(&self, wallet: Option<&str>, file_name: &str) -> String {
+        let path = match &self.out_dir {
+            None => file_name.to_string(),
+            Some(out_dir) => {
+                let mut directory = out_dir.clone();
+                directory.extend(wallet);
+                directory.push(&self.date);
+
+                directory.join(file_name).to_string_lossy().to_string()
+            }
+        };
+
+        // File names may contain a subdirectory, e.g. the per-contract files.
+        if let Some(directory) = Path::new(&path).parent().filter(|directory| !directory.as_os_str().is_empty()) {
+            std::fs::create_dir_all(directory).unwrap_or_else(|error| panic!("Could not create {}: {}", directory.display(), error));
+        }
+
+        path
+    }
+
+    /// Opens the temporary file of an output file, see `PendingFile::commit`.
+    fn create(&self, wallet: Option<&str>, file_name: &str) -> PendingFile {
+        let path = match self.encryption {
+            Some(_) => format!("{}.age", self.path(wallet, file_name)),
+            None => self.path(wallet, file_name)
+        };
+
+        self.create_at(path)
+    }
+
+    /// Starts writing the file at `path`, which is encrypted if requested.
+    fn create_at(&self, path: String) -> PendingFile {
+        let temporary = format!("{}.tmp", path);
+
+        let file = File::create(&temporary).unwrap_or_else(|error| panic!("Could not create {}: {}", temporary, error));
+        let file = BufWriter::new(HashingWriter::new(file));
+        let sink = match &self.encryption {
+            Some(encryption) => Sink::Encrypted(encryption.wrap(file)),
+            None => Sink::Plain(file)
+        };
+
+        PendingFile { path, temporary, sink, backups: self.backups }
+    }
+
+    /// Writes an output file, encrypting it if requested. Returns the path of the written file.
+    pub fn write(&self, wallet: Option<&str>, file_name: String, contents: String) -> String {
+        let mut file = self.create(wallet, &file_name);
+        file.sink.write_all(contents.as_bytes()).unwrap();
+        file.commit()
+    }
+}
+
+/// Output files are hashed below the encryption, so checksums are those of the bytes on disk.
+enum Sink {
+    Plain(BufWriter<HashingWriter<File>>),
+    Encrypted(age::stream::StreamWriter<BufWriter<HashingWriter<File>>>),
+}
+
+impl Write for Sink {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Sink::Plain(writer) => writer.write(bytes),
+            Sink::Encrypted(writer) => writer.write(bytes)
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Sink::Plain(writer) => writer.flush(),
+            Sink::Encrypted(writer) => writer.flush()
+        }
+    }
+}
+
+impl Sink {
+
+    /// Completes the file, returning it and its checksum.
+    fn finish(self) -> std::io::Result<(File, String)> {
+        let writer = match self {
+            Sink::Plain(writer) => writer,
+            Sink::Encrypted(writer) => writer.finish()?
+        };
+
+        Ok(writer.into_inner().map_err(|error| error.into_error())?.finish())
+    }
+}
+
+/// An output file written by this run.
+#[derive(Clone)]
+pub struct WrittenFile {
+    pub path: String,
+    pub sha256: String,
+}
+
+/// Every output file written by this run, in order, for uploading them afterwards.
+static WRITTEN: Mutex<Vec<WrittenFile>> = Mutex::new(vec![]);
+
+pub fn written() -> Vec<WrittenFile> {
+    WRITTEN.lock().unwrap().clone()
+}
+
+/// Writes `{path}.sha256` next to an export, see `checksum::sidecar_line`.
+fn write_sidecar(path: &str, sha256: &str) {
+    let sidecar = checksum::sidecar_path(path);
+    let temporary = format!("{}.tmp", sidecar);
+    let contents = checksum::sidecar_line(sha256, path);
+
+    std::fs::write(&temporary, &contents).and_then(|_| std::fs::rename(&temporary, &sidecar))
+        .unwrap_or_else(|error| panic!("Could not write {}: {}", sidecar, error));
+
+    WRITTEN.lock().unwrap().push(WrittenFile { path: sidecar, sha256: checksum::of(contents.as_bytes()) });
+}
+
+/// An output file which is written to a temporary file first.
+struct PendingFile {
+    path: String,
+    temporary: String,
+    sink: Sink,
+    backups: usize,
+}
+
+impl PendingFile {
+    /// Replaces the target with the temporary file in a single rename, so an interrupted run
+    /// leaves either the old or the new file behind, never a partial one. Returns the path.
+    fn commit(self) -> String {
+        self.commit_file().path
+    }
+
+    /// Commits an export, which gets a checksum sidecar.
+    fn commit_checksummed(self) -> String {
+        let written = self.commit_file();
+        write_sidecar(&written.path, &written.sha256);
+
+        written.path
+    }
+
+    fn commit_file(self) -> WrittenFile {
+        let (file, sha256) = self.sink.finish().unwrap_or_else(|error| panic!("Could not write {}: {}", self.temporary, error));
+        file.sync_all().unwrap();
+
+        if self.backups > 0 && Path::new(&self.path).exists() {
+            rotate(&self.path, self.backups).unwrap_or_else(|error| panic!("Could not rotate backups of {}: {}", self.path, error));
+        }
+
+        std::fs::rename(&self.temporary, &self.path).unwrap_or_else(|error| panic!("Could not write {}: {}", self.path, error));
+        let written = WrittenFile { path: self.path, sha256 };
+        WRITTEN.lock().unwrap().push(written.clone());
+
+        written
+    }
+}
+
+struct OpenChunk {
+    file: PendingFile,
+    records: usize,
+    bytes: usize,
+    first_block: Option<u64>,
+    last_block: Option<u64>,
+}
+
+/// Streams records into an export one at a time, so only a single record has to be held in
+/// memory. Chunks are started as the configured limit is reached.
+pub struct ExportWriter<'a> {
+    output: &'a Output,
+    wallet: Option<String>,
+    stem: String,
+    current: Option<OpenChunk>,
+    chunks: Vec<ChunkIndexEntry>,
+    paths: Vec<String>,
+}
+
+impl ExportWriter<'_> {
+    fn is_full(&self, chunk: &OpenChunk, record: &str) -> bool {
+        match self.output.chunking {
+            Some(Chunking::Records(limit)) => chunk.records >= limit,
+            // Every chunk holds at least one record, even if that record alone exceeds the limit.
+            Some(Chunking::Bytes(limit)) => chunk.records > 0 && chunk.bytes + record.len() + 1 > limit,
+            None => false
+        }
+    }
+
+    fn open(&mut self) {
+        let extension = self.output.format.extension();
+        let file_name = match self.output.chunking {
+            Some(_) => format!("{}.part{:03}.{}", self.stem, self.chunks.len() + 1, extension),
+            None => format!("{}.{}", self.stem, extension)
+        };
+
+        let mut file = self.output.create(self.wallet.as_deref(), &file_name);
+        let start = match &self.output.format {
+            Format::Json => "[".to_string(),
+            Format::Csv(columns) => format!("{}\n", columns.header()),
+            Format::CoinTracking => format!("{}\n", cointracking::HEADER),
+            Format::Ctc => format!("{}\n", ctc::HEADER),
+            Format::Arrow => unreachable!("Arrow exports are written by ArrowWriter")
+        };
+        file.sink.write_all(start.as_bytes()).unwrap();
+
+        let bytes = match self.output.format {
+            Format::Json => 2,
+            _ => start.len()
+        };
+        self.current = Some(OpenChunk { file, records: 0, bytes, first_block: None, last_block: None });
+    }
+
+    fn close(&mut self) {
+        if let Some(mut chunk) = self.current.take() {
+            if let Format::Json = self.output.format {
+                chunk.file.sink.write_all(b"]").unwrap();
+            }
+            let path = chunk.file.commit_checksummed();
+
+            self.chunks.push(ChunkIndexEntry {
+                file: Path::new(&path).file_name().unwrap().to_string_lossy().to_string(),
+                records: chunk.records,
+                first_block: chunk.first_block,
+                last_block: chunk.last_block,
+            });
+            self.paths.push(path);
+        }
+    }
+
+    /// Appends a serialized record, see `Output::format`.
+    pub fn push(&mut self, record: &str, block: u64) {
+        // Accounting exports have no rows for transactions without an economic effect.
+        if record.is_empty() && self.output.format.is_accounting() {
+            return;
+        }
+
+        if self.current.as_ref().is_some_and(|chunk| self.is_full(chunk, record)) {
+            self.close();
+        }
+        if self.current.is_none() {
+            self.open();
+        }
+
+        let chunk = self.current.as_mut().unwrap();
+
+        match self.output.format {
+            Format::Json => {
+                if chunk.records > 0 {
+                    chunk.file.sink.write_all(b",").unwrap();
+                }
+                chunk.file.sink.write_all(record.as_bytes()).unwrap();
+            }
+            _ => {
+                chunk.file.sink.write_all(record.as_bytes()).unwrap();
+                chunk.file.sink.write_all(b"\n").unwrap();
+            }
+        }
+
+        chunk.records += 1;
+        chunk.bytes += record.len() + 1;
+        chunk.first_block = Some(chunk.first_block.map_or(block, |first| first.min(block)));
+        chunk.last_block = Some(chunk.last_block.map_or(block, |last| last.max(block)));
+    }
+
+    /// Completes the export. Returns the path of the export or, when chunking, of the index
+    /// listing the chunks.
+    pub fn finish(mut self) -> String {
+        if self.current.is_none() && self.chunks.is_empty() {
+            self.open();
+        }
+        self.close();
+
+        match self.output.chunking {
+            None => self.paths.remove(0),
+            Some(_) => self.output.write(
+                self.wallet.as_deref(),
+                format!("{}.index.json", self.stem),
+                serde_json::to_string_pretty(&ChunkIndex { format_version: FORMAT_VERSION, network: network::current(), chunks: self.chunks }).unwrap()
+            )
+        }
+    }
+}
+
+/// Streams groups of records into a single JSON object, see `Output::grouped_writer`. Groups
+/// have to be written one after another.
+pub struct GroupedWriter {
+    file: PendingFile,
+    groups: usize,
+    records: usize,
+}
+
+impl GroupedWriter {
+    /// Starts the next group, closing the previous one.
+    pub fn group(&mut self, key: &str) {
+        if self.groups > 0 {
+            self.file.sink.write_all(b"],").unwrap();
+        }
+
+        self.file.sink.write_all(serde_json::to_string(key).unwrap().as_bytes()).unwrap();
+        self.file.sink.write_all(b":[").unwrap();
+
+        self.groups += 1;
+        self.records = 0;
+    }
+
+    /// Appends a serialized record to the current group.
+    pub fn push(&mut self, record: &str) {
+        if self.records > 0 {
+            self.file.sink.write_all(b",").unwrap();
+        }
+        self.file.sink.write_all(record.as_bytes()).unwrap();
+
+        self.records += 1;
+    }
+
+    /// Completes the export and returns its path.
+    pub fn finish(mut self) -> String {
+        if self.groups > 0 {
+            self.file.sink.write_all(b"]").unwrap();
+        }
+        self.file.sink.write_all(b"}").unwrap();
+
+        self.file.commit_checksummed()
+    }
+}
+
+/// Streams records into an Arrow IPC file, see `Output::arrow_writer`. Records are written in
+/// batches of `columnar::BATCH_SIZE`.
+pub struct ArrowWriter {
+    path: String,
+    temporary: String,
+    backups: usize,
+    writer: FileWriter<Sink>,
+    batch: Batch,
+}
+
+impl ArrowWriter {
+    /// Appends a serialized record. `wallet` is the address (or pseudonym) the export belongs to
+    /// and its direction, unset in merged exports.
+    pub fn push(&mut self, record: &Value, wallet: Option<(&str, Direction)>) {
+        self.batch.push(record, wallet);
+
+        if self.batch.records() >= columnar::BATCH_SIZE {
+            self.write_batch();
+        }
+    }
+
+    fn write_batch(&mut self) {
+        let batch = self.batch.finish();
+        self.writer.write(&batch).unwrap_or_else(|error| panic!("Could not write {}: {}", self.temporary, error));
+    }
+
+    /// Completes the export and returns its path.
+    pub fn finish(mut self) -> String {
+        if self.batch.records() > 0 {
+            self.write_batch();
+        }
+
+        let sink = self.writer.into_inner().unwrap_or_else(|error| panic!("Could not write {}: {}", self.temporary, error));
+        PendingFile { path: self.path, temporary: self.temporary, sink, backups: self.backups }.commit_checksummed()
+    }
+}
+
+/// Streams records into a newline delimited JSON file, see `Output::ndjson_writer`.
+pub struct NdjsonWriter {
+    file: PendingFile,
+}
+
+impl NdjsonWriter {
+    /// Appends a serialized record as a line.
+    pub fn push(&mut self, record: &str) {
+        self.file.sink.write_all(record.as_bytes()).unwrap();
+        self.file.sink.write_all(b"\n").unwrap();
+    }
+
+    /// Completes the file and returns its path.
+    pub fn finish(self) -> String {
+        self.file.commit_checksummed()
+    }
+}
+
+/// Streams records into a JSON array, see `Output::json_writer`.
+pub struct JsonWriter {
+    file: PendingFile,
+    records: usize,
+}
+
+impl JsonWriter {
+    /// Appends a serialized record.
+    pub fn push(&mut self, record: &str) {
+        if self.records > 0 {
+            self.file.sink.write_all(b",").unwrap();
+        }
+        self.file.sink.write_all(record.as_bytes()).unwrap();
+
+        self.records += 1;
+    }
+
+    /// Completes the file and returns its path and the number of records.
+    pub fn finish(mut self) -> (String, usize) {
+        self.file.sink.write_all(b"]").unwrap();
+
+        (self.file.commit_checksummed(), self.records)
+    }
+}
+
+/// Opens the plain JSON export at `path` for appending, positioned at its closing bracket. Exports
+/// end with the closing bracket, possibly followed by whitespace.
+fn open_in_place(path: &str) -> BufWriter<File> {
+    let mut file = std::fs::OpenOptions::new().read(true).write(true).open(path)
+        .unwrap_or_else(|error| panic!("Could not open {}: {}", path, error));
+
+    let mut end = file.metadata().unwrap().len();
+    let mut byte = [0u8];
+    while end > 0 {
+        file.seek(SeekFrom::Start(end - 1)).unwrap();
+        file.read_exact(&mut byte).unwrap();
+        if !byte[0].is_ascii_whitespace() {
+            break;
+        }
+        end -= 1;
+    }
+    if end == 0 || byte[0] != b']' {
+        panic!("Could not append to {}, it does not end with a JSON array", path);
+    }
+
+    file.seek(SeekFrom::Start(end - 1)).unwrap();
+    BufWriter::new(file)
+}
+
+/// Where appended records are written to.
+enum AppendTarget {
+    /// The existing plain export.
+    InPlace(BufWriter<File>),
+    /// A new file holding the decrypted records of an encrypted export, replacing it once complete.
+    Rewritten(Box<PendingFile>),
+}
+
+impl Write for AppendTarget {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        match self {
+            AppendTarget::InPlace(file) => file.write(bytes),
+            AppendTarget::Rewritten(file) => file.sink.write(bytes)
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            AppendTarget::InPlace(file) => file.flush(),
+            AppendTarget::Rewritten(file) => file.sink.flush()
+        }
+    }
+}
+
+/// Appends records to an existing JSON export. The bytes of the existing records of plain exports
+/// are never rewritten.
+pub struct AppendWriter {
+    path: String,
+    target: AppendTarget,
+    records: usize,
+    appended: usize,
+}
+
+impl AppendWriter {
+    /// Appends a serialized record.
+    pub fn push(&mut self, record: &str) {
+        if self.records + self.appended > 0 {
+            self.target.write_all(b",").unwrap();
+        }
+        self.target.write_all(record.as_bytes()).unwrap();
+        self.appended += 1;
+    }
+
+    /// Closes the array again and returns the path and the number of appended records. Nothing is
+    /// touched without new records. The checksum covers the existing records as well, so a plain
+    /// file is read once more for it.
+    pub fn finish(mut self) -> (String, usize) {
+        if self.appended == 0 {
+            if let AppendTarget::Rewritten(file) = self.target {
+                let _ = std::fs::remove_file(&file.temporary);
+            }
+            return (self.path, 0);
+        }
+
+        self.target.write_all(b"]").unwrap();
+        match self.target {
+            AppendTarget::Rewritten(file) => {
+                file.commit_checksummed();
+            }
+            AppendTarget::InPlace(file) => {
+                let mut file = file.into_inner().map_err(|error| error.into_error())
+                    .unwrap_or_else(|error| panic!("Could not write {}: {}", self.path, error));
+                // Whitespace after the old closing bracket would otherwise remain behind the new one.
+                let end = file.stream_position().unwrap();
+                if end < file.metadata().unwrap().len() {
+                    file.set_len(end).unwrap();
+                }
+                file.sync_all().unwrap_or_else(|error| panic!("Could not write {}: {}", self.path, error));
+
+                let sha256 = checksum::of_file(&self.path).unwrap_or_else(|error| panic!("Could not read {}: {}", self.path, error));
+                WRITTEN.lock().unwrap().push(WrittenFile { path: self.path.clone(), sha256: sha256.clone() });
+                write_sidecar(&self.path, &sha256);
+            }
+        }
+
+        (self.path, self.appended)
+    }
+}
+
+/// Moves `{name}.1` ... `{name}.{backups - 1}` one slot up, dropping the oldest, and links the
+/// current file to `{name}.1`. The current file itself stays in place.
+fn rotate(file_name: &str, backups: usize) -> std::io::Result<()> {
+    let backup = |n: usize| format!("{}.{}", file_name, n);
+
+    if Path::new(&backup(backups)).exists() {
+        std::fs::remove_file(backup(backups))?;
+    }
+
+    for n in (1..backups).rev() {
+        if Path::new(&backup(n)).exists() {
+            std::fs::rename(backup(n), backup(n + 1))?;
+        }
+    }
+
+    if std::fs::hard_link(file_name, backup(1)).is_err() {
+        std::fs::copy(file_name, backup(1))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use crate::receipt::RRReceipt;
+use crate::integrity::Verification;
+use crate::provider::WalletDataProvider;
+use crate::{blobs, breaker, genes, integrity, logging, multicall, ArgParser, RRTransaction, RRTransactionHash, TxStatus};
+
+/// Transactions buffered between two stages. A slow writer fills the channels and so throttles the
+/// fetchers instead of piling up responses in memory.
+const CHANNEL_CAPACITY: usize = 64;
+
+/// A transaction with everything the exporter needs to know about it.
+pub struct Fetched {
+    pub hash: RRTransactionHash,
+    pub tx: RRTransaction,
+    pub output: Option<RRReceipt>,
+    pub status: Option<TxStatus>,
+    pub input: Option<serde_json::Value>,
+    pub raw_logs: Option<serde_json::Value>,
+    /// The number of strings in `input` and `output` truncated by `--max-blob-bytes`.
+    pub truncated_blobs: usize,
+    /// Set when the transaction was given up on after `--tx-max-attempts` failed requests.
+    pub error: Option<String>,
+    /// The recomputed hash with `--verify-hashes`.
+    pub verification: Option<Verification>,
+}
+
+impl Fetched {
+    pub fn fetch_failed(&self) -> bool {
+        self.error.is_some() || (self.tx.to == "null" && self.tx.from == "null")
+    }
+}
+
+/// The number of failed requests after which a transaction is given up on, from `--tx-max-attempts`.
+pub fn tx_max_attempts() -> Option<usize> {
+    ArgParser::split(&"--tx-max-attempts".to_string()).map(|attempts| match attempts.parse::<usize>() {
+        Ok(attempts) if attempts > 0 => attempts,
+        _ => panic!("Could not parse --tx-max-attempts={}, expected a positive number", attempts)
+    })
+}
+
+/// What the pipeline requests for every transaction.
+pub struct Options {
+    pub max_attempts: Option<usize>,
+    pub decode_input: bool,
+    pub decode_output: bool,
+    pub raw_logs: bool,
+    pub max_blob_bytes: Option<usize>,
+    pub verify_hashes: bool,
+    pub decode_genes: bool,
+}
+
+impl Options {
+    pub fn from_args() -> Options {
+        Options {
+            max_attempts: tx_max_attempts(),
+            decode_input: !ArgParser::flag("--no-decode-input"),
+            decode_output: !ArgParser::flag("--no-decode-output"),
+            raw_logs: ArgParser::flag("--raw-logs"),
+            max_blob_bytes: blobs::max_blob_bytes(),
+            verify_hashes: integrity::enabled(),
+            decode_genes: genes::enabled(),
+        }
+    }
+}
+
+/// Repeats `request` for `subject`, e.g. `transaction 0x…`, until it succeeds. `attempts` counts
+/// the failed requests of a subject across all endpoints. Without a limit a failed request ends the
+/// run, like any other API error.
+pub async fn attempt<T, F, R>(subject: &str, attempts: &AtomicUsize, max_attempts: Option<usize>, mut request: F) -> Result<T, String>
+    where F: FnMut() -> R, R: Future<Output = Result<T, String>> {
+    loop {
+        let error = match request().await {
+            Ok(value) => return Ok(value),
+            Err(error) => error
+        };
+
+        let failed = attempts.fetch_add(1, Ordering::SeqCst) + 1;
+        logging::warn(&format!("Request {} for {} failed: {}", failed, subject, error));
+
+        match max_attempts {
+            // The run is given up as a whole, see `breaker::aborted`.
+            None if breaker::aborted() => return Err(error),
+            None => panic!("{}", error),
+            Some(max_attempts) if failed >= max_attempts => return Err(format!("{} failed requests, last: {}", failed, error)),
+            Some(_) => {}
+        }
+    }
+}
+
+/// Stops feeding new transactions into the pipeline on the first Ctrl+C so the transactions in
+/// flight can drain. A second Ctrl+C exits immediately.
+pub fn interrupt_on_ctrl_c() -> Arc<AtomicBool> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let flag = interrupted.clone();
+
+    tokio::spawn(async move {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if flag.swap(true, Ordering::SeqCst) {
+                std::process::exit(130);
+            }
+        }
+    });
+
+    interrupted
+}
+
+/// The fetch (getTransaction) and decode (receipt and method) stages feeding the writer.
+pub struct Pipeline {
+    receiver: mpsc::Receiver<Fetched>,
+    stages: Vec<JoinHandle<()>>,
+}
+
+impl Pipeline {
+    /// Returns the next transaction in the order of the hashes, or `None` once all of them were
+    /// processed or the run was interrupted. A panic in one of the stages is raised here instead
+    /// of silently ending the export early.
+    pub async fn recv(&mut self) -> Option<Fetched> {
+        if let Some(fetched) = self.receiver.recv().await {
+            return Some(fetched);
+        }
+
+        for stage in self.stages.drain(..) {
+            if let Err(error) = stage.await {
+                if error.is_panic() {
+                    std::panic::resume_unwind(error.into_panic());
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Starts the stages for `hashes`.
+pub fn spawn(rr: Arc<dyn WalletDataProvider>, hashes: Vec<RRTransactionHash>, options: Options, interrupted: Arc<AtomicBool>) -> Pipeline {
+    let max_attempts = options.max_attempts;
+    let verify_hashes = options.verify_hashes;
+
+    let (fetched_sender, mut fetched_receiver) = mpsc::channel::<(RRTransactionHash, Result<(RRTransaction, Option<Verification>), String>, usize)>(CHANNEL_CAPACITY);
+    let (decoded_sender, decoded_receiver) = mpsc::channel::<Fetched>(CHANNEL_CAPACITY);
+
+    let fetcher = rr.clone();
+    let fetch = tokio::spawn(async move {
+        for hash in hashes {
+            if interrupted.load(Ordering::SeqCst) || breaker::aborted() {
+                break;
+            }
+
+            let subject = format!("transaction {}", hash);
+            let attempts = AtomicUsize::new(0);
+            let tx = match verify_hashes {
+                true => attempt(&subject, &attempts, max_attempts, || fetcher.get_transaction_fields(&hash)).await
+                    .map(|(tx, fields)| (tx, Some(integrity::verify(&hash, &fields)))),
+                false => attempt(&subject, &attempts, max_attempts, || fetcher.get_transaction(&hash)).await.map(|tx| (tx, None))
+            };
+
+            if fetched_sender.send((hash, tx, attempts.into_inner())).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let decode = tokio::spawn(async move {
+        // The genes of `--decode-genes` by token ID.
+        let mut axies = HashMap::new();
+
+        while let Some((hash, tx, attempts)) = fetched_receiver.recv().await {
+            let (tx, verification) = match tx {
+                Ok(tx) => tx,
+                Err(error) => {
+                    let fetched = Fetched { hash, tx: RRTransaction::unavailable(), output: None, status: None, input: None, raw_logs: None, truncated_blobs: 0, error: Some(error), verification: None };
+                    match decoded_sender.send(fetched).await {
+                        Ok(_) => continue,
+                        Err(_) => break
+                    }
+                }
+            };
+
+            // The receipt, the method and the raw logs are requested concurrently, a failure of one
+            // keeps the others.
+            let subject = format!("transaction {}", hash);
+            let attempts = AtomicUsize::new(attempts);
+            let mut errors = vec![];
+            let exported = tx.to != tx.from;
+            let (output, input, raw_logs) = tokio::join!(
+                async {
+                    match exported && options.decode_output {
+                        true => attempt(&subject, &attempts, max_attempts, || rr.decode_receipt(&hash)).await.map(Some),
+                        false => Ok(None)
+                    }
+                },
+                async {
+                    match exported && options.decode_input {
+                        true => attempt(&subject, &attempts, max_attempts, || rr.decode_input(&hash)).await.map(|input| Some(multicall::expand(input))),
+                        false => Ok(None)
+                    }
+                },
+                async {
+                    match exported && options.raw_logs {
+                        true => attempt(&subject, &attempts, max_attempts, || rr.raw_logs(&hash)).await.map(Some),
+                        false => Ok(None)
+                    }
+                }
+            );
+            let mut output = output.unwrap_or_else(|error| {
+                errors.push(error);
+                None
+            });
+            let mut input = input.unwrap_or_else(|error| {
+                errors.push(error);
+                None
+            });
+            let raw_logs = raw_logs.unwrap_or_else(|error| {
+                errors.push(error);
+                None
+            });
+            if let Some(receipt) = output.as_mut().filter(|_| options.decode_genes) {
+                genes::attach(receipt, rr.as_ref(), &mut axies).await;
+            }
+            let status = output.as_ref().and_then(|receipt| receipt.status);
+
+            // Truncated after the status and the multicalls were decoded from the full data.
+            let truncated_blobs = match options.max_blob_bytes {
+                Some(max_bytes) => {
+                    input.as_mut().map_or(0, |input| blobs::truncate(input, max_bytes))
+                        + output.as_mut().map_or(0, |receipt| receipt.edit(|raw| blobs::truncate(raw, max_bytes)))
+                }
+                None => 0
+            };
+            let error = match errors.is_empty() {
+                true => None,
+                false => Some(errors.join("; "))
+            };
+
+            let fetched = Fetched { hash, tx, output, status, input, raw_logs, truncated_blobs, error, verification };
+
+            if decoded_sender.send(fetched).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    Pipeline {
+        receiver: decoded_receiver,
+        stages: vec![fetch, decode],
+    }
+}
@@ -0,0 +1,127 @@
+use std::sync::Arc;
+use web3::types::U256;
+use crate::block::RRBlock;
+use crate::receipt::RRReceipt;
+use crate::transfers::{TokenStandard, TransferList};
+use crate::{graphql, logging, pipeline, skymavis, ArgParser, RRTransaction, RRTransactionDict, RRTransactionHash, RoninRest};
+
+/// What a data source can deliver beyond listing and fetching transactions. Exports from a source
+/// without a capability leave the corresponding fields empty instead of failing.
+#[derive(Clone, Copy)]
+pub struct Capabilities {
+    /// Decoding the method and the receipt of a transaction.
+    pub decode: bool,
+    /// The undecoded receipt logs, `--raw-logs`.
+    pub raw_logs: bool,
+    /// All fields of a transaction including the signature, `--verify-hashes`.
+    pub transaction_fields: bool,
+    /// The timestamp, miner and transactions of a block, `wally blocks`.
+    pub blocks: bool,
+    /// The genes of an axie, `--decode-genes`.
+    pub genes: bool,
+    /// The verified ABIs of contracts, `wally abi`.
+    pub abis: bool,
+    /// The per-address token transfer lists, `wally token-transfers`.
+    pub token_transfers: bool,
+}
+
+/// A source of wallet data, selected with `--provider`.
+#[async_trait::async_trait]
+pub trait WalletDataProvider: Send + Sync {
+    /// The name used for `--provider`.
+    fn name(&self) -> &'static str;
+
+    fn capabilities(&self) -> Capabilities;
+
+    /// Checks that the source is reachable before anything is fetched.
+    async fn health_check(&self) -> Result<(), String>;
+
+    /// The host that served the latest request.
+    fn active_host(&self) -> &str;
+
+    /// The number of requests sent to each host.
+    fn host_requests(&self) -> Vec<(&str, u64)>;
+
+    async fn list_sent(&self, address: &str) -> RRTransactionDict;
+
+    async fn list_received(&self, address: &str) -> RRTransactionDict;
+
+    async fn get_transaction(&self, hash: &RRTransactionHash) -> Result<RRTransaction, String>;
+
+    /// The transaction along with all fields of the response, needs `transaction_fields`.
+    async fn get_transaction_fields(&self, hash: &RRTransactionHash) -> Result<(RRTransaction, serde_json::Value), String>;
+
+    /// The decoded method of a transaction, needs `decode`.
+    async fn decode_input(&self, hash: &RRTransactionHash) -> Result<serde_json::Value, String>;
+
+    /// The decoded receipt of a transaction, needs `decode`.
+    async fn decode_receipt(&self, hash: &RRTransactionHash) -> Result<RRReceipt, String>;
+
+    /// The logs of the undecoded receipt, needs `raw_logs`.
+    async fn raw_logs(&self, hash: &RRTransactionHash) -> Result<serde_json::Value, String>;
+
+    async fn latest_block(&self) -> Result<u64, String>;
+
+    /// Block `number`, needs `blocks`.
+    async fn get_block(&self, number: u64) -> Result<RRBlock, String>;
+
+    /// The genes of the axie with token ID `token_id` as hex, needs `genes`.
+    async fn axie_genes(&self, token_id: &str) -> Result<String, String>;
+
+    /// The verified ABI of the contract at `address`, needs `abis`.
+    async fn contract_abi(&self, address: &str) -> Result<serde_json::Value, String>;
+
+    /// The token transfers of one standard from or to `address`, needs `token_transfers`.
+    async fn token_transfers(&self, standard: TokenStandard, address: &str) -> Result<TransferList, String>;
+
+    /// The current RON balance of `address` in wei.
+    async fn balance(&self, address: &str) -> Result<U256, String>;
+}
+
+/// The provider of `--provider`, ronin.rest by default.
+pub fn from_args() -> Arc<dyn WalletDataProvider> {
+    match ArgParser::split(&"--provider".to_string()).as_deref().map(str::to_lowercase).as_deref() {
+        None | Some("ronin-rest") => {
+            if !ArgParser::all("--graphql-url").is_empty() || !ArgParser::all("--graphql-query").is_empty() {
+                panic!("--graphql-url and --graphql-query require --provider=graphql");
+            }
+            if !ArgParser::all("--skymavis-key").is_empty() || !ArgParser::all("--skymavis-url").is_empty() {
+                panic!("--skymavis-key and --skymavis-url require --provider=skymavis");
+            }
+            Arc::new(RoninRest::from_args())
+        }
+        Some("graphql") => Arc::new(graphql::GraphQl::from_args()),
+        Some("skymavis") => Arc::new(skymavis::SkyMavis::from_args()),
+        Some(provider) => panic!("Invalid --provider value {}, expected ronin-rest, graphql or skymavis", provider)
+    }
+}
+
+/// Turns off what `provider` can't deliver, printing what is left out of the export.
+pub fn restrict(options: &mut pipeline::Options, provider: &dyn WalletDataProvider) {
+    let capabilities = provider.capabilities();
+    let mut dropped = vec![];
+
+    if !capabilities.decode && (options.decode_input || options.decode_output) {
+        options.decode_input = false;
+        options.decode_output = false;
+        dropped.push("the decoded method and receipt");
+    }
+    if !capabilities.raw_logs && options.raw_logs {
+        options.raw_logs = false;
+        dropped.push("the raw logs of --raw-logs");
+    }
+    if !capabilities.transaction_fields && options.verify_hashes {
+        options.verify_hashes = false;
+        dropped.push("the hash verification of --verify-hashes");
+    }
+    if !capabilities.genes && options.decode_genes {
+        options.decode_genes = false;
+        dropped.push("the axie genes of --decode-genes");
+    }
+
+    if !dropped.is_empty() {
+        let message = format!("The {} provider can't deliver {}, the export is written without them", provider.name(), dropped.join(", "));
+        println!("{}", message);
+        logging::warn(&message);
+    }
+}
@@ -0,0 +1,217 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use web3::types::U256;
+use crate::{filters, parse_amount, parse_quantity, registry, TxStatus};
+
+/// A decoded log of a receipt.
+#[derive(Clone)]
+pub struct RRLog {
+    pub address: Option<String>,
+    /// The event name, if the decoder recognized it.
+    pub name: Option<String>,
+    pub params: Vec<(String, Value)>,
+    /// The log as decoded.
+    pub raw: Value,
+}
+
+impl RRLog {
+    fn from_value(raw: Value) -> RRLog {
+        RRLog {
+            address: filters::log_address(&raw).map(str::to_string),
+            name: filters::log_name(&raw).map(str::to_string),
+            params: filters::params(&raw).into_iter().map(|(name, value)| (name.to_string(), value.clone())).collect(),
+            raw,
+        }
+    }
+
+    /// The sender, recipient and amount of a `Transfer` log. ERC-721 transfers carry a token ID
+    /// instead of an amount and return `None`.
+    pub fn transfer(&self) -> Option<(&str, &str, U256)> {
+        if self.name.as_deref() != Some("Transfer") {
+            return None;
+        }
+
+        let mut from = None;
+        let mut to = None;
+        let mut amount = None;
+
+        for (name, value) in &self.params {
+            match name.trim_start_matches('_').to_lowercase().as_str() {
+                "from" | "src" => from = value.as_str(),
+                "to" | "dst" => to = value.as_str(),
+                "value" | "amount" | "wad" => amount = parse_amount(value),
+                _ => {}
+            }
+        }
+
+        Some((from?, to?, amount?))
+    }
+}
+
+/// A decoded receipt. Decoders put the receipt fields on the top level or inside a nested
+/// `receipt` object, both are understood. It is exported as it was returned, including fields
+/// not covered here.
+#[derive(Clone)]
+pub struct RRReceipt {
+    pub status: Option<TxStatus>,
+    pub gas_used: Option<u64>,
+    pub effective_gas_price: Option<u64>,
+    /// The address of the deployed contract, for contract creations.
+    pub contract_address: Option<String>,
+    pub logs: Vec<RRLog>,
+    /// The complete decoded receipt.
+    pub raw: Value,
+}
+
+/// The status is encoded as a boolean, a number or a hex string.
+fn parse_status(status: &Value) -> Option<TxStatus> {
+    let success = match status {
+        Value::Bool(success) => *success,
+        _ => parse_quantity(status)? != 0
+    };
+
+    match success {
+        true => Some(TxStatus::Success),
+        false => Some(TxStatus::Failed)
+    }
+}
+
+fn field<'a>(raw: &'a Value, key: &str) -> Option<&'a Value> {
+    raw.get(key).or_else(|| raw.get("receipt").and_then(|receipt| receipt.get(key)))
+}
+
+/// The logs of a decoded receipt, found like `filters::receipt_logs`.
+pub fn logs_mut(raw: &mut Value) -> Option<&mut Vec<Value>> {
+    if raw.is_array() {
+        return raw.as_array_mut();
+    }
+    match raw.get("logs").is_some() {
+        true => raw.get_mut("logs"),
+        false => raw.get_mut("receipt").and_then(|receipt| receipt.get_mut("logs"))
+    }.and_then(Value::as_array_mut)
+}
+
+impl RRReceipt {
+    pub fn from_value(raw: Value) -> RRReceipt {
+        let field = |key: &str| field(&raw, key);
+
+        RRReceipt {
+            status: field("status").and_then(parse_status),
+            gas_used: field("gasUsed").and_then(parse_quantity),
+            effective_gas_price: field("effectiveGasPrice").and_then(parse_quantity),
+            contract_address: field("contractAddress").and_then(Value::as_str).map(str::to_string),
+            logs: filters::receipt_logs(&raw).into_iter().cloned().map(RRLog::from_value).collect(),
+            raw,
+        }
+    }
+
+    /// A field of the decoded receipt which has no typed counterpart.
+    pub fn field(&self, key: &str) -> Option<&Value> {
+        field(&self.raw, key)
+    }
+
+    /// Adds `amountFormatted`, the amount as a decimal string, to the `Transfer` logs of tokens
+    /// with known decimals. The amount in the events remains authoritative.
+    pub fn with_formatted_amounts(mut self) -> RRReceipt {
+        let formatted: Vec<Option<String>> = self.logs.iter().map(|log| {
+            let (_, _, amount) = log.transfer()?;
+            let (_, decimals) = registry::token(log.address.as_deref()?)?;
+            Some(filters::format_units(amount, decimals))
+        }).collect();
+
+        if formatted.iter().all(Option::is_none) {
+            return self;
+        }
+
+        self.edit(|raw| {
+            for (log, formatted) in logs_mut(raw).into_iter().flatten().zip(formatted) {
+                if let (Some(log), Some(formatted)) = (log.as_object_mut(), formatted) {
+                    log.insert("amountFormatted".to_string(), Value::String(formatted));
+                }
+            }
+        });
+        self
+    }
+
+    /// Applies `edit` to the decoded receipt and parses it again.
+    pub fn edit<T>(&mut self, edit: impl FnOnce(&mut Value) -> T) -> T {
+        let mut raw = std::mem::take(&mut self.raw);
+        let result = edit(&mut raw);
+        *self = RRReceipt::from_value(raw);
+        result
+    }
+}
+
+impl Serialize for RRReceipt {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        self.raw.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RRReceipt {
+    fn deserialize<D>(deserializer: D) -> Result<RRReceipt, D::Error> where D: Deserializer<'de> {
+        Value::deserialize(deserializer).map(RRReceipt::from_value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receipt(fixture: &str) -> RRReceipt {
+        serde_json::from_str(fixture).unwrap()
+    }
+
+    #[test]
+    fn successful_transaction() {
+        let receipt = receipt(include_str!("../tests/fixtures/receipt_success.json"));
+
+        assert!(receipt.status == Some(TxStatus::Success));
+        assert_eq!(receipt.gas_used, Some(53_496));
+        assert_eq!(receipt.effective_gas_price, Some(20_000_000_000));
+        assert_eq!(receipt.contract_address, None);
+        assert_eq!(receipt.logs.len(), 2);
+
+        let (from, to, amount) = receipt.logs[0].transfer().unwrap();
+        assert_eq!(from, "0x1111111111111111111111111111111111111111");
+        assert_eq!(to, "0x2222222222222222222222222222222222222222");
+        assert_eq!(amount, U256::from(250_000_000_000_000_000u64));
+        assert_eq!(receipt.logs[1].name.as_deref(), Some("Approval"));
+        assert!(receipt.logs[1].transfer().is_none());
+    }
+
+    #[test]
+    fn reverted_transaction() {
+        let receipt = receipt(include_str!("../tests/fixtures/receipt_reverted.json"));
+
+        assert!(receipt.status == Some(TxStatus::Failed));
+        assert_eq!(receipt.gas_used, Some(48_213));
+        assert_eq!(receipt.effective_gas_price, Some(20_000_000_000));
+        assert!(receipt.logs.is_empty());
+        assert_eq!(receipt.field("revertReason").and_then(Value::as_str), Some("ERC20: transfer amount exceeds balance"));
+    }
+
+    #[test]
+    fn contract_creation() {
+        let receipt = receipt(include_str!("../tests/fixtures/receipt_contract_creation.json"));
+
+        assert!(receipt.status == Some(TxStatus::Success));
+        assert_eq!(receipt.gas_used, Some(1_100_000));
+        assert_eq!(receipt.contract_address.as_deref(), Some("0x4444444444444444444444444444444444444444"));
+        assert_eq!(receipt.logs[0].address.as_deref(), Some("0x4444444444444444444444444444444444444444"));
+        assert_eq!(receipt.logs[0].name.as_deref(), Some("OwnershipTransferred"));
+        assert_eq!(receipt.logs[0].params.len(), 2);
+    }
+
+    #[test]
+    fn unknown_fields_are_exported() {
+        for fixture in [
+            include_str!("../tests/fixtures/receipt_success.json"),
+            include_str!("../tests/fixtures/receipt_reverted.json"),
+            include_str!("../tests/fixtures/receipt_contract_creation.json"),
+        ] {
+            let raw: Value = serde_json::from_str(fixture).unwrap();
+            assert_eq!(serde_json::to_value(receipt(fixture)).unwrap(), raw);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use crate::network::{self, Network};
+
+/// Well-known Ronin mainnet contracts and their labels.
+const MAINNET_CONTRACTS: &[(&str, &str)] = &[
+    ("0xe514d9deb7966c8be0ca922de8a064264ea6bcd4", "wron"),
+    ("0x97a9107c1793bc407d6f527b77e7fff4d812bece", "axs"),
+    ("0xa8754b9fa15fc18bb59458815510e40a12cd2014", "slp"),
+    ("0xc99a6a985ed2cac1ef41640596c5a5f9f4e19ef5", "weth"),
+    ("0x0b7007c13325c48911f73a2dad5fa5dcbf808adc", "usdc"),
+    ("0x32950db2a7164ae833121501c797d79e7b79d74c", "axie"),
+    ("0x8c811e3c958e190f5ec15fb376533a3398620500", "land"),
+    ("0x213073989821f738a7ba3520c3d31a1f9ad31bbd", "axie-marketplace"),
+    ("0x7d0556d55ca1a92708681e2e231733ebd922597d", "katana-router"),
+    ("0xe35d62ebe18413d96ca2a2f7cf215bb21a406b4b", "ronin-gateway"),
+];
+
+/// Well-known Saigon testnet contracts and their labels.
+const SAIGON_CONTRACTS: &[(&str, &str)] = &[
+    ("0xa959726154953bae111746e265e6d754f48570e6", "wron"),
+    ("0x3c4e17b9056272ce1b49f6900d8cfd6171a1869d", "axs"),
+    ("0x82f5483623d636bc3deba8ae67e1751b6cf2bad2", "slp"),
+    ("0x29c6f8349a028e1bdfc68bfa08bdee7bc5d47e16", "weth"),
+    ("0x067fbff8990c58ab90bae3c97241c5d736053f77", "usdc"),
+    ("0xcaca1c072d26e46686d932686015207fbe08fdb8", "axie"),
+    ("0xda44546c0715ae78d454fe8b84f0235081584fe0", "katana-router"),
+];
+
+/// Fungible tokens among the well-known contracts by label, with their ticker and decimals.
+const TOKENS: &[(&str, &str, usize)] = &[
+    ("wron", "WRON", 18),
+    ("axs", "AXS", 18),
+    ("slp", "SLP", 0),
+    ("weth", "WETH", 18),
+    ("usdc", "USDC", 6),
+];
+
+/// The well-known contracts of the network of this run.
+fn contracts() -> &'static [(&'static str, &'static str)] {
+    match network::current() {
+        Network::Mainnet => MAINNET_CONTRACTS,
+        Network::Saigon => SAIGON_CONTRACTS,
+    }
+}
+
+pub fn label(address: &str) -> Option<&'static str> {
+    contracts().iter()
+        .find(|(contract, _)| contract.eq_ignore_ascii_case(address))
+        .map(|(_, label)| *label)
+}
+
+/// The ticker and decimals of a well-known token.
+pub fn token(address: &str) -> Option<(&'static str, usize)> {
+    let label = label(address)?;
+
+    TOKENS.iter()
+        .find(|(token, _, _)| *token == label)
+        .map(|(_, ticker, decimals)| (*ticker, *decimals))
+}
@@ -0,0 +1,36 @@
+use web3::transports::Http;
+use web3::types::{Address, Bytes, CallRequest};
+use web3::Web3;
+use crate::{network, ArgParser};
+
+/// A JSON-RPC node for what ronin.rest doesn't offer, like calls to contracts. `--rpc-url`, the
+/// public endpoint of the network by default.
+pub struct Rpc {
+    web3: Web3<Http>,
+    url: String,
+}
+
+impl Rpc {
+    pub fn from_args() -> Result<Rpc, String> {
+        let url = ArgParser::split(&"--rpc-url".to_string()).unwrap_or_else(|| network::current().default_rpc_url().to_string());
+        let transport = Http::new(&url).map_err(|error| format!("Invalid --rpc-url value {}: {}", url, error))?;
+
+        Ok(Rpc { web3: Web3::new(transport), url })
+    }
+
+    /// The deployed code at `address`, empty for wallets.
+    pub async fn code(&self, address: Address) -> Result<Vec<u8>, String> {
+        self.web3.eth().code(address, None).await
+            .map(|code| code.0)
+            .map_err(|error| format!("Could not get the code of {:?} from {}: {}", address, self.url, error))
+    }
+
+    /// Calls `to` with `data` at the latest block, returning the result.
+    pub async fn call(&self, to: Address, data: Vec<u8>) -> Result<Vec<u8>, String> {
+        let request = CallRequest { to: Some(to), data: Some(Bytes(data)), ..Default::default() };
+
+        self.web3.eth().call(request, None).await
+            .map(|result| result.0)
+            .map_err(|error| format!("Call to {:?} failed: {}", to, error))
+    }
+}
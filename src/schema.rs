@@ -0,0 +1,65 @@
+use serde_json::{json, Value};
+use crate::pipeline::Options;
+use crate::{ArgParser, RRDecodedTransaction};
+
+/// The optional fields of a record which are enabled by flags outside of the pipeline options.
+pub struct Fields {
+    /// Records of the merged export list the wallets involved.
+    pub merged: bool,
+    pub confirmations: bool,
+    pub balance: bool,
+}
+
+impl Fields {
+    pub fn from_args() -> Fields {
+        Fields {
+            merged: ArgParser::flag("--merge") || ArgParser::split(&"--merge".to_string()).is_some(),
+            confirmations: ArgParser::flag("--confirmations"),
+            balance: crate::balance::enabled(),
+        }
+    }
+}
+
+/// The JSON Schema of an exported record as produced with the given options. It is generated from
+/// the serialized type and narrowed down to the fields the options actually produce.
+pub fn export_schema(options: &Options, fields: &Fields) -> Value {
+    let mut schema = serde_json::to_value(schemars::schema_for!(RRDecodedTransaction)).unwrap();
+    schema["title"] = json!("wally export record");
+    schema["additionalProperties"] = json!(false);
+
+    let properties = schema["properties"].as_object_mut().unwrap();
+
+    if !options.decode_input {
+        properties.insert("input".to_string(), json!({"type": "null"}));
+    }
+    if !options.decode_output {
+        properties.insert("output".to_string(), json!({"type": "null"}));
+        for field in ["status", "gasUsed", "effectiveGasPrice", "feeRon"] {
+            properties.insert(field.to_string(), json!({"type": "null"}));
+        }
+    }
+    if !options.raw_logs {
+        properties.remove("rawLogs");
+    }
+    if options.max_blob_bytes.is_none() {
+        properties.remove("truncatedBlobs");
+    }
+    if !fields.confirmations {
+        properties.remove("confirmations");
+    }
+    if !fields.balance {
+        properties.remove("balanceAfter");
+    }
+    if !fields.merged {
+        properties.remove("wallet");
+        properties.remove("wallets");
+        properties.remove("directions");
+    }
+
+    schema
+}
+
+/// The schema for the flags of the current invocation.
+pub fn from_args() -> Value {
+    export_schema(&Options::from_args(), &Fields::from_args())
+}
@@ -0,0 +1,67 @@
+use std::sync::OnceLock;
+use web3::signing::keccak256;
+use crate::ArgParser;
+
+/// One of `count` disjoint parts of the transactions of a run, `--shard=INDEX/COUNT`. Shards are
+/// numbered from 1.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl Shard {
+    pub fn parse(text: &str) -> Result<Shard, String> {
+        let invalid = || format!("Invalid --shard value {}, expected e.g. 2/4", text);
+        let (index, count) = text.split_once('/').ok_or_else(invalid)?;
+        let index: usize = index.trim().parse().map_err(|_| invalid())?;
+        let count: usize = count.trim().parse().map_err(|_| invalid())?;
+
+        match index >= 1 && index <= count {
+            true => Ok(Shard { index, count }),
+            false => Err(format!("Invalid --shard value {}, the shard must be between 1 and {}", text, count))
+        }
+    }
+
+    /// The shard a transaction hash belongs to. Only depends on the hash, so retrying a shard
+    /// processes exactly the same transactions.
+    pub fn of(hash: &str, count: usize) -> usize {
+        let digest = keccak256(hash.to_lowercase().as_bytes());
+        let number = u64::from_be_bytes(digest[..8].try_into().unwrap());
+
+        (number % count as u64) as usize + 1
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        Shard::of(hash, self.count) == self.index
+    }
+
+    /// Appended to the file names of the shard, e.g. `shard2of4`.
+    pub fn suffix(&self) -> String {
+        format!("shard{}of{}", self.index, self.count)
+    }
+
+    /// Splits the file name of a shard export, e.g. `0x….shard2of4.json`, into the stem of the
+    /// complete export and the shard.
+    pub fn from_file_name(file_name: &str) -> Option<(&str, Shard)> {
+        let (stem, suffix) = file_name.strip_suffix(".json")?.rsplit_once(".shard")?;
+        let (index, count) = suffix.split_once("of")?;
+        let shard = Shard::parse(&format!("{}/{}", index, count)).ok()?;
+
+        Some((stem, shard))
+    }
+}
+
+/// The shard of this run, if any. Output file names depend on it, so it is parsed once.
+pub fn current() -> Option<Shard> {
+    static SHARD: OnceLock<Option<Shard>> = OnceLock::new();
+    *SHARD.get_or_init(|| ArgParser::split(&"--shard".to_string()).map(|shard| Shard::parse(&shard).unwrap_or_else(|error| panic!("{}", error))))
+}
+
+/// The file stem of the current shard of an export, e.g. `0x….shard2of4`.
+pub fn stem(stem: &str) -> String {
+    match current() {
+        Some(shard) => format!("{}.{}", stem, shard.suffix()),
+        None => stem.to_string()
+    }
+}
@@ -0,0 +1,461 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
+use reqwest_retry::policies::ExponentialBackoff;
+use reqwest_retry::RetryTransientMiddleware;
+use serde_json::{json, Map, Value};
+use web3::types::U256;
+use crate::block::RRBlock;
+use crate::provider::{Capabilities, WalletDataProvider};
+use crate::receipt::RRReceipt;
+use crate::transfers::{TokenStandard, TransferList};
+use crate::{compression, hashes, logging, parse_amount, parse_quantity, pipeline, traffic, ArgParser, RRTransaction, RRTransactionDict, RRTransactionHash, DEFAULT_USER_AGENT};
+
+/// The Sky Mavis API gateway unless `--skymavis-url` is passed.
+const DEFAULT_URL: &str = "https://api-gateway.skymavis.com";
+
+/// The prefix of the explorer endpoints of the gateway.
+const WEB3: &str = "/skynet/ronin/web3/v2";
+
+/// Transactions requested per page unless `--page-size` is passed.
+const PAGE_SIZE: usize = 100;
+
+/// The transaction details kept for the receipt and the decoded method, which come with the same
+/// response. Transactions in flight are far fewer.
+const DETAIL_CACHE: usize = 1024;
+
+/// The fields of ronin.rest transactions and the names the explorer may give them.
+const TRANSACTION_FIELDS: &[(&str, &[&str])] = &[
+    ("hash", &["hash", "transactionHash", "txHash", "transaction_hash", "tx_hash"]),
+    ("from", &["from", "fromAddress", "from_address"]),
+    ("to", &["to", "toAddress", "to_address"]),
+    ("blockNumber", &["blockNumber", "block_number"]),
+    ("nonce", &["nonce"]),
+    ("transactionIndex", &["transactionIndex", "transaction_index", "txIndex"]),
+    ("value", &["value"]),
+    ("gasPrice", &["gasPrice", "gas_price"]),
+    ("payer", &["payer"]),
+];
+
+/// The fields of ronin.rest receipts and the names the explorer may give them.
+const RECEIPT_FIELDS: &[(&str, &[&str])] = &[
+    ("status", &["status"]),
+    ("gasUsed", &["gasUsed", "gas_used"]),
+    ("effectiveGasPrice", &["effectiveGasPrice", "effective_gas_price"]),
+    ("contractAddress", &["contractAddress", "contract_address"]),
+    ("logs", &["decodedLogs", "decoded_logs", "logs"]),
+    ("payer", &["payer"]),
+];
+
+/// Picks `fields` out of `object` under their ronin.rest names. Missing fields are left out.
+fn rename(object: &Value, fields: &[(&str, &[&str])]) -> Value {
+    let mut renamed = Map::new();
+
+    for (name, aliases) in fields {
+        if let Some(value) = aliases.iter().find_map(|alias| object.get(alias)) {
+            renamed.insert(name.to_string(), value.clone());
+        }
+    }
+
+    Value::Object(renamed)
+}
+
+/// An explorer transaction in the shape of `/ronin/getTransaction`.
+fn transaction(detail: &Value) -> Value {
+    rename(detail.get("transaction").unwrap_or(detail), TRANSACTION_FIELDS)
+}
+
+/// The receipt within an explorer transaction in the shape of `/ronin/decodeTransactionReceipt`.
+/// The explorer returns the transaction and its receipt in one object, or the receipt nested.
+fn receipt(detail: &Value) -> Value {
+    rename(detail.get("receipt").unwrap_or(detail), RECEIPT_FIELDS)
+}
+
+/// The decoded method of an explorer transaction, `null` for transfers and unknown methods.
+fn decoded_input(detail: &Value) -> Value {
+    ["decodedInput", "decoded_input", "decodedMethod", "method"].iter()
+        .find_map(|key| detail.get(key).filter(|decoded| decoded.is_object()))
+        .cloned()
+        .unwrap_or(Value::Null)
+}
+
+/// The transactions and the cursor of the next page of an account's transaction list.
+fn page(result: &Value) -> Result<(Vec<Value>, Option<String>), String> {
+    let items = result.get("items").or_else(|| result.get("data")).and_then(Value::as_array)
+        .ok_or_else(|| format!("expected a list of items, got {}", result))?;
+    let paging = result.get("paging").unwrap_or(result);
+    let cursor = ["nextCursor", "next_cursor"].iter().find_map(|key| paging.get(key)).and_then(Value::as_str)
+        .filter(|cursor| !cursor.is_empty())
+        .map(str::to_string);
+
+    Ok((items.iter().map(transaction).collect(), cursor))
+}
+
+/// The message of an error response, which the gateway sends in several shapes.
+fn error_message(body: &str) -> String {
+    let Ok(response) = serde_json::from_str::<Value>(body) else {
+        return body.chars().take(200).collect();
+    };
+
+    ["/error/message", "/error", "/message", "/errorMessage"].iter()
+        .find_map(|pointer| response.pointer(pointer).and_then(Value::as_str))
+        .map(str::to_string)
+        .unwrap_or_else(|| response.to_string())
+}
+
+/// The explorer API of Sky Mavis, selected with `--provider=skymavis`. Requests are authorized
+/// with the key of the Sky Mavis developer console, and the lists, transactions and receipts are
+/// read into the same structs as the ones of ronin.rest so every format and report works
+/// unchanged. The latest block and balances come from the RPC endpoint of the gateway.
+pub struct SkyMavis {
+    url: String,
+    key: String,
+    client: ClientWithMiddleware,
+    /// The same connection pool without retries, for the health check.
+    http: reqwest::Client,
+    page_size: usize,
+    requests: AtomicU64,
+    details: Mutex<HashMap<RRTransactionHash, Value>>,
+}
+
+impl SkyMavis {
+    pub fn from_args() -> SkyMavis {
+        let key = ArgParser::all("--skymavis-key").pop().or_else(|| std::env::var("SKYMAVIS_API_KEY").ok())
+            .unwrap_or_else(|| panic!("--provider=skymavis requires an API key, pass --skymavis-key or set SKYMAVIS_API_KEY"));
+        if !ArgParser::all("--host").is_empty() || ArgParser::flag("--localhost") || ArgParser::split(&"--unix-socket".to_string()).is_some() {
+            panic!("--provider=skymavis can not be combined with --host, --localhost or --unix-socket");
+        }
+        let url = ArgParser::all("--skymavis-url").pop().unwrap_or_else(|| DEFAULT_URL.to_string()).trim_end_matches('/').to_string();
+        let page_size = match ArgParser::split(&"--page-size".to_string()) {
+            Some(page_size) => match page_size.parse::<usize>() {
+                Ok(page_size) if page_size > 0 => page_size,
+                _ => panic!("Could not parse --page-size={}, expected a positive number of transactions", page_size)
+            },
+            None => PAGE_SIZE
+        };
+
+        println!(">> Using the Sky Mavis explorer API at {} <<", url);
+
+        let http = compression::client().build().expect("Could not create the HTTP client!");
+        let max_retries = match pipeline::tx_max_attempts() {
+            None => 25,
+            Some(_) => 3
+        };
+
+        SkyMavis {
+            client: ClientBuilder::new(http.clone()).with(
+                RetryTransientMiddleware::new_with_policy(
+                    ExponentialBackoff {
+                        max_n_retries: max_retries,
+                        min_retry_interval: Duration::from_secs(1),
+                        max_retry_interval: Duration::from_secs(15),
+                        backoff_exponent: 2
+                    }
+                )
+            ).with(traffic::Counter).build(),
+            http,
+            url,
+            key,
+            page_size,
+            requests: AtomicU64::new(0),
+            details: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sends `request` for `path`, counted as `endpoint`, and returns the parsed response.
+    async fn send(&self, endpoint: &str, path: &str, request: reqwest_middleware::RequestBuilder) -> Result<Value, String> {
+        traffic::requested(endpoint);
+        self.requests.fetch_add(1, Ordering::Relaxed);
+
+        let mut extensions = task_local_extensions::Extensions::new();
+        extensions.insert(traffic::ApiPath(endpoint.to_string()));
+
+        let response = request
+            .header("user-agent", DEFAULT_USER_AGENT)
+            .header("x-api-key", &self.key)
+            .send_with_extensions(&mut extensions).await
+            .map_err(|error| format!("Could not request {}: {}", path, error))?;
+
+        let status = response.status();
+        let text = response.text().await.map_err(|error| format!("Could not read the response to {}: {}", path, error))?;
+        traffic::downloaded(endpoint, text.len());
+
+        match status {
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => Err(format!("The Sky Mavis API key was rejected ({}): {}", status, error_message(&text))),
+            status if !status.is_success() => Err(format!("Unexpected response ({}) to {}: {}", status, path, error_message(&text))),
+            _ => serde_json::from_str(&text).map_err(|_| format!("Could not parse the response to {}", path))
+        }
+    }
+
+    /// Requests `path` of the explorer and returns its `result`.
+    async fn get(&self, endpoint: &str, path: String) -> Result<Value, String> {
+        let response = self.send(endpoint, &path, self.client.get(format!("{}{}", self.url, path))).await?;
+
+        Ok(response.get("result").cloned().unwrap_or(response))
+    }
+
+    /// Calls `method` of the RPC endpoint of the gateway.
+    async fn rpc(&self, method: &str, params: Value) -> Result<Value, String> {
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }).to_string();
+        let request = self.client.post(format!("{}/rpc", self.url)).header("content-type", "application/json").body(body);
+        let response = self.send("/skymavis/rpc", "/rpc", request).await?;
+
+        if let Some(error) = response.get("error") {
+            return Err(format!("The RPC call {} failed: {}", method, error.get("message").and_then(Value::as_str).map(str::to_string).unwrap_or_else(|| error.to_string())));
+        }
+        response.get("result").cloned().ok_or_else(|| format!("The response to the RPC call {} has no result", method))
+    }
+
+    /// The explorer's transaction `hash` with its receipt, requested once for the transaction, the
+    /// receipt and the decoded method.
+    async fn detail(&self, hash: &RRTransactionHash) -> Result<Value, String> {
+        if let Some(detail) = self.details.lock().unwrap().get(hash) {
+            return Ok(detail.clone());
+        }
+
+        let detail = self.get("/skymavis/txs", format!("{}/txs/{}", WEB3, hash)).await?;
+
+        let mut details = self.details.lock().unwrap();
+        if details.len() >= DETAIL_CACHE {
+            details.clear();
+        }
+        details.insert(hash.clone(), detail.clone());
+        Ok(detail)
+    }
+
+    /// Fetches the transactions of `address` page by page, keeping the ones `keep` selects. Pages
+    /// are deduplicated and a cursor that repeats is reported in `issues`.
+    async fn transaction_list(&self, list: &str, address: &str, keep: impl Fn(&Value) -> bool) -> RRTransactionDict {
+        let progress = indicatif::ProgressBar::new_spinner();
+        let mut data = RRTransactionDict::default();
+        let mut seen: HashSet<RRTransactionHash> = HashSet::new();
+        let mut cursors: HashSet<String> = HashSet::new();
+        let mut cursor: Option<String> = None;
+        let mut entries = vec![];
+
+        for number in 1.. {
+            let path = match &cursor {
+                None => format!("{}/accounts/{}/txs?limit={}", WEB3, address, self.page_size),
+                Some(cursor) => format!("{}/accounts/{}/txs?limit={}&cursor={}", WEB3, address, self.page_size, cursor),
+            };
+            let result = self.get("/skymavis/accountTxs", path).await.unwrap_or_else(|error| panic!("{}", error));
+            let (transactions, next) = page(&result).unwrap_or_else(|error| panic!("Could not parse page {} of {} for {}: {}", number, list, address, error));
+
+            for transaction in transactions.iter().filter(|transaction| keep(transaction)) {
+                match transaction.get("hash").and_then(Value::as_str) {
+                    Some(hash) if seen.insert(hash.to_lowercase()) => entries.push(hash.to_string()),
+                    Some(_) => {}
+                    None => entries.push(transaction.to_string())
+                }
+            }
+
+            progress.set_message(format!("{} {}: {} transactions ({} pages)", list, address, entries.len(), number));
+            progress.tick();
+
+            match next {
+                None => break,
+                Some(_) if transactions.is_empty() => break,
+                Some(next) if cursors.insert(next.clone()) => cursor = Some(next),
+                Some(next) => {
+                    data.issues.push(format!("{}: page {} repeated the cursor {}, the list may be incomplete", list, number, next));
+                    break;
+                }
+            }
+        }
+
+        progress.finish_and_clear();
+
+        (data.transactions, data.rejected) = hashes::sanitize(list, entries);
+        for rejected in &data.rejected {
+            logging::warn(&format!("Dropped the entry '{}' of {} for {}: {}", rejected.entry, list, address, rejected.reason));
+        }
+
+        data
+    }
+}
+
+/// Whether the transaction `field` of an explorer list entry is `address`.
+fn is(transaction: &Value, field: &str, address: &str) -> bool {
+    transaction.get(field).and_then(Value::as_str).is_some_and(|value| value.eq_ignore_ascii_case(address))
+}
+
+#[async_trait::async_trait]
+impl WalletDataProvider for SkyMavis {
+    fn name(&self) -> &'static str {
+        "skymavis"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { decode: true, raw_logs: false, transaction_fields: false, blocks: false, genes: false, abis: false, token_transfers: false }
+    }
+
+    /// Checks that the gateway accepts the key, with a page of the zero address.
+    async fn health_check(&self) -> Result<(), String> {
+        let path = format!("{}/accounts/{:?}/txs?limit=1", WEB3, web3::types::Address::zero());
+        traffic::requested("/skymavis/accountTxs");
+        traffic::attempted("/skymavis/accountTxs");
+
+        let response = self.http.get(format!("{}{}", self.url, path))
+            .header("user-agent", DEFAULT_USER_AGENT)
+            .header("x-api-key", &self.key)
+            .timeout(crate::HEALTH_CHECK_TIMEOUT)
+            .send().await
+            .map_err(|error| format!("Could not reach {}: {}", self.url, crate::diagnostics::describe(&error, &self.url)))?;
+
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        traffic::downloaded("/skymavis/accountTxs", body.len());
+
+        match status {
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => Err(format!("The Sky Mavis API key was rejected ({}): {}", status, error_message(&body))),
+            status if status.is_success() => match serde_json::from_str::<Value>(&body).ok().map(|body| body.get("result").cloned().unwrap_or(body)) {
+                Some(result) if page(&result).is_ok() => Ok(()),
+                _ => Err(format!("{} does not look like the Sky Mavis explorer API: unexpected response to {}", self.url, path))
+            },
+            status => Err(format!("{} does not look like the Sky Mavis explorer API: unexpected response ({}) to {}", self.url, status, path))
+        }
+    }
+
+    fn active_host(&self) -> &str {
+        &self.url
+    }
+
+    fn host_requests(&self) -> Vec<(&str, u64)> {
+        vec![(self.url.as_str(), self.requests.load(Ordering::Relaxed))]
+    }
+
+    /// The explorer lists the transactions of an account in both directions, those sent by it.
+    async fn list_sent(&self, address: &str) -> RRTransactionDict {
+        self.transaction_list("listSentTransactions", address, |transaction| is(transaction, "from", address)).await
+    }
+
+    async fn list_received(&self, address: &str) -> RRTransactionDict {
+        self.transaction_list("listReceivedTransactions", address, |transaction| is(transaction, "to", address)).await
+    }
+
+    /// Fetches a transaction. Transactions the explorer has no details for are `RRTransaction::unavailable`.
+    async fn get_transaction(&self, hash: &RRTransactionHash) -> Result<RRTransaction, String> {
+        let detail = self.detail(hash).await?;
+
+        Ok(serde_json::from_value(transaction(&detail)).unwrap_or_else(|_| RRTransaction::unavailable()))
+    }
+
+    async fn get_transaction_fields(&self, _hash: &RRTransactionHash) -> Result<(RRTransaction, Value), String> {
+        Err("The skymavis provider doesn't deliver all fields of a transaction".to_string())
+    }
+
+    async fn decode_input(&self, hash: &RRTransactionHash) -> Result<Value, String> {
+        self.detail(hash).await.map(|detail| decoded_input(&detail))
+    }
+
+    async fn decode_receipt(&self, hash: &RRTransactionHash) -> Result<RRReceipt, String> {
+        self.detail(hash).await.map(|detail| RRReceipt::from_value(receipt(&detail)))
+    }
+
+    async fn raw_logs(&self, _hash: &RRTransactionHash) -> Result<Value, String> {
+        Err("The skymavis provider doesn't deliver raw logs".to_string())
+    }
+
+    async fn get_block(&self, _number: u64) -> Result<RRBlock, String> {
+        Err("The skymavis provider doesn't deliver blocks".to_string())
+    }
+
+    async fn axie_genes(&self, _token_id: &str) -> Result<String, String> {
+        Err("The skymavis provider doesn't deliver axie genes".to_string())
+    }
+
+    async fn contract_abi(&self, _address: &str) -> Result<Value, String> {
+        Err("The skymavis provider doesn't deliver contract ABIs".to_string())
+    }
+
+    async fn token_transfers(&self, _standard: TokenStandard, _address: &str) -> Result<TransferList, String> {
+        Err("The skymavis provider doesn't deliver token transfer lists".to_string())
+    }
+
+    async fn latest_block(&self) -> Result<u64, String> {
+        let result = self.rpc("eth_blockNumber", json!([])).await?;
+
+        parse_quantity(&result).ok_or_else(|| format!("Could not parse the latest block number {}", result))
+    }
+
+    /// The current RON balance of `address` in wei.
+    async fn balance(&self, address: &str) -> Result<U256, String> {
+        let result = self.rpc("eth_getBalance", json!([address, "latest"])).await?;
+
+        parse_amount(&result).ok_or_else(|| format!("Could not parse the balance {} of {}", result, address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(fixture: &str) -> Value {
+        let response: Value = serde_json::from_str(fixture).unwrap();
+        response["result"].clone()
+    }
+
+    #[test]
+    fn transactions_match_ronin_rest() {
+        let detail = fixture(include_str!("../tests/fixtures/skymavis_transaction.json"));
+        let skymavis: RRTransaction = serde_json::from_value(transaction(&detail)).unwrap();
+        let ronin_rest: RRTransaction = serde_json::from_str(include_str!("../tests/fixtures/ronin_rest_transaction.json")).unwrap();
+
+        assert_eq!(serde_json::to_value(&skymavis).unwrap(), serde_json::to_value(&ronin_rest).unwrap());
+    }
+
+    #[test]
+    fn receipts_match_ronin_rest() {
+        let detail = fixture(include_str!("../tests/fixtures/skymavis_transaction.json"));
+        let skymavis = RRReceipt::from_value(receipt(&detail));
+        let ronin_rest: RRReceipt = serde_json::from_str(include_str!("../tests/fixtures/receipt_success.json")).unwrap();
+
+        assert!(skymavis.status == ronin_rest.status);
+        assert_eq!(skymavis.gas_used, ronin_rest.gas_used);
+        assert_eq!(skymavis.effective_gas_price, ronin_rest.effective_gas_price);
+        assert_eq!(skymavis.contract_address, ronin_rest.contract_address);
+        // The decoded logs, not the raw ones.
+        let logs = |receipt: &RRReceipt| receipt.logs.iter().map(|log| log.raw.clone()).collect::<Vec<Value>>();
+        assert_eq!(logs(&skymavis), logs(&ronin_rest));
+        assert_eq!(skymavis.logs[0].transfer(), ronin_rest.logs[0].transfer());
+        // Only receipt fields are exported as the receipt.
+        assert!(skymavis.field("from").is_none() && skymavis.field("decodedInput").is_none());
+    }
+
+    #[test]
+    fn decoded_method() {
+        let mut detail = fixture(include_str!("../tests/fixtures/skymavis_transaction.json"));
+
+        assert_eq!(decoded_input(&detail)["name"], "transfer");
+        detail.as_object_mut().unwrap().remove("decodedInput");
+        assert_eq!(decoded_input(&detail), Value::Null);
+    }
+
+    #[test]
+    fn pages_and_directions() {
+        let (transactions, cursor) = page(&fixture(include_str!("../tests/fixtures/skymavis_page.json"))).unwrap();
+        let wallet = "0x1111111111111111111111111111111111111111";
+
+        assert_eq!(cursor.as_deref(), Some("MzEyNDUxMTA6Mg=="));
+        assert_eq!(transactions[0]["hash"], "0x5e744c5a4c8e2bb747d7d1ba5fc1dbc9d5b1a59c1a3e3ac2c1b9d4a4f0a1e0c1");
+
+        let sent: Vec<bool> = transactions.iter().map(|transaction| is(transaction, "from", wallet)).collect();
+        let received: Vec<bool> = transactions.iter().map(|transaction| is(transaction, "to", wallet)).collect();
+        assert_eq!(sent, [true, false, true]);
+        assert_eq!(received, [false, true, true]);
+
+        // The last page has no cursor, or an empty one.
+        assert_eq!(page(&serde_json::json!({ "items": [], "paging": { "nextCursor": "" } })).unwrap().1, None);
+        assert_eq!(page(&serde_json::json!({ "items": [] })).unwrap().1, None);
+        assert!(page(&serde_json::json!({ "message": "not found" })).is_err());
+    }
+
+    #[test]
+    fn error_messages() {
+        assert_eq!(error_message(r#"{"error":{"code":401,"message":"invalid api key"}}"#), "invalid api key");
+        assert_eq!(error_message(r#"{"message":"rate limited"}"#), "rate limited");
+        assert_eq!(error_message("Bad Gateway"), "Bad Gateway");
+    }
+}
@@ -0,0 +1,256 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use crate::{cache, output, ArgParser, RRDecodedTransaction};
+
+/// Records kept in memory before a sorted run is spilled to disk.
+const RUN_SIZE: usize = 5_000;
+
+/// The number of sorters sharing `--max-memory`, and the runs and bytes spilled so far.
+static SORTERS: AtomicUsize = AtomicUsize::new(0);
+static SPILLED_RUNS: AtomicUsize = AtomicUsize::new(0);
+static SPILLED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// The limit of `--max-memory=SIZE` for the buffered records of all wallets.
+pub fn memory_limit() -> Option<usize> {
+    static LIMIT: OnceLock<Option<usize>> = OnceLock::new();
+    *LIMIT.get_or_init(|| ArgParser::split(&"--max-memory".to_string()).map(|size| {
+        output::parse_size(&size).unwrap_or_else(|error| panic!("Invalid --max-memory value: {}", error))
+    }))
+}
+
+/// The number of sorted runs spilled to disk and their size.
+pub fn spilled() -> (usize, usize) {
+    (SPILLED_RUNS.load(Ordering::Relaxed), SPILLED_BYTES.load(Ordering::Relaxed))
+}
+
+/// The directory for spilled runs, under `--cache-dir` if it is given. It is removed when the
+/// sorter is dropped.
+fn spill_directory() -> tempfile::TempDir {
+    let mut builder = tempfile::Builder::new();
+    builder.prefix("wally-");
+
+    match cache::dir() {
+        Some(dir) => {
+            let dir = dir.join("tmp");
+            std::fs::create_dir_all(&dir).unwrap_or_else(|error| panic!("Could not create {}: {}", dir.display(), error));
+            builder.tempdir_in(&dir)
+        }
+        None => builder.tempdir()
+    }.expect("Could not create a temporary directory")
+}
+
+/// Records are ordered by block number. Transactions within the same block are ordered by their
+/// index (and hash as a last resort) so repeated exports of the same wallet are byte-identical.
+type SortKey = (u64, Option<u64>, String);
+
+fn sort_key(record: &RRDecodedTransaction) -> SortKey {
+    (record.block_number, record.transaction_index, record.hash.clone())
+}
+
+/// Sorts records with bounded memory: every `RUN_SIZE` records, or whenever the buffer exceeds its
+/// share of `--max-memory`, the buffer is sorted and spilled to a temporary file, and the runs
+/// are merged when iterating.
+pub struct RecordSorter {
+    buffer: Vec<RRDecodedTransaction>,
+    /// The approximate size of `buffer`, the JSON size of its records. Only tracked with `--max-memory`.
+    buffered: usize,
+    /// Whether the buffer counts towards `--max-memory`, see `RecordSorter::bounded`.
+    bounded: bool,
+    runs: Vec<PathBuf>,
+    directory: Option<tempfile::TempDir>,
+    records: usize,
+}
+
+impl RecordSorter {
+    pub fn new() -> RecordSorter {
+        RecordSorter {
+            buffer: vec![],
+            buffered: 0,
+            bounded: false,
+            runs: vec![],
+            directory: None,
+            records: 0,
+        }
+    }
+
+    /// A sorter for the records of a wallet, which shares `--max-memory` with the other wallets.
+    /// The sorters of groups and reports only hold records read back from these, in runs of
+    /// `RUN_SIZE`.
+    pub fn bounded() -> RecordSorter {
+        SORTERS.fetch_add(1, Ordering::Relaxed);
+        RecordSorter { bounded: true, ..RecordSorter::new() }
+    }
+
+    /// The number of records pushed.
+    pub fn len(&self) -> usize {
+        self.records
+    }
+
+    pub fn push(&mut self, record: RRDecodedTransaction) {
+        self.records += 1;
+
+        // The sorters of all wallets fill up at the same time, each gets an equal share.
+        let limit = memory_limit().filter(|_| self.bounded).map(|limit| limit / SORTERS.load(Ordering::Relaxed).max(1));
+        if limit.is_some() {
+            self.buffered += serde_json::to_vec(&record).map(|json| json.len()).unwrap_or_default();
+        }
+        self.buffer.push(record);
+
+        if self.buffer.len() >= RUN_SIZE || limit.is_some_and(|limit| self.buffered > limit) {
+            self.spill();
+        }
+    }
+
+    fn spill(&mut self) {
+        self.buffer.sort_by_cached_key(sort_key);
+
+        let directory = self.directory.get_or_insert_with(spill_directory);
+        let path = directory.path().join(format!("run{}.ndjson", self.runs.len()));
+        let mut file = BufWriter::new(File::create(&path).unwrap_or_else(|error| panic!("Could not create {}: {}", path.display(), error)));
+
+        for record in self.buffer.drain(..) {
+            serde_json::to_writer(&mut file, &record).unwrap();
+            file.write_all(b"\n").unwrap();
+        }
+
+        file.flush().unwrap();
+        self.buffered = 0;
+        SPILLED_RUNS.fetch_add(1, Ordering::Relaxed);
+        SPILLED_BYTES.fetch_add(std::fs::metadata(&path).map(|metadata| metadata.len() as usize).unwrap_or_default(), Ordering::Relaxed);
+        self.runs.push(path);
+    }
+
+    /// Iterates over all records in order. Can be called repeatedly.
+    pub fn sorted(&mut self) -> impl Iterator<Item = RRDecodedTransaction> + '_ {
+        self.buffer.sort_by_cached_key(sort_key);
+
+        let mut sources: Vec<Box<dyn Iterator<Item = RRDecodedTransaction> + '_>> = self.runs.iter().map(|path| {
+            let file = File::open(path).unwrap_or_else(|error| panic!("Could not open {}: {}", path.display(), error));
+
+            Box::new(BufReader::new(file).lines().map(|line| serde_json::from_str(&line.unwrap()).unwrap())) as Box<dyn Iterator<Item = RRDecodedTransaction>>
+        }).collect();
+
+        sources.push(Box::new(self.buffer.iter().cloned()));
+
+        KWayMerge::new(sources).map(|(_, record)| record)
+    }
+}
+
+/// Merges sorted sources into one sorted stream, yielding the index of the source along with
+/// every record. Equal records are yielded in the order of their sources.
+pub(crate) struct KWayMerge<I> {
+    sources: Vec<I>,
+    heads: Vec<Option<RRDecodedTransaction>>,
+    heap: BinaryHeap<Reverse<(SortKey, usize)>>,
+}
+
+impl<I: Iterator<Item = RRDecodedTransaction>> KWayMerge<I> {
+    pub fn new(mut sources: Vec<I>) -> KWayMerge<I> {
+        let mut heap = BinaryHeap::new();
+        let heads = sources.iter_mut().enumerate().map(|(index, source)| {
+            let head = source.next();
+            if let Some(record) = &head {
+                heap.push(Reverse((sort_key(record), index)));
+            }
+            head
+        }).collect();
+
+        KWayMerge { sources, heads, heap }
+    }
+}
+
+impl<I: Iterator<Item = RRDecodedTransaction>> Iterator for KWayMerge<I> {
+    type Item = (usize, RRDecodedTransaction);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((_, index)) = self.heap.pop()?;
+        let record = self.heads[index].take()?;
+
+        if let Some(next) = self.sources[index].next() {
+            self.heap.push(Reverse((sort_key(&next), index)));
+            self.heads[index] = Some(next);
+        }
+
+        Some((index, record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use crate::testing::record;
+    use crate::Direction;
+    use super::*;
+
+    /// The records of one block, more than a run, some without a transaction index.
+    fn block() -> Vec<RRDecodedTransaction> {
+        (0..RUN_SIZE as u64 + 1_500)
+            .map(|n| match n % 7 {
+                0 => record(n, 100, json!({})),
+                _ => record(n, 100, json!({ "transactionIndex": n / 3 })),
+            })
+            .collect()
+    }
+
+    fn export(records: Vec<RRDecodedTransaction>) -> String {
+        let mut sorter = RecordSorter::new();
+        for record in records {
+            sorter.push(record);
+        }
+        assert!(!sorter.runs.is_empty(), "the records should have been spilled");
+
+        sorter.sorted().map(|record| serde_json::to_string(&record).unwrap() + "\n").collect()
+    }
+
+    #[test]
+    fn same_block_order_is_byte_identical() {
+        let mut shuffled = block();
+        shuffled.shuffle(&mut StdRng::seed_from_u64(1));
+        let mut reshuffled = block();
+        reshuffled.shuffle(&mut StdRng::seed_from_u64(2));
+
+        let mut expected = block();
+        expected.sort_by_cached_key(sort_key);
+        let expected: String = expected.iter().map(|record| serde_json::to_string(record).unwrap() + "\n").collect();
+
+        assert_eq!(export(shuffled), expected);
+        assert_eq!(export(reshuffled), expected);
+    }
+
+    #[test]
+    fn merges_several_runs_in_order() {
+        let count = RUN_SIZE as u64 * 4 + 321;
+        let directions = ["sent", "received", "both"];
+        let mut records: Vec<_> = (0..count)
+            .map(|n| record(n, n % 997, json!({ "transactionIndex": n % 13, "direction": directions[n as usize % 3] })))
+            .collect();
+        let expected: HashMap<String, Direction> = records.iter().map(|record| (record.hash.clone(), record.direction)).collect();
+        records.shuffle(&mut StdRng::seed_from_u64(3));
+
+        let mut sorter = RecordSorter::new();
+        for record in records {
+            sorter.push(record);
+        }
+        assert_eq!(sorter.runs.len(), 4);
+        assert_eq!(sorter.len(), count as usize);
+
+        let merged: Vec<RRDecodedTransaction> = sorter.sorted().collect();
+        assert!(merged.iter().all(|record| expected[&record.hash] == record.direction), "the direction should survive the spill");
+
+        let keys: Vec<SortKey> = merged.iter().map(sort_key).collect();
+        assert_eq!(keys.len(), count as usize);
+        assert!(keys.windows(2).all(|pair| pair[0] <= pair[1]), "the merged records should be in order");
+
+        // Iterating again yields the same records.
+        assert_eq!(sorter.sorted().count(), count as usize);
+    }
+}
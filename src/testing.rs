@@ -0,0 +1,148 @@
+//! Synthetic records and an in-memory data source for the unit tests.
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use serde_json::{json, Value};
+use web3::types::U256;
+use crate::block::RRBlock;
+use crate::provider::{Capabilities, WalletDataProvider};
+use crate::receipt::RRReceipt;
+use crate::transfers::{TokenStandard, TransferList};
+use crate::{collect_pages, RRDecodedTransaction, RRTransaction, RRTransactionDict, RRTransactionHash};
+
+pub const WALLET: &str = "0x1111111111111111111111111111111111111111";
+pub const OTHER: &str = "0x2222222222222222222222222222222222222222";
+
+/// The hash of the `n`th synthetic transaction.
+pub fn hash(n: u64) -> String {
+    format!("0x{:064x}", n)
+}
+
+/// The `n`th synthetic transaction, sent by `WALLET` to `OTHER` in `block_number`. `fields`
+/// replace the defaults, in the JSON form of an export.
+pub fn record(n: u64, block_number: u64, fields: Value) -> RRDecodedTransaction {
+    let mut record = json!({ "from": WALLET, "to": OTHER, "hash": hash(n), "blockNumber": block_number, "input": null, "output": null });
+    for (key, value) in fields.as_object().cloned().unwrap_or_default() {
+        record[key] = value;
+    }
+
+    serde_json::from_value(record).unwrap()
+}
+
+/// The `n`th synthetic transaction as a data source returns it, from `from` to `to` in block `n`.
+pub fn transaction(n: u64, from: &str, to: &str) -> RRTransaction {
+    RRTransaction { from: from.to_string(), to: to.to_string(), hash: hash(n), block_number: n, ..RRTransaction::default() }
+}
+
+/// A data source serving transaction lists and transactions from memory. The lists are kept as
+/// the pages an API returns, with `page_size` they are requested page by page like the archive of
+/// ronin.rest with `--page-size`, otherwise as a whole.
+#[derive(Default)]
+pub struct FakeProvider {
+    pub sent: HashMap<String, Vec<Vec<RRTransactionHash>>>,
+    pub received: HashMap<String, Vec<Vec<RRTransactionHash>>>,
+    pub transactions: HashMap<RRTransactionHash, RRTransaction>,
+    pub page_size: Option<usize>,
+    /// The timestamps of the blocks it serves, it has no blocks if empty.
+    pub blocks: HashMap<u64, u64>,
+    /// The number of pages, transactions and blocks requested.
+    pub requests: AtomicU64,
+}
+
+impl FakeProvider {
+    async fn list(&self, list: &str, pages: Option<&Vec<Vec<RRTransactionHash>>>, address: &str) -> RRTransactionDict {
+        let pages = pages.cloned().unwrap_or_default();
+
+        match self.page_size {
+            Some(page_size) => collect_pages(list, address, page_size, |offset, limit| {
+                self.requests.fetch_add(1, Ordering::Relaxed);
+                let page = pages.get(offset / limit).cloned().unwrap_or_default();
+                async move { json!({ "transactions": page }).to_string() }
+            }).await,
+            None => {
+                self.requests.fetch_add(1, Ordering::Relaxed);
+                RRTransactionDict { transactions: pages.concat(), ..RRTransactionDict::default() }
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl WalletDataProvider for FakeProvider {
+    fn name(&self) -> &'static str {
+        "fake"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { decode: false, raw_logs: false, transaction_fields: false, blocks: !self.blocks.is_empty(), genes: false, abis: false, token_transfers: false }
+    }
+
+    async fn health_check(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn active_host(&self) -> &str {
+        "memory"
+    }
+
+    fn host_requests(&self) -> Vec<(&str, u64)> {
+        vec![("memory", self.requests.load(Ordering::Relaxed))]
+    }
+
+    async fn list_sent(&self, address: &str) -> RRTransactionDict {
+        self.list("listSentTransactions", self.sent.get(address), address).await
+    }
+
+    async fn list_received(&self, address: &str) -> RRTransactionDict {
+        self.list("listReceivedTransactions", self.received.get(address), address).await
+    }
+
+    async fn get_transaction(&self, hash: &RRTransactionHash) -> Result<RRTransaction, String> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let tx = self.transactions.get(hash).ok_or_else(|| format!("Unknown transaction {}", hash))?;
+
+        Ok(RRTransaction { from: tx.from.clone(), to: tx.to.clone(), hash: tx.hash.clone(), block_number: tx.block_number, ..RRTransaction::default() })
+    }
+
+    async fn get_transaction_fields(&self, _: &RRTransactionHash) -> Result<(RRTransaction, Value), String> {
+        Err("the fake provider has no transaction fields".to_string())
+    }
+
+    async fn decode_input(&self, _: &RRTransactionHash) -> Result<Value, String> {
+        Err("the fake provider can't decode".to_string())
+    }
+
+    async fn decode_receipt(&self, _: &RRTransactionHash) -> Result<RRReceipt, String> {
+        Err("the fake provider can't decode".to_string())
+    }
+
+    async fn raw_logs(&self, _: &RRTransactionHash) -> Result<Value, String> {
+        Err("the fake provider has no raw logs".to_string())
+    }
+
+    async fn latest_block(&self) -> Result<u64, String> {
+        Ok(self.transactions.values().map(|tx| tx.block_number).max().unwrap_or_default())
+    }
+
+    async fn get_block(&self, number: u64) -> Result<RRBlock, String> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let timestamp = *self.blocks.get(&number).ok_or_else(|| format!("Unknown block {}", number))?;
+
+        RRBlock::from_value(number, &json!({ "timestamp": timestamp, "miner": OTHER, "transactions": [] }))
+    }
+
+    async fn axie_genes(&self, _: &str) -> Result<String, String> {
+        Err("the fake provider has no genes".to_string())
+    }
+
+    async fn contract_abi(&self, _: &str) -> Result<Value, String> {
+        Err("the fake provider has no ABIs".to_string())
+    }
+
+    async fn token_transfers(&self, _: TokenStandard, _: &str) -> Result<TransferList, String> {
+        Err("the fake provider has no token transfers".to_string())
+    }
+
+    async fn balance(&self, _: &str) -> Result<U256, String> {
+        Ok(U256::zero())
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+use reqwest::{Request, Response};
+use reqwest_middleware::{Middleware, Next};
+use serde::Serialize;
+use task_local_extensions::Extensions;
+
+/// The requests of one API endpoint.
+#[derive(Default, Clone, Copy)]
+pub struct EndpointTraffic {
+    pub requests: u64,
+    /// Requests sent, including the retries of the retry middleware.
+    pub attempts: u64,
+    /// The size of the responses, decompressed.
+    pub bytes: u64,
+}
+
+impl EndpointTraffic {
+    pub fn retries(&self) -> u64 {
+        self.attempts.saturating_sub(self.requests)
+    }
+}
+
+fn counters() -> &'static Mutex<BTreeMap<String, EndpointTraffic>> {
+    static COUNTERS: OnceLock<Mutex<BTreeMap<String, EndpointTraffic>>> = OnceLock::new();
+
+    COUNTERS.get_or_init(|| Mutex::new(BTreeMap::new()))
+}
+
+/// The endpoint of a request path, e.g. `/ronin/decodeTransaction` for
+/// `/ronin/decodeTransaction/0x…`. Hashes, addresses and query parameters are dropped.
+pub fn endpoint(path: &str) -> String {
+    let path = path.split('?').next().unwrap_or_default();
+
+    path.split('/').filter(|segment| !segment.is_empty()).take(2).fold(String::new(), |endpoint, segment| endpoint + "/" + segment)
+}
+
+fn count(path: &str, update: impl FnOnce(&mut EndpointTraffic)) {
+    update(counters().lock().unwrap().entry(endpoint(path)).or_default());
+}
+
+/// Counts a request to `path`, once however often it is retried.
+pub fn requested(path: &str) {
+    count(path, |traffic| traffic.requests += 1);
+}
+
+/// Counts a single attempt of a request, see `Counter`.
+pub fn attempted(path: &str) {
+    count(path, |traffic| traffic.attempts += 1);
+}
+
+/// Counts a response of `bytes` bytes.
+pub fn downloaded(path: &str, bytes: usize) {
+    count(path, |traffic| traffic.bytes += bytes as u64);
+}
+
+/// The traffic of the run so far, by endpoint.
+pub fn report() -> Vec<(String, EndpointTraffic)> {
+    counters().lock().unwrap().iter().map(|(endpoint, traffic)| (endpoint.clone(), *traffic)).collect()
+}
+
+pub fn total() -> EndpointTraffic {
+    report().into_iter().fold(EndpointTraffic::default(), |total, (_, traffic)| EndpointTraffic {
+        requests: total.requests + traffic.requests,
+        attempts: total.attempts + traffic.attempts,
+        bytes: total.bytes + traffic.bytes,
+    })
+}
+
+/// The requests of the run or an endpoint in the file of `--summary-json`.
+#[derive(Serialize)]
+pub struct TrafficSummary {
+    pub requests: u64,
+    pub retries: u64,
+    /// The size of the responses, decompressed.
+    pub bytes: u64,
+}
+
+impl From<EndpointTraffic> for TrafficSummary {
+    fn from(traffic: EndpointTraffic) -> TrafficSummary {
+        TrafficSummary { requests: traffic.requests, retries: traffic.retries(), bytes: traffic.bytes }
+    }
+}
+
+/// The `traffic` of `--summary-json`: the totals of `total` and the endpoints of `report`.
+#[derive(Serialize)]
+pub struct RunTraffic {
+    #[serde(flatten)]
+    pub total: TrafficSummary,
+    pub endpoints: BTreeMap<String, TrafficSummary>,
+}
+
+pub fn summary() -> RunTraffic {
+    RunTraffic {
+        total: total().into(),
+        endpoints: report().into_iter().map(|(endpoint, traffic)| (endpoint, traffic.into())).collect(),
+    }
+}
+
+/// Formats a number of bytes for the summary, e.g. `1.5 MB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+
+    if bytes < 1000 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// The API path of a request, passed to the middleware so hosts with a path prefix are counted
+/// by endpoint as well.
+#[derive(Clone)]
+pub struct ApiPath(pub String);
+
+/// Counts every request the client sends. Added after the retry middleware, so it also sees the
+/// retries that never reach `RoninRest`.
+pub struct Counter;
+
+#[async_trait::async_trait]
+impl Middleware for Counter {
+    async fn handle(&self, req: Request, extensions: &mut Extensions, next: Next<'_>) -> reqwest_middleware::Result<Response> {
+        match extensions.get::<ApiPath>() {
+            Some(ApiPath(path)) => attempted(path),
+            None => attempted(req.url().path())
+        }
+        next.run(req, extensions).await
+    }
+}
@@ -0,0 +1,212 @@
+use serde::Serialize;
+use serde_json::Value;
+use web3::types::U256;
+use crate::receipt::{RRLog, RRReceipt};
+use crate::{filters, normalize_address, parse_amount, parse_quantity, registry};
+
+pub const HEADER: &str = "transactionHash,blockNumber,logIndex,standard,token,label,from,to,direction,value,tokenId,amountFormatted";
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenStandard {
+    Erc20,
+    Erc721,
+    Erc1155,
+}
+
+impl TokenStandard {
+    pub const ALL: [TokenStandard; 3] = [TokenStandard::Erc20, TokenStandard::Erc721, TokenStandard::Erc1155];
+
+    pub fn parse(text: &str) -> Result<TokenStandard, String> {
+        match text.trim().to_lowercase().replace('-', "").as_str() {
+            "erc20" => Ok(TokenStandard::Erc20),
+            "erc721" => Ok(TokenStandard::Erc721),
+            "erc1155" => Ok(TokenStandard::Erc1155),
+            _ => Err(format!("Invalid --standard value {}, expected erc20, erc721 or erc1155", text))
+        }
+    }
+
+    /// The name in the endpoint, `/archive/list<Name>Transfers/ADDRESS`.
+    pub fn endpoint_name(&self) -> &'static str {
+        match self {
+            TokenStandard::Erc20 => "Erc20",
+            TokenStandard::Erc721 => "Erc721",
+            TokenStandard::Erc1155 => "Erc1155",
+        }
+    }
+}
+
+/// The transfers of one standard as the transfer endpoint listed them, with what looked wrong
+/// while paging through them.
+#[derive(Default)]
+pub struct TransferList {
+    pub transfers: Vec<Value>,
+    pub total: Option<usize>,
+    pub issues: Vec<String>,
+}
+
+/// A token transfer from or to a wallet, the same whether it comes from the transfer endpoints
+/// or from the `Transfer`, `TransferSingle` and `TransferBatch` logs of a receipt.
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferRecord {
+    pub transaction_hash: String,
+    pub block_number: u64,
+    pub log_index: Option<u64>,
+    pub standard: TokenStandard,
+    /// The token contract.
+    pub token: String,
+    pub label: Option<&'static str>,
+    pub from: String,
+    pub to: String,
+    pub direction: &'static str,
+    /// The amount moved in the smallest unit as a decimal string, 1 for ERC-721 tokens.
+    pub value: String,
+    pub token_id: Option<String>,
+    /// `value` as a decimal string for tokens with known decimals, `value` remains authoritative.
+    pub amount_formatted: Option<String>,
+}
+
+fn direction(from: &str, to: &str, wallet: &str) -> &'static str {
+    match (from.eq_ignore_ascii_case(wallet), to.eq_ignore_ascii_case(wallet)) {
+        (true, true) => "both",
+        (true, false) => "sent",
+        _ => "received"
+    }
+}
+
+impl TransferRecord {
+    #[allow(clippy::too_many_arguments)]
+    fn new(hash: &str, block_number: u64, log_index: Option<u64>, standard: TokenStandard, token: &str, from: &str, to: &str, value: U256, token_id: Option<U256>, wallet: &str) -> TransferRecord {
+        let token = normalize_address(token).to_lowercase();
+        let (from, to) = (normalize_address(from).to_lowercase(), normalize_address(to).to_lowercase());
+        let amount_formatted = match standard {
+            TokenStandard::Erc20 => registry::token(&token).map(|(_, decimals)| filters::format_units(value, decimals)),
+            _ => None
+        };
+
+        TransferRecord {
+            transaction_hash: hash.to_lowercase(),
+            block_number,
+            log_index,
+            standard,
+            label: registry::label(&token),
+            token,
+            direction: direction(&from, &to, wallet),
+            from,
+            to,
+            value: value.to_string(),
+            token_id: token_id.map(|token_id| token_id.to_string()),
+            amount_formatted,
+        }
+    }
+
+    /// Normalizes an entry of a transfer endpoint. Wording differs between API versions, so the
+    /// common names of each field are accepted.
+    pub fn from_api(standard: TokenStandard, entry: &Value, wallet: &str) -> Result<TransferRecord, String> {
+        let field = |keys: &[&str]| keys.iter().find_map(|key| entry.get(*key)).filter(|value| !value.is_null());
+        let text = |keys: &[&str], name: &str| field(keys).and_then(Value::as_str).ok_or_else(|| format!("no {}", name));
+
+        let hash = text(&["transactionHash", "txHash", "hash"], "transaction hash")?;
+        let token = text(&["tokenAddress", "token", "contract", "address"], "token contract")?;
+        let from = text(&["from"], "sender")?;
+        let to = text(&["to"], "recipient")?;
+        let block_number = field(&["blockNumber", "block"]).and_then(parse_quantity).unwrap_or_default();
+        let log_index = field(&["logIndex"]).and_then(parse_quantity);
+        let token_id = match field(&["tokenId", "id"]) {
+            None => None,
+            Some(token_id) => Some(parse_amount(token_id).ok_or_else(|| format!("invalid token ID {}", token_id))?)
+        };
+        let value = match (field(&["value", "amount"]), standard) {
+            (Some(value), _) => parse_amount(value).ok_or_else(|| format!("invalid value {}", value))?,
+            (None, TokenStandard::Erc721) => U256::one(),
+            (None, _) => return Err("no value".to_string())
+        };
+        if standard != TokenStandard::Erc20 && token_id.is_none() {
+            return Err("no token ID".to_string());
+        }
+
+        Ok(TransferRecord::new(hash, block_number, log_index, standard, token, from, to, value, token_id, wallet))
+    }
+
+    /// The token transfers from or to `wallet` in the logs of a decoded receipt. A `Transfer` with
+    /// an amount is an ERC-20 transfer and one with a token ID an ERC-721 transfer. The log index
+    /// is the position of the log in the receipt.
+    pub fn from_receipt(hash: &str, block_number: u64, receipt: &RRReceipt, wallet: &str) -> Vec<TransferRecord> {
+        let mut records = vec![];
+
+        for (index, log) in receipt.logs.iter().enumerate() {
+            let Some(token) = log.address.as_deref() else {
+                continue;
+            };
+            let new = |standard, from: &str, to: &str, value, token_id| TransferRecord::new(hash, block_number, Some(index as u64), standard, token, from, to, value, token_id, wallet);
+
+            match log.name.as_deref() {
+                Some("Transfer") => match (log.transfer(), param(log, &["from", "src"]), param(log, &["to", "dst"]), amount(log, &["tokenid", "id"])) {
+                    (Some((from, to, value)), _, _, _) => records.push(new(TokenStandard::Erc20, from, to, value, None)),
+                    (None, Some(from), Some(to), Some(token_id)) => records.push(new(TokenStandard::Erc721, from, to, U256::one(), Some(token_id))),
+                    _ => {}
+                },
+                Some("TransferSingle") => {
+                    if let (Some(from), Some(to), Some(id), Some(value)) = (param(log, &["from"]), param(log, &["to"]), amount(log, &["id"]), amount(log, &["value"])) {
+                        records.push(new(TokenStandard::Erc1155, from, to, value, Some(id)));
+                    }
+                }
+                Some("TransferBatch") => {
+                    if let (Some(from), Some(to), Some(ids), Some(values)) = (param(log, &["from"]), param(log, &["to"]), amounts(log, "ids"), amounts(log, "values")) {
+                        records.extend(ids.into_iter().zip(values).map(|(id, value)| new(TokenStandard::Erc1155, from, to, value, Some(id))));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        records.retain(|record| record.from.eq_ignore_ascii_case(wallet) || record.to.eq_ignore_ascii_case(wallet));
+        records
+    }
+
+    /// The wallet's counterparty.
+    pub fn counterparty(&self) -> &str {
+        match self.direction {
+            "sent" => &self.to,
+            _ => &self.from
+        }
+    }
+
+    pub fn csv_row(&self) -> String {
+        let optional = |field: &Option<String>| field.as_deref().map(crate::csv::escape).unwrap_or_default();
+
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.transaction_hash, self.block_number, self.log_index.map(|index| index.to_string()).unwrap_or_default(),
+            serde_json::to_value(self.standard).unwrap().as_str().unwrap(), self.token, self.label.unwrap_or_default(),
+            self.from, self.to, self.direction, self.value, optional(&self.token_id), optional(&self.amount_formatted)
+        )
+    }
+
+    /// What identifies a transfer in both sources. Log indexes are left out since the endpoints
+    /// don't always count them like the receipts do.
+    pub fn key(&self) -> (String, String, String, String, Option<String>) {
+        (self.transaction_hash.clone(), self.token.clone(), self.from.clone(), self.to.clone(), self.token_id.clone())
+    }
+}
+
+/// A param of a log by name, ignoring case and leading underscores.
+fn param<'a>(log: &'a RRLog, names: &[&str]) -> Option<&'a str> {
+    log.params.iter()
+        .find(|(name, _)| names.contains(&name.trim_start_matches('_').to_lowercase().as_str()))
+        .and_then(|(_, value)| value.as_str())
+}
+
+fn amount(log: &RRLog, names: &[&str]) -> Option<U256> {
+    log.params.iter()
+        .find(|(name, _)| names.contains(&name.trim_start_matches('_').to_lowercase().as_str()))
+        .and_then(|(_, value)| parse_amount(value))
+}
+
+fn amounts(log: &RRLog, name: &str) -> Option<Vec<U256>> {
+    log.params.iter()
+        .find(|(param, _)| param.trim_start_matches('_').eq_ignore_ascii_case(name))
+        .and_then(|(_, value)| value.as_array())
+        .and_then(|values| values.iter().map(parse_amount).collect())
+}
@@ -0,0 +1,235 @@
+#[cfg(unix)]
+use std::future::Future;
+#[cfg(unix)]
+use std::pin::Pin;
+#[cfg(unix)]
+use std::sync::Arc;
+#[cfg(unix)]
+use std::task::{Context, Poll};
+#[cfg(unix)]
+use hyper::client::connect::{Connected, Connection};
+#[cfg(unix)]
+use hyper::service::Service;
+#[cfg(unix)]
+use hyper::Uri;
+#[cfg(unix)]
+use reqwest::StatusCode;
+use reqwest::{Request, Response};
+use reqwest_middleware::{Middleware, Next};
+use task_local_extensions::Extensions;
+#[cfg(unix)]
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use crate::ArgParser;
+
+/// The socket of `--unix-socket=PATH`.
+pub fn from_args() -> Option<String> {
+    ArgParser::split(&"--unix-socket".to_string())
+}
+
+/// Checks that `path` is a socket this user may connect to, with a message saying what is wrong.
+#[cfg(unix)]
+fn check(path: &str) -> Result<(), String> {
+    use std::io::ErrorKind;
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::metadata(path) {
+        Ok(metadata) if !metadata.file_type().is_socket() => Err(format!("{} is not a unix socket", path)),
+        Ok(_) => match std::os::unix::net::UnixStream::connect(path) {
+            Ok(_) => Ok(()),
+            Err(error) if error.kind() == ErrorKind::PermissionDenied => Err(format!("Permission denied for the socket {}, run wally as a user with write access to it", path)),
+            Err(error) if error.kind() == ErrorKind::ConnectionRefused => Err(format!("Nothing is listening on the socket {}, is ronin.rest running?", path)),
+            Err(error) => Err(format!("Could not connect to the socket {}: {}", path, error))
+        },
+        Err(error) if error.kind() == ErrorKind::NotFound => Err(format!("There is no socket at {}, is ronin.rest running and configured to listen there?", path)),
+        Err(error) if error.kind() == ErrorKind::PermissionDenied => Err(format!("Permission denied for the socket {}, run wally as a user with access to its directory", path)),
+        Err(error) => Err(format!("Could not access the socket {}: {}", path, error))
+    }
+}
+
+/// The host of the URLs of a `--unix-socket` run. Requests never leave the socket, the host is
+/// only sent in the `Host` header.
+pub const HOST: &str = "http://localhost";
+
+/// A connection to the socket.
+#[cfg(unix)]
+struct Stream(tokio::net::UnixStream);
+
+#[cfg(unix)]
+impl Connection for Stream {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+#[cfg(unix)]
+impl AsyncRead for Stream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+#[cfg(unix)]
+impl AsyncWrite for Stream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+/// Connects to the socket whatever the URL.
+#[cfg(unix)]
+#[derive(Clone)]
+struct Connector(Arc<str>);
+
+#[cfg(unix)]
+impl Service<Uri> for Connector {
+    type Response = Stream;
+    type Error = std::io::Error;
+    type Future = Pin<Box<dyn Future<Output = std::io::Result<Stream>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _: Uri) -> Self::Future {
+        let path = self.0.clone();
+        Box::pin(async move { tokio::net::UnixStream::connect(&*path).await.map(Stream) })
+    }
+}
+
+/// Sends the requests of a `--unix-socket` run over the socket. It is the last middleware of the
+/// client and answers requests itself, so they go through the same retries, circuit breaker and
+/// traffic counter as with `--host`.
+#[cfg(unix)]
+#[derive(Clone)]
+pub struct UnixSocket {
+    path: Arc<str>,
+    client: hyper::Client<Connector>,
+}
+
+#[cfg(unix)]
+impl UnixSocket {
+    async fn send(&self, request: Request) -> Result<Response, String> {
+        let mut builder = hyper::Request::builder().method(request.method().clone()).uri(request.url().as_str());
+        for (name, value) in request.headers() {
+            builder = builder.header(name, value);
+        }
+        let body = request.body().and_then(reqwest::Body::as_bytes).map(<[u8]>::to_vec).unwrap_or_default();
+        let request_timeout = request.timeout().copied();
+
+        let exchange = async {
+            let response = self.client.request(builder.body(hyper::Body::from(body)).unwrap()).await.map_err(|error| error.to_string())?;
+            let (parts, body) = response.into_parts();
+            let body = hyper::body::to_bytes(body).await.map_err(|error| error.to_string())?;
+            Ok(Response::from(hyper::Response::from_parts(parts, body.to_vec())))
+        };
+
+        match request_timeout {
+            Some(request_timeout) => tokio::time::timeout(request_timeout, exchange).await.unwrap_or_else(|_| Err("timed out".to_string())),
+            None => exchange.await
+        }
+    }
+}
+
+#[cfg(unix)]
+#[async_trait::async_trait]
+impl Middleware for UnixSocket {
+    async fn handle(&self, request: Request, _: &mut Extensions, _: Next<'_>) -> reqwest_middleware::Result<Response> {
+        let path = request.url().path().to_string();
+
+        match self.send(request).await {
+            Ok(response) => Ok(response),
+            Err(error) => {
+                let message = format!("Could not request {} over the socket {}: {}", path, self.path, error);
+                crate::logging::warn(&message);
+                // Answered like an unavailable server, so the request is retried and failed like any other.
+                Ok(Response::from(hyper::Response::builder().status(StatusCode::SERVICE_UNAVAILABLE).body(message).unwrap()))
+            }
+        }
+    }
+}
+
+/// Checks the socket at `path` and returns the middleware sending requests over it, with HTTP/2
+/// prior knowledge if `http2` is set.
+#[cfg(unix)]
+pub fn connect(path: &str, http2: bool) -> Result<UnixSocket, String> {
+    check(path)?;
+
+    let path: Arc<str> = Arc::from(path);
+    let client = hyper::Client::builder().http2_only(http2).build(Connector(path.clone()));
+
+    Ok(UnixSocket { path, client })
+}
+
+/// The middleware of `--unix-socket`, which can't be created on this system.
+#[cfg(not(unix))]
+#[derive(Clone)]
+pub enum UnixSocket {}
+
+#[cfg(not(unix))]
+#[async_trait::async_trait]
+impl Middleware for UnixSocket {
+    async fn handle(&self, _: Request, _: &mut Extensions, _: Next<'_>) -> reqwest_middleware::Result<Response> {
+        match *self {}
+    }
+}
+
+#[cfg(not(unix))]
+pub fn connect(_: &str, _: bool) -> Result<UnixSocket, String> {
+    Err("--unix-socket is only supported on unix systems".to_string())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::UnixListener;
+    use super::*;
+
+    /// A client sending its requests to the socket at `path`, which isn't checked.
+    fn client(path: &str) -> reqwest_middleware::ClientWithMiddleware {
+        let path: Arc<str> = Arc::from(path);
+        let socket = UnixSocket { client: hyper::Client::builder().build(Connector(path.clone())), path };
+        reqwest_middleware::ClientBuilder::new(reqwest::Client::new()).with(socket).build()
+    }
+
+    #[tokio::test]
+    async fn requests_go_over_the_socket() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("ronin.sock").to_str().unwrap().to_string();
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let server = tokio::spawn(async move {
+            let (mut connection, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let read = connection.read(&mut request).await.unwrap();
+            connection.write_all(b"HTTP/1.1 200 OK\r\nx-api-version: 1.2.3\r\ncontent-length: 2\r\n\r\n[]").await.unwrap();
+            String::from_utf8_lossy(&request[..read]).to_string()
+        });
+
+        let response = client(&path).get(format!("{}/archive/listSentTransactions/0x0?page=2", HOST)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-api-version"], "1.2.3");
+        assert_eq!(response.text().await.unwrap(), "[]");
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("GET /archive/listSentTransactions/0x0?page=2 HTTP/1.1\r\n"), "{}", request);
+        assert!(request.to_lowercase().contains("host: localhost\r\n"), "{}", request);
+    }
+
+    #[tokio::test]
+    async fn failures_are_answered_as_unavailable() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("missing.sock").to_str().unwrap().to_string();
+
+        let response = client(&path).get(format!("{}/archive/listSentTransactions/0x0", HOST)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(response.text().await.unwrap().contains("missing.sock"));
+    }
+}